@@ -7,7 +7,9 @@ mod event;
 mod event_kind;
 mod expr;
 mod func;
+mod kind;
 mod list;
+mod match_arm;
 mod name;
 mod proc;
 mod project;
@@ -31,7 +33,9 @@ pub use event::*;
 pub use event_kind::*;
 pub use expr::*;
 pub use func::*;
+pub use kind::*;
 pub use list::*;
+pub use match_arm::*;
 pub use name::*;
 pub use proc::*;
 pub use project::*;
@@ -40,6 +44,7 @@ pub use rotation_style::*;
 pub use sprite::*;
 pub use stmt::*;
 pub use struct_::*;
+pub use struct_field::*;
 pub use struct_literal_field::*;
 pub use type_::*;
 pub use value::*;
@@ -7,7 +7,7 @@ use serde::{
 use super::enum_variant::EnumVariant;
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Enum {
     pub name: SmolStr,
     pub span: Span,
@@ -7,7 +7,7 @@ use serde::{
 use super::value::Value;
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnumVariant {
     pub name: SmolStr,
     pub span: Span,
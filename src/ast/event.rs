@@ -1,4 +1,5 @@
 use logos::Span;
+use rustc_hash::FxHashMap;
 use serde::{
     Deserialize,
     Serialize,
@@ -8,12 +9,18 @@ use super::{
     event_kind::EventKind,
     stmt::Stmt,
     References,
+    Var,
 };
+use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
     pub kind: EventKind,
     pub span: Span,
     pub body: Vec<Stmt>,
     pub references: References,
+    /// The local variables declared in `body`.
+    pub locals: FxHashMap<SmolStr, Var>,
+    /// Qualifies the Scratch names of `locals`, such as `onflag#1`. Set by `pass0`.
+    pub scope: SmolStr,
 }
@@ -12,16 +12,39 @@ use super::{
 use crate::misc::SmolStr;
 
 #[allow(clippy::enum_variant_names)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EventKind {
     OnFlag,
-    OnKey { key: SmolStr, span: Span },
+    OnKey {
+        key: SmolStr,
+        span: Span,
+    },
     OnClick,
-    OnBackdrop { backdrop: SmolStr, span: Span },
-    OnLoudnessGt { value: Box<Expr> },
-    OnTimerGt { value: Box<Expr> },
+    OnBackdrop {
+        backdrop: SmolStr,
+        span: Span,
+    },
+    OnLoudnessGt {
+        value: Box<Expr>,
+    },
+    OnTimerGt {
+        value: Box<Expr>,
+    },
+    OnVideoMotionGt {
+        value: Box<Expr>,
+    },
+    OnMakeyKey {
+        key: SmolStr,
+        span: Span,
+    },
     OnClone,
-    On { event: SmolStr },
+    On {
+        event: SmolStr,
+    },
+    /// Only built by `goboscript test`, where it is run by itself.
+    Test {
+        name: SmolStr,
+    },
 }
 
 impl EventKind {
@@ -32,8 +55,39 @@ impl EventKind {
             EventKind::OnClick => "event_whenthisspriteclicked",
             EventKind::OnBackdrop { .. } => "event_whenbackdropswitchesto",
             EventKind::OnLoudnessGt { .. } | EventKind::OnTimerGt { .. } => "event_whengreaterthan",
+            EventKind::OnVideoMotionGt { .. } => "videoSensing_whenMotionGreaterThan",
+            EventKind::OnMakeyKey { .. } => "makeymakey_whenMakeyKeyPressed",
             EventKind::OnClone => "control_start_as_clone",
-            EventKind::On { .. } => "event_whenbroadcastreceived",
+            EventKind::On { .. } | EventKind::Test { .. } => "event_whenbroadcastreceived",
+        }
+    }
+
+    /// The broadcast that starts the event. Tests are started by a broadcast with
+    /// zero-width spaces in its name, so that it does not clash with other broadcasts.
+    pub fn broadcast(&self) -> Option<SmolStr> {
+        match self {
+            EventKind::On { event } => Some(event.clone()),
+            EventKind::Test { name } => {
+                Some(format!("\u{200b}\u{200b}test\u{200b}\u{200b} {name}").into())
+            }
+            _ => None,
+        }
+    }
+
+    /// The keyword which declares the event.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            EventKind::OnFlag => "onflag",
+            EventKind::OnKey { .. } => "onkey",
+            EventKind::OnClick => "onclick",
+            EventKind::OnBackdrop { .. } => "onbackdrop",
+            EventKind::OnLoudnessGt { .. } => "onloudness",
+            EventKind::OnTimerGt { .. } => "ontimer",
+            EventKind::OnVideoMotionGt { .. } => "onvideomotion",
+            EventKind::OnMakeyKey { .. } => "onmakeykey",
+            EventKind::OnClone => "onclone",
+            EventKind::On { .. } => "on",
+            EventKind::Test { .. } => "test",
         }
     }
 
@@ -44,6 +98,8 @@ impl EventKind {
             body,
             span,
             references: Default::default(),
+            locals: Default::default(),
+            scope: Default::default(),
         }
     }
 }
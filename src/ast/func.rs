@@ -7,15 +7,25 @@ use serde::{
 use super::Type;
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Func {
     pub name: SmolStr,
     pub span: Span,
     pub type_: Type,
+    /// Whether the function was declared with `inline`.
+    pub inline: bool,
+    /// Whether the function was declared with `recursive`.
+    pub recursive: bool,
 }
 
 impl Func {
-    pub fn new(name: SmolStr, span: Span, type_: Type) -> Self {
-        Self { name, span, type_ }
+    pub fn new(name: SmolStr, span: Span, type_: Type, inline: bool, recursive: bool) -> Self {
+        Self {
+            name,
+            span,
+            type_,
+            inline,
+            recursive,
+        }
     }
 }
@@ -0,0 +1,76 @@
+use core::fmt;
+use std::fmt::Display;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::Value;
+
+/// The kind of value that an expression evaluates to, or that an input takes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Kind {
+    Number,
+    String,
+    Boolean,
+    Unknown,
+}
+
+impl Kind {
+    /// The kind named by a type annotation, such as `number` in `var number score = 0;`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "number" => Some(Self::Number),
+            "string" => Some(Self::String),
+            "boolean" => Some(Self::Boolean),
+            _ => None,
+        }
+    }
+
+    /// Strings which Scratch would read as a number, like `"5"`, are numbers.
+    pub fn of_value(value: &Value) -> Self {
+        match value {
+            Value::Boolean(_) => Self::Boolean,
+            Value::Number(_) => Self::Number,
+            Value::String(string) => {
+                if string.trim().parse::<f64>().is_ok() {
+                    Self::Number
+                } else {
+                    Self::String
+                }
+            }
+        }
+    }
+
+    /// Whether an input of this kind takes a value of kind `found`. Every value can be
+    /// joined into a string, `true` and `false` are the numbers 1 and 0, and a value of
+    /// unknown kind is always taken.
+    pub fn accepts(self, found: Kind) -> bool {
+        match (self, found) {
+            (Self::String | Self::Unknown, _) | (_, Self::Unknown) => true,
+            (Self::Boolean, Self::Number) => true,
+            _ => self == found,
+        }
+    }
+
+    /// The kind shared by both values, if they are of the same kind.
+    pub fn union(self, other: Kind) -> Self {
+        if self == other {
+            self
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Number => write!(f, "number"),
+            Kind::String => write!(f, "string"),
+            Kind::Boolean => write!(f, "boolean"),
+            Kind::Unknown => write!(f, "value"),
+        }
+    }
+}
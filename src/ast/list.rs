@@ -10,7 +10,7 @@ use super::{
 };
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct List {
     pub name: SmolStr,
     pub span: Span,
@@ -19,7 +19,7 @@ pub struct List {
     pub is_used: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ListDefault {
     Values(Vec<ConstExpr>),
     File { path: SmolStr, span: Span },
@@ -0,0 +1,28 @@
+use logos::Span;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::{
+    Expr,
+    Name,
+    Stmt,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchArm {
+    pub patterns: Vec<Expr>,
+    pub span: Span,
+    pub body: Vec<Stmt>,
+}
+
+impl MatchArm {
+    /// The `_` arm, which matches any value.
+    pub fn is_wildcard(&self) -> bool {
+        matches!(
+            self.patterns.as_slice(),
+            [Expr::Name(Name::Name { name, .. })] if name == "_"
+        )
+    }
+}
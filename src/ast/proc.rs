@@ -6,15 +6,25 @@ use serde::{
 
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Proc {
     pub name: SmolStr,
     pub span: Span,
     pub warp: bool,
+    /// Whether the procedure was declared with `inline`.
+    pub inline: bool,
+    /// Whether the procedure was declared with `recursive`.
+    pub recursive: bool,
 }
 
 impl Proc {
-    pub fn new(name: SmolStr, span: Span, warp: bool) -> Self {
-        Self { name, span, warp }
+    pub fn new(name: SmolStr, span: Span, warp: bool, inline: bool, recursive: bool) -> Self {
+        Self {
+            name,
+            span,
+            warp,
+            inline,
+            recursive,
+        }
     }
 }
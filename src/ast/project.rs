@@ -8,7 +8,7 @@ use tsify::Tsify;
 use super::sprite::Sprite;
 use crate::misc::SmolStr;
 
-#[derive(Debug, Tsify, Serialize, Deserialize)]
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
 pub struct Project {
     pub stage: Sprite,
     pub sprites: FxHashMap<SmolStr, Sprite>,
@@ -6,7 +6,7 @@ use serde::{
 
 use crate::misc::SmolStr;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct References {
     pub procs: FxHashSet<SmolStr>,
     pub funcs: FxHashSet<SmolStr>,
@@ -14,9 +14,15 @@ pub struct References {
     pub generated_names: FxHashSet<NameReference>,
     pub structs: FxHashSet<SmolStr>,
     pub args: FxHashSet<NameReference>,
+    /// Variables and lists which statements assign to or change, such as `x` in `x = 1;`.
+    pub targets: FxHashSet<SmolStr>,
+    /// Properties of other sprites which are read with `"Sprite"."property"`.
+    pub properties: FxHashSet<SmolStr>,
+    /// Variables which are never assigned to, whose reads were replaced by their values.
+    pub constants: FxHashSet<SmolStr>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub struct NameReference {
     pub name: SmolStr,
     pub field: Option<SmolStr>,
@@ -5,7 +5,7 @@ use serde::{
     Serialize,
 };
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub enum RotationStyle {
     LeftRight,
     #[default]
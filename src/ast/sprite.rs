@@ -14,11 +14,12 @@ use crate::{
     diagnostic::{
         Diagnostic,
         DiagnosticKind,
+        Note,
     },
     misc::SmolStr,
 };
 
-#[derive(Debug, Default, Tsify, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Tsify, Serialize, Deserialize)]
 #[tsify(type = "object")]
 pub struct Sprite {
     pub costumes: Vec<Asset>,
@@ -40,6 +41,13 @@ pub struct Sprite {
     pub events: Vec<Event>,
     pub used_procs: FxHashSet<SmolStr>,
     pub used_funcs: FxHashSet<SmolStr>,
+    /// Procedures and functions whose calls were all replaced by their bodies, so their
+    /// definitions are left out of the project.
+    pub inlined_procs: FxHashSet<SmolStr>,
+    pub inlined_funcs: FxHashSet<SmolStr>,
+    /// Variables and lists which are used by the events, or the procedures and functions
+    /// that are used.
+    pub used_names: FxHashSet<SmolStr>,
     pub volume: Option<(Value, Span)>,
     pub x_position: Option<(Value, Span)>,
     pub y_position: Option<(Value, Span)>,
@@ -47,15 +55,29 @@ pub struct Sprite {
     pub direction: Option<(Value, Span)>,
     pub rotation_style: RotationStyle,
     pub hidden: bool,
+    /// Spans of the top-level items of the sprite, in the order they are written.
+    pub items: Vec<Span>,
+    /// Names written in the code which could not be parsed and was left out. That code may
+    /// have declared them, so they are not reported as unrecognized.
+    pub unparsed_names: FxHashSet<SmolStr>,
 }
 
 impl Sprite {
+    /// The span of the variable or list named `name`, which share a namespace.
+    fn declaration_span(&self, name: &str) -> Option<Span> {
+        self.vars
+            .get(name)
+            .map(|var| var.span.clone())
+            .or_else(|| self.lists.get(name).map(|list| list.span.clone()))
+    }
+
     pub(crate) fn add_var(&mut self, var: Var, diagnostics: &mut Vec<Diagnostic>) {
         let name = var.name.clone();
-        if self.vars.contains_key(&name) || self.lists.contains_key(&name) {
+        if let Some(span) = self.declaration_span(&name) {
             diagnostics.push(Diagnostic {
                 kind: DiagnosticKind::VariableRedefinition(name),
                 span: var.span.clone(),
+                notes: vec![Note::new("first defined here", &span)],
             });
             return;
         }
@@ -64,10 +86,11 @@ impl Sprite {
 
     pub(crate) fn add_list(&mut self, list: List, diagnostics: &mut Vec<Diagnostic>) {
         let name = list.name.clone();
-        if self.vars.contains_key(&name) || self.lists.contains_key(&name) {
+        if let Some(span) = self.declaration_span(&name) {
             diagnostics.push(Diagnostic {
                 kind: DiagnosticKind::ListRedefinition(name),
                 span: list.span.clone(),
+                notes: vec![Note::new("first defined here", &span)],
             });
             return;
         }
@@ -76,23 +99,25 @@ impl Sprite {
 
     pub(crate) fn add_struct(&mut self, struct_: Struct, diagnostics: &mut Vec<Diagnostic>) {
         let name = struct_.name.clone();
-        let mut fields = FxHashSet::default();
+        let mut fields: FxHashMap<&SmolStr, &Span> = FxHashMap::default();
         for field in &struct_.fields {
-            if !fields.insert(field.name.clone()) {
+            if let Some(first) = fields.insert(&field.name, &field.span) {
                 diagnostics.push(Diagnostic {
                     kind: DiagnosticKind::DuplicateField {
                         struct_name: name,
                         field_name: field.name.clone(),
                     },
                     span: field.span.clone(),
+                    notes: vec![Note::new("first defined here", first)],
                 });
                 return;
             }
         }
-        if self.structs.contains_key(&name) {
+        if let Some(first) = self.structs.get(&name) {
             diagnostics.push(Diagnostic {
                 kind: DiagnosticKind::StructRedefinition(name),
                 span: struct_.span.clone(),
+                notes: vec![Note::new("first defined here", &first.span)],
             });
             return;
         }
@@ -101,23 +126,25 @@ impl Sprite {
 
     pub(crate) fn add_enum(&mut self, enum_: Enum, diagnostics: &mut Vec<Diagnostic>) {
         let name = enum_.name.clone();
-        let mut variants = FxHashSet::default();
+        let mut variants: FxHashMap<&SmolStr, &Span> = FxHashMap::default();
         for variant in &enum_.variants {
-            if !variants.insert(variant.name.clone()) {
+            if let Some(first) = variants.insert(&variant.name, &variant.span) {
                 diagnostics.push(Diagnostic {
                     kind: DiagnosticKind::DuplicateEnumVariant {
                         enum_name: name,
                         variant_name: variant.name.clone(),
                     },
                     span: variant.span.clone(),
+                    notes: vec![Note::new("first defined here", first)],
                 });
                 return;
             }
         }
-        if self.enums.contains_key(&name) {
+        if let Some(first) = self.enums.get(&name) {
             diagnostics.push(Diagnostic {
                 kind: DiagnosticKind::EnumRedefinition(name),
                 span: enum_.span.clone(),
+                notes: vec![Note::new("first defined here", &first.span)],
             });
             return;
         }
@@ -132,10 +159,11 @@ impl Sprite {
         diagnostics: &mut Vec<Diagnostic>,
     ) {
         let name = proc.name.clone();
-        if self.procs.contains_key(&name) {
+        if let Some(first) = self.procs.get(&name) {
             diagnostics.push(Diagnostic {
                 kind: DiagnosticKind::ProcedureRedefinition(name),
                 span: proc.span.clone(),
+                notes: vec![Note::new("first defined here", &first.span)],
             });
             return;
         }
@@ -153,10 +181,11 @@ impl Sprite {
         diagnostics: &mut Vec<Diagnostic>,
     ) {
         let name = func.name.clone();
-        if self.funcs.contains_key(&name) {
+        if let Some(first) = self.funcs.get(&name) {
             diagnostics.push(Diagnostic {
                 kind: DiagnosticKind::FunctionRedefinition(name),
                 span: func.span.clone(),
+                notes: vec![Note::new("first defined here", &first.span)],
             });
             return;
         }
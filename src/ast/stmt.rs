@@ -8,6 +8,7 @@ use serde::{
 use super::{
     expr::Expr,
     type_::Type,
+    MatchArm,
     Name,
     Value,
 };
@@ -38,6 +39,28 @@ pub enum Stmt {
         cond: Box<Expr>,
         body: Vec<Stmt>,
     },
+    While {
+        cond: Box<Expr>,
+        body: Vec<Stmt>,
+    },
+    /// Counts `name` from `start` up to and including `end`.
+    For {
+        name: Name,
+        start: Box<Expr>,
+        end: Box<Expr>,
+        body: Vec<Stmt>,
+    },
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
+    },
+    Match {
+        value: Box<Expr>,
+        arms: Vec<MatchArm>,
+        span: Span,
+    },
     SetVar {
         name: Name,
         value: Box<Expr>,
@@ -45,6 +68,12 @@ pub enum Stmt {
         is_local: bool,
         is_cloud: bool,
     },
+    /// Assigns each field of a struct value, in order, to one of `names`.
+    Destructure {
+        names: Vec<Name>,
+        value: Box<Expr>,
+        is_local: bool,
+    },
     ChangeVar {
         name: Name,
         value: Box<Expr>,
@@ -101,7 +130,15 @@ impl Stmt {
             Stmt::Forever { span, .. } => span.clone(),
             Stmt::Branch { cond, .. } => cond.span(),
             Stmt::Until { cond, .. } => cond.span(),
+            Stmt::While { cond, .. } => cond.span(),
+            Stmt::For { name, .. } => name.span(),
+            Stmt::Break { span } => span.clone(),
+            Stmt::Continue { span } => span.clone(),
+            Stmt::Match { span, .. } => span.clone(),
             Stmt::SetVar { name, .. } => name.span(),
+            Stmt::Destructure { names, .. } => {
+                names[0].span().start..names[names.len() - 1].span().end
+            }
             Stmt::ChangeVar { name, .. } => name.span(),
             Stmt::Show(name) => name.span(),
             Stmt::Hide(name) => name.span(),
@@ -7,10 +7,11 @@ use serde::{
 use super::{
     struct_field::StructField,
     ConstExpr,
+    Type,
 };
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Struct {
     pub name: SmolStr,
     pub span: Span,
@@ -19,15 +20,20 @@ pub struct Struct {
 }
 
 impl Struct {
-    pub fn new(name: SmolStr, span: Span, fields: Vec<(SmolStr, Span, Option<ConstExpr>)>) -> Self {
+    pub fn new(
+        name: SmolStr,
+        span: Span,
+        fields: Vec<(Type, SmolStr, Span, Option<ConstExpr>)>,
+    ) -> Self {
         Self {
             name,
             span,
             fields: fields
                 .into_iter()
-                .map(|(name, span, default)| StructField {
+                .map(|(type_, name, span, default)| StructField {
                     name,
                     span,
+                    type_,
                     default,
                     is_used: false,
                 })
@@ -35,4 +41,86 @@ impl Struct {
             is_used: false,
         }
     }
+
+    /// The struct holding the values of a `return a, b;` with `len` values, whose fields are
+    /// named by their position.
+    pub fn tuple(len: usize, span: Span) -> Self {
+        Self {
+            name: Self::tuple_name(len),
+            span: span.clone(),
+            fields: (0..len)
+                .map(|i| StructField {
+                    name: i.to_string().into(),
+                    span: span.clone(),
+                    type_: Type::Value,
+                    default: None,
+                    is_used: true,
+                })
+                .collect(),
+            is_used: true,
+        }
+    }
+
+    pub fn tuple_name(len: usize) -> SmolStr {
+        format!("tuple of {len}").into()
+    }
+
+    /// Returns the fields that hold values, with the fields of nested structs flattened
+    /// into dotted paths such as `pos.x`. Recursive structs must be rejected beforehand.
+    pub fn leaves<'a, F>(&'a self, get_struct: &F) -> Vec<(SmolStr, &'a StructField)>
+    where F: Fn(&str) -> Option<&'a Struct> {
+        let mut leaves = vec![];
+        for field in &self.fields {
+            match field.type_.struct_().and_then(|(name, _)| get_struct(name)) {
+                Some(struct_) => {
+                    for (path, leaf) in struct_.leaves(get_struct) {
+                        leaves.push((format!("{}.{}", field.name, path).into(), leaf));
+                    }
+                }
+                None => leaves.push((field.name.clone(), field)),
+            }
+        }
+        leaves
+    }
+
+    /// Returns the type of the field at the dotted `path`.
+    pub fn field_type<'a, F>(&'a self, path: &str, get_struct: &F) -> Option<&'a Type>
+    where F: Fn(&str) -> Option<&'a Struct> {
+        let (name, rest) = match path.split_once('.') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let field = self.fields.iter().find(|field| field.name == name)?;
+        match rest {
+            None => Some(&field.type_),
+            Some(rest) => {
+                let (type_name, _) = field.type_.struct_()?;
+                get_struct(type_name)?.field_type(rest, get_struct)
+            }
+        }
+    }
+
+    /// Returns the default value of the field at the dotted `path`. A default given to a
+    /// struct-typed field takes the place of the nested struct's own defaults.
+    pub fn field_default<'a, F>(&'a self, path: &str, get_struct: &F) -> Option<ConstExpr>
+    where F: Fn(&str) -> Option<&'a Struct> {
+        let (name, rest) = match path.split_once('.') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let field = self.fields.iter().find(|field| field.name == name)?;
+        let Some(rest) = rest else {
+            return field.default.clone();
+        };
+        if let Some(ConstExpr::StructLiteral { fields, .. }) = &field.default {
+            if let Some(field) = fields.iter().find(|field| field.name == rest) {
+                return Some(ConstExpr::Value {
+                    value: field.value.clone(),
+                    span: field.value_span.clone(),
+                });
+            }
+        }
+        let (type_name, _) = field.type_.struct_()?;
+        get_struct(type_name)?.field_default(rest, get_struct)
+    }
 }
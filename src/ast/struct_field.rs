@@ -4,13 +4,17 @@ use serde::{
     Serialize,
 };
 
-use super::ConstExpr;
+use super::{
+    ConstExpr,
+    Type,
+};
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StructField {
     pub name: SmolStr,
     pub span: Span,
+    pub type_: Type,
     pub default: Option<ConstExpr>,
     pub is_used: bool,
 }
@@ -13,3 +13,22 @@ pub struct StructLiteralField {
     pub span: Span,
     pub value: Box<Expr>,
 }
+
+impl StructLiteralField {
+    /// Returns the value of the field at the dotted `path`, looking into the literals given
+    /// to fields which are structs.
+    pub fn find<'a>(fields: &'a [Self], path: &str) -> Option<&'a Expr> {
+        let (name, rest) = match path.split_once('.') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let field = fields.iter().find(|field| field.name == name)?;
+        match rest {
+            None => Some(&field.value),
+            Some(rest) => match field.value.as_ref() {
+                Expr::StructLiteral { fields, .. } => Self::find(fields, rest),
+                _ => None,
+            },
+        }
+    }
+}
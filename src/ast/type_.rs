@@ -7,17 +7,43 @@ use serde::{
     Serialize,
 };
 
+use super::Kind;
 use crate::misc::SmolStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Type {
     Value,
-    Struct { name: SmolStr, span: Span },
+    /// A value annotated with its kind, such as `number`. It is stored like any other
+    /// value, and is only used to check the values given to it.
+    Kind {
+        kind: Kind,
+        span: Span,
+    },
+    Struct {
+        name: SmolStr,
+        span: Span,
+    },
 }
 
 impl Type {
+    /// The type written as `name`, which is a struct unless it names a kind.
+    pub fn from_name(name: SmolStr, span: Span) -> Self {
+        match Kind::from_name(&name) {
+            Some(kind) => Self::Kind { kind, span },
+            None => Self::Struct { name, span },
+        }
+    }
+
     pub fn is_value(&self) -> bool {
-        matches!(self, Self::Value)
+        matches!(self, Self::Value | Self::Kind { .. })
+    }
+
+    /// The kind of the values this type holds, which is unknown unless annotated.
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::Kind { kind, .. } => *kind,
+            _ => Kind::Unknown,
+        }
     }
 
     pub fn is_struct(&self) -> bool {
@@ -36,6 +62,7 @@ impl Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Type::Value => write!(f, "value"),
+            Type::Kind { kind, span: _ } => write!(f, "{}", kind),
             Type::Struct { name, span: _ } => write!(f, "{}", name),
         }
     }
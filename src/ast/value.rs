@@ -2,6 +2,7 @@ mod bin_op;
 mod js;
 mod un_op;
 
+pub use js::round as js_round;
 use serde::{
     Deserialize,
     Serialize,
@@ -47,7 +48,7 @@ impl Value {
             Value::Boolean(true) => 1.0,
             Value::Number(number) if number.is_nan() => 0.0,
             Value::Number(number) => *number,
-            Value::String(string) => match js::parse_float(string) {
+            Value::String(string) => match js::string_to_number(string) {
                 number if number.is_nan() => 0.0,
                 number => number,
             },
@@ -59,7 +60,7 @@ impl Value {
             Value::Boolean(false) => 0.0,
             Value::Boolean(true) => 1.0,
             Value::Number(number) => *number,
-            Value::String(string) => js::parse_float(string),
+            Value::String(string) => js::string_to_number(string),
         }
     }
 
@@ -87,8 +88,7 @@ impl Value {
             Value::Number(number) if number.is_infinite() && number.is_sign_negative() => {
                 arcstr::literal!("-Infinity")
             }
-            Value::Number(number) if number.fract() == 0.0 => (*number as i64).to_string().into(),
-            Value::Number(number) => serde_json::to_string(number).unwrap().into(),
+            Value::Number(number) => js::number_to_string(*number).into(),
             Value::String(string) => string.clone(),
         }
     }
@@ -199,7 +199,11 @@ impl Serialize for Value {
     where S: serde::Serializer {
         match self {
             Value::Boolean(boolean) => serializer.serialize_bool(*boolean),
-            Value::Number(number) if number.fract() == 0.0 => {
+            // JSON has no NaN or Infinity, but Scratch reads them back from strings.
+            Value::Number(number) if !number.is_finite() => {
+                serializer.serialize_str(&self.to_string())
+            }
+            Value::Number(number) if number.fract() == 0.0 && number.abs() < 2f64.powi(53) => {
                 serializer.serialize_i64(*number as i64)
             }
             Value::Number(number) => serializer.serialize_f64(*number),
@@ -249,3 +253,51 @@ impl<'de> Deserialize<'de> for Value {
         deserializer.deserialize_any(ValueVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn folded_constants_match_scratch() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            onflag {
+                say sin 180;
+                say round -2.5;
+                say length "é😀";
+                say "B" in "abc";
+                say 1 / 0;
+                say 0.1 + 0.2;
+                say 1000000000 * 1000000000000;
+                say 1 / 1000000;
+                say 1 / 3 / 10000000;
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(
+            output,
+            [
+                "main: 0",
+                "main: -2",
+                "main: 3",
+                "main: true",
+                "main: Infinity",
+                "main: 0.30000000000000004",
+                "main: 1e+21",
+                "main: 0.000001",
+                "main: 3.3333333333333334e-8",
+            ]
+        );
+        let (artifact, _) = build_sb3(
+            "costumes \"blank.svg\";\nonflag { say sin 180 + round 2.5 & \"x\"; }\n",
+            false,
+        );
+        assert_eq!(artifact.block_count, 2);
+    }
+}
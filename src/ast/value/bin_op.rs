@@ -39,22 +39,34 @@ fn modulo(lhs: f64, rhs: f64) -> f64 {
     }
 }
 
+/// `lhs in rhs`, which is true if `rhs` contains `lhs`.
 fn contains(lhs: &Value, rhs: &Value) -> Value {
-    lhs.to_string()
+    rhs.to_string()
         .to_lowercase()
-        .contains(&rhs.to_string().to_lowercase())
+        .contains(&lhs.to_string().to_lowercase())
         .into()
 }
 
-fn letter_of(lhs: &Value, rhs: &Value) -> Value {
-    let error = Value::from(arcstr::literal!(""));
+/// The code unit of `lhs[rhs]`, as strings are indexed by UTF-16 code units in JavaScript.
+fn letter_unit(lhs: &Value, rhs: &Value) -> Option<u16> {
     let index = (rhs.to_number() - 1.0).floor();
-    let str = lhs.to_string();
     if index < 0.0 {
-        return error;
+        return None;
+    }
+    lhs.to_string().encode_utf16().nth(index as usize)
+}
+
+fn letter_of(lhs: &Value, rhs: &Value) -> Value {
+    match letter_unit(lhs, rhs) {
+        Some(unit) => Value::from(SmolStr::from(String::from_utf16_lossy(&[unit]))),
+        None => Value::from(arcstr::literal!("")),
     }
-    if let Some(letter) = str.chars().nth(index as usize) {
-        return Value::from(SmolStr::from(letter.to_string()));
+}
+
+impl Value {
+    /// Whether `lhs[rhs]` is half of a surrogate pair, such as half of an emoji, which
+    /// cannot be held by a string outside of JavaScript.
+    pub fn letter_is_surrogate(lhs: &Value, rhs: &Value) -> bool {
+        letter_unit(lhs, rhs).is_some_and(|unit| (0xD800..=0xDFFF).contains(&unit))
     }
-    return error;
 }
@@ -41,31 +41,94 @@ const fn is_trimmable_whitespace(c: char) -> bool {
     )
 }
 
-pub fn parse_float(input_string: &str) -> f64 {
-    let s = input_string.trim_start_matches(is_trimmable_whitespace);
-    let s_prefix = s.chars().take(4).collect::<String>();
-    let s_prefix_lower = s_prefix.to_ascii_lowercase();
-    // TODO: write our own lexer to match syntax StrDecimalLiteral
-    if s.starts_with("Infinity") || s.starts_with("+Infinity") {
-        f64::INFINITY
-    } else if s.starts_with("-Infinity") {
-        f64::NEG_INFINITY
-    } else if s_prefix_lower.starts_with("inf")
-        || s_prefix_lower.starts_with("+inf")
-        || s_prefix_lower.starts_with("-inf")
-    {
-        // Prevent fast_float from parsing "inf", "+inf" as Infinity and "-inf" as -Infinity
-        f64::NAN
+/// `Number` in JavaScript for strings, which Scratch uses to convert strings to numbers.
+/// Unlike `parseFloat`, the whole string must be a number, and it can be written in
+/// hexadecimal, octal or binary.
+pub fn string_to_number(input_string: &str) -> f64 {
+    let s = input_string.trim_matches(is_trimmable_whitespace);
+    if s.is_empty() {
+        return 0.0;
+    }
+    let radix = match s.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+    if radix != 10 && s.len() > 2 {
+        return parse_integer(&s[2..], radix);
+    }
+    let unsigned = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if unsigned == "Infinity" {
+        return if s.starts_with('-') {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+    }
+    // Prevent fast_float from parsing "inf" and "nan", which are not numbers in JavaScript.
+    if !unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return f64::NAN;
+    }
+    match fast_float2::parse_partial::<f64, _>(s) {
+        Ok((number, len)) if len == s.len() => number,
+        _ => f64::NAN,
+    }
+}
+
+fn parse_integer(digits: &str, radix: u32) -> f64 {
+    digits
+        .chars()
+        .try_fold(0.0, |number, c| {
+            c.to_digit(radix)
+                .map(|digit| number * radix as f64 + digit as f64)
+        })
+        .unwrap_or(f64::NAN)
+}
+
+/// `Math.round` in JavaScript, which rounds halves up.
+pub fn round(number: f64) -> f64 {
+    let floor = number.floor();
+    if number - floor >= 0.5 {
+        floor + 1.0
+    } else {
+        floor
+    }
+}
+
+/// Trigonometric functions are rounded to 10 decimal places like in Scratch, so that
+/// `sin 180` is 0.
+pub fn round_10(number: f64) -> f64 {
+    format!("{number:.10}").parse().unwrap_or(number)
+}
+
+/// `Number.prototype.toString` in JavaScript, for finite numbers.
+pub fn number_to_string(number: f64) -> String {
+    if number == 0.0 {
+        return "0".to_string();
+    }
+    let sign = if number < 0.0 { "-" } else { "" };
+    // Rust formats the shortest digits which round-trip, which is also what JavaScript
+    // does. Only the placement of the decimal point and the exponent differs.
+    let formatted = format!("{:e}", number.abs());
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap() + 1;
+    if k <= n && n <= 21 {
+        format!("{sign}{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        let (int, fract) = digits.split_at(n as usize);
+        format!("{sign}{int}.{fract}")
+    } else if -6 < n && n <= 0 {
+        format!("{sign}0.{}{digits}", "0".repeat(-n as usize))
     } else {
-        fast_float2::parse_partial::<f64, _>(s).map_or_else(
-            |_| f64::NAN,
-            |(f, len)| {
-                if len > 0 {
-                    f
-                } else {
-                    f64::NAN
-                }
-            },
+        let exponent_sign = if n > 0 { "+" } else { "-" };
+        let (first, rest) = digits.split_at(1);
+        let point = if rest.is_empty() { "" } else { "." };
+        format!(
+            "{sign}{first}{point}{rest}e{exponent_sign}{}",
+            (n - 1).abs()
         )
     }
 }
@@ -1,32 +1,46 @@
 use std::{
-    f64::consts::PI,
+    f64::consts::{
+        LN_10,
+        PI,
+    },
     ops::Not,
 };
 
-use super::Value;
+use super::{
+    js,
+    Value,
+};
 use crate::blocks::UnOp;
 
 impl Value {
     pub fn un_op(op: UnOp, opr: &Value) -> Value {
         match op {
             UnOp::Not => opr.to_boolean().not().into(),
-            UnOp::Length => opr.to_string().len().into(),
-            UnOp::Round => opr.to_number().round().into(),
+            UnOp::Length => opr.to_string().encode_utf16().count().into(),
+            UnOp::Round => js::round(opr.to_number()).into(),
             UnOp::Abs => opr.to_number().abs().into(),
             UnOp::Floor => opr.to_number().floor().into(),
             UnOp::Ceil => opr.to_number().ceil().into(),
             UnOp::Sqrt => opr.to_number().sqrt().into(),
-            UnOp::Sin => (opr.to_number() * PI / 180.0).sin().into(),
-            UnOp::Cos => (opr.to_number() * PI / 180.0).cos().into(),
-            UnOp::Tan => (opr.to_number() * PI / 180.0).tan().into(),
+            UnOp::Sin => js::round_10((PI * opr.to_number() / 180.0).sin()).into(),
+            UnOp::Cos => js::round_10((PI * opr.to_number() / 180.0).cos()).into(),
+            UnOp::Tan => tan(opr.to_number()).into(),
             UnOp::Asin => (opr.to_number().asin() * 180.0 / PI).into(),
             UnOp::Acos => (opr.to_number().acos() * 180.0 / PI).into(),
             UnOp::Atan => (opr.to_number().atan() * 180.0 / PI).into(),
             UnOp::Ln => opr.to_number().ln().into(),
-            UnOp::Log => opr.to_number().log(10.0).into(),
+            UnOp::Log => (opr.to_number().ln() / LN_10).into(),
             UnOp::AntiLn => opr.to_number().exp().into(),
             UnOp::AntiLog => (10.0_f64).powf(opr.to_number()).into(),
             UnOp::Minus => (-opr.to_number()).into(),
         }
     }
 }
+
+fn tan(angle: f64) -> f64 {
+    match angle % 360.0 {
+        -270.0 | 90.0 => f64::INFINITY,
+        -90.0 | 270.0 => f64::NEG_INFINITY,
+        angle => js::round_10((PI * angle / 180.0).tan()),
+    }
+}
@@ -10,7 +10,7 @@ use super::{
 };
 use crate::misc::SmolStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Var {
     pub name: SmolStr,
     pub span: Span,
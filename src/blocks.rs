@@ -2,12 +2,27 @@ use serde::{
     Deserialize,
     Serialize,
 };
+
+use crate::ast::Kind;
+
 pub struct Menu {
     pub input: &'static str,
     pub opcode: &'static str,
     pub default: &'static str,
     pub field: &'static str,
 }
+/// The kind of value that an input takes, from the name Scratch gives it. Inputs with a
+/// menu take the name of an option, so they are of unknown kind.
+pub fn input_kind(input: &str) -> Kind {
+    match input {
+        "STEPS" | "DEGREES" | "X" | "Y" | "SECS" | "DIRECTION" | "DX" | "DY" | "SIZE"
+        | "CHANGE" | "VALUE" | "NUM" | "NUM1" | "NUM2" | "VOLUME" | "DURATION" | "BEATS"
+        | "NOTE" | "TEMPO" | "TRANSPARENCY" | "FROM" | "TO" => Kind::Number,
+        "CONDITION" => Kind::Boolean,
+        _ => Kind::Unknown,
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum UnOp {
     Not,
@@ -77,6 +92,15 @@ impl UnOp {
         }
     }
 
+    /// The kinds of the operand and of the result.
+    pub fn kinds(&self) -> (Kind, Kind) {
+        match self {
+            Self::Not => (Kind::Boolean, Kind::Boolean),
+            Self::Length => (Kind::Unknown, Kind::Number),
+            _ => (Kind::Number, Kind::Number),
+        }
+    }
+
     pub fn fields(&self) -> Option<&'static str> {
         match self {
             Self::Not => None,
@@ -161,6 +185,22 @@ impl BinOp {
         }
     }
 
+    /// The kinds of the left operand, the right operand and the result. The left operand
+    /// of `Of` is a string, unless it is a list.
+    pub fn kinds(&self) -> (Kind, Kind, Kind) {
+        match self {
+            Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Mod | Self::FloorDiv => {
+                (Kind::Number, Kind::Number, Kind::Number)
+            }
+            Self::Lt | Self::Gt | Self::Eq | Self::Le | Self::Ge | Self::Ne | Self::In => {
+                (Kind::Unknown, Kind::Unknown, Kind::Boolean)
+            }
+            Self::And | Self::Or => (Kind::Boolean, Kind::Boolean, Kind::Boolean),
+            Self::Join => (Kind::Unknown, Kind::Unknown, Kind::String),
+            Self::Of => (Kind::Unknown, Kind::Number, Kind::String),
+        }
+    }
+
     pub fn rhs(&self) -> &'static str {
         match self {
             Self::Add => "NUM2",
@@ -282,6 +322,18 @@ pub enum Block {
     Rest,
     SetTempo,
     ChangeTempo,
+    Speak,
+    SetVoiceAlto,
+    SetVoiceTenor,
+    SetVoiceSqueak,
+    SetVoiceGiant,
+    SetVoiceKitten,
+    SetVoice,
+    SetSpeechLanguage,
+    TurnVideoOff,
+    TurnVideoOn,
+    TurnVideoOnFlipped,
+    SetVideoTransparency,
 }
 
 impl Block {
@@ -449,6 +501,66 @@ impl Block {
                 field: "INSTRUMENT",
                 default: "1",
             }),
+            Self::SetVoiceAlto => Some(Menu {
+                opcode: "text2speech_menu_voices",
+                input: "VOICE",
+                field: "voices",
+                default: "ALTO",
+            }),
+            Self::SetVoiceTenor => Some(Menu {
+                opcode: "text2speech_menu_voices",
+                input: "VOICE",
+                field: "voices",
+                default: "TENOR",
+            }),
+            Self::SetVoiceSqueak => Some(Menu {
+                opcode: "text2speech_menu_voices",
+                input: "VOICE",
+                field: "voices",
+                default: "SQUEAK",
+            }),
+            Self::SetVoiceGiant => Some(Menu {
+                opcode: "text2speech_menu_voices",
+                input: "VOICE",
+                field: "voices",
+                default: "GIANT",
+            }),
+            Self::SetVoiceKitten => Some(Menu {
+                opcode: "text2speech_menu_voices",
+                input: "VOICE",
+                field: "voices",
+                default: "KITTEN",
+            }),
+            Self::SetVoice => Some(Menu {
+                opcode: "text2speech_menu_voices",
+                input: "VOICE",
+                field: "voices",
+                default: "ALTO",
+            }),
+            Self::SetSpeechLanguage => Some(Menu {
+                opcode: "text2speech_menu_languages",
+                input: "LANGUAGE",
+                field: "languages",
+                default: "en",
+            }),
+            Self::TurnVideoOff => Some(Menu {
+                opcode: "videoSensing_menu_VIDEO_STATE",
+                input: "VIDEO_STATE",
+                field: "VIDEO_STATE",
+                default: "off",
+            }),
+            Self::TurnVideoOn => Some(Menu {
+                opcode: "videoSensing_menu_VIDEO_STATE",
+                input: "VIDEO_STATE",
+                field: "VIDEO_STATE",
+                default: "on",
+            }),
+            Self::TurnVideoOnFlipped => Some(Menu {
+                opcode: "videoSensing_menu_VIDEO_STATE",
+                input: "VIDEO_STATE",
+                field: "VIDEO_STATE",
+                default: "on-flipped",
+            }),
             _ => None,
         }
     }
@@ -571,6 +683,18 @@ impl Block {
             ("rest", _) => Some(Self::Rest),
             ("set_tempo", _) => Some(Self::SetTempo),
             ("change_tempo", _) => Some(Self::ChangeTempo),
+            ("speak", _) => Some(Self::Speak),
+            ("set_voice_alto", _) => Some(Self::SetVoiceAlto),
+            ("set_voice_tenor", _) => Some(Self::SetVoiceTenor),
+            ("set_voice_squeak", _) => Some(Self::SetVoiceSqueak),
+            ("set_voice_giant", _) => Some(Self::SetVoiceGiant),
+            ("set_voice_kitten", _) => Some(Self::SetVoiceKitten),
+            ("set_voice", _) => Some(Self::SetVoice),
+            ("set_speech_language", _) => Some(Self::SetSpeechLanguage),
+            ("turn_video_off", _) => Some(Self::TurnVideoOff),
+            ("turn_video_on", _) => Some(Self::TurnVideoOn),
+            ("turn_video_on_flipped", _) => Some(Self::TurnVideoOnFlipped),
+            ("set_video_transparency", _) => Some(Self::SetVideoTransparency),
             _ => None,
         }
     }
@@ -677,6 +801,18 @@ impl Block {
             Self::Rest => "rest",
             Self::SetTempo => "set_tempo",
             Self::ChangeTempo => "change_tempo",
+            Self::Speak => "speak",
+            Self::SetVoiceAlto => "set_voice_alto",
+            Self::SetVoiceTenor => "set_voice_tenor",
+            Self::SetVoiceSqueak => "set_voice_squeak",
+            Self::SetVoiceGiant => "set_voice_giant",
+            Self::SetVoiceKitten => "set_voice_kitten",
+            Self::SetVoice => "set_voice",
+            Self::SetSpeechLanguage => "set_speech_language",
+            Self::TurnVideoOff => "turn_video_off",
+            Self::TurnVideoOn => "turn_video_on",
+            Self::TurnVideoOnFlipped => "turn_video_on_flipped",
+            Self::SetVideoTransparency => "set_video_transparency",
         }
     }
 
@@ -777,6 +913,18 @@ impl Block {
             "rest",
             "set_tempo",
             "change_tempo",
+            "speak",
+            "set_voice_alto",
+            "set_voice_tenor",
+            "set_voice_squeak",
+            "set_voice_giant",
+            "set_voice_kitten",
+            "set_voice",
+            "set_speech_language",
+            "turn_video_off",
+            "turn_video_on",
+            "turn_video_on_flipped",
+            "set_video_transparency",
         ]
     }
 
@@ -882,6 +1030,18 @@ impl Block {
             Self::Rest => "music_restForBeats",
             Self::SetTempo => "music_setTempo",
             Self::ChangeTempo => "music_changeTempo",
+            Self::Speak => "text2speech_speakAndWait",
+            Self::SetVoiceAlto => "text2speech_setVoice",
+            Self::SetVoiceTenor => "text2speech_setVoice",
+            Self::SetVoiceSqueak => "text2speech_setVoice",
+            Self::SetVoiceGiant => "text2speech_setVoice",
+            Self::SetVoiceKitten => "text2speech_setVoice",
+            Self::SetVoice => "text2speech_setVoice",
+            Self::SetSpeechLanguage => "text2speech_setLanguage",
+            Self::TurnVideoOff => "videoSensing_videoToggle",
+            Self::TurnVideoOn => "videoSensing_videoToggle",
+            Self::TurnVideoOnFlipped => "videoSensing_videoToggle",
+            Self::SetVideoTransparency => "videoSensing_setVideoTransparency",
         }
     }
 
@@ -987,6 +1147,18 @@ impl Block {
             Self::Rest => &["BEATS"],
             Self::SetTempo => &["TEMPO"],
             Self::ChangeTempo => &["TEMPO"],
+            Self::Speak => &["WORDS"],
+            Self::SetVoiceAlto => &[],
+            Self::SetVoiceTenor => &[],
+            Self::SetVoiceSqueak => &[],
+            Self::SetVoiceGiant => &[],
+            Self::SetVoiceKitten => &[],
+            Self::SetVoice => &["VOICE"],
+            Self::SetSpeechLanguage => &["LANGUAGE"],
+            Self::TurnVideoOff => &[],
+            Self::TurnVideoOn => &[],
+            Self::TurnVideoOnFlipped => &[],
+            Self::SetVideoTransparency => &["TRANSPARENCY"],
         }
     }
 
@@ -1094,6 +1266,18 @@ impl Block {
             Self::Rest => None,
             Self::SetTempo => None,
             Self::ChangeTempo => None,
+            Self::Speak => None,
+            Self::SetVoiceAlto => None,
+            Self::SetVoiceTenor => None,
+            Self::SetVoiceSqueak => None,
+            Self::SetVoiceGiant => None,
+            Self::SetVoiceKitten => None,
+            Self::SetVoice => None,
+            Self::SetSpeechLanguage => None,
+            Self::TurnVideoOff => None,
+            Self::TurnVideoOn => None,
+            Self::TurnVideoOnFlipped => None,
+            Self::SetVideoTransparency => None,
         }
     }
 }
@@ -1134,9 +1318,33 @@ pub enum Repr {
     Answer,
     Random,
     Contains,
+    Translate,
+    Language,
 }
 
 impl Repr {
+    /// The kind of value the reporter reports.
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::TouchingMousePointer
+            | Self::TouchingEdge
+            | Self::Touching
+            | Self::KeyPressed
+            | Self::MouseDown
+            | Self::Online
+            | Self::TouchingColor
+            | Self::ColorIsTouchingColor
+            | Self::Contains => Kind::Boolean,
+            Self::CostumeName
+            | Self::BackdropName
+            | Self::Username
+            | Self::Translate
+            | Self::Language => Kind::String,
+            Self::Answer => Kind::Unknown,
+            _ => Kind::Number,
+        }
+    }
+
     pub fn menu(&self) -> Option<Menu> {
         match self {
             Self::DistanceToMousePointer => Some(Menu {
@@ -1175,6 +1383,12 @@ impl Repr {
                 field: "KEY_OPTION",
                 default: "any",
             }),
+            Self::Translate => Some(Menu {
+                opcode: "translate_menu_languages",
+                input: "LANGUAGE",
+                field: "languages",
+                default: "en",
+            }),
             _ => None,
         }
     }
@@ -1220,6 +1434,8 @@ impl Repr {
             ("answer", _) => Some(Self::Answer),
             ("random", _) => Some(Self::Random),
             ("contains", _) => Some(Self::Contains),
+            ("translate", _) => Some(Self::Translate),
+            ("language", _) => Some(Self::Language),
             _ => None,
         }
     }
@@ -1261,6 +1477,8 @@ impl Repr {
             Self::Answer => "answer",
             Self::Random => "random",
             Self::Contains => "contains",
+            Self::Translate => "translate",
+            Self::Language => "language",
         }
     }
 
@@ -1301,6 +1519,8 @@ impl Repr {
             "answer",
             "random",
             "contains",
+            "translate",
+            "language",
         ]
     }
 
@@ -1341,6 +1561,8 @@ impl Repr {
             Self::Answer => "sensing_answer",
             Self::Random => "operator_random",
             Self::Contains => "operator_contains",
+            Self::Translate => "translate_getTranslate",
+            Self::Language => "translate_getViewerLanguage",
         }
     }
 
@@ -1381,6 +1603,8 @@ impl Repr {
             Self::Answer => &[],
             Self::Random => &["FROM", "TO"],
             Self::Contains => &["STRING1", "STRING2"],
+            Self::Translate => &["WORDS", "LANGUAGE"],
+            Self::Language => &[],
         }
     }
 
@@ -1421,6 +1645,8 @@ impl Repr {
             Self::Answer => None,
             Self::Random => None,
             Self::Contains => None,
+            Self::Translate => None,
+            Self::Language => None,
         }
     }
 }
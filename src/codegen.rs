@@ -6,6 +6,7 @@ pub mod datalists;
 pub mod debug_info;
 pub mod event;
 pub mod expr;
+pub mod extension;
 pub mod input;
 pub mod mutation;
 pub mod node;
@@ -13,5 +14,6 @@ pub mod node_id;
 pub mod node_id_factory;
 pub mod sb3;
 pub mod sounds;
+pub mod source_map;
 pub mod stmt;
 pub mod turbowarp_config;
@@ -4,6 +4,7 @@ use std::{
         Seek,
         Write,
     },
+    iter,
     path::PathBuf,
     rc::Rc,
 };
@@ -21,6 +22,7 @@ use zip::{
 
 use crate::{
     ast::{
+        EventKind,
         Project,
         Sprite,
     },
@@ -30,7 +32,10 @@ use crate::{
     },
     config::Config,
     diagnostic::{
+        is_lint,
         Artifact,
+        Diagnostic,
+        LintLevel,
         SpriteDiagnostics,
     },
     misc::SmolStr,
@@ -45,12 +50,70 @@ use crate::{
     visitor,
 };
 
+/// Sprites parsed by previous builds, by the path of their source file. A sprite is only
+/// parsed again if its translation unit has changed since it was last parsed.
+#[derive(Default)]
+pub struct ParseCache {
+    sprites: FxHashMap<PathBuf, (Vec<u8>, Sprite)>,
+    /// Number of sprites that were parsed during the last build.
+    pub parsed: usize,
+}
+
+impl ParseCache {
+    fn parse(&mut self, sprite_diagnostics: &mut SpriteDiagnostics) -> Sprite {
+        let unit = &sprite_diagnostics.translation_unit;
+        if let Some((text, sprite)) = self.sprites.get(&unit.path) {
+            if *text == unit.text {
+                return sprite.clone();
+            }
+        }
+        self.parsed += 1;
+        let (sprite, parse_diagnostics) = parser::parse(unit);
+        if parse_diagnostics.is_empty() {
+            self.sprites
+                .insert(unit.path.clone(), (unit.text.clone(), sprite.clone()));
+        } else {
+            self.sprites.remove(&unit.path);
+        }
+        sprite_diagnostics.diagnostics.extend(parse_diagnostics);
+        sprite
+    }
+}
+
 pub fn build_impl<T: Write + Seek>(
     fs: Rc<RefCell<dyn VFS>>,
     input: PathBuf,
     file: T,
     stdlib: Option<StandardLibrary>,
+    include_tests: bool,
+) -> anyhow::Result<Artifact> {
+    build_with_cache(
+        fs,
+        input,
+        file,
+        stdlib,
+        include_tests,
+        true,
+        &[],
+        &mut ParseCache::default(),
+    )
+}
+
+/// `test` blocks are left out of the project, unless `include_tests` is set. If `optimize`
+/// is set, procedures and functions are inlined, and unused procedures, functions,
+/// variables and lists are left out. The levels in `lints` are set over the levels of
+/// lints from `goboscript.toml`, in order.
+pub fn build_with_cache<T: Write + Seek>(
+    fs: Rc<RefCell<dyn VFS>>,
+    input: PathBuf,
+    file: T,
+    stdlib: Option<StandardLibrary>,
+    include_tests: bool,
+    optimize: bool,
+    lints: &[(SmolStr, LintLevel)],
+    cache: &mut ParseCache,
 ) -> anyhow::Result<Artifact> {
+    cache.parsed = 0;
     let config_path = input.join("goboscript.toml");
     let config_src = fs
         .borrow_mut()
@@ -66,6 +129,11 @@ pub fn build_impl<T: Write + Seek>(
         config.post_build.is_none(),
         "post_build is deprecated; use a Makefile instead"
     );
+    if let Some(lint) = config.lints.keys().find(|lint| !is_lint(lint)) {
+        anyhow::bail!("unknown lint `{lint}` in {}", config_path.display());
+    }
+    let mut lint_levels = config.lints.clone();
+    lint_levels.extend(lints.iter().cloned());
     let stdlib = if let Some(stdlib) = stdlib {
         stdlib
     } else if let Some(std) = &config.std {
@@ -86,8 +154,9 @@ pub fn build_impl<T: Write + Seek>(
     let stage_path = input.join("stage.gs");
     let mut stage_diagnostics = SpriteDiagnostics::new(fs.clone(), stage_path, &stdlib)
         .context("failed to read stage.gs")?;
-    let (stage, parse_diagnostics) = parser::parse(&stage_diagnostics.translation_unit);
-    stage_diagnostics.diagnostics.extend(parse_diagnostics);
+    let stage = cache.parse(&mut stage_diagnostics);
+    stage_diagnostics.lints = lint_levels.clone();
+    stage_diagnostics.read_lint_attributes(&stage);
     let mut sprites_diagnostics: FxHashMap<SmolStr, SpriteDiagnostics> = Default::default();
     let mut sprites: FxHashMap<SmolStr, Sprite> = Default::default();
     let files = fs.borrow_mut().read_dir(&input)?;
@@ -112,24 +181,38 @@ pub fn build_impl<T: Write + Seek>(
             .into();
         let mut sprite_diagnostics = SpriteDiagnostics::new(fs.clone(), sprite_path, &stdlib)
             .with_context(|| format!("failed to read {}.gs", sprite_name))?;
-        let (sprite, parse_diagnostics) = parser::parse(&sprite_diagnostics.translation_unit);
-        sprite_diagnostics.diagnostics.extend(parse_diagnostics);
+        let sprite = cache.parse(&mut sprite_diagnostics);
+        sprite_diagnostics.lints = lint_levels.clone();
+        sprite_diagnostics.read_lint_attributes(&sprite);
         sprites_diagnostics.insert(sprite_name.clone(), sprite_diagnostics);
         sprites.insert(sprite_name, sprite);
     }
+    cache.sprites.retain(|path, _| {
+        path == &stage_diagnostics.translation_unit.path
+            || sprites_diagnostics
+                .values()
+                .any(|sprite_diagnostics| path == &sprite_diagnostics.translation_unit.path)
+    });
+    // Sprites with syntax errors are still checked, without the statements and items
+    // which could not be parsed.
     let mut project = Project { stage, sprites };
-    if !(stage_diagnostics.diagnostics.is_empty()
-        && sprites_diagnostics
-            .values()
-            .all(|sprite_diagnostics| sprite_diagnostics.diagnostics.is_empty()))
-    {
-        return Ok(Artifact {
-            project,
-            stage_diagnostics,
-            sprites_diagnostics,
-            block_count: 0,
-        });
+    if !include_tests {
+        for sprite in iter::once(&mut project.stage).chain(project.sprites.values_mut()) {
+            sprite
+                .events
+                .retain(|event| !matches!(event.kind, EventKind::Test { .. }));
+        }
     }
+    visitor::match_::visit_project(
+        &mut project,
+        &mut stage_diagnostics,
+        &mut sprites_diagnostics,
+    );
+    visitor::loops::visit_project(
+        &mut project,
+        &mut stage_diagnostics,
+        &mut sprites_diagnostics,
+    );
     visitor::ternary::visit_project(&mut project);
     {
         let mut fs = fs.borrow_mut();
@@ -141,16 +224,29 @@ pub fn build_impl<T: Write + Seek>(
             &mut sprites_diagnostics,
         );
     }
+    visitor::infer::visit_project(&project, &mut stage_diagnostics, &mut sprites_diagnostics);
     visitor::pass1::visit_project(&mut project);
+    visitor::inline::visit_project(
+        &mut project,
+        config.inline_threshold,
+        optimize,
+        &mut stage_diagnostics,
+        &mut sprites_diagnostics,
+    );
     visitor::pass2::visit_project(
         &mut project,
         &mut stage_diagnostics,
         &mut sprites_diagnostics,
     );
+    visitor::recursion::visit_project(
+        &mut project,
+        &mut stage_diagnostics,
+        &mut sprites_diagnostics,
+    );
     visitor::pass3::visit_project(&mut project);
     visitor::pass4::visit_project(&mut project);
     log::info!("{:#?}", project);
-    let mut sb3 = Sb3::new(fs.clone(), input.clone());
+    let mut sb3 = Sb3::new(fs.clone(), input.clone(), optimize);
     sb3.project(
         fs.clone(),
         &input,
@@ -159,11 +255,13 @@ pub fn build_impl<T: Write + Seek>(
         &mut stage_diagnostics,
         &mut sprites_diagnostics,
     )?;
+    forget_unparsed_names(&project, &mut stage_diagnostics, &mut sprites_diagnostics);
     let artifact = Artifact {
         project,
         stage_diagnostics,
         sprites_diagnostics,
         block_count: sb3.block_count,
+        blocks_saved: sb3.blocks_saved,
     };
     if artifact.failure() {
         return Ok(artifact);
@@ -191,3 +289,70 @@ pub fn build_impl<T: Write + Seek>(
     zip.finish()?;
     Ok(artifact)
 }
+
+/// The code which could not be parsed may have declared the names that the rest of the
+/// project uses, so those names are not reported as unrecognized.
+fn forget_unparsed_names(
+    project: &Project,
+    stage_diagnostics: &mut SpriteDiagnostics,
+    sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
+) {
+    let is_unparsed = |sprite: &Sprite, diagnostic: &Diagnostic| {
+        diagnostic.kind.unrecognized_name().is_some_and(|name| {
+            sprite.unparsed_names.contains(name) || project.stage.unparsed_names.contains(name)
+        })
+    };
+    stage_diagnostics
+        .diagnostics
+        .retain(|diagnostic| !is_unparsed(&project.stage, diagnostic));
+    for (sprite_name, sprite_diagnostics) in sprites_diagnostics {
+        let sprite = &project.sprites[sprite_name];
+        sprite_diagnostics
+            .diagnostics
+            .retain(|diagnostic| !is_unparsed(sprite, diagnostic));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn only_changed_sprites_are_parsed_again() {
+        let fs = Rc::new(RefCell::new(TestFS(HashMap::from([
+            ("stage.gs".into(), b"costumes \"blank.svg\";\n".to_vec()),
+            ("blank.svg".into(), b"<svg/>".to_vec()),
+            (
+                "a.gs".into(),
+                b"costumes \"blank.svg\";\nonflag { say 1; }\n".to_vec(),
+            ),
+            (
+                "b.gs".into(),
+                b"costumes \"blank.svg\";\nonflag { say 2; }\n".to_vec(),
+            ),
+        ]))));
+        let mut cache = ParseCache::default();
+        build(&fs, &mut cache);
+        assert_eq!(cache.parsed, 3);
+        build(&fs, &mut cache);
+        assert_eq!(cache.parsed, 0);
+        fs.borrow_mut().0.insert(
+            "b.gs".into(),
+            b"costumes \"blank.svg\";\nonflag { say 3; }\n".to_vec(),
+        );
+        let artifact = build(&fs, &mut cache);
+        assert_eq!(cache.parsed, 1);
+        assert_eq!(artifact.block_count, 4);
+    }
+
+    #[test]
+    fn test_blocks_are_only_built_with_tests() {
+        let (artifact, _) = build_sb3(TESTS, false);
+        assert_eq!(artifact.block_count, 2);
+        let (artifact, _) = build_sb3(TESTS, true);
+        assert!(artifact.block_count > 2);
+    }
+}
@@ -1,8 +1,12 @@
-use std::io;
+use std::io::{
+    self,
+    Write,
+};
 
 use logos::Span;
 
 use super::{
+    node::Node,
     node_id::NodeID,
     sb3::{
         Sb3,
@@ -73,6 +77,51 @@ impl Sb3 {
         self.expr(s, d, value, value_id, this_id)
     }
 
+    pub fn on_video_motion_gt(
+        &mut self,
+        s: S,
+        d: D,
+        this_id: NodeID,
+        value: &Expr,
+    ) -> io::Result<()> {
+        let value_id = self.id.new_id();
+        self.begin_inputs()?;
+        self.input(s, d, "REFERENCE", value, value_id, false)?;
+        self.end_obj()?; // inputs
+        self.end_obj()?; // node
+        self.expr(s, d, value, value_id, this_id)
+    }
+
+    /// Keys are named like in `onkey`, and translated to the values of the Makey Makey
+    /// key menu.
+    pub fn on_makey_key(
+        &mut self,
+        _s: S,
+        _d: D,
+        this_id: NodeID,
+        key: &SmolStr,
+        _span: &Span,
+    ) -> io::Result<()> {
+        let menu_id = self.id.new_id();
+        let key = match key.as_str() {
+            "space" => "SPACE",
+            "up arrow" => "UP",
+            "down arrow" => "DOWN",
+            "left arrow" => "LEFT",
+            "right arrow" => "RIGHT",
+            key => key,
+        };
+        write!(self.json, r#","inputs":{{"KEY":[1,{menu_id}]}}"#)?;
+        self.end_obj()?; // node
+        self.begin_node(
+            Node::new("makeymakey_menu_KEY", menu_id)
+                .parent_id(this_id)
+                .shadow(true),
+        )?;
+        self.single_field("KEY", key)?;
+        self.end_obj() // node
+    }
+
     pub fn on_clone(&mut self, _s: S, _d: D, _this_id: NodeID) -> io::Result<()> {
         self.end_obj() // node
     }
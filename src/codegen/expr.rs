@@ -163,9 +163,9 @@ impl Sb3 {
                     .shadow(true),
             )?;
             if let Some(menu_value) = menu_value {
-                self.single_field(menu.input, &menu_value.to_string())?;
+                self.single_field(menu.field, &menu_value.to_string())?;
             } else {
-                self.single_field(menu.input, menu.default)?;
+                self.single_field(menu.field, menu.default)?;
             }
             self.end_obj()?; // node
         }
@@ -243,17 +243,19 @@ impl Sb3 {
                 rhs_span: _,
             } = rhs
             {
-                if let Expr::Name(name) = dot_lhs.as_ref() {
+                if let Some((name, path)) = dot_path(dot_lhs, dot_rhs) {
                     if let Some(list) = s.get_list(name.basename()) {
-                        if let Some((type_name, _type_span)) = list.type_.struct_() {
-                            let struct_ = s.get_struct(type_name).unwrap();
-                            if struct_
-                                .fields
+                        if let Some(struct_) = list
+                            .type_
+                            .struct_()
+                            .and_then(|(type_name, _type_span)| s.get_struct(type_name))
+                        {
+                            if s.struct_leaves(struct_)
                                 .iter()
-                                .any(|field| field.name == dot_rhs.as_str())
+                                .any(|(leaf, _)| *leaf == path)
                             {
                                 let qualified_name =
-                                    qualify_struct_var_name(dot_rhs, name.basename());
+                                    qualify_struct_var_name(&path, name.basename());
                                 return self.list_contains(
                                     s,
                                     d,
@@ -335,14 +337,20 @@ impl Sb3 {
     ) -> io::Result<()> {
         self.begin_node(Node::new("data_lengthoflist", this_id).parent_id(parent_id))?;
         let list = s.get_list(name).unwrap();
-        if let Some((type_name, _type_span)) = list.type_.struct_() {
-            let struct_ = s.get_struct(type_name).unwrap();
-            if struct_.fields.is_empty() {
-                // For empty structs, we can't access fields[0], so we use the list name directly
-                self.single_field_id("LIST", name)?;
-            } else {
-                let qualified_name = qualify_struct_var_name(&struct_.fields[0].name, name);
-                self.single_field_id("LIST", &qualified_name)?;
+        // The struct of a list is missing when its declaration could not be parsed, which
+        // was already reported.
+        if let Some(struct_) = list
+            .type_
+            .struct_()
+            .and_then(|(type_name, _type_span)| s.get_struct(type_name))
+        {
+            match s.struct_leaves(struct_).first() {
+                Some((path, _)) => {
+                    let qualified_name = qualify_struct_var_name(path, name);
+                    self.single_field_id("LIST", &qualified_name)?;
+                }
+                // For empty structs, there is no field list, so we use the list name directly
+                None => self.single_field_id("LIST", name)?,
             }
         } else {
             self.single_field_id("LIST", name)?;
@@ -378,7 +386,7 @@ impl Sb3 {
         self.begin_inputs()?;
         for (arg, arg_value) in signature.iter().zip(args) {
             match &arg.type_ {
-                Type::Value => {
+                Type::Value | Type::Kind { .. } => {
                     let arg_id = self.id.new_id();
                     self.input(s, d, &arg.name, arg_value, arg_id, false)?;
                     qualified_args.push((arg.name.clone(), arg_id));
@@ -423,13 +431,11 @@ impl Sb3 {
                             continue;
                         }
                     };
-                    for field in &struct_.fields {
-                        let qualified_arg_name = qualify_struct_var_name(&field.name, &arg.name);
+                    for (path, _) in s.struct_leaves(struct_) {
+                        let qualified_arg_name = qualify_struct_var_name(&path, &arg.name);
                         let arg_id = self.id.new_id();
-                        let field_value = struct_literal_fields
-                            .iter()
-                            .find(|f| f.name == field.name)
-                            .map(|f| f.value.as_ref().clone());
+                        let field_value =
+                            StructLiteralField::find(struct_literal_fields, &path).cloned();
                         let (value, is_placeholder) = match field_value {
                             Some(v) => (v, false),
                             None => (
@@ -472,7 +478,7 @@ impl Sb3 {
         rhs: &SmolStr,
         rhs_span: Span,
     ) -> io::Result<()> {
-        if let Expr::Name(name) = lhs {
+        if let Some((name, path)) = dot_path(lhs, rhs) {
             if let Some(_enum_) = s.get_enum(name.basename()) {
                 return Ok(());
             }
@@ -482,14 +488,17 @@ impl Sb3 {
             if let Some(list) = s.get_list(name.basename()) {
                 if let Some((type_name, _type_span)) = list.type_.struct_() {
                     // This is a struct list, check if field exists in struct
-                    let struct_ = s.get_struct(type_name).unwrap();
+                    let Some(struct_) = s.get_struct(type_name) else {
+                        // The declaration of the struct could not be parsed, which was
+                        // already reported.
+                        return Ok(());
+                    };
                     // Verify the field exists in the struct
-                    if struct_
-                        .fields
+                    if s.struct_leaves(struct_)
                         .iter()
-                        .any(|field| field.name == rhs.as_str())
+                        .any(|(leaf, _)| *leaf == path)
                     {
-                        let qualified_name = qualify_struct_var_name(rhs, name.basename());
+                        let qualified_name = qualify_struct_var_name(&path, name.basename());
                         let qualified_list_name = QualifiedName::List(qualified_name, Type::Value);
                         match qualified_list_name {
                             QualifiedName::Var(qname, _) => {
@@ -505,7 +514,7 @@ impl Sb3 {
                     d.report(
                         DiagnosticKind::StructDoesNotHaveField {
                             type_name: type_name.clone(),
-                            field_name: rhs.clone(),
+                            field_name: path,
                         },
                         &rhs_span,
                     );
@@ -576,3 +585,19 @@ impl Sb3 {
         self.expr(s, d, object, object_id, this_id)
     }
 }
+
+/// Splits a field access such as `a.b.c` into the name `a` and the field path `b.c`.
+fn dot_path<'a>(lhs: &'a Expr, rhs: &SmolStr) -> Option<(&'a Name, SmolStr)> {
+    match lhs {
+        Expr::Name(name) => Some((name, rhs.clone())),
+        Expr::Dot {
+            lhs,
+            rhs: lhs_rhs,
+            rhs_span: _,
+        } => {
+            let (name, path) = dot_path(lhs, lhs_rhs)?;
+            Some((name, format!("{path}.{rhs}").into()))
+        }
+        _ => None,
+    }
+}
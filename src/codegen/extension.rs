@@ -0,0 +1,246 @@
+use std::io::{
+    self,
+    Write,
+};
+
+use logos::Span;
+use serde_json::json;
+
+use super::{
+    input::coerce_condition,
+    node::Node,
+    node_id::NodeID,
+    sb3::{
+        Sb3,
+        D,
+        S,
+    },
+};
+use crate::{
+    ast::Expr,
+    config::{
+        Extension,
+        ExtensionArg,
+        ExtensionBlock,
+    },
+    diagnostic::DiagnosticKind,
+    misc::write_comma_io,
+};
+
+impl Sb3 {
+    /// The inputs and fields of an extension block, whose node has already been begun.
+    /// Menus are shadow blocks with the opcode `<extension>_menu_<menu>`, like Scratch
+    /// generates them for extensions.
+    pub fn extension_block(
+        &mut self,
+        s: S,
+        d: D,
+        this_id: NodeID,
+        extension: &Extension,
+        block: &ExtensionBlock,
+        span: &Span,
+        args: &[Expr],
+    ) -> io::Result<()> {
+        self.add_extension(&extension.id);
+        if block.args.len() != args.len() {
+            d.report(
+                DiagnosticKind::ExtensionArgsCountMismatch {
+                    block: block.name.clone(),
+                    expected: block.args.len(),
+                    given: args.len(),
+                },
+                span,
+            );
+        }
+        let mut inputs = vec![];
+        let mut menus = vec![];
+        let mut fields = vec![];
+        self.begin_inputs()?;
+        for (arg, arg_value) in block.args.iter().zip(args) {
+            let arg_id = self.id.new_id();
+            match arg {
+                ExtensionArg::Input(name) => {
+                    self.input(s, d, name, arg_value, arg_id, false)?;
+                    inputs.push((arg_value.clone(), arg_id));
+                }
+                ExtensionArg::Table {
+                    name, field: true, ..
+                } => match arg_value {
+                    Expr::Value { value, .. } => fields.push((name, value.to_string())),
+                    _ => d.report(
+                        DiagnosticKind::FieldNotLiteral(name.clone()),
+                        &arg_value.span(),
+                    ),
+                },
+                ExtensionArg::Table {
+                    name,
+                    menu: Some(menu),
+                    default,
+                    ..
+                } => {
+                    let menu_id = self.id.new_id();
+                    let menu_value = match arg_value {
+                        Expr::Value { value, .. } => {
+                            write_comma_io(&mut self.json, &mut self.inputs_comma)?;
+                            write!(self.json, r#"{}:[1,{menu_id}]"#, json!(**name))?;
+                            value.to_string()
+                        }
+                        _ => {
+                            self.input_with_shadow(s, d, name, arg_value, arg_id, menu_id)?;
+                            inputs.push((arg_value.clone(), arg_id));
+                            default.clone().unwrap_or_default()
+                        }
+                    };
+                    menus.push((menu, menu_id, menu_value));
+                }
+                ExtensionArg::Table {
+                    name,
+                    boolean: true,
+                    ..
+                } => {
+                    let arg_value = coerce_condition(arg_value, s);
+                    self.input(s, d, name, &arg_value, arg_id, true)?;
+                    inputs.push((arg_value, arg_id));
+                }
+                ExtensionArg::Table { name, .. } => {
+                    self.input(s, d, name, arg_value, arg_id, false)?;
+                    inputs.push((arg_value.clone(), arg_id));
+                }
+            }
+        }
+        self.end_obj()?; // inputs
+        if !fields.is_empty() {
+            write!(self.json, r#","fields":{{"#)?;
+            let mut comma = false;
+            for (name, value) in &fields {
+                write_comma_io(&mut self.json, &mut comma)?;
+                write!(self.json, r#"{}:[{},null]"#, json!(**name), json!(value))?;
+            }
+            write!(self.json, "}}")?;
+        }
+        self.end_obj()?; // node
+        for (arg_value, arg_id) in &inputs {
+            self.expr(s, d, arg_value, *arg_id, this_id)?;
+        }
+        for (menu, menu_id, menu_value) in menus {
+            let opcode = format!("{}_menu_{}", extension.id, menu);
+            self.begin_node(Node::new(&opcode, menu_id).parent_id(this_id).shadow(true))?;
+            self.single_field(menu, &menu_value)?;
+            self.end_obj()?; // node
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        path::Path,
+    };
+
+    use semver::Version;
+
+    use crate::{
+        codegen::build::{
+            build_with_cache,
+            ParseCache,
+        },
+        diagnostic::DiagnosticKind,
+        standard_library::new_standard_library,
+        testing::*,
+    };
+
+    #[test]
+    fn extension_blocks_are_declared_in_config() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             onflag {\n\
+                 change_voice \"SQUEAK\";\n\
+                 if is_exactly(\"a\", \"A\") { speak_words \"hi\"; }\n\
+             }\n",
+        );
+        fs.borrow_mut().0.insert(
+            "goboscript.toml".into(),
+            br#"
+            [[extensions]]
+            id = "text2speech"
+            blocks = [
+                { name = "speak_words", opcode = "text2speech_speakAndWait", args = ["WORDS"] },
+                { name = "change_voice", opcode = "text2speech_setVoice", args = [
+                    { name = "VOICE", menu = "voices", default = "ALTO" },
+                ] },
+            ]
+
+            [[extensions]]
+            id = "utilities"
+            url = "https://extensions.turbowarp.org/utilities.js"
+            blocks = [
+                { name = "is_exactly", opcode = "utilities_isExactly", shape = "boolean", args = ["A", "B"] },
+            ]
+            "#
+            .to_vec(),
+        );
+        let stdlib = new_standard_library(Version::new(0, 0, 0), Path::new("std"));
+        let mut sb3 = Cursor::new(vec![]);
+        let artifact = build_with_cache(
+            fs,
+            "".into(),
+            &mut sb3,
+            Some(stdlib),
+            false,
+            true,
+            &[],
+            &mut Default::default(),
+        )
+        .unwrap();
+        assert!(!artifact.failure());
+        let mut zip = zip::ZipArchive::new(sb3).unwrap();
+        let project: serde_json::Value =
+            serde_json::from_reader(zip.by_name("project.json").unwrap()).unwrap();
+        assert_eq!(
+            project["extensions"],
+            serde_json::json!(["text2speech", "utilities"])
+        );
+        assert_eq!(
+            project["extensionURLs"],
+            serde_json::json!({"utilities": "https://extensions.turbowarp.org/utilities.js"})
+        );
+        let blocks = project["targets"][1]["blocks"].as_object().unwrap();
+        let block = |opcode: &str| {
+            blocks
+                .values()
+                .find(|block| block["opcode"] == opcode)
+                .unwrap()
+        };
+        assert_eq!(
+            block("text2speech_menu_voices")["fields"]["voices"][0],
+            "SQUEAK"
+        );
+        assert_eq!(block("utilities_isExactly")["inputs"]["B"][1][1], "A");
+        assert_eq!(block("control_if")["inputs"]["CONDITION"][0], 2);
+    }
+
+    #[test]
+    fn extension_block_argument_count_is_checked() {
+        let fs = project("costumes \"blank.svg\";\nonflag { speak_words; }\n");
+        fs.borrow_mut().0.insert(
+            "goboscript.toml".into(),
+            br#"
+            [[extensions]]
+            id = "text2speech"
+            blocks = [{ name = "speak_words", opcode = "text2speech_speakAndWait", args = ["WORDS"] }]
+            "#
+            .to_vec(),
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(matches!(
+            artifact.sprites_diagnostics["main"].diagnostics[0].kind,
+            DiagnosticKind::ExtensionArgsCountMismatch {
+                expected: 1,
+                given: 0,
+                ..
+            }
+        ));
+    }
+}
@@ -25,9 +25,15 @@ use crate::{
         Repr,
         UnOp,
     },
+    config::ExtensionBlockShape,
 };
 
 pub fn is_expr_boolean(expr: &Expr, s: S) -> bool {
+    if let Expr::FuncCall { name, .. } = expr {
+        return s
+            .extension_block(name)
+            .is_some_and(|(_, block)| block.shape == ExtensionBlockShape::Boolean);
+    }
     if let Expr::BinOp {
         op: BinOp::Of, lhs, ..
     } = expr
@@ -137,7 +143,7 @@ impl Sb3 {
                     write!(self.json, "[1,[4,\"NaN\"]]")
                 }
             },
-            Value::Number(number) if number.fract() == 0.0 => {
+            Value::Number(number) if number.fract() == 0.0 && number.abs() < 2f64.powi(53) => {
                 write!(self.json, "[1,[4,{}]]", json!(*number as i64))
             }
             Value::Number(number) => {
@@ -6,8 +6,8 @@ use std::fmt::{
 use super::node_id::NodeID;
 
 #[derive(Debug, Copy, Clone)]
-pub struct Node {
-    pub opcode: &'static str,
+pub struct Node<'a> {
+    pub opcode: &'a str,
     this_id: NodeID,
     next_id: Option<NodeID>,
     parent_id: Option<NodeID>,
@@ -15,8 +15,8 @@ pub struct Node {
     shadow: bool,
 }
 
-impl Node {
-    pub fn new(opcode: &'static str, this_id: NodeID) -> Self {
+impl<'a> Node<'a> {
+    pub fn new(opcode: &'a str, this_id: NodeID) -> Self {
         Self {
             opcode,
             this_id,
@@ -51,7 +51,7 @@ impl Node {
     }
 }
 
-impl Display for Node {
+impl Display for Node<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}:{{", self.this_id)?;
         write!(f, "\"opcode\":\"{}\"", self.opcode)?;
@@ -5,6 +5,7 @@ use std::{
         self,
         Write,
     },
+    mem,
     path::{
         Path,
         PathBuf,
@@ -34,9 +35,15 @@ use crate::{
         datalists::read_list,
         mutation::Mutation,
     },
-    config::Config,
+    config::{
+        Config,
+        Extension,
+        ExtensionBlock,
+        ExtensionBlockShape,
+    },
     diagnostic::{
         DiagnosticKind,
+        Note,
         SpriteDiagnostics,
     },
     misc::{
@@ -46,7 +53,7 @@ use crate::{
     vfs::VFS,
 };
 
-const STAGE_NAME: &str = "Stage";
+pub const STAGE_NAME: &str = "Stage";
 
 #[derive(Debug, Copy, Clone)]
 pub struct S<'a> {
@@ -54,6 +61,8 @@ pub struct S<'a> {
     pub sprite: &'a Sprite,
     pub proc: Option<&'a Proc>,
     pub func: Option<&'a Func>,
+    pub event: Option<&'a Event>,
+    pub config: &'a Config,
 }
 
 pub type D<'a> = &'a mut SpriteDiagnostics;
@@ -63,10 +72,16 @@ pub enum QualifiedName {
     List(SmolStr, Type),
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct Extensions {
     pen: bool,
     music: bool,
+    text2speech: bool,
+    translate: bool,
+    video_sensing: bool,
+    makeymakey: bool,
+    /// IDs of the extensions declared in `goboscript.toml` that are used.
+    custom: FxHashSet<SmolStr>,
 }
 
 pub fn qualify_local_var_name(proc_name: &str, var_name: &str) -> SmolStr {
@@ -77,7 +92,16 @@ pub fn qualify_struct_var_name(field_name: &str, var_name: &str) -> SmolStr {
     format!("{}.{}", var_name, field_name).into()
 }
 
-impl S<'_> {
+impl<'a> S<'a> {
+    /// The extension block with this name, if it is not shadowed by a procedure or
+    /// function.
+    pub fn extension_block(&self, name: &str) -> Option<(&'a Extension, &'a ExtensionBlock)> {
+        if self.sprite.procs.contains_key(name) || self.sprite.funcs.contains_key(name) {
+            return None;
+        }
+        self.config.extension_block(name)
+    }
+
     pub fn is_name_list(&self, name: &Name) -> bool {
         self.sprite.lists.contains_key(name.basename())
             || self
@@ -92,6 +116,15 @@ impl S<'_> {
                 self.func
                     .and_then(|func| self.sprite.func_locals[&func.name].get(name))
             })
+            .or_else(|| self.event.and_then(|event| event.locals.get(name)))
+    }
+
+    /// The name which qualifies the local variables in scope.
+    fn local_scope(&self) -> &SmolStr {
+        self.proc
+            .map(|proc| &proc.name)
+            .or_else(|| self.func.map(|func| &func.name))
+            .unwrap_or_else(|| &self.event.unwrap().scope)
     }
 
     fn get_var(&self, name: &str) -> Option<&Var> {
@@ -108,13 +141,20 @@ impl S<'_> {
             .or_else(|| self.stage.and_then(|stage| stage.lists.get(name)))
     }
 
-    pub fn get_struct(&self, name: &str) -> Option<&Struct> {
+    pub fn get_struct(&self, name: &str) -> Option<&'a Struct> {
         self.sprite
             .structs
             .get(name)
             .or_else(|| self.stage.and_then(|stage| stage.structs.get(name)))
     }
 
+    /// The fields of `struct_` which hold values, with nested structs flattened into
+    /// dotted paths.
+    pub fn struct_leaves(&self, struct_: &'a Struct) -> Vec<(SmolStr, &'a StructField)> {
+        let s = *self;
+        struct_.leaves(&|name| s.get_struct(name))
+    }
+
     pub fn get_enum(&self, name: &str) -> Option<&Enum> {
         self.sprite
             .enums
@@ -135,7 +175,7 @@ impl S<'_> {
         T: FnOnce(SmolStr, Type) -> QualifiedName,
     {
         match type_ {
-            Type::Value => match field_name {
+            Type::Value | Type::Kind { .. } => match field_name {
                 None => Some(variant(qualified_var_name, type_.clone())),
                 Some(_) => {
                     if let Some(d) = d {
@@ -151,7 +191,11 @@ impl S<'_> {
                 None => None,
                 Some(field_name) => {
                     let struct_ = self.get_struct(type_name)?;
-                    if !struct_.fields.iter().any(|field| field.name == field_name) {
+                    if !self
+                        .struct_leaves(struct_)
+                        .iter()
+                        .any(|(path, _)| *path == field_name)
+                    {
                         if let Some(d) = d {
                             d.report(
                                 DiagnosticKind::StructDoesNotHaveField {
@@ -187,12 +231,7 @@ impl S<'_> {
             );
         }
         if let Some(var) = self.get_local_var(basename) {
-            let qualified_var_name = qualify_local_var_name(
-                self.proc
-                    .map(|proc| &proc.name)
-                    .unwrap_or_else(|| &self.func.unwrap().name),
-                &var.name,
-            );
+            let qualified_var_name = qualify_local_var_name(self.local_scope(), &var.name);
             return self.qualify_field(
                 d,
                 &name.span(),
@@ -213,11 +252,6 @@ impl S<'_> {
             );
         }
         if let Some(d) = d {
-            if d.find_diagnostic_for_span(&name.span())
-                .is_some_and(|it| matches!(it.kind, DiagnosticKind::LocalNotSupported))
-            {
-                return None;
-            }
             d.report(
                 DiagnosticKind::UnrecognizedVariable(basename.clone()),
                 &name.span(),
@@ -283,7 +317,7 @@ impl Stmt {
         )
     }
 
-    fn opcode(&self, s: S) -> &'static str {
+    fn opcode<'a>(&'a self, s: S<'a>) -> &'a str {
         match self {
             Stmt::Repeat { .. } => "control_repeat",
             Stmt::Forever { .. } => "control_forever",
@@ -318,9 +352,20 @@ impl Stmt {
             Stmt::InsertAtList { .. } => "data_insertatlist",
             Stmt::SetListIndex { .. } => "data_replaceitemoflist",
             Stmt::Block { block, .. } => block.opcode(),
-            Stmt::ProcCall { .. } => "procedures_call",
+            Stmt::ProcCall { name, .. } => match s.extension_block(name) {
+                Some((_, block)) => &block.opcode,
+                None => "procedures_call",
+            },
             Stmt::FuncCall { .. } => "procedures_call",
             Stmt::Return { .. } => "data_setvariableto",
+            Stmt::While { .. }
+            | Stmt::For { .. }
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Match { .. }
+            | Stmt::Destructure { .. } => {
+                unreachable!()
+            }
         }
     }
 }
@@ -331,34 +376,79 @@ pub struct Sb3 {
     pub node_comma: bool,
     pub inputs_comma: bool,
     pub block_count: usize,
+    /// Leave out the variables and lists which are not used. Unused procedures and
+    /// functions, and unreachable statements, are always left out.
+    pub eliminate_dead_code: bool,
+    /// How many blocks were left out, because they were unused or unreachable.
+    pub blocks_saved: usize,
     pub asset_object_store: AssetObjectStore,
     extensions: Extensions,
 }
 
 impl Sb3 {
-    pub fn new(fs: Rc<RefCell<dyn VFS>>, input: PathBuf) -> Self {
+    pub fn new(fs: Rc<RefCell<dyn VFS>>, input: PathBuf, eliminate_dead_code: bool) -> Self {
         Self {
             json: Vec::new(),
             id: NodeIDFactory::new(),
             node_comma: false,
             inputs_comma: false,
             block_count: 0,
+            eliminate_dead_code,
+            blocks_saved: 0,
             asset_object_store: AssetObjectStore::new(input, fs),
             extensions: Extensions::default(),
         }
     }
 
-    pub fn begin_node(&mut self, node: Node) -> io::Result<()> {
+    /// Generates blocks with `f` only to count them, and then throws them away along with
+    /// any diagnostics reported while generating them.
+    fn count_blocks(
+        &mut self,
+        d: D,
+        f: impl FnOnce(&mut Self, D) -> io::Result<()>,
+    ) -> io::Result<usize> {
+        let json_len = self.json.len();
+        let block_count = self.block_count;
+        let node_comma = self.node_comma;
+        let inputs_comma = self.inputs_comma;
+        let extensions = self.extensions.clone();
+        let diagnostics_len = d.diagnostics.len();
+        let debug_info = mem::take(&mut d.debug_info);
+        f(self, d)?;
+        let count = self.block_count - block_count;
+        self.json.truncate(json_len);
+        self.block_count = block_count;
+        self.node_comma = node_comma;
+        self.inputs_comma = inputs_comma;
+        self.extensions = extensions;
+        d.diagnostics.truncate(diagnostics_len);
+        d.debug_info = debug_info;
+        Ok(count)
+    }
+
+    pub fn begin_node(&mut self, node: Node<'_>) -> io::Result<()> {
         self.block_count += 1;
         if node.opcode.starts_with("pen_") {
             self.extensions.pen = true;
         } else if node.opcode.starts_with("music_") {
             self.extensions.music = true;
+        } else if node.opcode.starts_with("text2speech_") {
+            self.extensions.text2speech = true;
+        } else if node.opcode.starts_with("translate_") {
+            self.extensions.translate = true;
+        } else if node.opcode.starts_with("videoSensing_") {
+            self.extensions.video_sensing = true;
+        } else if node.opcode.starts_with("makeymakey_") {
+            self.extensions.makeymakey = true;
         }
         write_comma_io(&mut self.json, &mut self.node_comma)?;
         write!(self.json, "{node}")
     }
 
+    pub fn add_extension(&mut self, id: &SmolStr) {
+        self.extensions.custom.insert(id.clone());
+    }
+
     pub fn end_obj(&mut self) -> io::Result<()> {
         self.json.write_all(b"}")
     }
@@ -368,7 +458,7 @@ impl Sb3 {
         self.json.write_all(br#","inputs":{"#)
     }
 
-    pub fn single_field(&mut self, name: &'static str, value: &str) -> io::Result<()> {
+    pub fn single_field(&mut self, name: &str, value: &str) -> io::Result<()> {
         write!(
             self.json,
             r#","fields":{{"{name}":[{},null]}}"#,
@@ -408,13 +498,7 @@ impl Sb3 {
             .events
             .iter()
             .chain(project.sprites.values().flat_map(|sprite| &sprite.events))
-            .filter_map(|event| {
-                if let EventKind::On { event } = &event.kind {
-                    Some(event.clone())
-                } else {
-                    None
-                }
-            })
+            .filter_map(|event| event.kind.broadcast())
             .collect();
         write!(self.json, "{{")?;
         write!(self.json, r#""targets":["#)?;
@@ -447,14 +531,44 @@ impl Sb3 {
         }
         write!(self.json, "]")?; // targets
         write!(self.json, r#","monitors":[]"#)?;
-        if self.extensions.pen && self.extensions.music {
-            write!(self.json, r#","extensions":["pen","music"]"#)?;
-        } else if self.extensions.pen {
-            write!(self.json, r#","extensions":["pen"]"#)?;
-        } else if self.extensions.music {
-            write!(self.json, r#","extensions":["music"]"#)?;
-        } else {
-            write!(self.json, r#","extensions":[]"#)?;
+        let mut extensions: Vec<&str> = vec![];
+        if self.extensions.pen {
+            extensions.push("pen");
+        }
+        if self.extensions.music {
+            extensions.push("music");
+        }
+        if self.extensions.text2speech {
+            extensions.push("text2speech");
+        }
+        if self.extensions.translate {
+            extensions.push("translate");
+        }
+        if self.extensions.video_sensing {
+            extensions.push("videoSensing");
+        }
+        if self.extensions.makeymakey {
+            extensions.push("makeymakey");
+        }
+        let custom_extensions: Vec<_> = config
+            .extensions
+            .iter()
+            .filter(|extension| self.extensions.custom.contains(&extension.id))
+            .collect();
+        for extension in &custom_extensions {
+            if !extensions.contains(&extension.id.as_str()) {
+                extensions.push(&extension.id);
+            }
+        }
+        write!(self.json, r#","extensions":{}"#, json!(extensions))?;
+        let extension_urls: serde_json::Map<String, serde_json::Value> = custom_extensions
+            .iter()
+            .filter_map(|extension| {
+                Some((extension.id.to_string(), json!(extension.url.as_ref()?)))
+            })
+            .collect();
+        if !extension_urls.is_empty() {
+            write!(self.json, r#","extensionURLs":{}"#, json!(extension_urls))?;
         }
         write!(self.json, r#","meta":{{"#)?;
         write!(self.json, r#""semver":"3.0.0""#)?;
@@ -484,6 +598,9 @@ impl Sb3 {
         layer_order: usize,
     ) -> io::Result<()> {
         for proc in sprite.procs.values() {
+            if sprite.inlined_procs.contains(&proc.name) {
+                continue;
+            }
             if !sprite.used_procs.contains(&proc.name) {
                 d.report(DiagnosticKind::UnusedProc(proc.name.clone()), &proc.span);
             } else {
@@ -495,6 +612,9 @@ impl Sb3 {
             }
         }
         for func in sprite.funcs.values() {
+            if sprite.inlined_funcs.contains(&func.name) {
+                continue;
+            }
             if !sprite.used_funcs.contains(&func.name) {
                 d.report(DiagnosticKind::UnusedFunc(func.name.clone()), &func.span);
             } else {
@@ -527,19 +647,21 @@ impl Sb3 {
                 }
             }
         }
-        let mut costumes = FxHashSet::default();
+        let mut costumes: FxHashMap<&SmolStr, &Span> = FxHashMap::default();
         for costume in &sprite.costumes {
-            if costumes.contains(&costume.name) {
-                d.report(
+            if let Some(first) = costumes.get(&costume.name) {
+                d.report_with_notes(
                     if stage.is_none() {
                         DiagnosticKind::DuplicateBackdrop(costume.name.clone())
                     } else {
                         DiagnosticKind::DuplicateCostume(costume.name.clone())
                     },
                     &costume.span,
+                    vec![Note::new("first added here", first)],
                 );
+            } else {
+                costumes.insert(&costume.name, &costume.span);
             }
-            costumes.insert(&costume.name);
         }
         self.id.reset();
         write!(self.json, "{{")?;
@@ -578,11 +700,9 @@ impl Sb3 {
         write!(self.json, "}}")?; // broadcasts
         write!(self.json, r#","variables":{{"#)?;
         let mut comma = false;
-        for proc in sprite
-            .procs
-            .values()
-            .filter(|proc| sprite.used_procs.contains(&proc.name))
-        {
+        for proc in sprite.procs.values().filter(|proc| {
+            !sprite.inlined_procs.contains(&proc.name) && sprite.used_procs.contains(&proc.name)
+        }) {
             for var in sprite.proc_locals[&proc.name].values() {
                 self.local_var_declaration(
                     S {
@@ -590,6 +710,8 @@ impl Sb3 {
                         stage,
                         proc: None,
                         func: None,
+                        event: None,
+                        config,
                     },
                     &proc.name,
                     var,
@@ -598,11 +720,9 @@ impl Sb3 {
                 )?;
             }
         }
-        for func in sprite
-            .funcs
-            .values()
-            .filter(|func| sprite.used_funcs.contains(&func.name))
-        {
+        for func in sprite.funcs.values().filter(|func| {
+            !sprite.inlined_funcs.contains(&func.name) && sprite.used_funcs.contains(&func.name)
+        }) {
             for var in sprite.func_locals[&func.name].values() {
                 self.local_var_declaration(
                     S {
@@ -610,6 +730,8 @@ impl Sb3 {
                         stage,
                         proc: None,
                         func: None,
+                        event: None,
+                        config,
                     },
                     &func.name,
                     var,
@@ -618,13 +740,40 @@ impl Sb3 {
                 )?;
             }
         }
+        for event in &sprite.events {
+            for var in event.locals.values() {
+                self.local_var_declaration(
+                    S {
+                        sprite,
+                        stage,
+                        proc: None,
+                        func: None,
+                        event: None,
+                        config,
+                    },
+                    &event.scope,
+                    var,
+                    &mut comma,
+                    d,
+                )?;
+            }
+        }
         for var in sprite.vars.values() {
+            let is_used = var.is_cloud || sprite.used_names.contains(&var.name);
+            if !is_used && !var.is_used {
+                d.report(DiagnosticKind::UnusedVariable(var.name.clone()), &var.span);
+            }
+            if !is_used && self.eliminate_dead_code {
+                continue;
+            }
             self.var_declaration(
                 S {
                     sprite,
                     stage,
                     proc: None,
                     func: None,
+                    event: None,
+                    config,
                 },
                 var,
                 &mut comma,
@@ -635,6 +784,13 @@ impl Sb3 {
         write!(self.json, r#","lists":{{"#)?;
         let mut comma = false;
         for list in sprite.lists.values() {
+            let is_used = sprite.used_names.contains(&list.name);
+            if !is_used && !list.is_used {
+                d.report(DiagnosticKind::UnusedList(list.name.clone()), &list.span);
+            }
+            if !is_used && self.eliminate_dead_code {
+                continue;
+            }
             self.list_declaration(
                 fs.clone(),
                 input,
@@ -643,6 +799,8 @@ impl Sb3 {
                     sprite,
                     proc: None,
                     func: None,
+                    event: None,
+                    config,
                 },
                 list,
                 &mut comma,
@@ -652,41 +810,48 @@ impl Sb3 {
         write!(self.json, "}}")?; // lists
         write!(self.json, r#","blocks":{{"#)?;
         self.node_comma = false;
+        // The definitions of inlined procedures and functions are not called anywhere.
         for proc in sprite
             .procs
             .values()
-            .filter(|proc| sprite.used_procs.contains(&proc.name))
+            .filter(|proc| !sprite.inlined_procs.contains(&proc.name))
         {
             let proc_definition = sprite.proc_definitions.get(&proc.name).unwrap();
-            self.proc(
-                S {
-                    stage,
-                    sprite,
-                    proc: Some(proc),
-                    func: None,
-                },
-                d,
-                proc,
-                proc_definition,
-            )?;
+            let s = S {
+                stage,
+                sprite,
+                proc: Some(proc),
+                func: None,
+                event: None,
+                config,
+            };
+            if sprite.used_procs.contains(&proc.name) {
+                self.proc(s, d, proc, proc_definition)?;
+            } else {
+                self.blocks_saved +=
+                    self.count_blocks(d, |sb3, d| sb3.proc(s, d, proc, proc_definition))?;
+            }
         }
         for func in sprite
             .funcs
             .values()
-            .filter(|func| sprite.used_funcs.contains(&func.name))
+            .filter(|func| !sprite.inlined_funcs.contains(&func.name))
         {
             let func_definition = sprite.func_definitions.get(&func.name).unwrap();
-            self.func(
-                S {
-                    stage,
-                    sprite,
-                    proc: None,
-                    func: Some(func),
-                },
-                d,
-                func,
-                func_definition,
-            )?;
+            let s = S {
+                stage,
+                sprite,
+                proc: None,
+                func: Some(func),
+                event: None,
+                config,
+            };
+            if sprite.used_funcs.contains(&func.name) {
+                self.func(s, d, func, func_definition)?;
+            } else {
+                self.blocks_saved +=
+                    self.count_blocks(d, |sb3, d| sb3.func(s, d, func, func_definition))?;
+            }
         }
         for event in &sprite.events {
             self.event(
@@ -695,6 +860,8 @@ impl Sb3 {
                     sprite,
                     proc: None,
                     func: None,
+                    event: Some(event),
+                    config,
                 },
                 d,
                 event,
@@ -779,7 +946,10 @@ impl Sb3 {
 
     pub fn var_declaration(&mut self, s: S, var: &Var, comma: &mut bool, d: D) -> io::Result<()> {
         match &var.type_ {
-            Type::Value => {
+            Type::Value | Type::Kind { .. } => {
+                d.debug_info
+                    .variables
+                    .insert(var.name.to_string(), var.span.clone());
                 self.json_var_declaration(
                     &var.name,
                     var.default
@@ -830,26 +1000,32 @@ impl Sb3 {
                     }
                     None => None,
                 };
-                for field in &struct_.fields {
-                    let qualified_var_name = qualify_struct_var_name(&field.name, &var.name);
+                for (path, _) in s.struct_leaves(struct_) {
+                    let qualified_var_name = qualify_struct_var_name(&path, &var.name);
+                    d.debug_info
+                        .variables
+                        .insert(qualified_var_name.to_string(), var.span.clone());
+                    let field_default = struct_.field_default(&path, &|name| s.get_struct(name));
                     self.json_var_declaration(
                         &qualified_var_name,
-                        match (&default, &field.default) {
+                        match (&default, &field_default) {
                             (Some(fields), fdef) => {
                                 let dvalue = fields
                                     .iter()
-                                    .find(|dfield| dfield.name == field.name)
+                                    .find(|dfield| dfield.name == path)
                                     .map(|dfield| dfield.value.clone());
                                 if dvalue.is_none() && fdef.is_none() {
                                     d.report(
                                         DiagnosticKind::MissingField {
                                             struct_name: type_name.clone(),
-                                            field_name: field.name.clone(),
+                                            field_name: path.clone(),
                                         },
                                         &var.default.as_ref().unwrap().span(),
                                     )
                                 }
-                                dvalue
+                                dvalue.or_else(|| {
+                                    fdef.as_ref().map(|fdef| s.evaluate_const_expr(d, fdef))
+                                })
                             }
                             (None, Some(default)) => Some(s.evaluate_const_expr(d, default)),
                             (None, None) => None,
@@ -872,8 +1048,11 @@ impl Sb3 {
         d: D,
     ) -> io::Result<()> {
         match &var.type_ {
-            Type::Value => {
+            Type::Value | Type::Kind { .. } => {
                 let qualified_var_name = qualify_local_var_name(proc_name, &var.name);
+                d.debug_info
+                    .variables
+                    .insert(qualified_var_name.to_string(), var.span.clone());
                 self.json_var_declaration(&qualified_var_name, None, false, comma)?;
             }
             Type::Struct {
@@ -887,11 +1066,14 @@ impl Sb3 {
                     );
                     return Ok(());
                 };
-                for field in &struct_.fields {
+                for (path, _) in s.struct_leaves(struct_) {
                     let qualified_var_name = qualify_local_var_name(
                         proc_name,
-                        &qualify_struct_var_name(&field.name, &var.name),
+                        &qualify_struct_var_name(&path, &var.name),
                     );
+                    d.debug_info
+                        .variables
+                        .insert(qualified_var_name.to_string(), var.span.clone());
                     self.json_var_declaration(&qualified_var_name, None, false, comma)?;
                 }
             }
@@ -924,7 +1106,7 @@ impl Sb3 {
                     .type_
                     .struct_()
                     .and_then(|(struct_name, _)| s.get_struct(struct_name))
-                    .map(|struct_| struct_.fields.len())
+                    .map(|struct_| s.struct_leaves(struct_).len())
                     .unwrap_or(1);
                 let value = s.evaluate_const_expr(d, value);
                 let len = s.evaluate_const_expr(d, length).to_number();
@@ -938,7 +1120,10 @@ impl Sb3 {
             None => vec![],
         };
         match &list.type_ {
-            Type::Value => {
+            Type::Value | Type::Kind { .. } => {
+                d.debug_info
+                    .lists
+                    .insert(list.name.to_string(), list.span.clone());
                 write_comma_io(&mut self.json, comma)?;
                 write!(
                     self.json,
@@ -959,11 +1144,15 @@ impl Sb3 {
                     );
                     return Ok(());
                 };
-                for (i, field) in struct_.fields.iter().enumerate() {
-                    let qualified_list_name = qualify_struct_var_name(&field.name, &list.name);
+                let leaves = s.struct_leaves(struct_);
+                for (i, (path, _)) in leaves.iter().enumerate() {
+                    let qualified_list_name = qualify_struct_var_name(path, &list.name);
+                    d.debug_info
+                        .lists
+                        .insert(qualified_list_name.to_string(), list.span.clone());
                     write_comma_io(&mut self.json, comma)?;
-                    let column = (0..(data.len() / struct_.fields.len()))
-                        .map(|j| &data[j * struct_.fields.len() + i])
+                    let column = (0..(data.len() / leaves.len()))
+                        .map(|j| &data[j * leaves.len() + i])
                         .collect::<Vec<_>>();
                     write!(
                         self.json,
@@ -979,9 +1168,15 @@ impl Sb3 {
     }
 
     pub fn proc(&mut self, s: S, d: D, proc: &Proc, definition: &[Stmt]) -> io::Result<()> {
+        d.debug_info
+            .procs
+            .insert(proc.name.to_string(), proc.span.clone());
         let this_id = self.id.new_id();
         let prototype_id = self.id.new_id();
         let next_id = self.id.new_id();
+        d.debug_info
+            .blocks
+            .insert(this_id.to_string(), proc.span.clone());
         self.begin_node(
             Node::new("procedures_definition", this_id)
                 .some_next_id((!definition.is_empty()).then_some(next_id))
@@ -994,7 +1189,7 @@ impl Sb3 {
         let mut qualified_args: Vec<(SmolStr, NodeID)> = Vec::new();
         for arg in &s.sprite.proc_args[&proc.name] {
             match &arg.type_ {
-                Type::Value => {
+                Type::Value | Type::Kind { .. } => {
                     let arg_id = self.id.new_id();
                     self.begin_node(
                         Node::new("argument_reporter_string_number", arg_id)
@@ -1016,8 +1211,8 @@ impl Sb3 {
                         );
                         continue;
                     };
-                    for field in &struct_.fields {
-                        let qualified_arg_name = qualify_struct_var_name(&field.name, &arg.name);
+                    for (path, _) in s.struct_leaves(struct_) {
+                        let qualified_arg_name = qualify_struct_var_name(&path, &arg.name);
                         let arg_id = self.id.new_id();
                         self.begin_node(
                             Node::new("argument_reporter_string_number", arg_id)
@@ -1053,9 +1248,15 @@ impl Sb3 {
     }
 
     pub fn func(&mut self, s: S, d: D, func: &Func, definition: &[Stmt]) -> io::Result<()> {
+        d.debug_info
+            .funcs
+            .insert(func.name.to_string(), func.span.clone());
         let this_id = self.id.new_id();
         let prototype_id = self.id.new_id();
         let next_id = self.id.new_id();
+        d.debug_info
+            .blocks
+            .insert(this_id.to_string(), func.span.clone());
         self.begin_node(
             Node::new("procedures_definition", this_id)
                 .some_next_id((!definition.is_empty()).then_some(next_id))
@@ -1068,7 +1269,7 @@ impl Sb3 {
         let mut qualified_args: Vec<(SmolStr, NodeID)> = Vec::new();
         for arg in &s.sprite.func_args[&func.name] {
             match &arg.type_ {
-                Type::Value => {
+                Type::Value | Type::Kind { .. } => {
                     let arg_id = self.id.new_id();
                     self.begin_node(
                         Node::new("argument_reporter_string_number", arg_id)
@@ -1090,8 +1291,8 @@ impl Sb3 {
                         );
                         continue;
                     };
-                    for field in &struct_.fields {
-                        let qualified_arg_name = qualify_struct_var_name(&field.name, &arg.name);
+                    for (path, _) in s.struct_leaves(struct_) {
+                        let qualified_arg_name = qualify_struct_var_name(&path, &arg.name);
                         let arg_id = self.id.new_id();
                         self.begin_node(
                             Node::new("argument_reporter_string_number", arg_id)
@@ -1129,6 +1330,9 @@ impl Sb3 {
     pub fn event(&mut self, s: S, d: D, event: &Event) -> io::Result<()> {
         let this_id = self.id.new_id();
         let next_id = self.id.new_id();
+        d.debug_info
+            .blocks
+            .insert(this_id.to_string(), event.span.clone());
         self.begin_node(
             Node::new(event.kind.opcode(), this_id)
                 .some_next_id((!event.body.is_empty()).then_some(next_id))
@@ -1136,6 +1340,7 @@ impl Sb3 {
         )?;
         match &event.kind {
             EventKind::On { event } => self.on(event),
+            EventKind::Test { .. } => self.on(&event.kind.broadcast().unwrap()),
             EventKind::OnFlag => self.on_flag(),
             EventKind::OnKey { key, span } => self.on_key(s, d, this_id, key, span),
             EventKind::OnClick => self.on_click(s, d, this_id),
@@ -1144,6 +1349,8 @@ impl Sb3 {
             }
             EventKind::OnLoudnessGt { value } => self.on_loudness_gt(s, d, this_id, value),
             EventKind::OnTimerGt { value } => self.on_timer_gt(s, d, this_id, value),
+            EventKind::OnVideoMotionGt { value } => self.on_video_motion_gt(s, d, this_id, value),
+            EventKind::OnMakeyKey { key, span } => self.on_makey_key(s, d, this_id, key, span),
             EventKind::OnClone => self.on_clone(s, d, this_id),
         }?;
         self.stmts(s, d, &event.body, next_id, Some(this_id))
@@ -1168,6 +1375,11 @@ impl Sb3 {
                         .find(|span| !span.is_empty())
                         .unwrap_or_else(|| stmt.span());
                     d.report(DiagnosticKind::FollowedByUnreachableCode, &span);
+                    let unreachable = &stmts[i + 1..];
+                    let unreachable_id = self.id.new_id();
+                    self.blocks_saved += self.count_blocks(d, |sb3, d| {
+                        sb3.stmts(s, d, unreachable, unreachable_id, None)
+                    })?;
                 }
                 break;
             }
@@ -1257,6 +1469,14 @@ impl Sb3 {
                 args,
             ),
             Stmt::Return { .. } => panic!(),
+            Stmt::While { .. }
+            | Stmt::For { .. }
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Match { .. }
+            | Stmt::Destructure { .. } => {
+                unreachable!()
+            }
         }
     }
 
@@ -1278,12 +1498,24 @@ impl Sb3 {
             Expr::Repr { repr, span, args } => {
                 self.repr(s, d, this_id, parent_id, repr, span, args)
             }
-            Expr::FuncCall { name, span, .. } => {
-                d.report(DiagnosticKind::UnrecognizedFunction(name.clone()), span);
-                Ok(())
-            }
+            Expr::FuncCall {
+                name, span, args, ..
+            } => match s.extension_block(name) {
+                Some((extension, block)) if block.shape != ExtensionBlockShape::Command => {
+                    self.begin_node(Node::new(&block.opcode, this_id).parent_id(parent_id))?;
+                    self.extension_block(s, d, this_id, extension, block, span, args)
+                }
+                _ => {
+                    d.report(DiagnosticKind::UnrecognizedFunction(name.clone()), span);
+                    Ok(())
+                }
+            },
             Expr::UnOp { op, opr, .. } => self.un_op(s, d, this_id, parent_id, op, opr),
             Expr::BinOp { op, lhs, rhs, .. } => self.bin_op(s, d, this_id, parent_id, op, lhs, rhs),
+            Expr::StructLiteral { name, span, .. } if s.get_struct(name).is_none() => {
+                d.report(DiagnosticKind::UnrecognizedStruct(name.clone()), span);
+                Ok(())
+            }
             Expr::StructLiteral { name, span, .. } => {
                 d.report(
                     DiagnosticKind::TypeMismatch {
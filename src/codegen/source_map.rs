@@ -0,0 +1,222 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+};
+
+use logos::Span;
+use rustc_hash::FxHashMap;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::{
+    debug_info::DebugInfo,
+    sb3::STAGE_NAME,
+};
+use crate::{
+    diagnostic::{
+        Artifact,
+        SpriteDiagnostics,
+    },
+    translation_unit::TranslationUnit,
+    vfs::VFS,
+};
+
+/// Name of the source map file, which is written next to the built project.
+pub const SOURCE_MAP_FILE_NAME: &str = "goboscript.map.json";
+
+/// Maps the blocks, variables, lists, procedures and functions of a built project back to
+/// the goboscript source code that they were compiled from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceMap {
+    pub version: u32,
+    /// By the name of the target in `project.json`.
+    pub targets: BTreeMap<String, TargetSourceMap>,
+}
+
+/// Locations of the things in a target, by their ID in `project.json`. Procedures and
+/// functions are by their name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TargetSourceMap {
+    pub blocks: BTreeMap<String, Location>,
+    pub variables: BTreeMap<String, Location>,
+    pub lists: BTreeMap<String, Location>,
+    pub procs: BTreeMap<String, Location>,
+    pub funcs: BTreeMap<String, Location>,
+}
+
+/// A range in a source file. The path is relative to the project directory if the file
+/// is inside it. The end is exclusive.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub start: Position,
+    pub end: Position,
+}
+
+/// Lines and columns start at 1, and columns are counted in characters.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceMap {
+    pub fn new(fs: Rc<RefCell<dyn VFS>>, input: &Path, artifact: &Artifact) -> io::Result<Self> {
+        let mut files = Files::new(fs, input);
+        let mut targets = BTreeMap::new();
+        targets.insert(
+            STAGE_NAME.to_owned(),
+            TargetSourceMap::new(&artifact.stage_diagnostics, &mut files)?,
+        );
+        for (sprite_name, sprite_diagnostics) in &artifact.sprites_diagnostics {
+            targets.insert(
+                sprite_name.to_string(),
+                TargetSourceMap::new(sprite_diagnostics, &mut files)?,
+            );
+        }
+        Ok(Self {
+            version: 1,
+            targets,
+        })
+    }
+}
+
+impl TargetSourceMap {
+    fn new(sprite_diagnostics: &SpriteDiagnostics, files: &mut Files) -> io::Result<Self> {
+        let unit = &sprite_diagnostics.translation_unit;
+        let DebugInfo {
+            blocks,
+            variables,
+            lists,
+            procs,
+            funcs,
+        } = &sprite_diagnostics.debug_info;
+        Ok(Self {
+            blocks: files.locations(unit, blocks)?,
+            variables: files.locations(unit, variables)?,
+            lists: files.locations(unit, lists)?,
+            procs: files.locations(unit, procs)?,
+            funcs: files.locations(unit, funcs)?,
+        })
+    }
+}
+
+/// Source files that have been read, with the position of the start of each line.
+pub(crate) struct Files<'a> {
+    fs: Rc<RefCell<dyn VFS>>,
+    input: &'a Path,
+    texts: FxHashMap<PathBuf, (String, Vec<usize>)>,
+}
+
+impl<'a> Files<'a> {
+    pub(crate) fn new(fs: Rc<RefCell<dyn VFS>>, input: &'a Path) -> Self {
+        Self {
+            fs,
+            input,
+            texts: Default::default(),
+        }
+    }
+
+    fn locations(
+        &mut self,
+        unit: &TranslationUnit,
+        spans: &FxHashMap<String, Span>,
+    ) -> io::Result<BTreeMap<String, Location>> {
+        let mut locations = BTreeMap::new();
+        for (id, span) in spans {
+            // Spans of code generated by the compiler are empty.
+            if span.is_empty() {
+                continue;
+            }
+            // Block IDs are recorded in the form they are written to `project.json` in,
+            // which is quoted.
+            locations.insert(id.trim_matches('"').to_owned(), self.location(unit, span)?);
+        }
+        Ok(locations)
+    }
+
+    fn location(&mut self, unit: &TranslationUnit, span: &Span) -> io::Result<Location> {
+        let (start, include) = unit.translate_position(span.start);
+        let (end, _) = unit.translate_position(span.end - 1);
+        let path = include.path.clone();
+        Ok(Location {
+            start: self.position(&path, start)?,
+            end: self.position(&path, end + 1)?,
+            path: self.relative_path(path),
+        })
+    }
+
+    /// `path` relative to the project directory, if it is inside it.
+    pub(crate) fn relative_path(&self, path: PathBuf) -> PathBuf {
+        path.strip_prefix(self.input)
+            .map(Path::to_path_buf)
+            .unwrap_or(path)
+    }
+
+    /// The line and column of the byte offset `offset` in the file at `path`.
+    pub(crate) fn position(&mut self, path: &Path, offset: usize) -> io::Result<Position> {
+        if !self.texts.contains_key(path) {
+            let text =
+                String::from_utf8_lossy(&self.fs.borrow_mut().read_to_vec(path)?).into_owned();
+            let line_starts = std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+                .collect();
+            self.texts.insert(path.to_path_buf(), (text, line_starts));
+        }
+        let (text, line_starts) = &self.texts[path];
+        let offset = offset.min(text.len());
+        let line = line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = line_starts[line];
+        let column = text
+            .get(start..offset)
+            .map(|prefix| prefix.chars().count())
+            .unwrap_or(offset - start);
+        Ok(Position {
+            line: line + 1,
+            column: column + 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashSet;
+
+    use super::*;
+    use crate::{
+        codegen::build::ParseCache,
+        testing::*,
+    };
+
+    #[test]
+    fn source_map_points_at_source_lines() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             onflag {\n\
+                 x = 1;\n\
+                 say x;\n\
+             }\n",
+        );
+        let artifact = build(&fs, &mut ParseCache::default());
+        let source_map = SourceMap::new(fs, Path::new(""), &artifact).unwrap();
+        let main = &source_map.targets["main"];
+        let x = &main.variables["x"];
+        assert_eq!(x.path, Path::new("main.gs"));
+        assert_eq!(x.start, Position { line: 3, column: 1 });
+        assert_eq!(x.end, Position { line: 3, column: 2 });
+        let lines: FxHashSet<usize> = main
+            .blocks
+            .values()
+            .map(|location| location.start.line)
+            .collect();
+        assert_eq!(lines, FxHashSet::from_iter([2, 3, 4]));
+    }
+}
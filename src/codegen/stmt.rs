@@ -24,6 +24,7 @@ use crate::{
         Name,
         Proc,
         Stmt,
+        StructLiteralField,
         Type,
         Value,
     },
@@ -419,6 +420,8 @@ impl Sb3 {
                     "\u{200b}\u{200b}log\u{200b}\u{200b}".into(),
                     span.clone(),
                     false,
+                    false,
+                    false,
                 ),
                 &[Arg::new("arg0".into(), span.clone(), Type::Value, None)],
                 s,
@@ -431,12 +434,17 @@ impl Sb3 {
             );
         }
         let Some(proc) = s.sprite.procs.get(name) else {
+            if let Some((extension, block)) = s.extension_block(name) {
+                return self.extension_block(s, d, this_id, extension, block, span, args);
+            }
             if name == "breakpoint" {
                 return self.proc_call_impl(
                     &Proc::new(
                         "\u{200b}\u{200b}breakpoint\u{200b}\u{200b}".into(),
                         span.clone(),
                         false,
+                        false,
+                        false,
                     ),
                     &[],
                     s,
@@ -454,6 +462,8 @@ impl Sb3 {
                         "\u{200b}\u{200b}error\u{200b}\u{200b}".into(),
                         span.clone(),
                         false,
+                        false,
+                        false,
                     ),
                     &[Arg::new("arg0".into(), span.clone(), Type::Value, None)],
                     s,
@@ -471,6 +481,27 @@ impl Sb3 {
                         "\u{200b}\u{200b}warn\u{200b}\u{200b}".into(),
                         span.clone(),
                         false,
+                        false,
+                        false,
+                    ),
+                    &[Arg::new("arg0".into(), span.clone(), Type::Value, None)],
+                    s,
+                    d,
+                    this_id,
+                    name,
+                    span,
+                    args,
+                    true,
+                );
+            }
+            if name == "assert" {
+                return self.proc_call_impl(
+                    &Proc::new(
+                        "\u{200b}\u{200b}assert\u{200b}\u{200b}".into(),
+                        span.clone(),
+                        false,
+                        false,
+                        false,
                     ),
                     &[Arg::new("arg0".into(), span.clone(), Type::Value, None)],
                     s,
@@ -482,6 +513,28 @@ impl Sb3 {
                     true,
                 );
             }
+            if name == "assert_eq" {
+                return self.proc_call_impl(
+                    &Proc::new(
+                        "\u{200b}\u{200b}assert_eq\u{200b}\u{200b}".into(),
+                        span.clone(),
+                        false,
+                        false,
+                        false,
+                    ),
+                    &[
+                        Arg::new("arg0".into(), span.clone(), Type::Value, None),
+                        Arg::new("arg1".into(), span.clone(), Type::Value, None),
+                    ],
+                    s,
+                    d,
+                    this_id,
+                    name,
+                    span,
+                    args,
+                    true,
+                );
+            }
             d.report(DiagnosticKind::UnrecognizedProcedure(name.clone()), span);
             return Ok(());
         };
@@ -528,7 +581,7 @@ impl Sb3 {
         self.begin_inputs()?;
         for (arg, arg_value) in signature.iter().zip(args) {
             match &arg.type_ {
-                Type::Value => {
+                Type::Value | Type::Kind { .. } => {
                     let arg_id = self.id.new_id();
                     self.input(s, d, &arg.name, arg_value, arg_id, false)?;
                     qualified_args.push((arg.name.clone(), arg_id));
@@ -573,13 +626,11 @@ impl Sb3 {
                             continue;
                         }
                     };
-                    for field in &struct_.fields {
-                        let qualified_arg_name = qualify_struct_var_name(&field.name, &arg.name);
+                    for (path, _) in s.struct_leaves(struct_) {
+                        let qualified_arg_name = qualify_struct_var_name(&path, &arg.name);
                         let arg_id = self.id.new_id();
-                        let field_value = struct_literal_fields
-                            .iter()
-                            .find(|f| f.name == field.name)
-                            .map(|f| f.value.as_ref().clone());
+                        let field_value =
+                            StructLiteralField::find(struct_literal_fields, &path).cloned();
                         let (value, is_placeholder) = match field_value {
                             Some(v) => (v, false),
                             None => (
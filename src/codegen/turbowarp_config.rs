@@ -3,6 +3,8 @@ use std::fmt::{
     Display,
 };
 
+use serde_json::Value as Json;
+
 use crate::config::Config;
 
 #[derive(Debug)]
@@ -32,6 +34,53 @@ impl Default for TurbowarpConfig {
     }
 }
 
+impl TurbowarpConfig {
+    /// Recover the settings from the text of a `_twconfig_` stage comment, as written
+    /// by [`Display`] or by TurboWarp itself.
+    pub fn from_comment(text: &str) -> Option<Self> {
+        if !text.trim_end().ends_with("_twconfig_") {
+            return None;
+        }
+        let begin = text.find('{')?;
+        let end = text.rfind('}')?;
+        // TurboWarp writes infinite clones as a bare `Infinity`, which is not valid JSON.
+        let json: Json =
+            serde_json::from_str(&text[begin..=end].replace("Infinity", "null")).ok()?;
+        let runtime_options = &json["runtimeOptions"];
+        let default = Self::default();
+        Some(Self {
+            frame_rate: json["framerate"]
+                .as_f64()
+                .map(|frame_rate| frame_rate as u64)
+                .unwrap_or(default.frame_rate),
+            max_clones: match &runtime_options["maxClones"] {
+                Json::Null if runtime_options.get("maxClones").is_some() => f64::INFINITY,
+                max_clones => max_clones.as_f64().unwrap_or(default.max_clones),
+            },
+            no_miscellaneous_limits: runtime_options["miscLimits"]
+                .as_bool()
+                .map(|misc_limits| !misc_limits)
+                .unwrap_or(default.no_miscellaneous_limits),
+            no_sprite_fencing: runtime_options["fencing"]
+                .as_bool()
+                .map(|fencing| !fencing)
+                .unwrap_or(default.no_sprite_fencing),
+            frame_interpolation: json["interpolation"]
+                .as_bool()
+                .unwrap_or(default.frame_interpolation),
+            high_quality_pen: json["hq"].as_bool().unwrap_or(default.high_quality_pen),
+            stage_width: json["width"]
+                .as_f64()
+                .map(|width| width as u64)
+                .unwrap_or(default.stage_width),
+            stage_height: json["height"]
+                .as_f64()
+                .map(|height| height as u64)
+                .unwrap_or(default.stage_height),
+        })
+    }
+}
+
 #[allow(clippy::write_with_newline)]
 impl Display for TurbowarpConfig {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -85,3 +134,26 @@ impl From<&Config> for TurbowarpConfig {
         }
     }
 }
+
+impl From<&TurbowarpConfig> for Config {
+    /// Only settings that differ from TurboWarp's defaults are kept, so that a
+    /// `goboscript.toml` written from this stays minimal.
+    fn from(turbowarp_config: &TurbowarpConfig) -> Self {
+        let default = TurbowarpConfig::default();
+        Self {
+            frame_rate: (turbowarp_config.frame_rate != default.frame_rate)
+                .then_some(turbowarp_config.frame_rate),
+            max_clones: (turbowarp_config.max_clones != default.max_clones)
+                .then_some(turbowarp_config.max_clones),
+            no_miscellaneous_limits: turbowarp_config.no_miscellaneous_limits.then_some(true),
+            no_sprite_fencing: turbowarp_config.no_sprite_fencing.then_some(true),
+            frame_interpolation: turbowarp_config.frame_interpolation.then_some(true),
+            high_quality_pen: turbowarp_config.high_quality_pen.then_some(true),
+            stage_width: (turbowarp_config.stage_width != default.stage_width)
+                .then_some(turbowarp_config.stage_width),
+            stage_height: (turbowarp_config.stage_height != default.stage_height)
+                .then_some(turbowarp_config.stage_height),
+            ..Default::default()
+        }
+    }
+}
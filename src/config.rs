@@ -1,8 +1,14 @@
+use rustc_hash::FxHashMap;
 use serde::{
     Deserialize,
     Serialize,
 };
 
+use crate::{
+    diagnostic::LintLevel,
+    misc::SmolStr,
+};
+
 #[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub struct Config {
     #[serde(default)]
@@ -31,4 +37,90 @@ pub struct Config {
     pub stage_width: Option<u64>,
     #[serde(default)]
     pub stage_height: Option<u64>,
+    /// Procedures and functions without loops, of at most this many statements, are
+    /// inlined even if they are not declared with `inline`.
+    #[serde(default)]
+    pub inline_threshold: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<Extension>,
+    /// Levels of lints, by lint name or `warnings` for every lint.
+    #[serde(default, skip_serializing_if = "FxHashMap::is_empty")]
+    pub lints: FxHashMap<SmolStr, LintLevel>,
+}
+
+/// A Scratch or TurboWarp extension, and the blocks of it that can be used.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Extension {
+    /// The ID of the extension, such as `text2speech`.
+    pub id: SmolStr,
+    /// Where to load the extension from, for extensions that are not built into
+    /// TurboWarp.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub blocks: Vec<ExtensionBlock>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct ExtensionBlock {
+    /// The name of the block in goboscript.
+    pub name: SmolStr,
+    /// The opcode of the block, such as `text2speech_speakAndWait`.
+    pub opcode: SmolStr,
+    #[serde(default)]
+    pub shape: ExtensionBlockShape,
+    #[serde(default)]
+    pub args: Vec<ExtensionArg>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtensionBlockShape {
+    #[default]
+    Command,
+    Reporter,
+    Boolean,
+}
+
+/// An argument of an extension block. It is either just the name of its input, or a
+/// table, for boolean inputs, inputs with a menu, and fields.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum ExtensionArg {
+    Input(SmolStr),
+    Table {
+        name: SmolStr,
+        #[serde(default)]
+        boolean: bool,
+        /// The name of the menu in the extension, such as `voices`.
+        #[serde(default)]
+        menu: Option<SmolStr>,
+        /// Fields only accept strings, and not reporters.
+        #[serde(default)]
+        field: bool,
+        /// The value of the menu when a reporter is put in it.
+        #[serde(default)]
+        default: Option<SmolStr>,
+    },
+}
+
+impl Config {
+    /// The extension block with this name in goboscript.
+    pub fn extension_block(&self, name: &str) -> Option<(&Extension, &ExtensionBlock)> {
+        self.extensions.iter().find_map(|extension| {
+            extension
+                .blocks
+                .iter()
+                .find(|block| block.name == name)
+                .map(|block| (extension, block))
+        })
+    }
+}
+
+impl ExtensionArg {
+    pub fn name(&self) -> &SmolStr {
+        match self {
+            ExtensionArg::Input(name) | ExtensionArg::Table { name, .. } => name,
+        }
+    }
 }
@@ -0,0 +1,1294 @@
+use std::fmt::Write;
+
+use anyhow::{
+    bail,
+    Context,
+};
+use logos::Logos;
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
+use serde_json::{
+    Map,
+    Value as Json,
+};
+
+use crate::{
+    ast::Value,
+    blocks::{
+        BinOp,
+        Block,
+        Menu,
+        Repr,
+        UnOp,
+    },
+    codegen::{
+        costumes::BITMAP_FORMATS,
+        turbowarp_config::TurbowarpConfig,
+    },
+    config::Config,
+    lexer::token::Token,
+};
+
+const UN_OPS: &[UnOp] = &[
+    UnOp::Not,
+    UnOp::Length,
+    UnOp::Round,
+    UnOp::Abs,
+    UnOp::Floor,
+    UnOp::Ceil,
+    UnOp::Sqrt,
+    UnOp::Sin,
+    UnOp::Cos,
+    UnOp::Tan,
+    UnOp::Asin,
+    UnOp::Acos,
+    UnOp::Atan,
+    UnOp::Ln,
+    UnOp::Log,
+    UnOp::AntiLn,
+    UnOp::AntiLog,
+];
+
+const BIN_OPS: &[BinOp] = &[
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Mod,
+    BinOp::Lt,
+    BinOp::Gt,
+    BinOp::Eq,
+    BinOp::And,
+    BinOp::Or,
+    BinOp::Join,
+    BinOp::In,
+    BinOp::Of,
+];
+
+/// Precedence of an expression that can be used anywhere without parentheses.
+const TERM: u8 = 1;
+/// Precedence of prefix operators, such as `not` and `-`.
+const UNARY: u8 = 2;
+
+#[derive(Debug)]
+pub struct Decompiled {
+    pub config: Config,
+    /// The stage is always first, followed by the sprites in layer order.
+    pub sprites: Vec<DecompiledSprite>,
+    /// Pairs of `md5ext` inside the `.sb3` and the path to write it to.
+    pub assets: Vec<(String, String)>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct DecompiledSprite {
+    pub name: String,
+    pub source: String,
+}
+
+/// Turn the `project.json` of a `.sb3` back into goboscript source code.
+pub fn decompile(project: &Json) -> anyhow::Result<Decompiled> {
+    let targets = project["targets"]
+        .as_array()
+        .context("project.json does not have any targets")?;
+    let Some(stage) = targets.iter().find(|target| target["isStage"] == true) else {
+        bail!("project.json does not have a stage");
+    };
+    let mut sprites: Vec<&Json> = targets
+        .iter()
+        .filter(|target| target["isStage"] != true)
+        .collect();
+    sprites.sort_by_key(|sprite| sprite["layerOrder"].as_u64().unwrap_or_default());
+
+    let mut decompiled = Decompiled {
+        config: stage["comments"]
+            .as_object()
+            .into_iter()
+            .flat_map(|comments| comments.values())
+            .find_map(|comment| TurbowarpConfig::from_comment(comment["text"].as_str()?))
+            .map(|turbowarp_config| Config::from(&turbowarp_config))
+            .unwrap_or_default(),
+        sprites: vec![],
+        assets: vec![],
+        warnings: vec![],
+    };
+    let mut assets = Assets::default();
+
+    let mut file_names = Namespace::default();
+    file_names.claim("stage");
+    let sprite_names: Vec<String> = sprites
+        .iter()
+        .map(|sprite| {
+            file_names.claim_with(&file_name(sprite["name"].as_str().unwrap_or("")), |_| true)
+        })
+        .collect();
+
+    let mut globals = Names::default();
+    globals.declare(stage);
+    let source =
+        Decompiler::new(stage, "stage", &globals, &mut decompiled.warnings).target(&mut assets);
+    decompiled.sprites.push(DecompiledSprite {
+        name: "stage".to_owned(),
+        source,
+    });
+    for (sprite, name) in sprites.iter().zip(&sprite_names) {
+        let mut names = globals.clone();
+        names.declare(sprite);
+        let source =
+            Decompiler::new(sprite, name, &names, &mut decompiled.warnings).target(&mut assets);
+        decompiled.sprites.push(DecompiledSprite {
+            name: name.clone(),
+            source,
+        });
+    }
+
+    let mut alphabetical = sprite_names.clone();
+    alphabetical.sort();
+    if alphabetical != sprite_names {
+        decompiled.config.layers = Some(sprite_names);
+    }
+    if assets.bitmap_resolution > 1 {
+        decompiled.config.bitmap_resolution = Some(assets.bitmap_resolution);
+    }
+    decompiled.assets = assets.files;
+    Ok(decompiled)
+}
+
+#[derive(Default)]
+struct Assets {
+    files: Vec<(String, String)>,
+    paths: FxHashMap<String, String>,
+    used: Namespace,
+    bitmap_resolution: u64,
+}
+
+impl Assets {
+    /// Returns the path that the asset will be written to, relative to the project.
+    fn add(&mut self, asset: &Json) -> Option<String> {
+        let md5ext = asset["md5ext"].as_str().map(str::to_owned).or_else(|| {
+            Some(format!(
+                "{}.{}",
+                asset["assetId"].as_str()?,
+                asset["dataFormat"].as_str()?
+            ))
+        })?;
+        let extension = md5ext.rsplit_once('.')?.1.to_owned();
+        if BITMAP_FORMATS.contains(&extension.as_str()) {
+            let bitmap_resolution = asset["bitmapResolution"].as_u64().unwrap_or(1);
+            self.bitmap_resolution = self.bitmap_resolution.max(bitmap_resolution);
+        }
+        if let Some(path) = self.paths.get(&md5ext) {
+            return Some(path.clone());
+        }
+        let stem = file_name(asset["name"].as_str().unwrap_or(""));
+        let stem = self
+            .used
+            .claim_with(&stem, |stem| !stem.eq_ignore_ascii_case("."));
+        let path = format!("assets/{stem}.{extension}");
+        self.paths.insert(md5ext.clone(), path.clone());
+        self.files.push((md5ext, path.clone()));
+        Some(path)
+    }
+}
+
+/// A set of names that are already taken, used to make generated names unique.
+#[derive(Debug, Default, Clone)]
+struct Namespace {
+    used: FxHashSet<String>,
+}
+
+impl Namespace {
+    fn claim(&mut self, name: &str) -> String {
+        self.claim_with(name, |_| true)
+    }
+
+    fn claim_with<F>(&mut self, name: &str, is_valid: F) -> String
+    where F: Fn(&str) -> bool {
+        let mut candidate = name.to_owned();
+        let mut i = 2;
+        while !is_valid(&candidate) || !self.used.insert(candidate.to_lowercase()) {
+            candidate = format!("{name}_{i}");
+            i += 1;
+        }
+        candidate
+    }
+}
+
+/// goboscript names for the variables and lists visible to a target.
+#[derive(Debug, Default, Clone)]
+struct Names {
+    namespace: Namespace,
+    by_id: FxHashMap<String, String>,
+    by_name: FxHashMap<String, String>,
+}
+
+impl Names {
+    fn declare(&mut self, target: &Json) {
+        for key in ["variables", "lists"] {
+            let Some(declarations) = target[key].as_object() else {
+                continue;
+            };
+            for (id, declaration) in declarations {
+                let Some(scratch_name) = declaration[0].as_str() else {
+                    continue;
+                };
+                let name = self
+                    .namespace
+                    .claim(&identifier(scratch_name.trim_start_matches("\u{2601} ")));
+                self.by_id.insert(id.clone(), name.clone());
+                self.by_name.insert(scratch_name.to_owned(), name);
+            }
+        }
+    }
+
+    fn get(&self, field: &Json) -> String {
+        field[1]
+            .as_str()
+            .and_then(|id| self.by_id.get(id))
+            .or_else(|| field[0].as_str().and_then(|name| self.by_name.get(name)))
+            .cloned()
+            .unwrap_or_else(|| identifier(field[0].as_str().unwrap_or("")))
+    }
+}
+
+#[derive(Debug)]
+struct Signature {
+    name: String,
+    warp: bool,
+    /// Pairs of Scratch argument ids and goboscript argument names.
+    args: Vec<(String, String)>,
+    /// Scratch argument names to goboscript argument names.
+    arg_names: FxHashMap<String, String>,
+}
+
+/// A rendered expression, along with the precedence of its outermost operator.
+struct Expr {
+    text: String,
+    precedence: u8,
+}
+
+impl Expr {
+    fn term(text: String) -> Self {
+        Self {
+            text,
+            precedence: TERM,
+        }
+    }
+
+    /// Wrap the expression in parentheses if it binds looser than `max_precedence`.
+    fn at_most(self, max_precedence: u8) -> String {
+        if self.precedence > max_precedence {
+            format!("({})", self.text)
+        } else {
+            self.text
+        }
+    }
+}
+
+struct Decompiler<'a> {
+    target_name: &'a str,
+    target: &'a Json,
+    blocks: Map<String, Json>,
+    names: &'a Names,
+    signatures: FxHashMap<String, Signature>,
+    /// The signature of the procedure being decompiled, used to resolve arguments.
+    proccode: Option<String>,
+    warnings: &'a mut Vec<String>,
+    src: String,
+    indent: usize,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(
+        target: &'a Json,
+        target_name: &'a str,
+        names: &'a Names,
+        warnings: &'a mut Vec<String>,
+    ) -> Self {
+        let blocks = target["blocks"].as_object().cloned().unwrap_or_default();
+        let mut proc_names = Namespace::default();
+        for name in Block::all_names() {
+            proc_names.claim(name);
+        }
+        let mut signatures = FxHashMap::default();
+        for block in blocks.values() {
+            if block["opcode"] != "procedures_prototype" {
+                continue;
+            }
+            let mutation = &block["mutation"];
+            let Some(proccode) = mutation["proccode"].as_str() else {
+                continue;
+            };
+            let name = proccode
+                .split_whitespace()
+                .filter(|word| !word.starts_with('%'))
+                .collect::<Vec<_>>()
+                .join("_");
+            let name = proc_names.claim(&identifier(&name));
+            let ids = json_list(&mutation["argumentids"]);
+            let scratch_names = json_list(&mutation["argumentnames"]);
+            let mut arg_names = Namespace::default();
+            let mut signature = Signature {
+                name,
+                warp: mutation["warp"] == true || mutation["warp"] == "true",
+                args: vec![],
+                arg_names: FxHashMap::default(),
+            };
+            for (id, scratch_name) in ids.into_iter().zip(scratch_names) {
+                let name = arg_names.claim(&identifier(&scratch_name));
+                signature.args.push((id, name.clone()));
+                signature.arg_names.insert(scratch_name, name);
+            }
+            signatures.insert(proccode.to_owned(), signature);
+        }
+        Self {
+            target_name,
+            target,
+            blocks,
+            names,
+            signatures,
+            proccode: None,
+            warnings,
+            src: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn warn(&mut self, message: String) {
+        self.warnings
+            .push(format!("{}: {}", self.target_name, message));
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.src.push_str("    ");
+        }
+        self.src.push_str(line);
+        self.src.push('\n');
+    }
+
+    fn block(&self, id: &str) -> Option<&Json> {
+        self.blocks.get(id).filter(|block| block.is_object())
+    }
+
+    fn target(mut self, assets: &mut Assets) -> String {
+        let target = self.target;
+        let is_stage = target["isStage"] == true;
+        for (key, keyword) in [("costumes", "costumes"), ("sounds", "sounds")] {
+            let mut declarations = vec![];
+            for asset in target[key].as_array().into_iter().flatten() {
+                let Some(path) = assets.add(asset) else {
+                    continue;
+                };
+                let name = asset["name"].as_str().unwrap_or("");
+                if std::path::Path::new(&path)
+                    .file_stem()
+                    .is_some_and(|stem| stem == name)
+                {
+                    declarations.push(string(&path));
+                } else {
+                    declarations.push(format!("{} as {}", string(&path), string(name)));
+                }
+            }
+            if !declarations.is_empty() {
+                let separator = format!(",\n{}", " ".repeat(keyword.len() + 1));
+                self.line(&format!("{keyword} {};", declarations.join(&separator)));
+            }
+        }
+        if !is_stage {
+            let mut properties = vec![];
+            for (key, keyword, default) in [
+                ("x", "set_x", 0.0),
+                ("y", "set_y", 0.0),
+                ("size", "set_size", 100.0),
+                ("direction", "point_in_direction", 90.0),
+                ("volume", "set_volume", 100.0),
+            ] {
+                if let Some(value) = target[key].as_f64().filter(|value| *value != default) {
+                    properties.push(format!("{keyword} {};", number(value).text));
+                }
+            }
+            match target["rotationStyle"].as_str() {
+                Some("left-right") => properties.push("set_rotation_style_left_right;".into()),
+                Some("don't rotate") => properties.push("set_rotation_style_do_not_rotate;".into()),
+                _ => {}
+            }
+            if target["visible"] == false {
+                properties.push("hide;".into());
+            }
+            for property in properties {
+                self.line(&property);
+            }
+        }
+        self.declarations();
+        let mut top_level: Vec<Json> = self
+            .blocks
+            .values()
+            .filter(|block| block["topLevel"] == true)
+            .cloned()
+            .collect();
+        // Procedure definitions come first, so that scripts read top-down.
+        top_level.sort_by_key(|block| block["opcode"] != "procedures_definition");
+        let mut loose = 0;
+        for block in top_level {
+            if !self.script(&block) {
+                loose += 1;
+            }
+        }
+        if loose > 0 {
+            self.warn(format!(
+                "skipped {loose} script(s) that do not start with a supported hat block"
+            ));
+        }
+        self.src
+    }
+
+    fn declarations(&mut self) {
+        let target = self.target;
+        let mut lines = vec![];
+        for (id, variable) in target["variables"].as_object().into_iter().flatten() {
+            let name = self
+                .names
+                .get(&Json::from(vec![variable[0].clone(), id.clone().into()]));
+            if variable.get(2) == Some(&Json::Bool(true)) {
+                lines.push(format!("cloud {name};"));
+            } else {
+                lines.push(format!("var {name} = {};", literal(&variable[1]).text));
+            }
+        }
+        for (id, list) in target["lists"].as_object().into_iter().flatten() {
+            let name = self
+                .names
+                .get(&Json::from(vec![list[0].clone(), id.clone().into()]));
+            let items: Vec<String> = list[1]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|item| literal(item).text)
+                .collect();
+            if items.is_empty() {
+                lines.push(format!("list {name};"));
+            } else {
+                lines.push(format!("list {name} = [{}];", items.join(", ")));
+            }
+        }
+        if !lines.is_empty() {
+            self.src.push('\n');
+        }
+        for line in lines {
+            self.line(&line);
+        }
+    }
+
+    /// Returns `false` if the script was skipped.
+    fn script(&mut self, block: &Json) -> bool {
+        let opcode = block["opcode"].as_str().unwrap_or("");
+        let header = match opcode {
+            "procedures_definition" => {
+                let Some(prototype) = block["inputs"]["custom_block"][1]
+                    .as_str()
+                    .and_then(|id| self.block(id))
+                else {
+                    return false;
+                };
+                let proccode = prototype["mutation"]["proccode"].as_str().unwrap_or("");
+                let Some(signature) = self.signatures.get(proccode) else {
+                    return false;
+                };
+                let mut header = String::new();
+                if !signature.warp {
+                    header.push_str("nowarp ");
+                }
+                write!(header, "proc {}", signature.name).unwrap();
+                let args: Vec<&str> = signature
+                    .args
+                    .iter()
+                    .map(|(_, name)| name.as_str())
+                    .collect();
+                if !args.is_empty() {
+                    write!(header, " {}", args.join(", ")).unwrap();
+                }
+                self.proccode = Some(proccode.to_owned());
+                header
+            }
+            "event_whenflagclicked" => "onflag".into(),
+            "event_whenkeypressed" => format!("onkey {}", string(&field(block, "KEY_OPTION"))),
+            "event_whenthisspriteclicked" | "event_whenstageclicked" => "onclick".into(),
+            "event_whenbackdropswitchesto" => {
+                format!("onbackdrop {}", string(&field(block, "BACKDROP")))
+            }
+            "event_whenbroadcastreceived" => {
+                format!("on {}", string(&field(block, "BROADCAST_OPTION")))
+            }
+            "event_whengreaterthan" => {
+                let value = self.input(block, "VALUE").text;
+                match field(block, "WHENGREATERTHANMENU").as_str() {
+                    "TIMER" => format!("ontimer > {value}"),
+                    _ => format!("onloudness > {value}"),
+                }
+            }
+            "videoSensing_whenMotionGreaterThan" => {
+                format!("onvideomotion > {}", self.input(block, "REFERENCE").text)
+            }
+            "makeymakey_whenMakeyKeyPressed" => {
+                let key = self
+                    .menu_value(
+                        block,
+                        &Menu {
+                            input: "KEY",
+                            opcode: "makeymakey_menu_KEY",
+                            default: "SPACE",
+                            field: "KEY",
+                        },
+                    )
+                    .unwrap_or_else(|| "SPACE".into());
+                let key = match key.as_str() {
+                    "SPACE" => "space",
+                    "UP" => "up arrow",
+                    "DOWN" => "down arrow",
+                    "LEFT" => "left arrow",
+                    "RIGHT" => "right arrow",
+                    key => key,
+                };
+                format!("onmakeykey {}", string(key))
+            }
+            "control_start_as_clone" => "onclone".into(),
+            _ => {
+                if !opcode.starts_with("procedures_") && block["shadow"] != true {
+                    let next = block["next"].as_str().is_some();
+                    if next || is_hat(opcode) {
+                        self.warn(format!("unsupported hat block `{opcode}`"));
+                    }
+                }
+                return false;
+            }
+        };
+        self.src.push('\n');
+        self.line(&format!("{header} {{"));
+        self.indent += 1;
+        let next = block["next"].as_str().map(str::to_owned);
+        self.stmts(next.as_deref());
+        self.indent -= 1;
+        self.line("}");
+        self.proccode = None;
+        true
+    }
+
+    fn stmts(&mut self, id: Option<&str>) {
+        let mut ids = vec![];
+        let mut id = id.map(str::to_owned);
+        while let Some(block) = id.as_deref().and_then(|id| self.block(id)) {
+            let next = block["next"].as_str().map(str::to_owned);
+            ids.push(id.unwrap());
+            id = next;
+        }
+        for id in ids {
+            let block = self.blocks[&id].clone();
+            self.stmt(&block);
+        }
+    }
+
+    fn substack(&mut self, block: &Json, name: &str) {
+        let id = block["inputs"][name][1].as_str().map(str::to_owned);
+        self.indent += 1;
+        self.stmts(id.as_deref());
+        self.indent -= 1;
+    }
+
+    fn stmt(&mut self, block: &Json) {
+        let opcode = block["opcode"].as_str().unwrap_or("");
+        match opcode {
+            "control_repeat" => {
+                let times = self.input(block, "TIMES").text;
+                self.line(&format!("repeat {times} {{"));
+                self.substack(block, "SUBSTACK");
+                self.line("}");
+            }
+            "control_forever" => {
+                self.line("forever {");
+                self.substack(block, "SUBSTACK");
+                self.line("}");
+            }
+            "control_repeat_until" | "control_wait_until" => {
+                let cond = self.condition(block).text;
+                self.line(&format!("until {cond} {{"));
+                self.substack(block, "SUBSTACK");
+                self.line("}");
+            }
+            "control_while" => {
+                let cond = self.condition(block).at_most(UNARY);
+                self.line(&format!("until not {cond} {{"));
+                self.substack(block, "SUBSTACK");
+                self.line("}");
+            }
+            "control_if" | "control_if_else" => self.branch(block, "if"),
+            "data_setvariableto" => {
+                let name = self.names.get(&block["fields"]["VARIABLE"]);
+                let value = self.input(block, "VALUE").text;
+                self.line(&format!("{name} = {value};"));
+            }
+            "data_changevariableby" => {
+                let name = self.names.get(&block["fields"]["VARIABLE"]);
+                let value = self.input(block, "VALUE").text;
+                self.line(&format!("{name} += {value};"));
+            }
+            "data_showvariable" | "data_hidevariable" => {
+                let name = self.names.get(&block["fields"]["VARIABLE"]);
+                let keyword = &opcode["data_".len().."data_".len() + 4];
+                self.line(&format!("{keyword} {name};"));
+            }
+            "data_showlist" | "data_hidelist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                let keyword = &opcode["data_".len().."data_".len() + 4];
+                self.line(&format!("{keyword} {name};"));
+            }
+            "data_addtolist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                let item = self.input(block, "ITEM").text;
+                self.line(&format!("add {item} to {name};"));
+            }
+            "data_deleteoflist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                let index = self.input(block, "INDEX").text;
+                self.line(&format!("delete {name}[{index}];"));
+            }
+            "data_deletealloflist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                self.line(&format!("delete {name};"));
+            }
+            "data_insertatlist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                let item = self.input(block, "ITEM").text;
+                let index = self.input(block, "INDEX").text;
+                self.line(&format!("insert {item} at {name}[{index}];"));
+            }
+            "data_replaceitemoflist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                let index = self.input(block, "INDEX").text;
+                let item = self.input(block, "ITEM").text;
+                self.line(&format!("{name}[{index}] = {item};"));
+            }
+            "procedures_call" => {
+                let proccode = block["mutation"]["proccode"].as_str().unwrap_or("");
+                let Some(signature) = self.signatures.get(proccode) else {
+                    self.warn(format!("call to undefined procedure `{proccode}`"));
+                    self.line(&format!(
+                        "# call to undefined procedure {}",
+                        string(proccode)
+                    ));
+                    return;
+                };
+                let name = signature.name.clone();
+                let ids: Vec<String> = signature.args.iter().map(|(id, _)| id.clone()).collect();
+                let args: Vec<String> = ids.iter().map(|id| self.input(block, id).text).collect();
+                self.call(&name, &args);
+            }
+            _ => match self.shape::<Block>(block) {
+                Some((shape, args)) => self.call(shape.name(), &args),
+                None => {
+                    self.warn(format!("unsupported block `{opcode}`"));
+                    self.line(&format!("# unsupported block: {opcode}"));
+                }
+            },
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[String]) {
+        if args.is_empty() {
+            self.line(&format!("{name};"));
+        } else {
+            self.line(&format!("{name} {};", args.join(", ")));
+        }
+    }
+
+    fn branch(&mut self, block: &Json, keyword: &str) {
+        let cond = self.condition(block).text;
+        self.line(&format!("{keyword} {cond} {{"));
+        self.substack(block, "SUBSTACK");
+        if block["opcode"] != "control_if_else" {
+            self.line("}");
+            return;
+        }
+        // An else branch that only contains another if becomes an elif.
+        let else_block = block["inputs"]["SUBSTACK2"][1]
+            .as_str()
+            .and_then(|id| self.block(id))
+            .cloned();
+        match else_block {
+            None => self.line("}"),
+            Some(else_block)
+                if matches!(
+                    else_block["opcode"].as_str(),
+                    Some("control_if" | "control_if_else")
+                ) && else_block["next"].is_null() =>
+            {
+                self.branch(&else_block, "} elif");
+            }
+            Some(_) => {
+                self.line("} else {");
+                self.substack(block, "SUBSTACK2");
+                self.line("}");
+            }
+        }
+    }
+
+    fn condition(&mut self, block: &Json) -> Expr {
+        if block["inputs"]["CONDITION"][1].is_null() {
+            return Expr::term("false".into());
+        }
+        self.input(block, "CONDITION")
+    }
+
+    /// Find the goboscript block whose opcode, fields and menu match a Scratch block,
+    /// and render its arguments.
+    fn shape<T: Shape>(&mut self, block: &Json) -> Option<(T, Vec<String>)> {
+        let opcode = block["opcode"].as_str()?;
+        let candidates: Vec<T> = T::all()
+            .into_iter()
+            .filter(|shape| shape.opcode() == opcode && fields_match(shape.fields(), block))
+            .collect();
+        // Prefer blocks which have the menu value baked in, such as `goto_mouse_pointer`,
+        // over the general form that takes the menu value as an argument.
+        let shape = candidates
+            .iter()
+            .find(|shape| {
+                shape.menu().is_some_and(|menu| {
+                    !shape.args().contains(&menu.input)
+                        && self.menu_value(block, &menu).as_deref() == Some(menu.default)
+                })
+            })
+            .or_else(|| {
+                candidates.iter().find(|shape| {
+                    shape
+                        .menu()
+                        .is_none_or(|menu| shape.args().contains(&menu.input))
+                })
+            })
+            .copied()?;
+        let menu = shape.menu();
+        let args = shape
+            .args()
+            .iter()
+            .map(|arg| match &menu {
+                Some(menu) if menu.input == *arg => match self.menu_value(block, menu) {
+                    Some(value) => string(&value),
+                    None => self.input(block, arg).text,
+                },
+                _ => self.input(block, arg).text,
+            })
+            .collect();
+        Some((shape, args))
+    }
+
+    /// The value of a menu input, if it has not been covered by a reporter.
+    fn menu_value(&self, block: &Json, menu: &Menu) -> Option<String> {
+        let input = &block["inputs"][menu.input];
+        let menu_block = self.block(input[1].as_str()?)?;
+        if menu_block["opcode"] != menu.opcode {
+            return None;
+        }
+        Some(field(menu_block, menu.field))
+    }
+
+    fn input(&mut self, block: &Json, name: &str) -> Expr {
+        let input = &block["inputs"][name];
+        match &input[1] {
+            Json::String(id) => self.expr(&id.clone()),
+            Json::Array(primitive) => self.primitive(primitive),
+            _ => Expr::term("\"\"".into()),
+        }
+    }
+
+    fn primitive(&self, primitive: &[Json]) -> Expr {
+        match primitive.first().and_then(Json::as_u64) {
+            Some(4..=8) => match &primitive[1] {
+                Json::String(value) => match value.parse::<f64>() {
+                    Ok(parsed) if number(parsed).text == *value => number(parsed),
+                    _ => Expr::term(string(value)),
+                },
+                value => literal(value),
+            },
+            Some(12) | Some(13) => Expr::term(self.names.get(&Json::from(vec![
+                primitive[1].clone(),
+                primitive[2].clone(),
+            ]))),
+            Some(_) => Expr::term(string(&json_string(&primitive[1]))),
+            None => Expr::term("\"\"".into()),
+        }
+    }
+
+    fn expr(&mut self, id: &str) -> Expr {
+        let Some(block) = self.block(id).cloned() else {
+            return Expr::term("\"\"".into());
+        };
+        let opcode = block["opcode"].as_str().unwrap_or("");
+        match opcode {
+            "data_variable" => return Expr::term(self.names.get(&block["fields"]["VARIABLE"])),
+            "data_listcontents" => return Expr::term(self.names.get(&block["fields"]["LIST"])),
+            "argument_reporter_string_number" | "argument_reporter_boolean" => {
+                let scratch_name = field(&block, "VALUE");
+                let name = self
+                    .proccode
+                    .as_ref()
+                    .and_then(|proccode| self.signatures.get(proccode))
+                    .and_then(|signature| signature.arg_names.get(&scratch_name))
+                    .cloned()
+                    .unwrap_or_else(|| identifier(&scratch_name));
+                return Expr::term(format!("${name}"));
+            }
+            "data_itemoflist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                let index = self.input(&block, "INDEX").text;
+                return Expr::term(format!("{name}[{index}]"));
+            }
+            "data_lengthoflist" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                return Expr {
+                    text: format!("length {name}"),
+                    precedence: UNARY,
+                };
+            }
+            "data_itemnumoflist" | "data_listcontainsitem" => {
+                let name = self.names.get(&block["fields"]["LIST"]);
+                let precedence = BinOp::In.precedence();
+                let item = self.input(&block, "ITEM").at_most(precedence);
+                return Expr {
+                    text: format!("{item} in {name}"),
+                    precedence,
+                };
+            }
+            "sensing_of" => {
+                let object = match self.menu_value(
+                    &block,
+                    &Menu {
+                        input: "OBJECT",
+                        opcode: "sensing_of_object_menu",
+                        default: "_stage_",
+                        field: "OBJECT",
+                    },
+                ) {
+                    Some(object) if object == "_stage_" => string("Stage"),
+                    Some(object) => string(&object),
+                    None => self.input(&block, "OBJECT").at_most(TERM),
+                };
+                let property = field(&block, "PROPERTY");
+                return Expr::term(format!("{object}.{}", string(&property)));
+            }
+            _ => {}
+        }
+        if let Some(op) = UN_OPS
+            .iter()
+            .find(|op| op.opcode() == opcode && fields_match(op.fields(), &block))
+        {
+            let operand = self.input(&block, op.input()).at_most(UNARY);
+            return Expr {
+                text: format!("{} {operand}", op.symbol()),
+                precedence: UNARY,
+            };
+        }
+        if let Some(op) = BIN_OPS.iter().find(|op| op.opcode() == opcode) {
+            let precedence = op.precedence();
+            if let BinOp::Of = op {
+                let lhs = self.input(&block, op.lhs()).at_most(TERM);
+                let rhs = self.input(&block, op.rhs()).text;
+                return Expr::term(format!("{lhs}[{rhs}]"));
+            }
+            // `&` is the only right associative operator.
+            let (lhs_max, rhs_max) = if let BinOp::Join = op {
+                (precedence - 1, precedence)
+            } else {
+                (precedence, precedence - 1)
+            };
+            let lhs = self.boolean_input(&block, op, op.lhs()).at_most(lhs_max);
+            let rhs = self.boolean_input(&block, op, op.rhs()).at_most(rhs_max);
+            return Expr {
+                text: format!("{lhs} {} {rhs}", op.symbol()),
+                precedence,
+            };
+        }
+        if let Some((shape, args)) = self.shape::<Repr>(&block) {
+            return Expr::term(format!("{}({})", shape.name(), args.join(", ")));
+        }
+        if block["shadow"] == true {
+            // Menus that are used directly as an argument, such as `looks_costume`.
+            if let Some(value) = block["fields"]
+                .as_object()
+                .and_then(|fields| fields.values().next())
+            {
+                return Expr::term(string(&json_string(&value[0])));
+            }
+        }
+        self.warn(format!("unsupported reporter `{opcode}`"));
+        Expr::term("\"\"".into())
+    }
+
+    fn boolean_input(&mut self, block: &Json, op: &BinOp, name: &str) -> Expr {
+        if matches!(op, BinOp::And | BinOp::Or) && block["inputs"][name][1].is_null() {
+            return Expr::term("false".into());
+        }
+        self.input(block, name)
+    }
+}
+
+trait Shape: Copy + Sized {
+    fn all() -> Vec<Self>;
+    fn opcode(&self) -> &'static str;
+    fn args(&self) -> &'static [&'static str];
+    fn fields(&self) -> Option<&'static str>;
+    fn menu(&self) -> Option<Menu>;
+}
+
+macro_rules! impl_shape {
+    ($type:ty) => {
+        impl Shape for $type {
+            fn all() -> Vec<Self> {
+                Self::all_names()
+                    .iter()
+                    .flat_map(|name| match Self::overloads(name) {
+                        [] => Self::from_shape(name, 0).into_iter().collect(),
+                        overloads => overloads.to_vec(),
+                    })
+                    .collect()
+            }
+
+            fn opcode(&self) -> &'static str {
+                <$type>::opcode(self)
+            }
+
+            fn args(&self) -> &'static [&'static str] {
+                <$type>::args(self)
+            }
+
+            fn fields(&self) -> Option<&'static str> {
+                <$type>::fields(self)
+            }
+
+            fn menu(&self) -> Option<Menu> {
+                <$type>::menu(self)
+            }
+        }
+    };
+}
+
+impl_shape!(Block);
+impl_shape!(Repr);
+
+impl UnOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            UnOp::Not => "not",
+            UnOp::Length => "length",
+            UnOp::Round => "round",
+            UnOp::Abs => "abs",
+            UnOp::Floor => "floor",
+            UnOp::Ceil => "ceil",
+            UnOp::Sqrt => "sqrt",
+            UnOp::Sin => "sin",
+            UnOp::Cos => "cos",
+            UnOp::Tan => "tan",
+            UnOp::Asin => "asin",
+            UnOp::Acos => "acos",
+            UnOp::Atan => "atan",
+            UnOp::Ln => "ln",
+            UnOp::Log => "log",
+            UnOp::AntiLn => "antiln",
+            UnOp::AntiLog => "antilog",
+            UnOp::Minus => "-",
+        }
+    }
+}
+
+impl BinOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Eq => "==",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+            BinOp::Join => "&",
+            BinOp::In => "in",
+            BinOp::Of => "[]",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::Ne => "!=",
+            BinOp::FloorDiv => "//",
+        }
+    }
+
+    /// Mirrors the precedence levels of `BinExpr` in the grammar.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Of => 1,
+            BinOp::Mul | BinOp::Div | BinOp::FloorDiv | BinOp::Mod => 3,
+            BinOp::Add | BinOp::Sub => 4,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 5,
+            BinOp::Join => 6,
+            BinOp::In | BinOp::Eq | BinOp::Ne => 7,
+            BinOp::And => 8,
+            BinOp::Or => 9,
+        }
+    }
+}
+
+fn is_hat(opcode: &str) -> bool {
+    opcode.contains("_when") || opcode.ends_with("_hat")
+}
+
+fn fields_match(fields: Option<&str>, block: &Json) -> bool {
+    let expected: Map<String, Json> = fields
+        .and_then(|fields| serde_json::from_str(fields).ok())
+        .unwrap_or_default();
+    let actual = block["fields"].as_object().cloned().unwrap_or_default();
+    expected.len() == actual.len()
+        && expected
+            .iter()
+            .all(|(key, value)| actual.get(key).is_some_and(|field| field[0] == value[0]))
+}
+
+fn field(block: &Json, name: &str) -> String {
+    json_string(&block["fields"][name][0])
+}
+
+fn json_string(value: &Json) -> String {
+    match value {
+        Json::String(value) => value.clone(),
+        Json::Null => String::new(),
+        Json::Number(number) => Value::from(number.as_f64().unwrap_or_default())
+            .to_string()
+            .to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Parse a list that Scratch stores as a JSON encoded string inside a mutation.
+fn json_list(value: &Json) -> Vec<String> {
+    match value {
+        Json::String(value) => serde_json::from_str(value).unwrap_or_default(),
+        Json::Array(values) => values.iter().map(json_string).collect(),
+        _ => vec![],
+    }
+}
+
+fn string(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn number(value: f64) -> Expr {
+    if !value.is_finite() {
+        return Expr::term(string(&Value::from(value).to_string()));
+    }
+    let text = if value.fract() == 0.0 && value.abs() < 1e15 {
+        (value as i64).to_string()
+    } else if value.fract() == 0.0 {
+        return Expr::term(string(&Value::from(value).to_string()));
+    } else {
+        value.to_string()
+    };
+    if text.starts_with('-') {
+        Expr {
+            text,
+            precedence: UNARY,
+        }
+    } else {
+        Expr::term(text)
+    }
+}
+
+/// A number or string literal, as found in variable and list defaults.
+fn literal(value: &Json) -> Expr {
+    match value {
+        Json::Number(value) => number(value.as_f64().unwrap_or_default()),
+        value => Expr::term(string(&json_string(value))),
+    }
+}
+
+/// Turn a Scratch name into a valid goboscript identifier.
+pub fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    let mut lexer = Token::lexer(&identifier);
+    if !matches!(lexer.next(), Some(Ok(Token::Name(_)))) || lexer.next().is_some() {
+        identifier.push('_');
+    }
+    identifier
+}
+
+/// Turn a Scratch sprite or asset name into a file name.
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim().trim_start_matches('.').to_owned();
+    if name.is_empty() {
+        "_".to_owned()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        path::PathBuf,
+        rc::Rc,
+    };
+
+    use base64::Engine;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        parser,
+        translation_unit::TranslationUnit,
+        vfs::MemFS,
+    };
+
+    fn parses(source: &str) -> bool {
+        let fs: MemFS = serde_json::from_value(json!({
+            "files": {
+                "main.gs": {
+                    "inner": base64::engine::general_purpose::STANDARD.encode(source),
+                },
+            },
+        }))
+        .unwrap();
+        let unit =
+            TranslationUnit::new(Rc::new(RefCell::new(fs)), PathBuf::from("main.gs")).unwrap();
+        parser::parse(&unit).1.is_empty()
+    }
+
+    fn project() -> Json {
+        json!({
+            "targets": [
+                {
+                    "isStage": true,
+                    "name": "Stage",
+                    "variables": {"v1": ["my score", 0]},
+                    "lists": {},
+                    "comments": {
+                        "twconfig": {
+                            "text": TurbowarpConfig {
+                                frame_rate: 60,
+                                max_clones: f64::INFINITY,
+                                ..Default::default()
+                            }
+                            .to_string(),
+                        },
+                    },
+                    "blocks": {},
+                    "costumes": [
+                        {"name": "backdrop1", "md5ext": "aaa.svg", "dataFormat": "svg"},
+                    ],
+                    "sounds": [],
+                },
+                {
+                    "isStage": false,
+                    "name": "Sprite1",
+                    "variables": {},
+                    "lists": {"l1": ["items", [1, "two", -3.5]]},
+                    "blocks": {
+                        "hat": {"opcode": "event_whenflagclicked", "next": "goto", "topLevel": true, "inputs": {}, "fields": {}},
+                        "goto": {"opcode": "motion_goto", "next": "if", "inputs": {"TO": [1, "menu"]}, "fields": {}},
+                        "menu": {"opcode": "motion_goto_menu", "shadow": true, "inputs": {}, "fields": {"TO": ["_mouse_", null]}},
+                        "if": {"opcode": "control_if_else", "next": "call", "inputs": {
+                            "CONDITION": [2, "lt"],
+                            "SUBSTACK": [2, "say"],
+                            "SUBSTACK2": [2, "set"],
+                        }, "fields": {}},
+                        "lt": {"opcode": "operator_lt", "inputs": {
+                            "OPERAND1": [3, [12, "my score", "v1"], [10, ""]],
+                            "OPERAND2": [3, "add", [10, ""]],
+                        }, "fields": {}},
+                        "add": {"opcode": "operator_add", "inputs": {
+                            "NUM1": [1, [4, "1"]],
+                            "NUM2": [1, [4, "2"]],
+                        }, "fields": {}},
+                        "say": {"opcode": "looks_say", "inputs": {"MESSAGE": [1, [10, "hi \"there\""]]}, "fields": {}},
+                        "set": {"opcode": "data_setvariableto", "inputs": {"VALUE": [1, [10, "10"]]}, "fields": {"VARIABLE": ["my score", "v1"]}},
+                        "call": {"opcode": "procedures_call", "inputs": {"a1": [1, [10, "x"]]}, "fields": {}, "mutation": {"proccode": "jump %s", "argumentids": "[\"a1\"]"}},
+                        "def": {"opcode": "procedures_definition", "topLevel": true, "next": "change", "inputs": {"custom_block": [1, "proto"]}, "fields": {}},
+                        "proto": {"opcode": "procedures_prototype", "shadow": true, "inputs": {}, "fields": {}, "mutation": {
+                            "proccode": "jump %s", "argumentids": "[\"a1\"]", "argumentnames": "[\"height\"]", "warp": "false",
+                        }},
+                        "change": {"opcode": "motion_changeyby", "inputs": {"DY": [3, "arg", [4, "10"]]}, "fields": {}},
+                        "arg": {"opcode": "argument_reporter_string_number", "inputs": {}, "fields": {"VALUE": ["height", null]}},
+                    },
+                    "costumes": [
+                        {"name": "costume1", "md5ext": "bbb.png", "dataFormat": "png", "bitmapResolution": 2},
+                    ],
+                    "sounds": [{"name": "pop", "md5ext": "ccc.wav", "dataFormat": "wav"}],
+                    "x": 10,
+                    "visible": false,
+                    "layerOrder": 1,
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn decompiled_project_parses() {
+        let decompiled = decompile(&project()).unwrap();
+        assert!(decompiled.warnings.is_empty(), "{:?}", decompiled.warnings);
+        for sprite in &decompiled.sprites {
+            assert!(parses(&sprite.source), "{}", sprite.source);
+        }
+        let sprite = &decompiled.sprites[1].source;
+        assert!(sprite.contains("nowarp proc jump height {"));
+        assert!(sprite.contains("change_y $height;"));
+        assert!(sprite.contains("goto_mouse_pointer;"));
+        assert!(sprite.contains("if my_score < 1 + 2 {"));
+        assert!(sprite.contains(r#"say "hi \"there\"";"#));
+        assert!(sprite.contains(r#"my_score = "10";"#));
+        assert!(sprite.contains(r#"jump "x";"#));
+        assert!(sprite.contains(r#"list items = [1, "two", -3.5];"#));
+        assert!(sprite.contains("set_x 10;"));
+        assert!(sprite.contains("hide;"));
+        assert!(decompiled.sprites[0].source.contains("var my_score = 0;"));
+    }
+
+    #[test]
+    fn turbowarp_settings_and_assets_are_recovered() {
+        let decompiled = decompile(&project()).unwrap();
+        assert_eq!(decompiled.config.frame_rate, Some(60));
+        assert_eq!(decompiled.config.max_clones, Some(f64::INFINITY));
+        assert_eq!(decompiled.config.stage_width, None);
+        assert_eq!(decompiled.config.bitmap_resolution, Some(2));
+        assert_eq!(
+            decompiled.assets,
+            vec![
+                ("aaa.svg".to_owned(), "assets/backdrop1.svg".to_owned()),
+                ("bbb.png".to_owned(), "assets/costume1.png".to_owned()),
+                ("ccc.wav".to_owned(), "assets/pop.wav".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers_avoid_keywords() {
+        assert_eq!(identifier("my var"), "my_var");
+        assert_eq!(identifier("2d"), "_2d");
+        assert_eq!(identifier("repeat"), "repeat_");
+        assert_eq!(identifier(""), "_");
+    }
+}
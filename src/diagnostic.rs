@@ -1,12 +1,16 @@
 mod diagnostic_kind;
 pub mod keys;
+mod lint;
 mod project_diagnostics;
+mod report;
 mod sprite_diagnostics;
 
 pub use diagnostic_kind::*;
 use lalrpop_util::ParseError;
+pub use lint::*;
 use logos::Span;
 pub use project_diagnostics::*;
+pub use report::*;
 use serde::{
     Deserialize,
     Serialize,
@@ -20,6 +24,26 @@ use crate::lexer::token::Token;
 pub struct Diagnostic {
     pub kind: DiagnosticKind,
     pub span: Span,
+    /// Other places in the code which the diagnostic is about, such as where a redefined
+    /// name was first defined. These may be in other included files.
+    #[serde(default)]
+    pub notes: Vec<Note>,
+}
+
+/// A labelled span which is shown alongside a diagnostic.
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+pub struct Note {
+    pub label: String,
+    pub span: Span,
+}
+
+impl Note {
+    pub fn new(label: impl ToString, span: &Span) -> Self {
+        Self {
+            label: label.to_string(),
+            span: span.clone(),
+        }
+    }
 }
 
 impl From<ParseError<usize, Token, Diagnostic>> for Diagnostic {
@@ -28,10 +52,12 @@ impl From<ParseError<usize, Token, Diagnostic>> for Diagnostic {
             ParseError::InvalidToken { location } => Self {
                 kind: DiagnosticKind::InvalidToken,
                 span: location..location + 1,
+                notes: vec![],
             },
             ParseError::UnrecognizedEof { location, expected } => Self {
                 kind: DiagnosticKind::UnrecognizedEof(expected),
                 span: location..location + 1,
+                notes: vec![],
             },
             ParseError::UnrecognizedToken {
                 token: (left, token, right),
@@ -39,12 +65,14 @@ impl From<ParseError<usize, Token, Diagnostic>> for Diagnostic {
             } => Self {
                 kind: DiagnosticKind::UnrecognizedToken(token, expected),
                 span: left..right,
+                notes: vec![],
             },
             ParseError::ExtraToken {
                 token: (left, token, right),
             } => Self {
                 kind: DiagnosticKind::ExtraToken(token),
                 span: left..right,
+                notes: vec![],
             },
             ParseError::User { error } => error,
         }
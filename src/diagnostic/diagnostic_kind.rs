@@ -8,6 +8,7 @@ use tsify::Tsify;
 
 use crate::{
     ast::{
+        Kind,
         Sprite,
         Type,
     },
@@ -22,6 +23,7 @@ use crate::{
         },
         sounds::SOUND_FORMATS,
     },
+    diagnostic::LINTS,
     lexer::token::Token,
     misc::SmolStr,
 };
@@ -30,6 +32,7 @@ use crate::{
 pub enum DiagnosticKind {
     // Errors
     InvalidToken,
+    InvalidEscape(SmolStr),
     UnrecognizedEof(Vec<String>),
     UnrecognizedToken(Token, Vec<String>),
     ExtraToken(Token),
@@ -82,6 +85,12 @@ pub enum DiagnosticKind {
         expected: usize,
         given: usize,
     },
+    ExtensionArgsCountMismatch {
+        block: SmolStr,
+        expected: usize,
+        given: usize,
+    },
+    FieldNotLiteral(SmolStr),
     CommandFailed {
         stderr: Vec<u8>,
     },
@@ -114,8 +123,33 @@ pub enum DiagnosticKind {
         variant_name: SmolStr,
     },
     EmptyStruct(SmolStr),
-    LocalNotSupported,
+    RecursiveStruct(SmolStr),
+    DestructureCountMismatch {
+        expected: usize,
+        given: usize,
+    },
     UnknownDirective(SmolStr),
+    InvalidImport,
+    PrivateSymbol {
+        namespace: SmolStr,
+        name: SmolStr,
+    },
+    NamespaceClash(SmolStr),
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    MissingMatchArms {
+        enum_name: SmolStr,
+        variant_names: Vec<SmolStr>,
+    },
+    DuplicateMatchArm {
+        enum_name: SmolStr,
+        variant_name: SmolStr,
+    },
+    /// Reported by `goboscript test` and `goboscript run`, when the project is run.
+    AssertionFailed {
+        /// The values that `assert_eq` compared.
+        values: Option<(SmolStr, SmolStr)>,
+    },
     // Warnings
     FollowedByUnreachableCode,
     UnrecognizedKey(SmolStr),
@@ -127,6 +161,20 @@ pub enum DiagnosticKind {
     UnusedArg(SmolStr),
     UnusedStructField(SmolStr),
     FixedLengthListInvalid(f64),
+    /// A procedure or function declared with `inline` is called normally instead.
+    CannotInline {
+        name: SmolStr,
+        reason: SmolStr,
+    },
+    /// A procedure or function reaches itself but is not declared with `recursive`.
+    UnsafeRecursion(SmolStr),
+    /// A value is given to an input which takes another kind of value.
+    KindMismatch {
+        expected: Kind,
+        found: Kind,
+    },
+    /// A lint attribute names a lint which does not exist.
+    UnknownLint(SmolStr),
 }
 
 impl DiagnosticKind {
@@ -140,6 +188,9 @@ impl DiagnosticKind {
     pub fn to_string(&self, sprite: &Sprite) -> String {
         match self {
             DiagnosticKind::InvalidToken => "invalid token".to_string(),
+            DiagnosticKind::InvalidEscape(escape) => {
+                format!("invalid escape sequence `{escape}`")
+            }
             DiagnosticKind::UnrecognizedEof(expected) => {
                 format!(
                     "unrecognized end of file, expected one of {}",
@@ -225,6 +276,14 @@ impl DiagnosticKind {
                     given
                 )
             }
+            DiagnosticKind::ExtensionArgsCountMismatch {
+                block,
+                expected,
+                given,
+            } => format!("block {block} expects {expected} arguments, but {given} were given"),
+            DiagnosticKind::FieldNotLiteral(field) => {
+                format!("{field} is a field, so it only accepts a string or a number")
+            }
             DiagnosticKind::MacroArgsCountMismatch { expected, given } => {
                 format!(
                     "macro expects {} arguments, but {} were given",
@@ -256,8 +315,33 @@ impl DiagnosticKind {
             DiagnosticKind::UnknownDirective(name) => {
                 format!("unknown pre-processor directive `%{}`", name)
             }
-            DiagnosticKind::LocalNotSupported => {
-                "local variables are not supported here".to_string()
+            DiagnosticKind::InvalidImport => "invalid import".to_string(),
+            DiagnosticKind::PrivateSymbol { namespace, name } => {
+                format!("{name} is private to module {namespace}")
+            }
+            DiagnosticKind::NamespaceClash(name) => {
+                format!("{name} is the name of both a module and a variable")
+            }
+            DiagnosticKind::BreakOutsideLoop => "`break` outside of a loop".to_string(),
+            DiagnosticKind::ContinueOutsideLoop => "`continue` outside of a loop".to_string(),
+            DiagnosticKind::MissingMatchArms {
+                enum_name,
+                variant_names,
+            } => format!(
+                "match does not handle {}",
+                variant_names
+                    .iter()
+                    .map(|variant_name| format!("`{enum_name}.{variant_name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            DiagnosticKind::DuplicateMatchArm {
+                enum_name,
+                variant_name,
+            } => format!("`{enum_name}.{variant_name}` is already handled by another arm"),
+            DiagnosticKind::AssertionFailed { values: None } => "assertion failed".to_string(),
+            DiagnosticKind::AssertionFailed { values: Some(_) } => {
+                "assertion `left == right` failed".to_string()
             }
             DiagnosticKind::FollowedByUnreachableCode => "followed by unreachable code".to_string(),
             DiagnosticKind::UnusedVariable(name) => format!("unused variable {name}"),
@@ -266,6 +350,16 @@ impl DiagnosticKind {
             DiagnosticKind::UnusedProc(name) => format!("unused procedure {name}"),
             DiagnosticKind::UnusedFunc(name) => format!("unused function {name}"),
             DiagnosticKind::UnusedArg(name) => format!("unused argument {name}"),
+            DiagnosticKind::CannotInline { name, reason } => {
+                format!("cannot inline {name}, because {reason}")
+            }
+            DiagnosticKind::UnsafeRecursion(name) => {
+                format!("{name} calls itself, so its local variables can be overwritten")
+            }
+            DiagnosticKind::KindMismatch { expected, found } => {
+                format!("expected {expected}, found {found}")
+            }
+            DiagnosticKind::UnknownLint(name) => format!("unknown lint `{name}`"),
             DiagnosticKind::UnusedStructField(name) => {
                 format!("unused struct field {name} (never read)")
             }
@@ -298,6 +392,12 @@ impl DiagnosticKind {
                 format!("duplicate variant {variant_name} in enum {enum_name}")
             }
             DiagnosticKind::EmptyStruct(name) => format!("struct {name} is empty"),
+            DiagnosticKind::RecursiveStruct(name) => {
+                format!("struct {name} contains itself")
+            }
+            DiagnosticKind::DestructureCountMismatch { expected, given } => {
+                format!("cannot destructure {expected} values into {given} names")
+            }
             DiagnosticKind::FixedLengthListInvalid(value) => {
                 if *value < 0_f64 {
                     return format!("list length cannot be negative");
@@ -414,7 +514,23 @@ impl DiagnosticKind {
                     None
                 }
             }
+            DiagnosticKind::InvalidImport => {
+                Some("imports are written as `import \"path\" as name`".to_owned())
+            }
+            DiagnosticKind::NamespaceClash(_) => {
+                Some("import the module as another name, or rename the variable".to_owned())
+            }
+            DiagnosticKind::MissingMatchArms { .. } => {
+                Some("add an arm for each of them, or a `_` arm to handle the rest".to_owned())
+            }
+            DiagnosticKind::AssertionFailed {
+                values: Some((left, right)),
+            } => Some(format!("left is `{left}`, right is `{right}`")),
             DiagnosticKind::IOError { help, .. } => help.clone(),
+            DiagnosticKind::UnknownLint(name) => Self::suggestion_help(name, LINTS),
+            DiagnosticKind::UnsafeRecursion(name) => Some(format!(
+                "declare it as `recursive` to keep its variables on a stack across calls to {name}"
+            )),
             _ => None,
         }
     }
@@ -435,6 +551,84 @@ impl DiagnosticKind {
         }
     }
 
+    /// A stable name of the kind of diagnostic, such as `unused_variable`, used to
+    /// identify it in machine-readable output. The names of warnings are their lint names.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiagnosticKind::InvalidToken => "invalid_token",
+            DiagnosticKind::InvalidEscape(..) => "invalid_escape",
+            DiagnosticKind::UnrecognizedEof(..) => "unrecognized_eof",
+            DiagnosticKind::UnrecognizedToken(..) => "unrecognized_token",
+            DiagnosticKind::ExtraToken(..) => "extra_token",
+            DiagnosticKind::IOError { .. } => "io_error",
+            DiagnosticKind::UnrecognizedReporter(..) => "unrecognized_reporter",
+            DiagnosticKind::UnrecognizedBlock(..) => "unrecognized_block",
+            DiagnosticKind::UnrecognizedVariable(..) => "unrecognized_variable",
+            DiagnosticKind::UnrecognizedList(..) => "unrecognized_list",
+            DiagnosticKind::UnrecognizedEnum(..) => "unrecognized_enum",
+            DiagnosticKind::UnrecognizedStruct(..) => "unrecognized_struct",
+            DiagnosticKind::UnrecognizedProcedure(..) => "unrecognized_procedure",
+            DiagnosticKind::UnrecognizedFunction(..) => "unrecognized_function",
+            DiagnosticKind::UnrecognizedArgument(..) => "unrecognized_argument",
+            DiagnosticKind::UnrecognizedStructField(..) => "unrecognized_struct_field",
+            DiagnosticKind::UnrecognizedEnumVariant { .. } => "unrecognized_enum_variant",
+            DiagnosticKind::NoCostumes => "no_costumes",
+            DiagnosticKind::DuplicateCostume(..) => "duplicate_costume",
+            DiagnosticKind::DuplicateBackdrop(..) => "duplicate_backdrop",
+            DiagnosticKind::InvalidCostumeName(..) => "invalid_costume_name",
+            DiagnosticKind::InvalidBackdropName(..) => "invalid_backdrop_name",
+            DiagnosticKind::InvalidCostumeFormat { .. } => "invalid_costume_format",
+            DiagnosticKind::InvalidSoundFormat { .. } => "invalid_sound_format",
+            DiagnosticKind::BlockArgsCountMismatch { .. } => "block_args_count_mismatch",
+            DiagnosticKind::ReprArgsCountMismatch { .. } => "repr_args_count_mismatch",
+            DiagnosticKind::ProcArgsCountMismatch { .. } => "proc_args_count_mismatch",
+            DiagnosticKind::FuncArgsCountMismatch { .. } => "func_args_count_mismatch",
+            DiagnosticKind::MacroArgsCountMismatch { .. } => "macro_args_count_mismatch",
+            DiagnosticKind::ExtensionArgsCountMismatch { .. } => "extension_args_count_mismatch",
+            DiagnosticKind::FieldNotLiteral(..) => "field_not_literal",
+            DiagnosticKind::CommandFailed { .. } => "command_failed",
+            DiagnosticKind::VariableRedefinition(..) => "variable_redefinition",
+            DiagnosticKind::ListRedefinition(..) => "list_redefinition",
+            DiagnosticKind::StructRedefinition(..) => "struct_redefinition",
+            DiagnosticKind::EnumRedefinition(..) => "enum_redefinition",
+            DiagnosticKind::ProcedureRedefinition(..) => "procedure_redefinition",
+            DiagnosticKind::FunctionRedefinition(..) => "function_redefinition",
+            DiagnosticKind::TypeMismatch { .. } => "type_mismatch",
+            DiagnosticKind::NotStruct => "not_struct",
+            DiagnosticKind::InvalidDotLhs => "invalid_dot_lhs",
+            DiagnosticKind::StructDoesNotHaveField { .. } => "struct_does_not_have_field",
+            DiagnosticKind::MissingField { .. } => "missing_field",
+            DiagnosticKind::DuplicateField { .. } => "duplicate_field",
+            DiagnosticKind::DuplicateEnumVariant { .. } => "duplicate_enum_variant",
+            DiagnosticKind::EmptyStruct(..) => "empty_struct",
+            DiagnosticKind::RecursiveStruct(..) => "recursive_struct",
+            DiagnosticKind::DestructureCountMismatch { .. } => "destructure_count_mismatch",
+            DiagnosticKind::UnknownDirective(..) => "unknown_directive",
+            DiagnosticKind::InvalidImport => "invalid_import",
+            DiagnosticKind::PrivateSymbol { .. } => "private_symbol",
+            DiagnosticKind::NamespaceClash(_) => "namespace_clash",
+            DiagnosticKind::BreakOutsideLoop => "break_outside_loop",
+            DiagnosticKind::ContinueOutsideLoop => "continue_outside_loop",
+            DiagnosticKind::MissingMatchArms { .. } => "missing_match_arms",
+            DiagnosticKind::DuplicateMatchArm { .. } => "duplicate_match_arm",
+            DiagnosticKind::AssertionFailed { .. } => "assertion_failed",
+            DiagnosticKind::FollowedByUnreachableCode => "followed_by_unreachable_code",
+            DiagnosticKind::UnrecognizedKey(..) => "unrecognized_key",
+            DiagnosticKind::UnusedVariable(..) => "unused_variable",
+            DiagnosticKind::UnusedList(..) => "unused_list",
+            DiagnosticKind::UnusedStruct(..) => "unused_struct",
+            DiagnosticKind::UnusedProc(..) => "unused_proc",
+            DiagnosticKind::UnusedFunc(..) => "unused_func",
+            DiagnosticKind::UnusedArg(..) => "unused_arg",
+            DiagnosticKind::UnusedStructField(..) => "unused_struct_field",
+            DiagnosticKind::FixedLengthListInvalid(..) => "fixed_length_list_invalid",
+            DiagnosticKind::CannotInline { .. } => "cannot_inline",
+            DiagnosticKind::UnsafeRecursion(..) => "unsafe_recursion",
+            DiagnosticKind::KindMismatch { .. } => "kind_mismatch",
+            DiagnosticKind::UnknownLint(..) => "unknown_lint",
+        }
+    }
+
     pub fn should_be_suppressed(&self) -> bool {
         match self {
             DiagnosticKind::UnrecognizedArgument(name) => name.starts_with('_'),
@@ -451,12 +645,29 @@ impl DiagnosticKind {
             _ => false,
         }
     }
+
+    /// The name which this diagnostic reports as not being declared.
+    pub fn unrecognized_name(&self) -> Option<&SmolStr> {
+        match self {
+            DiagnosticKind::UnrecognizedVariable(name)
+            | DiagnosticKind::UnrecognizedList(name)
+            | DiagnosticKind::UnrecognizedEnum(name)
+            | DiagnosticKind::UnrecognizedStruct(name)
+            | DiagnosticKind::UnrecognizedProcedure(name)
+            | DiagnosticKind::UnrecognizedFunction(name)
+            | DiagnosticKind::UnrecognizedArgument(name)
+            | DiagnosticKind::UnrecognizedStructField(name) => Some(name),
+            DiagnosticKind::UnrecognizedEnumVariant { variant_name, .. } => Some(variant_name),
+            _ => None,
+        }
+    }
 }
 
 impl From<&DiagnosticKind> for Level {
     fn from(val: &DiagnosticKind) -> Self {
         match val {
             | DiagnosticKind::InvalidToken
+            | DiagnosticKind::InvalidEscape(_)
             | DiagnosticKind::UnrecognizedEof(_)
             | DiagnosticKind::UnrecognizedToken(_, _)
             | DiagnosticKind::ExtraToken(_)
@@ -478,6 +689,8 @@ impl From<&DiagnosticKind> for Level {
             | DiagnosticKind::ProcArgsCountMismatch { .. }
             | DiagnosticKind::FuncArgsCountMismatch { .. }
             | DiagnosticKind::MacroArgsCountMismatch { .. }
+            | DiagnosticKind::ExtensionArgsCountMismatch { .. }
+            | DiagnosticKind::FieldNotLiteral(_)
             | DiagnosticKind::CommandFailed { .. }
             | DiagnosticKind::VariableRedefinition(_)
             | DiagnosticKind::ListRedefinition(_)
@@ -493,15 +706,24 @@ impl From<&DiagnosticKind> for Level {
             | DiagnosticKind::DuplicateEnumVariant { .. }
             | DiagnosticKind::StructDoesNotHaveField { .. }
             | DiagnosticKind::EmptyStruct(_)
+            | DiagnosticKind::RecursiveStruct(_)
+            | DiagnosticKind::DestructureCountMismatch { .. }
             | DiagnosticKind::InvalidCostumeName(_)
             | DiagnosticKind::DuplicateCostume(_)
             | DiagnosticKind::DuplicateBackdrop(_)
             | DiagnosticKind::InvalidBackdropName(_)
             | DiagnosticKind::InvalidCostumeFormat { .. }
             | DiagnosticKind::InvalidSoundFormat { .. }
-            | DiagnosticKind::LocalNotSupported
             | DiagnosticKind::FixedLengthListInvalid(..)
-            | DiagnosticKind::UnknownDirective(_) => Level::Error,
+            | DiagnosticKind::UnknownDirective(_)
+            | DiagnosticKind::InvalidImport
+            | DiagnosticKind::PrivateSymbol { .. }
+            | DiagnosticKind::NamespaceClash(_)
+            | DiagnosticKind::BreakOutsideLoop
+            | DiagnosticKind::ContinueOutsideLoop
+            | DiagnosticKind::MissingMatchArms { .. }
+            | DiagnosticKind::DuplicateMatchArm { .. }
+            | DiagnosticKind::AssertionFailed { .. } => Level::Error,
 
             | DiagnosticKind::FollowedByUnreachableCode
             | DiagnosticKind::UnrecognizedKey(_)
@@ -511,7 +733,11 @@ impl From<&DiagnosticKind> for Level {
             | DiagnosticKind::UnusedProc(_)
             | DiagnosticKind::UnusedFunc(_)
             | DiagnosticKind::UnusedArg(_)
-            | DiagnosticKind::UnusedStructField(_) => Level::Warning,
+            | DiagnosticKind::UnusedStructField(_)
+            | DiagnosticKind::CannotInline { .. }
+            | DiagnosticKind::UnsafeRecursion(_)
+            | DiagnosticKind::KindMismatch { .. }
+            | DiagnosticKind::UnknownLint(_) => Level::Warning,
         }
     }
 }
@@ -0,0 +1,273 @@
+use annotate_snippets::Level;
+use logos::Span;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tsify::Tsify;
+
+use super::{
+    Diagnostic,
+    DiagnosticKind,
+    SpriteDiagnostics,
+};
+use crate::{
+    ast::Sprite,
+    misc::SmolStr,
+};
+
+/// The names of the lints, which are the warnings whose level can be set. These are the
+/// codes of the warning kinds of [`DiagnosticKind`].
+pub const LINTS: &[&str] = &[
+    "followed_by_unreachable_code",
+    "unrecognized_key",
+    "unused_variable",
+    "unused_list",
+    "unused_struct",
+    "unused_proc",
+    "unused_func",
+    "unused_arg",
+    "unused_struct_field",
+    "cannot_inline",
+    "unsafe_recursion",
+    "kind_mismatch",
+    "unknown_lint",
+];
+
+/// The group of every lint, such as in `-D warnings`.
+pub const WARNINGS: &str = "warnings";
+
+pub fn is_lint(name: &str) -> bool {
+    name == WARNINGS || LINTS.contains(&name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// The lint is not reported.
+    Allow,
+    /// The lint is reported as a warning.
+    Warn,
+    /// The lint is reported as an error, so the build fails.
+    Deny,
+}
+
+/// An `#[allow(...)]`, `#[warn(...)]` or `#[deny(...)]` comment on the lines before a
+/// top-level item, which sets the level of lints reported inside of the item.
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+pub struct LintAttribute {
+    pub level: LintLevel,
+    pub lints: Vec<SmolStr>,
+    /// The span of the item.
+    pub span: Span,
+}
+
+/// Parses a lint attribute, returning its level and the names of its lints.
+fn parse_attribute(comment: &str) -> Option<(LintLevel, Vec<&str>)> {
+    let attribute = comment.strip_prefix("#[")?.strip_suffix(']')?;
+    let (level, lints) = attribute.split_once('(')?;
+    let level = match level.trim() {
+        "allow" => LintLevel::Allow,
+        "warn" => LintLevel::Warn,
+        "deny" => LintLevel::Deny,
+        _ => return None,
+    };
+    let lints = lints.trim_end().strip_suffix(')')?;
+    Some((
+        level,
+        lints
+            .split(',')
+            .map(str::trim)
+            .filter(|lint| !lint.is_empty())
+            .collect(),
+    ))
+}
+
+impl SpriteDiagnostics {
+    /// Reads the lint attributes before the top-level items of `sprite`, and reports the
+    /// lints they name which do not exist.
+    pub fn read_lint_attributes(&mut self, sprite: &Sprite) {
+        let text = String::from_utf8_lossy(&self.translation_unit.text).into_owned();
+        let mut unknown_lints = vec![];
+        for item in &sprite.items {
+            let line_start = text[..item.start].rfind('\n').map_or(0, |i| i + 1);
+            if !text[line_start..item.start].trim().is_empty() {
+                continue;
+            }
+            let mut attributes = vec![];
+            let mut end = line_start;
+            while end > 0 {
+                let start = text[..end - 1].rfind('\n').map_or(0, |i| i + 1);
+                let line = text[start..end - 1].trim();
+                if !line.starts_with('#') {
+                    break;
+                }
+                if let Some((level, lints)) = parse_attribute(line) {
+                    for lint in &lints {
+                        if !is_lint(lint) {
+                            let offset = lint.as_ptr() as usize - text.as_ptr() as usize;
+                            unknown_lints.push((SmolStr::from(*lint), offset..offset + lint.len()));
+                        }
+                    }
+                    attributes.push(LintAttribute {
+                        level,
+                        lints: lints.into_iter().map(SmolStr::from).collect(),
+                        span: item.clone(),
+                    });
+                }
+                end = start;
+            }
+            // Attributes were read from the bottom up.
+            attributes.reverse();
+            self.lint_attributes.extend(attributes);
+        }
+        for (lint, span) in unknown_lints {
+            self.report(DiagnosticKind::UnknownLint(lint), &span);
+        }
+    }
+
+    /// The level that `diagnostic` is reported at, or `None` if it is allowed. The level of
+    /// a lint is set by the last lint attribute of the item it is in, then by `lints`,
+    /// where the level of a lint comes before the level of `warnings`.
+    pub fn level(&self, diagnostic: &Diagnostic) -> Option<Level> {
+        let level = Level::from(&diagnostic.kind);
+        if level != Level::Warning {
+            return Some(level);
+        }
+        let code = diagnostic.kind.code();
+        let lint_level = self
+            .lint_attributes
+            .iter()
+            .rev()
+            .find(|attribute| {
+                attribute.span.contains(&diagnostic.span.start)
+                    && attribute
+                        .lints
+                        .iter()
+                        .any(|lint| lint == code || lint == WARNINGS)
+            })
+            .map(|attribute| attribute.level)
+            .or_else(|| self.lints.get(code).copied())
+            .or_else(|| self.lints.get(WARNINGS).copied())
+            .unwrap_or(LintLevel::Warn);
+        match lint_level {
+            LintLevel::Allow => None,
+            LintLevel::Warn => Some(Level::Warning),
+            LintLevel::Deny => Some(Level::Error),
+        }
+    }
+
+    /// Whether any diagnostic is reported as an error.
+    pub fn failure(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| self.level(diagnostic) == Some(Level::Error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        path::Path,
+    };
+
+    use semver::Version;
+
+    use super::*;
+    use crate::{
+        codegen::build::{
+            build_with_cache,
+            ParseCache,
+        },
+        diagnostic::Artifact,
+        standard_library::new_standard_library,
+        testing::*,
+    };
+
+    #[test]
+    fn lint_levels_are_set_by_attributes_and_config() {
+        fn levels(artifact: &Artifact) -> Vec<(&'static str, Option<Level>)> {
+            let main = &artifact.sprites_diagnostics["main"];
+            let mut diagnostics: Vec<_> = main.diagnostics.iter().collect();
+            diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.kind.code(), main.level(diagnostic)))
+                .collect()
+        }
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             # Called by the engine.\n\
+             #[allow(unused_arg)]\n\
+             proc callback a { say 1; }\n\
+             #[deny(unused_argg)]\n\
+             proc other b { say 2; }\n\
+             onflag { callback 1; other 2; }\n",
+        );
+        let artifact = build(&fs, &mut ParseCache::default());
+        assert_eq!(
+            levels(&artifact),
+            [
+                ("unused_arg", None),
+                ("unknown_lint", Some(Level::Warning)),
+                ("unused_arg", Some(Level::Warning)),
+            ]
+        );
+        fs.borrow_mut().0.insert(
+            "goboscript.toml".into(),
+            b"[lints]\nwarnings = \"deny\"\nunknown_lint = \"allow\"\n".to_vec(),
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(artifact.failure());
+        assert_eq!(
+            levels(&artifact),
+            [
+                ("unused_arg", None),
+                ("unknown_lint", None),
+                ("unused_arg", Some(Level::Error)),
+            ]
+        );
+    }
+    #[test]
+    fn lint_levels_from_the_command_line_are_set_over_the_config() {
+        let fs =
+            project("costumes \"blank.svg\";\nproc unused a { say 1; }\nonflag { unused 1; }\n");
+        fs.borrow_mut().0.insert(
+            "goboscript.toml".into(),
+            b"[lints]\nwarnings = \"deny\"\n".to_vec(),
+        );
+        let build = |lints: &[(SmolStr, LintLevel)]| {
+            let stdlib = new_standard_library(Version::new(0, 0, 0), Path::new("std"));
+            let mut sb3 = Cursor::new(vec![]);
+            let artifact = build_with_cache(
+                fs.clone(),
+                "".into(),
+                &mut sb3,
+                Some(stdlib),
+                false,
+                true,
+                lints,
+                &mut ParseCache::default(),
+            )
+            .unwrap();
+            (artifact, sb3.into_inner())
+        };
+        let (artifact, sb3) = build(&[]);
+        assert!(artifact.failure());
+        assert!(sb3.is_empty());
+        let (artifact, sb3) = build(&[("unused_arg".into(), LintLevel::Allow)]);
+        assert!(!artifact.failure());
+        assert!(!sb3.is_empty());
+        let (artifact, sb3) = build(&[
+            ("warnings".into(), LintLevel::Warn),
+            ("unused_arg".into(), LintLevel::Deny),
+        ]);
+        assert!(artifact.failure());
+        assert!(sb3.is_empty());
+        fs.borrow_mut().0.remove(Path::new("goboscript.toml"));
+        let (artifact, sb3) = build(&[("warnings".into(), LintLevel::Deny)]);
+        assert!(artifact.failure());
+        assert!(sb3.is_empty());
+    }
+}
@@ -9,9 +9,14 @@ use serde::{
 };
 use tsify::Tsify;
 
-use super::SpriteDiagnostics;
+use super::{
+    Diagnostic,
+    DiagnosticKind,
+    SpriteDiagnostics,
+};
 use crate::{
     ast::Project,
+    codegen::sb3::STAGE_NAME,
     misc::SmolStr,
 };
 
@@ -21,6 +26,9 @@ pub struct Artifact {
     pub stage_diagnostics: SpriteDiagnostics,
     pub sprites_diagnostics: FxHashMap<SmolStr, SpriteDiagnostics>,
     pub block_count: usize,
+    /// How many blocks were left out of the project because they were unused or
+    /// unreachable.
+    pub blocks_saved: usize,
 }
 
 impl Artifact {
@@ -36,16 +44,43 @@ impl Artifact {
         }
     }
 
+    /// The diagnostics of a target, by its name in `project.json`.
+    pub fn target_diagnostics(&self, target_name: &str) -> Option<&SpriteDiagnostics> {
+        if target_name == STAGE_NAME {
+            Some(&self.stage_diagnostics)
+        } else {
+            self.sprites_diagnostics.get(target_name)
+        }
+    }
+
+    /// Print a diagnostic for a block of the built project, which was found while the
+    /// project was running.
+    pub fn eprint_at_block(&self, target_name: &str, block_id: &str, kind: DiagnosticKind) {
+        let Some(sprite_diagnostics) = self.target_diagnostics(target_name) else {
+            return;
+        };
+        let span = sprite_diagnostics
+            .block_span(block_id)
+            .cloned()
+            .unwrap_or(0..0);
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        sprite_diagnostics.eprint_diagnostic(
+            &cwd,
+            &Renderer::styled(),
+            &self.project,
+            &Diagnostic {
+                kind,
+                span,
+                notes: vec![],
+            },
+        );
+    }
+
     pub fn failure(&self) -> bool {
-        self.stage_diagnostics
-            .diagnostics
-            .iter()
-            .any(|diag| matches!(Level::from(&diag.kind), Level::Error))
-            || self.sprites_diagnostics.values().any(|sprite_diagnostics| {
-                sprite_diagnostics
-                    .diagnostics
-                    .iter()
-                    .any(|diag| matches!(Level::from(&diag.kind), Level::Error))
-            })
+        self.stage_diagnostics.failure()
+            || self
+                .sprites_diagnostics
+                .values()
+                .any(SpriteDiagnostics::failure)
     }
 }
@@ -0,0 +1,309 @@
+use std::{
+    cell::RefCell,
+    io,
+    path::Path,
+    rc::Rc,
+};
+
+use annotate_snippets::Level;
+use serde::Serialize;
+use serde_json::{
+    json,
+    Value,
+};
+
+use super::{
+    Artifact,
+    SpriteDiagnostics,
+};
+use crate::{
+    ast::Project,
+    codegen::source_map::Files,
+    translation_unit::{
+        Include,
+        Owner,
+    },
+    vfs::VFS,
+};
+
+/// A diagnostic resolved to a position in one of the source files, for the
+/// machine-readable output of `goboscript build`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// The source file, relative to the current directory if it is inside it.
+    pub file: String,
+    /// The line and column of the first character, starting from 1. Columns count
+    /// characters, not bytes. These are not given for diagnostics about a whole file.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The line and column of the character after the last character.
+    pub end_line: Option<usize>,
+    pub end_column: Option<usize>,
+    /// `error` or `warning`.
+    pub severity: &'static str,
+    /// The stable name of the kind of diagnostic, such as `unused_variable`.
+    pub code: &'static str,
+    pub message: String,
+    pub help: Option<String>,
+    /// Other places in the code which the diagnostic is about.
+    pub notes: Vec<ReportNote>,
+}
+
+/// A labelled place in one of the source files which is shown alongside a [`Report`].
+#[derive(Debug, Serialize)]
+pub struct ReportNote {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub message: String,
+}
+
+impl SpriteDiagnostics {
+    fn reports(&self, files: &mut Files, project: &Project) -> io::Result<Vec<Report>> {
+        let sprite = match self.sprite_name.as_str() {
+            "stage" => &project.stage,
+            name => &project.sprites[name],
+        };
+        let mut reports = vec![];
+        for diagnostic in &self.diagnostics {
+            let Some(level) = self.level(diagnostic) else {
+                continue;
+            };
+            if diagnostic.kind.should_be_suppressed() {
+                continue;
+            }
+            let (span, include) = self.translation_unit.translate_span(&diagnostic.span);
+            if level != Level::Error && !matches!(include.owner, Owner::Local) {
+                continue;
+            }
+            let file = relative_path(files, include);
+            let (line, column, end_line, end_column) =
+                if diagnostic.span.start == 0 && diagnostic.span.end == 0 {
+                    (None, None, None, None)
+                } else {
+                    let start = files.position(&include.path, span.start)?;
+                    let end = files.position(&include.path, span.end)?;
+                    (
+                        Some(start.line),
+                        Some(start.column),
+                        Some(end.line),
+                        Some(end.column),
+                    )
+                };
+            let mut notes = vec![];
+            for note in &diagnostic.notes {
+                let (span, include) = self.translation_unit.translate_span(&note.span);
+                let start = files.position(&include.path, span.start)?;
+                let end = files.position(&include.path, span.end)?;
+                notes.push(ReportNote {
+                    file: relative_path(files, include),
+                    line: start.line,
+                    column: start.column,
+                    end_line: end.line,
+                    end_column: end.column,
+                    message: note.label.clone(),
+                });
+            }
+            reports.push(Report {
+                file,
+                line,
+                column,
+                end_line,
+                end_column,
+                severity: if level == Level::Error {
+                    "error"
+                } else {
+                    "warning"
+                },
+                code: diagnostic.kind.code(),
+                message: diagnostic.kind.to_string(sprite),
+                help: diagnostic.kind.help(sprite),
+                notes,
+            });
+        }
+        Ok(reports)
+    }
+}
+
+fn relative_path(files: &Files, include: &Include) -> String {
+    files
+        .relative_path(include.path.clone())
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+impl Artifact {
+    /// The diagnostics which `eprint` would print, resolved to positions in the source
+    /// files read from `fs`, with paths relative to `cwd`. The diagnostics of the stage
+    /// come first, then those of each sprite in order of their names.
+    pub fn reports(&self, fs: Rc<RefCell<dyn VFS>>, cwd: &Path) -> io::Result<Vec<Report>> {
+        let mut files = Files::new(fs, cwd);
+        let mut reports = self.stage_diagnostics.reports(&mut files, &self.project)?;
+        let mut sprite_names: Vec<_> = self.sprites_diagnostics.keys().collect();
+        sprite_names.sort();
+        for sprite_name in sprite_names {
+            reports
+                .extend(self.sprites_diagnostics[sprite_name].reports(&mut files, &self.project)?);
+        }
+        Ok(reports)
+    }
+}
+
+/// A SARIF 2.1.0 log of one run of goboscript, with a result for each report.
+pub fn sarif(reports: &[Report]) -> Value {
+    let mut rules: Vec<&str> = vec![];
+    let mut results = vec![];
+    for report in reports {
+        let rule_index = match rules.iter().position(|&code| code == report.code) {
+            Some(index) => index,
+            None => {
+                rules.push(report.code);
+                rules.len() - 1
+            }
+        };
+        let mut message = report.message.clone();
+        if let Some(help) = &report.help {
+            message.push_str("\nhelp: ");
+            message.push_str(help);
+        }
+        let mut physical_location = json!({
+            "artifactLocation": { "uri": report.file },
+        });
+        if let (Some(line), Some(column), Some(end_line), Some(end_column)) = (
+            report.line,
+            report.column,
+            report.end_line,
+            report.end_column,
+        ) {
+            physical_location["region"] = json!({
+                "startLine": line,
+                "startColumn": column,
+                "endLine": end_line,
+                "endColumn": end_column,
+            });
+        }
+        let related_locations: Vec<Value> = report
+            .notes
+            .iter()
+            .enumerate()
+            .map(|(id, note)| {
+                json!({
+                    "id": id,
+                    "message": { "text": note.message },
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": note.file },
+                        "region": {
+                            "startLine": note.line,
+                            "startColumn": note.column,
+                            "endLine": note.end_line,
+                            "endColumn": note.end_column,
+                        },
+                    },
+                })
+            })
+            .collect();
+        let mut result = json!({
+            "ruleId": report.code,
+            "ruleIndex": rule_index,
+            "level": report.severity,
+            "message": { "text": message },
+            "locations": [{ "physicalLocation": physical_location }],
+        });
+        if !related_locations.is_empty() {
+            result["relatedLocations"] = related_locations.into();
+        }
+        results.push(result);
+    }
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "goboscript",
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules
+                        .iter()
+                        .map(|code| json!({ "id": code }))
+                        .collect::<Vec<_>>(),
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::build::ParseCache,
+        testing::*,
+    };
+
+    #[test]
+    fn reports_resolve_diagnostics_to_lines_and_columns() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             onflag {\n\
+             \x20   local number x = \"ab\";\n\
+             \x20   say x;\n\
+             }\n",
+        );
+        let artifact = build(&fs, &mut ParseCache::default());
+        let reports = artifact.reports(fs, Path::new("")).unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.file, "main.gs");
+        assert_eq!((report.line, report.column), (Some(3), Some(22)));
+        assert_eq!((report.end_line, report.end_column), (Some(3), Some(26)));
+        assert_eq!(report.severity, "warning");
+        assert_eq!(report.code, "kind_mismatch");
+        assert_eq!(report.message, "expected number, found string");
+        let sarif = sarif(&reports);
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "kind_mismatch");
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["region"]["startColumn"],
+            22
+        );
+    }
+
+    #[test]
+    fn redefinitions_have_a_note_at_the_first_definition() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             %include lib/greet\n\
+             proc greet { say \"hello\"; }\n\
+             onflag { greet; }\n",
+        );
+        fs.borrow_mut().0.insert(
+            "lib/greet.gs".into(),
+            b"proc greet { say \"hi\"; }\n".to_vec(),
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(artifact.failure());
+        let reports = artifact.reports(fs, Path::new("")).unwrap();
+        let report = reports
+            .iter()
+            .find(|report| report.code == "procedure_redefinition")
+            .unwrap();
+        assert_eq!((report.file.as_str(), report.line), ("main.gs", Some(3)));
+        assert_eq!(report.notes.len(), 1);
+        let note = &report.notes[0];
+        assert_eq!(note.file, "lib/greet.gs");
+        assert_eq!((note.line, note.column), (1, 6));
+        assert_eq!((note.end_line, note.end_column), (1, 11));
+        assert_eq!(note.message, "first defined here");
+        let sarif = sarif(&reports);
+        let related = &sarif["runs"][0]["results"][0]["relatedLocations"][0];
+        assert_eq!(
+            related["physicalLocation"]["artifactLocation"]["uri"],
+            "lib/greet.gs"
+        );
+    }
+}
@@ -18,6 +18,7 @@ use annotate_snippets::{
 };
 use colored::Colorize;
 use logos::Span;
+use rustc_hash::FxHashMap;
 use serde::{
     Deserialize,
     Serialize,
@@ -27,13 +28,18 @@ use tsify::Tsify;
 use super::{
     diagnostic_kind::DiagnosticKind,
     Diagnostic,
+    LintAttribute,
+    LintLevel,
+    Note,
 };
 use crate::{
     ast::Project,
     codegen::debug_info::DebugInfo,
+    misc::SmolStr,
     standard_library::StandardLibrary,
     translation_unit::{
         parse_translation_unit,
+        Include,
         Owner,
         TranslationUnit,
     },
@@ -46,6 +52,9 @@ pub struct SpriteDiagnostics {
     pub translation_unit: TranslationUnit,
     pub diagnostics: Vec<Diagnostic>,
     pub debug_info: DebugInfo,
+    /// Levels of lints set by `goboscript.toml` and the command line, by lint name.
+    pub lints: FxHashMap<SmolStr, LintLevel>,
+    pub lint_attributes: Vec<LintAttribute>,
 }
 
 impl SpriteDiagnostics {
@@ -63,13 +72,22 @@ impl SpriteDiagnostics {
             translation_unit: unit,
             diagnostics,
             debug_info: Default::default(),
+            lints: Default::default(),
+            lint_attributes: vec![],
         })
     }
 
     pub fn report(&mut self, kind: DiagnosticKind, span: &Span) {
+        self.report_with_notes(kind, span, vec![]);
+    }
+
+    /// Reports a diagnostic with notes about other places in the code, such as where a
+    /// name was first defined.
+    pub fn report_with_notes(&mut self, kind: DiagnosticKind, span: &Span, notes: Vec<Note>) {
         self.diagnostics.push(Diagnostic {
             kind,
             span: span.clone(),
+            notes,
         });
     }
 
@@ -78,71 +96,98 @@ impl SpriteDiagnostics {
     }
 
     pub fn eprint(&self, cwd: &Path, renderer: &Renderer, project: &Project, print_level: &Level) {
+        for diagnostic in &self.diagnostics {
+            if self.level(diagnostic).as_ref() != Some(print_level) {
+                continue;
+            }
+            self.eprint_diagnostic(cwd, renderer, project, diagnostic);
+        }
+    }
+
+    pub fn eprint_diagnostic(
+        &self,
+        cwd: &Path,
+        renderer: &Renderer,
+        project: &Project,
+        diagnostic: &Diagnostic,
+    ) {
         let sprite = match self.sprite_name.as_str() {
             "stage" => &project.stage,
             name => &project.sprites[name],
         };
-        for diagnostic in &self.diagnostics {
-            let level: Level = (&diagnostic.kind).into();
-            if &level != print_level {
-                continue;
-            }
-            let title = diagnostic.kind.to_string(sprite);
-            let help = diagnostic.kind.help(sprite);
-            let help = help.as_ref();
-            let (start, include) = self
-                .translation_unit
-                .translate_position(diagnostic.span.start);
-            if level != Level::Error && !matches!(include.owner, Owner::Local) {
-                continue;
+        let Some(level) = self.level(diagnostic) else {
+            return;
+        };
+        let title = diagnostic.kind.to_string(sprite);
+        let help = diagnostic.kind.help(sprite);
+        let help = help.as_ref();
+        let (span, include) = self.translation_unit.translate_span(&diagnostic.span);
+        if level != Level::Error && !matches!(include.owner, Owner::Local) {
+            return;
+        }
+        if diagnostic.kind.should_be_suppressed() {
+            return;
+        }
+        let notes: Vec<(Span, &Include, &str)> = diagnostic
+            .notes
+            .iter()
+            .map(|note| {
+                let (span, include) = self.translation_unit.translate_span(&note.span);
+                (span, include, note.label.as_str())
+            })
+            .collect();
+        // The snippets borrow the text of their files, so every file is read first. The
+        // notes in other files are shown in a snippet for each file.
+        // TODO: memoize this using a memoization crate.
+        let mut files: Vec<(&Path, String)> = vec![];
+        for include in std::iter::once(include).chain(notes.iter().map(|(_, include, _)| *include))
+        {
+            if !files.iter().any(|(path, _)| *path == include.path) {
+                files.push((&include.path, fs::read_to_string(&include.path).unwrap()));
             }
-            if diagnostic.kind.should_be_suppressed() {
-                continue;
+        }
+        let mut message = level.title(&title);
+        for (path, text) in &files {
+            let origin = path.strip_prefix(cwd).unwrap_or(path).to_str().unwrap();
+            let mut snippet = Snippet::source(text).origin(origin).fold(true);
+            if *path == include.path && !(diagnostic.span.start == 0 && diagnostic.span.end == 0) {
+                snippet = snippet.annotation(level.span(clamp_span(&span, text)));
             }
-            // TODO: memoize this using a memoization crate.
-            let text = fs::read_to_string(&include.path).unwrap();
-            let include_path = include
-                .path
-                .strip_prefix(cwd)
-                .unwrap_or(&include.path)
-                .to_str()
-                .unwrap();
-            if diagnostic.span.start == 0 && diagnostic.span.end == 0 {
-                let mut message = level
-                    .title(&title)
-                    .snippet(Snippet::source(&text).origin(include_path).fold(true));
-                if let Some(help) = help {
-                    message = message.footer(Level::Help.title(help));
-                }
-                eprintln!("{}", renderer.render(message));
-            } else {
-                let (end, _) = self
-                    .translation_unit
-                    .translate_position(diagnostic.span.end - 1);
-                let end = end + 1;
-                let mut message = level.title(&title).snippet(
-                    Snippet::source(&text)
-                        .origin(include_path)
-                        .fold(true)
-                        .annotation(level.span(start..end)),
-                );
-                if let Some(help) = help {
-                    message = message.footer(Level::Help.title(help));
-                }
-                eprintln!("{}", renderer.render(message));
+            for (note_span, _, label) in notes
+                .iter()
+                .filter(|(_, note_include, _)| note_include.path == *path)
+            {
+                snippet =
+                    snippet.annotation(Level::Note.span(clamp_span(note_span, text)).label(label));
             }
-            if let DiagnosticKind::CommandFailed { stderr } = &diagnostic.kind {
-                eprintln!("{}:", "stderr".red().bold());
-                for line in stderr.split(|&b| b == b'\n') {
-                    eprintln!("    {}", std::str::from_utf8(line).unwrap().red());
-                }
+            message = message.snippet(snippet);
+        }
+        if let Some(help) = help {
+            message = message.footer(Level::Help.title(help));
+        }
+        eprintln!("{}", renderer.render(message));
+        if let DiagnosticKind::CommandFailed { stderr } = &diagnostic.kind {
+            eprintln!("{}:", "stderr".red().bold());
+            for line in stderr.split(|&b| b == b'\n') {
+                eprintln!("    {}", std::str::from_utf8(line).unwrap().red());
             }
         }
     }
 
+    /// The span of a block in the built project, by its ID in `project.json`.
+    pub fn block_span(&self, block_id: &str) -> Option<&Span> {
+        self.debug_info.blocks.get(&format!("\"{block_id}\""))
+    }
+
     pub fn find_diagnostic_for_span(&self, span: &Span) -> Option<&Diagnostic> {
         self.diagnostics.iter().find(|diagnostic| {
             diagnostic.span.start <= span.start && diagnostic.span.end >= span.end
         })
     }
 }
+
+/// `span` within `text`. The span of an unexpected end of file is past the last character.
+fn clamp_span(span: &Span, text: &str) -> Span {
+    let end = span.end.min(text.len());
+    span.start.min(end)..end
+}
@@ -1,7 +1,15 @@
-use std::{
-    fs,
-    path::PathBuf,
-};
+use std::mem;
+
+use anyhow::anyhow;
+use logos::Logos;
+use similar::TextDiff;
+
+use crate::lexer::token::Token;
+
+/// Lines are wrapped to fit in this many columns, and the backslashes which continue a
+/// `%define` are aligned to its last column.
+const MAX_LINE_LENGTH: usize = 88;
+const INDENT: &str = "    ";
 
 pub enum FmtError {
     AnyhowError(anyhow::Error),
@@ -15,47 +23,851 @@ where T: Into<anyhow::Error>
     }
 }
 
-pub fn format_file(path: PathBuf) -> Result<(), FmtError> {
-    let mut src = fs::read(&path).unwrap();
-    format_buffer_inplace(&mut src)?;
-    fs::write(path, src).unwrap();
-    Ok(())
+/// Formats goboscript source code. Blocks are indented by four spaces, tokens are spaced
+/// consistently, and lines which do not fit in 88 columns are split at their brackets.
+/// Comments and blank lines are kept, and preprocessor directives and imports are left
+/// as they are, apart from aligning the backslashes of multi-line `%define`s.
+pub fn format_source(src: &str) -> Result<String, FmtError> {
+    let mut src = src.to_owned();
+    if !src.is_empty() && !src.ends_with('\n') {
+        src.push('\n');
+    }
+    let (items, comments) = scan(&src)?;
+    let formatted = Formatter::default().format(items, &comments);
+    // Only whitespace should change, which is checked here so that a mistake in the
+    // formatter can never change the meaning of a file.
+    if signature(&scan(&src)?.0) != signature(&scan(&formatted)?.0) {
+        return Err(anyhow!("formatting would change the tokens of this file").into());
+    }
+    Ok(formatted)
+}
+
+/// A unified diff from `old` to `new`, with `path` in its headers.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(path, path)
+        .to_string()
+}
+
+/// A comment on a line of its own.
+struct Comment {
+    text: String,
+    blank_before: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Open,
+    Close,
+    Separator,
+    Other,
 }
 
-fn format_buffer_inplace(src: &mut Vec<u8>) -> Result<(), FmtError> {
-    let max_line_length = 88;
+/// A token, along with the comments before and after it.
+struct Piece<'src> {
+    token: Token,
+    text: &'src str,
+    /// Whether the token was preceded by whitespace in the source.
+    spaced: bool,
+    blank_before: bool,
+    comments: Vec<Comment>,
+    /// A comment after the token on the same line, with the whitespace before it.
+    trailing: Option<String>,
+    role: Role,
+    /// Whether the token is a minus sign which negates the value after it.
+    unary: bool,
+    /// Whether the token is preceded by a space in the formatted line.
+    space: bool,
+}
+
+enum Item<'src> {
+    Piece(Piece<'src>),
+    /// A preprocessor directive or an import, which is kept as it is.
+    Verbatim {
+        text: String,
+        blank_before: bool,
+        comments: Vec<Comment>,
+    },
+}
+
+/// Splits `src` into tokens and verbatim lines, and returns the comments at its end.
+fn scan(src: &str) -> Result<(Vec<Item<'_>>, Vec<Comment>), FmtError> {
+    let mut scanner = Scanner {
+        src,
+        items: vec![],
+        comments: vec![],
+        newlines: 0,
+    };
+    let mut code_start = 0;
     let mut i = 0;
-    let mut line_begin = true;
     while i < src.len() {
-        if line_begin && src[i] == b'%' {
-            loop {
-                let begin = i;
-                i += src[i..].split(|c| *c == b'\n').next().unwrap().len();
-                if src[i - 1] != b'\\' {
-                    i += 1;
-                    break;
-                }
-                i -= 1;
-                let mut slash = i;
-                let diff = slash - begin;
-                if diff >= (max_line_length - 1) {
-                    for _ in 0..(diff - (max_line_length - 1)) {
-                        src.remove(slash - 1);
-                        slash -= 1;
-                        i -= 1;
-                    }
-                } else {
-                    for _ in 0..((max_line_length - 1) - diff) {
-                        src.insert(slash, b' ');
-                        i += 1;
-                    }
-                }
-                i += 2;
+        let mut end = next_line(src, i);
+        let line = &src[i..end];
+        if line.starts_with('%') || is_import(line) {
+            scanner.code(code_start, i)?;
+            let mut lines = vec![line];
+            while line.starts_with('%')
+                && end < src.len()
+                && ends_with_backslash(lines[lines.len() - 1])
+            {
+                let next = next_line(src, end);
+                lines.push(&src[end..next]);
+                end = next;
+            }
+            scanner.verbatim(&lines);
+            code_start = end;
+        }
+        i = end;
+    }
+    scanner.code(code_start, src.len())?;
+    Ok((scanner.items, scanner.comments))
+}
+
+fn next_line(src: &str, i: usize) -> usize {
+    src[i..].find('\n').map_or(src.len(), |j| i + j + 1)
+}
+
+fn ends_with_backslash(line: &str) -> bool {
+    line.trim_end().ends_with('\\')
+}
+
+/// Whether `line` is an import, which is found the same way as in `translation_unit`.
+fn is_import(line: &str) -> bool {
+    line.trim_start_matches([' ', '\t'])
+        .strip_prefix("import")
+        .is_some_and(|rest| rest.trim_start().starts_with('"'))
+}
+
+/// Moves the backslash at the end of a line of a `%define` to the last column.
+fn align_backslash(line: &str) -> String {
+    let line = line.trim_end();
+    let Some(code) = line.strip_suffix('\\') else {
+        return line.to_owned();
+    };
+    let code = code.trim_end();
+    let width = code.chars().count();
+    let padding = (MAX_LINE_LENGTH - 1).saturating_sub(width).max(1);
+    format!("{code}{}\\", " ".repeat(padding))
+}
+
+/// The line and column of `offset` in `src`.
+fn position(src: &str, offset: usize) -> String {
+    let line_start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = src[..offset].matches('\n').count() + 1;
+    let column = src[line_start..offset].chars().count() + 1;
+    format!("{line}:{column}")
+}
+
+struct Scanner<'src> {
+    src: &'src str,
+    items: Vec<Item<'src>>,
+    /// Comments which are waiting for the token after them.
+    comments: Vec<Comment>,
+    /// The number of line breaks since the last token or comment.
+    newlines: usize,
+}
+
+impl<'src> Scanner<'src> {
+    fn code(&mut self, start: usize, end: usize) -> Result<(), FmtError> {
+        let src = self.src;
+        let mut last = start;
+        for (token, span) in Token::lexer(&src[start..end]).spanned() {
+            let span = start + span.start..start + span.end;
+            let Ok(token) = token else {
+                return Err(anyhow!("{}: invalid token", position(src, span.start)).into());
+            };
+            if token == Token::Newline {
+                continue;
+            }
+            let gap = &src[last..span.start];
+            self.gap(gap);
+            self.items.push(Item::Piece(Piece {
+                token,
+                text: &src[span.clone()],
+                spaced: !gap.is_empty(),
+                blank_before: self.newlines > 1,
+                comments: mem::take(&mut self.comments),
+                trailing: None,
+                role: Role::Other,
+                unary: false,
+                space: false,
+            }));
+            self.newlines = 0;
+            last = span.end;
+        }
+        self.gap(&src[last..end]);
+        Ok(())
+    }
+
+    /// Collects the comments and line breaks between two tokens.
+    fn gap(&mut self, gap: &str) {
+        let mut rest = gap;
+        let mut spaces = 0;
+        while let Some(c) = rest.chars().next() {
+            if c == '#' {
+                let end = rest.find('\n').unwrap_or(rest.len());
+                let text = rest[..end].trim_end();
+                match self.items.last_mut() {
+                    Some(Item::Piece(piece)) if self.newlines == 0 && self.comments.is_empty() => {
+                        piece.trailing = Some(format!("{}{text}", " ".repeat(spaces.max(1))));
+                    }
+                    _ => self.comments.push(Comment {
+                        text: text.to_owned(),
+                        blank_before: self.newlines > 1,
+                    }),
+                }
+                self.newlines = 0;
+                rest = &rest[end..];
+                continue;
+            }
+            if c == '\n' {
+                self.newlines += 1;
+                spaces = 0;
+            } else {
+                spaces += 1;
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    fn verbatim(&mut self, lines: &[&str]) {
+        let text = lines
+            .iter()
+            .map(|line| align_backslash(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.items.push(Item::Verbatim {
+            text,
+            blank_before: self.newlines > 1,
+            comments: mem::take(&mut self.comments),
+        });
+        // The line break at the end of the directive has already been consumed.
+        self.newlines = 1;
+    }
+}
+
+/// Everything in the source except for whitespace.
+fn signature<'a>(items: &'a [Item]) -> Vec<&'a str> {
+    let mut signature = vec![];
+    for item in items {
+        match item {
+            Item::Piece(piece) => {
+                signature.extend(piece.comments.iter().map(|comment| comment.text.as_str()));
+                signature.push(piece.text);
+                signature.extend(piece.trailing.as_deref().map(str::trim));
             }
+            Item::Verbatim { text, comments, .. } => {
+                signature.extend(comments.iter().map(|comment| comment.text.as_str()));
+                signature.push(text);
+            }
+        }
+    }
+    signature
+}
+
+/// Tokens which begin the header of a block.
+fn is_header(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Proc
+            | Token::Func
+            | Token::Inline
+            | Token::Recursive
+            | Token::NoWarp
+            | Token::On
+            | Token::OnFlag
+            | Token::OnKey
+            | Token::OnClick
+            | Token::OnBackdrop
+            | Token::OnLoudness
+            | Token::OnTimer
+            | Token::OnVideoMotion
+            | Token::OnMakeyKey
+            | Token::OnClone
+            | Token::Test
+            | Token::If
+            | Token::Elif
+            | Token::Else
+            | Token::Repeat
+            | Token::Until
+            | Token::Forever
+            | Token::While
+            | Token::For
+            | Token::Match
+    )
+}
+
+/// Whether `piece` can be the last token of a value.
+fn ends_value(piece: &Piece) -> bool {
+    matches!(
+        piece.token,
+        Token::Name(_)
+            | Token::Arg(_)
+            | Token::Int(_)
+            | Token::Float(_)
+            | Token::Hex(_)
+            | Token::Oct(_)
+            | Token::Bin(_)
+            | Token::Str(_)
+            | Token::FStr
+            | Token::True
+            | Token::False
+            | Token::RParen
+            | Token::RBracket
+    ) || (piece.token == Token::RBrace && piece.role == Role::Close)
+}
+
+fn space_between(prev: &Piece, next: &Piece) -> bool {
+    if prev.unary {
+        // `- -x` must not become `--x`.
+        return matches!(
+            next.token,
+            Token::Minus | Token::Decrement | Token::AssignSubtract
+        );
+    }
+    match (&prev.token, &next.token) {
+        (Token::LParen | Token::LBracket | Token::Dot, _) => false,
+        (
+            _,
+            Token::Comma
+            | Token::Semicolon
+            | Token::RParen
+            | Token::RBracket
+            | Token::Dot
+            | Token::Colon
+            | Token::Increment
+            | Token::Decrement,
+        ) => false,
+        // `say (1 + 2) * 3;` and `say(1 + 2) * 3;` are both common, so whether a name or a
+        // keyword is followed by a space before a bracket is kept.
+        (_, Token::LParen)
+            if prev
+                .text
+                .starts_with(|c: char| c.is_alphabetic() || c == '_') =>
+        {
+            next.spaced
+        }
+        (_, Token::LBracket) => !ends_value(prev),
+        (Token::LBrace, Token::RBrace) => false,
+        _ => true,
+    }
+}
+
+fn flat(pieces: &[Piece]) -> String {
+    let mut line = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        if i > 0 && piece.space {
+            line.push(' ');
+        }
+        line.push_str(piece.text);
+    }
+    line
+}
+
+fn fits(indent: usize, pieces: &[Piece]) -> bool {
+    indent * INDENT.len() + flat(pieces).chars().count() <= MAX_LINE_LENGTH
+}
+
+/// The first place inside of `pieces` which must be followed by a line break because of
+/// a comment, if any.
+fn inner_comment(pieces: &[Piece]) -> Option<usize> {
+    pieces.iter().enumerate().find_map(|(i, piece)| {
+        if i > 0 && !piece.comments.is_empty() {
+            Some(i - 1)
+        } else if i + 1 < pieces.len() && piece.trailing.is_some() {
+            Some(i)
         } else {
-            line_begin = src[i] == b'\n';
-            i += 1;
+            None
+        }
+    })
+}
+
+/// The outermost bracketed groups of `pieces` which are not empty.
+fn groups(pieces: &[Piece]) -> Vec<(usize, usize)> {
+    let mut groups = vec![];
+    let mut opens = vec![];
+    for (i, piece) in pieces.iter().enumerate() {
+        match piece.role {
+            Role::Open => opens.push(i),
+            Role::Close => {
+                if let Some(open) = opens.pop() {
+                    if opens.is_empty() && i > open + 1 {
+                        groups.push((open, i));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    groups
+}
+
+/// The binary operators outside of brackets which bind the loosest, such as each `+` in
+/// `a * b + c + d`.
+fn operators(pieces: &[Piece]) -> Vec<usize> {
+    let mut operators = vec![];
+    let mut loosest = 0;
+    let mut depth = 0usize;
+    for (i, piece) in pieces.iter().enumerate() {
+        match piece.role {
+            Role::Open => depth += 1,
+            Role::Close => depth = depth.saturating_sub(1),
+            _ if depth == 0 && i > 0 => {
+                let Some(precedence) = precedence(piece) else {
+                    continue;
+                };
+                if precedence > loosest {
+                    loosest = precedence;
+                    operators.clear();
+                }
+                if precedence == loosest {
+                    operators.push(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    operators
+}
+
+/// The precedence of a binary operator, as in the grammar, where higher binds looser.
+fn precedence(piece: &Piece) -> Option<u8> {
+    Some(match piece.token {
+        Token::Star | Token::Slash | Token::FloorDiv | Token::Percent => 3,
+        Token::Plus => 4,
+        Token::Minus if !piece.unary => 4,
+        Token::Lt | Token::Le | Token::Gt | Token::Ge => 5,
+        Token::Amp => 6,
+        Token::Eq | Token::Ne => 7,
+        Token::And => 8,
+        Token::Or => 9,
+        _ => return None,
+    })
+}
+
+/// Splits the inside of a group after each of its commas.
+fn elements<'a, 'src>(pieces: &'a [Piece<'src>]) -> Vec<&'a [Piece<'src>]> {
+    let mut elements = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, piece) in pieces.iter().enumerate() {
+        match piece.role {
+            Role::Open => depth += 1,
+            Role::Close => depth = depth.saturating_sub(1),
+            Role::Separator if depth == 0 => {
+                elements.push(&pieces[start..=i]);
+                start = i + 1;
+            }
+            _ => {}
         }
     }
-    Ok(())
+    if start < pieces.len() {
+        elements.push(&pieces[start..]);
+    }
+    elements
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    /// A statement or the header of a block.
+    Statement,
+    /// A struct or enum declaration, which always has one field on each line.
+    Declaration,
+    /// An element inside of brackets, or part of one.
+    Element,
+}
+
+#[derive(Default)]
+struct Formatter<'src> {
+    lines: Vec<String>,
+    indent: usize,
+    /// The tokens of the statement or header being formatted.
+    line: Vec<Piece<'src>>,
+    /// The number of brackets which are open in `line`.
+    depth: usize,
+    /// Whether `line` is the header of a block, so the next `{` opens the block.
+    header: bool,
+    /// Whether the last line ends with an opening bracket.
+    after_open: bool,
+    /// Whether the last line is code without a comment after it.
+    joinable: bool,
+}
+
+impl<'src> Formatter<'src> {
+    fn format(mut self, items: Vec<Item<'src>>, comments: &[Comment]) -> String {
+        let mut items = items.into_iter().peekable();
+        while let Some(item) = items.next() {
+            let mut piece = match item {
+                Item::Piece(piece) => piece,
+                Item::Verbatim {
+                    text,
+                    blank_before,
+                    comments,
+                } => {
+                    self.flush();
+                    self.comments(&comments, self.indent);
+                    if blank_before {
+                        self.blank();
+                    }
+                    self.lines.extend(text.lines().map(str::to_owned));
+                    self.after_open = false;
+                    self.joinable = false;
+                    continue;
+                }
+            };
+            let statement_start = self
+                .line
+                .last()
+                .is_none_or(|last| last.token == Token::RBrace && last.role == Role::Other);
+            if statement_start && is_header(&piece.token) {
+                self.header = true;
+            }
+            match piece.token {
+                Token::LBrace if self.depth == 0 && self.header => {
+                    let has_trailing = piece.trailing.is_some();
+                    self.push(piece);
+                    // An empty block stays on the line of its header.
+                    let close = items.next_if(|next| {
+                        matches!(next, Item::Piece(close)
+                            if close.token == Token::RBrace && close.comments.is_empty())
+                            && !has_trailing
+                    });
+                    if let Some(Item::Piece(close)) = close {
+                        self.header = false;
+                        self.push(close);
+                        self.end_block(items.peek());
+                    } else {
+                        self.flush();
+                        self.indent += 1;
+                    }
+                }
+                Token::RBrace if self.depth == 0 => {
+                    self.flush();
+                    self.comments(&mem::take(&mut piece.comments), self.indent);
+                    self.indent = self.indent.saturating_sub(1);
+                    piece.blank_before = false;
+                    self.push(piece);
+                    self.end_block(items.peek());
+                }
+                // An extra semicolon is kept at the end of the previous line.
+                Token::Semicolon
+                    if self.line.is_empty() && piece.comments.is_empty() && self.joinable =>
+                {
+                    let last = self.lines.last_mut().unwrap();
+                    last.push(';');
+                    if let Some(trailing) = &piece.trailing {
+                        last.push_str(trailing);
+                        self.joinable = false;
+                    }
+                }
+                Token::Semicolon if self.depth == 0 => {
+                    self.push(piece);
+                    self.flush();
+                }
+                _ => {
+                    if piece.token == Token::FatArrow && self.depth == 0 {
+                        self.header = true;
+                    }
+                    piece.role = match piece.token {
+                        Token::LParen | Token::LBracket | Token::LBrace => Role::Open,
+                        Token::RParen | Token::RBracket | Token::RBrace => Role::Close,
+                        Token::Comma | Token::Semicolon => Role::Separator,
+                        _ => Role::Other,
+                    };
+                    match piece.role {
+                        Role::Open => self.depth += 1,
+                        Role::Close => self.depth = self.depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    let closes_declaration = piece.role == Role::Close
+                        && self.depth == 0
+                        && matches!(
+                            self.line.first().map(|first| &first.token),
+                            Some(Token::Struct | Token::Enum)
+                        );
+                    self.push(piece);
+                    if closes_declaration {
+                        self.flush();
+                    }
+                }
+            }
+        }
+        self.flush();
+        self.comments(comments, self.indent);
+        while self.lines.last().is_some_and(String::is_empty) {
+            self.lines.pop();
+        }
+        if self.lines.is_empty() {
+            return String::new();
+        }
+        self.lines.join("\n") + "\n"
+    }
+
+    /// Ends the line after the `}` of a block, unless an `else` or a semicolon follows.
+    fn end_block(&mut self, next: Option<&Item>) {
+        let continues = matches!(
+            next,
+            Some(Item::Piece(next))
+                if next.comments.is_empty()
+                    && matches!(next.token, Token::Else | Token::Elif | Token::Semicolon)
+        );
+        if !continues {
+            self.flush();
+        }
+    }
+
+    fn push(&mut self, mut piece: Piece<'src>) {
+        if let Some(prev) = self.line.last() {
+            // The name at the start of a statement is a block or procedure, so a minus
+            // sign after it is a negative argument.
+            piece.unary = piece.token == Token::Minus
+                && (!ends_value(prev)
+                    || (self.line.len() == 1 && matches!(prev.token, Token::Name(_))));
+            piece.space = space_between(prev, &piece);
+        } else {
+            piece.unary = piece.token == Token::Minus;
+        }
+        self.line.push(piece);
+    }
+
+    fn flush(&mut self) {
+        if self.line.is_empty() {
+            return;
+        }
+        let line = mem::take(&mut self.line);
+        let context = match line[0].token {
+            Token::Struct | Token::Enum => Context::Declaration,
+            _ => Context::Statement,
+        };
+        self.layout(&line, self.indent, context);
+        self.depth = 0;
+        self.header = false;
+    }
+
+    fn blank(&mut self) {
+        if !self.after_open && self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn comments(&mut self, comments: &[Comment], indent: usize) {
+        for comment in comments {
+            if comment.blank_before {
+                self.blank();
+            }
+            self.lines
+                .push(format!("{}{}", INDENT.repeat(indent), comment.text));
+            self.after_open = false;
+            self.joinable = false;
+        }
+    }
+
+    fn emit(&mut self, pieces: &[Piece], indent: usize) {
+        let last = &pieces[pieces.len() - 1];
+        let mut line = format!("{}{}", INDENT.repeat(indent), flat(pieces));
+        if let Some(trailing) = &last.trailing {
+            line.push_str(trailing);
+        }
+        self.lines.push(line);
+        self.after_open = matches!(last.token, Token::LParen | Token::LBracket | Token::LBrace);
+        self.joinable = last.trailing.is_none();
+    }
+
+    fn layout(&mut self, pieces: &[Piece], indent: usize, context: Context) {
+        self.comments(&pieces[0].comments, indent);
+        if pieces[0].blank_before {
+            self.blank();
+        }
+        self.layout_code(pieces, indent, context);
+    }
+
+    /// Formats `pieces` as one line if it fits, or splits it at the outermost brackets.
+    /// Elements inside of brackets are split before their operators first, and code
+    /// without brackets is split before its operators.
+    fn layout_code(&mut self, pieces: &[Piece], indent: usize, context: Context) {
+        let comment = inner_comment(pieces);
+        if context != Context::Declaration && comment.is_none() && fits(indent, pieces) {
+            self.emit(pieces, indent);
+            return;
+        }
+        let operators = operators(pieces);
+        if context == Context::Element && comment.is_none() && !operators.is_empty() {
+            self.split_operators(pieces, &operators, indent, indent);
+            return;
+        }
+        let groups = groups(pieces);
+        let group = if context == Context::Declaration {
+            groups.first()
+        } else if let Some(comment) = comment {
+            groups
+                .iter()
+                .find(|(open, close)| *open <= comment && comment < *close)
+        } else {
+            // Prefer the last group, unless the code around it would still be too long.
+            groups
+                .iter()
+                .rev()
+                .find(|(open, close)| {
+                    fits(indent, &pieces[..=*open]) && fits(indent, &pieces[*close..])
+                })
+                .or(groups.last())
+        };
+        let Some(&(open, close)) = group else {
+            if comment.is_none() && !operators.is_empty() {
+                self.split_operators(pieces, &operators, indent, indent + 1);
+            } else {
+                self.hang(pieces, indent);
+            }
+            return;
+        };
+        self.layout_code(&pieces[..=open], indent, Context::Statement);
+        for element in elements(&pieces[open + 1..close]) {
+            self.layout(element, indent + 1, Context::Element);
+        }
+        self.comments(&pieces[close].comments, indent + 1);
+        self.layout_code(&pieces[close..], indent, Context::Statement);
+    }
+
+    /// Starts a new line before each of `operators`, indented by `continuation`.
+    fn split_operators(
+        &mut self,
+        pieces: &[Piece],
+        operators: &[usize],
+        indent: usize,
+        continuation: usize,
+    ) {
+        let mut start = 0;
+        for &end in operators.iter().chain([&pieces.len()]) {
+            let indent = if start == 0 { indent } else { continuation };
+            self.layout_code(&pieces[start..end], indent, Context::Element);
+            start = end;
+        }
+    }
+
+    /// Formats `pieces` which cannot be split at brackets, only breaking the line where
+    /// a comment requires it.
+    fn hang(&mut self, pieces: &[Piece], indent: usize) {
+        let mut start = 0;
+        let mut current = indent;
+        for (i, piece) in pieces.iter().enumerate() {
+            if i > 0 && !piece.comments.is_empty() {
+                if i > start {
+                    self.emit(&pieces[start..i], current);
+                }
+                current = indent + 1;
+                self.comments(&piece.comments, current);
+                start = i;
+            }
+            if piece.trailing.is_some() && i + 1 < pieces.len() {
+                self.emit(&pieces[start..=i], current);
+                current = indent + 1;
+                start = i + 1;
+            }
+        }
+        if start < pieces.len() {
+            self.emit(&pieces[start..], current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(src: &str) -> String {
+        let Ok(formatted) = format_source(src) else {
+            panic!("could not format {src:?}");
+        };
+        let Ok(again) = format_source(&formatted) else {
+            panic!("could not format {formatted:?}");
+        };
+        assert_eq!(formatted, again, "formatting is not idempotent");
+        formatted
+    }
+
+    #[test]
+    fn layout_is_normalized() {
+        let src = r#"struct P {x=0,y}
+onflag{x=-1;if x>0{say x;}
+else{}
+
+
+  P p=P{x:1,y:2};}
+"#;
+        assert_eq!(
+            format(src),
+            r#"struct P {
+    x = 0,
+    y
+}
+onflag {
+    x = -1;
+    if x > 0 {
+        say x;
+    } else {}
+
+    P p = P { x: 1, y: 2 };
+}
+"#
+        );
+    }
+
+    #[test]
+    fn comments_and_directives_are_kept() {
+        let src = r#"import "lib" as lib
+%define A(x) x + \
+  1
+# leading
+onflag { # header
+ say f"{A(1)}";   # trailing
+        # end
+}
+"#;
+        let define = format!("{:<87}\\", "%define A(x) x +");
+        assert_eq!(
+            format(src),
+            format!(
+                r#"import "lib" as lib
+{define}
+  1
+# leading
+onflag {{ # header
+    say f"{{A(1)}}";   # trailing
+    # end
+}}
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn long_lines_are_split() {
+        let src = r#"onflag {
+    say join("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb");
+    total = first_value_with_a_long_name * 2 + second_value_with_a_long_name * 3 + third;
+}
+"#;
+        assert_eq!(
+            format(src),
+            r#"onflag {
+    say join(
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+    );
+    total = first_value_with_a_long_name * 2
+        + second_value_with_a_long_name * 3
+        + third;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn invalid_tokens_are_not_formatted() {
+        let Err(FmtError::AnyhowError(err)) = format_source("onflag {\n    say ?;\n}\n") else {
+            panic!("formatted a file with an invalid token");
+        };
+        assert_eq!(err.to_string(), "2:9: invalid token");
+    }
 }
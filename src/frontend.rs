@@ -1,9 +1,17 @@
 pub mod build;
 mod cli;
+mod decompile;
 mod fmt;
 mod new;
+mod run;
 
-use std::process::ExitCode;
+use std::{
+    cell::RefCell,
+    env,
+    path::PathBuf,
+    process::ExitCode,
+    rc::Rc,
+};
 
 use clap::{
     CommandFactory,
@@ -12,33 +20,79 @@ use clap::{
 use cli::{
     Cli,
     Command,
+    MessageFormat,
 };
 use colored::Colorize;
 use new::NewError;
+use run::Run;
 
 use crate::{
     config::Config,
+    diagnostic::{
+        sarif,
+        Artifact,
+        LintLevel,
+    },
     fmt::FmtError,
+    misc::SmolStr,
+    vfs::RealFS,
+    vm::Outcome,
 };
 
 pub fn frontend() -> ExitCode {
     match Cli::parse().command {
-        Command::Build { input, output } => match build::build(input, output) {
-            Ok(artifact) => {
-                artifact.eprint();
-                eprintln!();
-                if artifact.block_count > 0 {
-                    eprintln!(
-                        "{} {} {}",
-                        "Emitted".dimmed(),
-                        artifact.block_count.to_string().dimmed(),
-                        "blocks".dimmed()
-                    );
-                }
-                if artifact.failure() {
-                    ExitCode::FAILURE
-                } else {
+        Command::Build {
+            input,
+            output,
+            watch: false,
+            source_map,
+            debug,
+            allow,
+            warn,
+            deny,
+            message_format,
+        } => report_build(
+            build::build(
+                input,
+                output,
+                source_map,
+                debug,
+                lint_levels(allow, warn, deny),
+            ),
+            message_format,
+        ),
+        Command::Build {
+            input,
+            output,
+            watch: true,
+            source_map,
+            debug,
+            allow,
+            warn,
+            deny,
+            message_format,
+        } => match build::watch(
+            input,
+            output,
+            source_map,
+            debug,
+            lint_levels(allow, warn, deny),
+            |result| {
+                report_build(result, message_format);
+            },
+        ) {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{}: {:?}", "error".red().bold(), err);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Run { input, timeout } => match run::run(input, timeout, true) {
+            Ok(run) => {
+                if report_run(&run, timeout) {
                     ExitCode::SUCCESS
+                } else {
+                    ExitCode::FAILURE
                 }
             }
             Err(err) => {
@@ -46,6 +100,14 @@ pub fn frontend() -> ExitCode {
                 ExitCode::FAILURE
             }
         },
+        Command::Test { inputs, timeout } => test(inputs, timeout),
+        Command::Lsp => match crate::lsp::serve() {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{}: {:?}", "error".red().bold(), err);
+                ExitCode::FAILURE
+            }
+        },
         Command::Completions { shell } => {
             shell.generate(&mut Cli::command(), &mut std::io::stdout());
             ExitCode::SUCCESS
@@ -83,6 +145,9 @@ pub fn frontend() -> ExitCode {
                     high_quality_pen: high_quality_pen.then_some(true),
                     stage_width,
                     stage_height,
+                    inline_threshold: None,
+                    extensions: vec![],
+                    lints: Default::default(),
                 },
             ) {
                 Err(NewError::AnyhowError(err)) => {
@@ -105,8 +170,21 @@ pub fn frontend() -> ExitCode {
                 Ok(_) => ExitCode::SUCCESS,
             }
         }
-        Command::Fmt { input } => match fmt::fmt(input) {
-            Ok(_) => ExitCode::SUCCESS,
+        Command::Decompile { input, output } => match decompile::decompile(input, output) {
+            Ok(decompiled) => {
+                for warning in &decompiled.warnings {
+                    eprintln!("{}: {}", "warning".yellow().bold(), warning);
+                }
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{}: {:?}", "error".red().bold(), err);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Fmt { input, check } => match fmt::fmt(input, check) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::FAILURE,
             Err(FmtError::AnyhowError(err)) => {
                 eprintln!("{}: {:?}", "error".red().bold(), err);
                 ExitCode::FAILURE
@@ -114,3 +192,127 @@ pub fn frontend() -> ExitCode {
         },
     }
 }
+
+fn test(mut inputs: Vec<PathBuf>, timeout: f64) -> ExitCode {
+    if inputs.is_empty() {
+        inputs.push(env::current_dir().unwrap());
+    }
+    let mut passed = 0;
+    let mut failed = 0;
+    for input in &inputs {
+        eprintln!("{} {}", "Testing".cyan().bold(), input.display());
+        let result = run::test(input.clone(), timeout, |test, run| {
+            let name = match test {
+                Some(test) => format!("{}: {}", test.sprite, test.name),
+                None => input.display().to_string(),
+            };
+            if report_run(run, Some(timeout)) {
+                passed += 1;
+                eprintln!("{} {}", "PASS".green().bold(), name);
+            } else {
+                failed += 1;
+                eprintln!("{} {}", "FAIL".red().bold(), name);
+            }
+        });
+        if let Err(err) = result {
+            eprintln!("{}: {:?}", "error".red().bold(), err);
+            failed += 1;
+            eprintln!("{} {}", "FAIL".red().bold(), input.display());
+        }
+    }
+    eprintln!();
+    eprintln!("{} passed, {} failed", passed, failed);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn report_run(run: &Run, timeout: Option<f64>) -> bool {
+    for opcode in &run.unsupported {
+        eprintln!(
+            "{}: {} blocks are not supported, and did nothing",
+            "warning".yellow().bold(),
+            opcode
+        );
+    }
+    if run.outcome == Some(Outcome::TimedOut) {
+        eprintln!(
+            "{}: still running after {} seconds",
+            "error".red().bold(),
+            timeout.unwrap()
+        );
+    }
+    run.passed()
+}
+
+/// The levels of lints given on the command line, in the order they are applied.
+fn lint_levels(
+    allow: Vec<String>,
+    warn: Vec<String>,
+    deny: Vec<String>,
+) -> Vec<(SmolStr, LintLevel)> {
+    let allow = allow
+        .into_iter()
+        .map(|lint| (lint.into(), LintLevel::Allow));
+    let warn = warn.into_iter().map(|lint| (lint.into(), LintLevel::Warn));
+    let deny = deny.into_iter().map(|lint| (lint.into(), LintLevel::Deny));
+    allow.chain(warn).chain(deny).collect()
+}
+
+fn report_build(result: anyhow::Result<Artifact>, message_format: MessageFormat) -> ExitCode {
+    match result {
+        Ok(artifact) => {
+            match message_format {
+                MessageFormat::Human => artifact.eprint(),
+                MessageFormat::Json | MessageFormat::Sarif => {
+                    let cwd = env::current_dir().unwrap().canonicalize().unwrap();
+                    let reports = match artifact.reports(Rc::new(RefCell::new(RealFS)), &cwd) {
+                        Ok(reports) => reports,
+                        Err(err) => {
+                            eprintln!("{}: {:?}", "error".red().bold(), err);
+                            return ExitCode::FAILURE;
+                        }
+                    };
+                    if let MessageFormat::Json = message_format {
+                        for report in &reports {
+                            println!("{}", serde_json::to_string(report).unwrap());
+                        }
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&sarif(&reports)).unwrap()
+                        );
+                    }
+                }
+            }
+            eprintln!();
+            if artifact.block_count > 0 {
+                eprintln!(
+                    "{} {} {}",
+                    "Emitted".dimmed(),
+                    artifact.block_count.to_string().dimmed(),
+                    "blocks".dimmed()
+                );
+            }
+            if artifact.blocks_saved > 0 {
+                eprintln!(
+                    "{} {} {}",
+                    "Saved".dimmed(),
+                    artifact.blocks_saved.to_string().dimmed(),
+                    "unused or unreachable blocks".dimmed()
+                );
+            }
+            if artifact.failure() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        Err(err) => {
+            eprintln!("{}: {:?}", "error".red().bold(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
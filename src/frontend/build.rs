@@ -2,23 +2,245 @@ use std::{
     cell::RefCell,
     env,
     fs::File,
-    io::BufWriter,
-    path::PathBuf,
+    io::{
+        BufWriter,
+        Cursor,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
     rc::Rc,
+    sync::mpsc,
+    time::Duration,
 };
 
+use colored::Colorize;
+use notify::{
+    EventKind,
+    RecursiveMode,
+    Watcher,
+};
+use rustc_hash::FxHashSet;
+
 use crate::{
-    codegen::build::build_impl,
-    diagnostic::Artifact,
+    codegen::{
+        build::{
+            build_with_cache,
+            ParseCache,
+        },
+        source_map::{
+            SourceMap,
+            SOURCE_MAP_FILE_NAME,
+        },
+    },
+    diagnostic::{
+        is_lint,
+        Artifact,
+        LintLevel,
+    },
+    misc::SmolStr,
+    translation_unit::Owner,
     vfs::RealFS,
 };
 
-pub fn build(input: Option<PathBuf>, output: Option<PathBuf>) -> anyhow::Result<Artifact> {
+/// How long to wait for more changes after one is seen, so that saving several files at
+/// once only causes one rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+fn paths(input: Option<PathBuf>, output: Option<PathBuf>) -> anyhow::Result<(PathBuf, PathBuf)> {
     let input = input.unwrap_or_else(|| env::current_dir().unwrap());
     let canonical_input = input.canonicalize()?;
     let project_name = canonical_input.file_name().unwrap().to_str().unwrap();
     let output = output.unwrap_or_else(|| input.join(format!("{project_name}.sb3")));
+    Ok((canonical_input, output))
+}
+
+fn source_map_path(output: &Path) -> PathBuf {
+    output.with_file_name(SOURCE_MAP_FILE_NAME)
+}
+
+fn check_lints(lints: &[(SmolStr, LintLevel)]) -> anyhow::Result<()> {
+    if let Some((lint, _)) = lints.iter().find(|(lint, _)| !is_lint(lint)) {
+        anyhow::bail!("unknown lint `{lint}`");
+    }
+    Ok(())
+}
+
+fn build_once(
+    input: &Path,
+    output: &Path,
+    source_map: bool,
+    debug: bool,
+    lints: &[(SmolStr, LintLevel)],
+    cache: &mut ParseCache,
+) -> anyhow::Result<Artifact> {
     let fs = Rc::new(RefCell::new(RealFS));
-    let file = BufWriter::new(File::create(&output)?);
-    build_impl(fs, canonical_input, file, None)
+    let file = BufWriter::new(File::create(output)?);
+    let artifact = build_with_cache(
+        fs.clone(),
+        input.to_path_buf(),
+        file,
+        None,
+        false,
+        !debug,
+        lints,
+        cache,
+    )?;
+    if source_map && !artifact.failure() {
+        let source_map = SourceMap::new(fs, input, &artifact)?;
+        let file = BufWriter::new(File::create(source_map_path(output))?);
+        serde_json::to_writer(file, &source_map)?;
+    }
+    Ok(artifact)
+}
+
+pub fn build(
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    source_map: bool,
+    debug: bool,
+    lints: Vec<(SmolStr, LintLevel)>,
+) -> anyhow::Result<Artifact> {
+    check_lints(&lints)?;
+    let (input, output) = paths(input, output)?;
+    build_once(
+        &input,
+        &output,
+        source_map,
+        debug,
+        &lints,
+        &mut ParseCache::default(),
+    )
+}
+
+/// Build the project into memory instead of a file, to run it.
+pub fn build_in_memory(
+    input: Option<PathBuf>,
+    include_tests: bool,
+) -> anyhow::Result<(Artifact, Vec<u8>)> {
+    let input = input
+        .unwrap_or_else(|| env::current_dir().unwrap())
+        .canonicalize()?;
+    let mut sb3 = Cursor::new(vec![]);
+    let artifact = build_with_cache(
+        Rc::new(RefCell::new(RealFS)),
+        input,
+        &mut sb3,
+        None,
+        include_tests,
+        true,
+        &[],
+        &mut ParseCache::default(),
+    )?;
+    Ok((artifact, sb3.into_inner()))
+}
+
+/// Build the project, and then build it again every time one of its source files or
+/// assets changes. Only returns if the project directory can no longer be watched.
+pub fn watch(
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    source_map: bool,
+    debug: bool,
+    lints: Vec<(SmolStr, LintLevel)>,
+    mut report: impl FnMut(anyhow::Result<Artifact>),
+) -> anyhow::Result<()> {
+    check_lints(&lints)?;
+    let (input, output) = paths(input, output)?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&input, RecursiveMode::Recursive)?;
+    let mut cache = ParseCache::default();
+    // Files outside the project directory that the project depends on, and the
+    // directories that are being watched for them.
+    let mut external_files: FxHashSet<PathBuf> = FxHashSet::default();
+    let mut external_dirs: FxHashSet<PathBuf> = FxHashSet::default();
+    loop {
+        let result = build_once(&input, &output, source_map, debug, &lints, &mut cache);
+        if let Ok(artifact) = &result {
+            external_files = dependencies(&input, artifact)
+                .into_iter()
+                .filter(|path| !path.starts_with(&input))
+                .collect();
+            let dirs: FxHashSet<PathBuf> = external_files
+                .iter()
+                .filter_map(|path| path.parent().map(Path::to_path_buf))
+                .collect();
+            for dir in external_dirs.difference(&dirs) {
+                let _ = watcher.unwatch(dir);
+            }
+            for dir in dirs.difference(&external_dirs) {
+                if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    eprintln!(
+                        "{}: cannot watch {}: {}",
+                        "warning".yellow().bold(),
+                        dir.display(),
+                        err
+                    );
+                }
+            }
+            external_dirs = dirs;
+        }
+        report(result);
+        let output = output.canonicalize().unwrap_or_else(|_| output.clone());
+        let source_map_path = source_map_path(&output);
+        let is_relevant = |path: &Path| {
+            path != output
+                && path != source_map_path
+                && if path.starts_with(&input) {
+                    !path.strip_prefix(&input).unwrap().starts_with(".git")
+                } else {
+                    external_files.contains(path)
+                }
+        };
+        let mut changed = None;
+        while changed.is_none() {
+            let event = receiver.recv()??;
+            changed = changed_path(&event, is_relevant);
+        }
+        // Collect the rest of the changes that happen at the same time.
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            event?;
+        }
+        let changed = changed.unwrap();
+        eprintln!();
+        eprintln!(
+            "{} {}",
+            "Changed".cyan().bold(),
+            changed.strip_prefix(&input).unwrap_or(&changed).display()
+        );
+    }
+}
+
+fn changed_path(event: &notify::Event, is_relevant: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+        return None;
+    }
+    event.paths.iter().find(|path| is_relevant(path)).cloned()
+}
+
+/// Every file that a build of the project read: the included source files and the assets
+/// of every sprite.
+fn dependencies(input: &Path, artifact: &Artifact) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    for sprite_diagnostics in
+        std::iter::once(&artifact.stage_diagnostics).chain(artifact.sprites_diagnostics.values())
+    {
+        for include in sprite_diagnostics.translation_unit.includes() {
+            if matches!(include.owner, Owner::Local) {
+                paths.push(include.path.clone());
+            }
+        }
+    }
+    for sprite in std::iter::once(&artifact.project.stage).chain(artifact.project.sprites.values())
+    {
+        for asset in sprite.costumes.iter().chain(&sprite.sounds) {
+            paths.push(input.join(&*asset.path));
+        }
+    }
+    paths
+        .into_iter()
+        .map(|path| path.normalize_lexically().unwrap_or(path))
+        .collect()
 }
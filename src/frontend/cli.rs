@@ -8,6 +8,7 @@ use clap::builder::Styles;
 use clap_derive::{
     Parser,
     Subcommand,
+    ValueEnum,
 };
 
 const CLAP_STYLES: Styles = Styles::styled()
@@ -39,6 +40,57 @@ pub enum Command {
         #[arg(short, long)]
         /// Output file, if not given, it will be the project directory's name + `.sb3`
         output: Option<PathBuf>,
+        #[arg(short, long)]
+        /// Keep running, and rebuild whenever a source file or asset changes.
+        watch: bool,
+        #[arg(long)]
+        /// Also write a source map, which maps the blocks of the project back to the
+        /// source code, to `goboscript.map.json` next to the output file.
+        source_map: bool,
+        #[arg(long)]
+        /// Keep the procedures, functions, variables and lists which are not used, instead
+        /// of leaving them out of the project, and do not inline any calls.
+        debug: bool,
+        #[arg(short = 'A', long, value_name = "LINT")]
+        /// Do not report a lint. `warnings` is every lint.
+        allow: Vec<String>,
+        #[arg(short = 'W', long, value_name = "LINT")]
+        /// Report a lint as a warning.
+        warn: Vec<String>,
+        #[arg(short = 'D', long, value_name = "LINT")]
+        /// Report a lint as an error, which fails the build. A lint given to several of
+        /// these options is denied, then warned about, then allowed.
+        deny: Vec<String>,
+        #[arg(long, value_enum, default_value = "human")]
+        /// How to print diagnostics. `json` prints one JSON object per line and `sarif`
+        /// prints a SARIF 2.1.0 log, both to stdout.
+        message_format: MessageFormat,
+    },
+
+    /// Build a goboscript project, and run it without graphics. What sprites say is
+    /// printed.
+    #[command(alias = "r")]
+    Run {
+        #[arg()]
+        /// Project directory, if not given, the current directory is used.
+        input: Option<PathBuf>,
+        #[arg(short, long)]
+        /// Stop after this many seconds have passed in the project.
+        timeout: Option<f64>,
+    },
+
+    /// Build and run the `test` blocks of goboscript projects without graphics, and
+    /// check that they finish without errors or failed assertions. Projects without
+    /// `test` blocks are run from the green flag instead.
+    #[command(alias = "t")]
+    Test {
+        #[arg()]
+        /// Project directories, if not given, the current directory is used.
+        inputs: Vec<PathBuf>,
+        #[arg(short, long, default_value_t = 60.0)]
+        /// Fail if a test is still running after this many seconds have passed in the
+        /// project.
+        timeout: f64,
     },
 
     /// Create a new goboscript project with a blank backdrop, a main sprite with a
@@ -100,14 +152,34 @@ pub enum Command {
         makefile: bool,
     },
 
+    /// Convert a `.sb3` file back into a goboscript project.
+    #[command(alias = "d")]
+    Decompile {
+        /// The `.sb3` file to decompile.
+        #[arg()]
+        input: PathBuf,
+        /// Project directory to create, if not given, it will be the input file's name
+        /// without the `.sb3` extension. If it exists, it must be empty.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
     /// Format a goboscript project.
     #[command(alias = "f")]
     Fmt {
         /// Project directory or file, if not given, the current directory is used.
         #[arg(short, long)]
         input: Option<PathBuf>,
+        /// Don't write the files, instead print a diff of each file which is not
+        /// formatted and fail if there are any.
+        #[arg(long)]
+        check: bool,
     },
 
+    /// Start the language server, which communicates over stdin and stdout.
+    #[command()]
+    Lsp,
+
     /// Generate completions for a shell.
     #[command()]
     Completions {
@@ -116,3 +188,13 @@ pub enum Command {
         shell: clap_complete_command::Shell,
     },
 }
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MessageFormat {
+    /// Annotated source code, printed to stderr.
+    Human,
+    /// One JSON object per diagnostic, each on its own line.
+    Json,
+    /// A SARIF 2.1.0 log, for code scanning tools.
+    Sarif,
+}
@@ -0,0 +1,64 @@
+use std::{
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        Read,
+    },
+    path::PathBuf,
+};
+
+use anyhow::{
+    bail,
+    Context,
+};
+use zip::ZipArchive;
+
+use crate::decompile::{
+    self,
+    Decompiled,
+};
+
+pub fn decompile(input: PathBuf, output: Option<PathBuf>) -> anyhow::Result<Decompiled> {
+    let output = output.unwrap_or_else(|| input.with_extension(""));
+    if output == input {
+        bail!("output directory is the same as the input file, pass --output");
+    }
+    let mut archive = ZipArchive::new(File::open(&input)?)
+        .with_context(|| format!("{} is not a valid .sb3 file", input.display()))?;
+    let mut project_json = String::new();
+    archive
+        .by_name("project.json")
+        .context("project.json not found in .sb3 file")?
+        .read_to_string(&mut project_json)?;
+    let project = serde_json::from_str(&project_json)?;
+    let mut decompiled = decompile::decompile(&project)?;
+
+    let _ = fs::create_dir_all(&output);
+    if output.read_dir()?.count() > 0 {
+        bail!("{} is not empty", output.display());
+    }
+    for sprite in &decompiled.sprites {
+        fs::write(output.join(format!("{}.gs", sprite.name)), &sprite.source)?;
+    }
+    fs::create_dir_all(output.join("assets"))?;
+    for (md5ext, path) in &decompiled.assets {
+        let Ok(mut file) = archive.by_name(md5ext) else {
+            decompiled.warnings.push(format!(
+                "{md5ext} not found in .sb3 file, {path} was not written"
+            ));
+            continue;
+        };
+        io::copy(&mut file, &mut File::create(output.join(path))?)?;
+    }
+    let toml_data = toml::to_string(&decompiled.config)?;
+    fs::write(
+        output.join("goboscript.toml"),
+        format!(
+            "# Configuration Reference: <https://aspiz.uk/goboscript/docs/configuration.html>\n{toml_data}"
+        ),
+    )?;
+    Ok(decompiled)
+}
@@ -1,8 +1,14 @@
 use std::{
     env,
-    path::PathBuf,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
 };
 
+use anyhow::Context;
+use colored::Colorize;
 use glob::glob;
 
 use crate::fmt::{
@@ -10,15 +16,48 @@ use crate::fmt::{
     FmtError,
 };
 
-pub fn fmt(input: Option<PathBuf>) -> Result<(), FmtError> {
+/// Formats every `.gs` file in `input`. With `check`, files are not written, instead a
+/// diff is printed for each file which is not formatted. Files which cannot be formatted
+/// are reported and skipped. Returns whether every file was formatted, or was already
+/// formatted with `check`.
+pub fn fmt(input: Option<PathBuf>, check: bool) -> Result<bool, FmtError> {
     let input = input.unwrap_or_else(|| env::current_dir().unwrap());
-    if input.is_file() {
-        fmt::format_file(input)?;
+    let paths = if input.is_file() {
+        vec![input]
     } else {
-        for path in glob(input.join("**/*.gs").to_str().unwrap())? {
-            let path = path?;
-            fmt::format_file(path)?;
+        glob(input.join("**/*.gs").to_str().unwrap())?.collect::<Result<_, _>>()?
+    };
+    let mut formatted = true;
+    for path in paths {
+        match format_file(&path, check) {
+            Ok(is_formatted) => formatted &= is_formatted,
+            Err(FmtError::AnyhowError(err)) => {
+                eprintln!("{}: {:?}", "error".red().bold(), err);
+                formatted = false;
+            }
         }
     }
-    Ok(())
+    Ok(formatted)
+}
+
+fn format_file(path: &Path, check: bool) -> Result<bool, FmtError> {
+    let src =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let new = match fmt::format_source(&src) {
+        Ok(new) => new,
+        Err(FmtError::AnyhowError(err)) => {
+            return Err(err
+                .context(format!("failed to format {}", path.display()))
+                .into())
+        }
+    };
+    if new == src {
+        return Ok(true);
+    }
+    if check {
+        print!("{}", fmt::unified_diff(&path.to_string_lossy(), &src, &new));
+        return Ok(false);
+    }
+    fs::write(path, new).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(true)
 }
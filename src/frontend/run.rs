@@ -0,0 +1,197 @@
+use std::{
+    io::{
+        self,
+        BufRead,
+        Cursor,
+    },
+    path::PathBuf,
+};
+
+use colored::Colorize;
+
+use super::build::build_in_memory;
+use crate::{
+    ast::{
+        EventKind,
+        Sprite,
+    },
+    codegen::sb3::STAGE_NAME,
+    diagnostic::{
+        Artifact,
+        DiagnosticKind,
+    },
+    misc::SmolStr,
+    vm::{
+        Host,
+        LogLevel,
+        Outcome,
+        VM,
+    },
+};
+
+/// Prints what sprites say, and reads answers to questions from stdin if interactive.
+/// Failed assertions are reported as diagnostics of the project.
+struct Console<'a> {
+    artifact: &'a Artifact,
+    is_interactive: bool,
+    errors: usize,
+}
+
+impl Host for Console<'_> {
+    fn say(&mut self, sprite: &str, message: &str) {
+        println!("{sprite}: {message}");
+    }
+
+    fn log(&mut self, level: LogLevel, sprite: &str, message: &str) {
+        match level {
+            LogLevel::Log => println!("{sprite}: {message}"),
+            LogLevel::Warn => eprintln!("{}: {sprite}: {message}", "warning".yellow().bold()),
+            LogLevel::Error => {
+                self.errors += 1;
+                eprintln!("{}: {sprite}: {message}", "error".red().bold());
+            }
+        }
+    }
+
+    fn assertion_failed(&mut self, sprite: &str, block: &str, values: Option<(&str, &str)>) {
+        self.artifact.eprint_at_block(
+            sprite,
+            block,
+            DiagnosticKind::AssertionFailed {
+                values: values.map(|(left, right)| (left.into(), right.into())),
+            },
+        );
+    }
+
+    fn ask(&mut self, sprite: &str, question: &str) -> String {
+        if !self.is_interactive {
+            return String::new();
+        }
+        println!("{sprite}: {question}");
+        let mut answer = String::new();
+        let _ = io::stdin().lock().read_line(&mut answer);
+        answer.trim_end_matches(['\r', '\n']).to_owned()
+    }
+}
+
+pub struct Run {
+    /// `None` if the project failed to build.
+    pub outcome: Option<Outcome>,
+    /// How many times `error` was used.
+    pub errors: usize,
+    /// Opcodes of blocks that were run, but are not supported and did nothing.
+    pub unsupported: Vec<SmolStr>,
+}
+
+impl Run {
+    pub fn passed(&self) -> bool {
+        self.outcome
+            .is_some_and(|outcome| !matches!(outcome, Outcome::TimedOut | Outcome::Failed))
+            && self.errors == 0
+    }
+}
+
+/// A `test` block.
+pub struct Test {
+    /// Name of the target in `project.json`.
+    pub sprite: SmolStr,
+    pub name: SmolStr,
+    broadcast: SmolStr,
+}
+
+/// Build the project, and run it until it finishes, or until `timeout` seconds have
+/// passed in the project. Diagnostics are printed before it starts running.
+pub fn run(
+    input: Option<PathBuf>,
+    timeout: Option<f64>,
+    is_interactive: bool,
+) -> anyhow::Result<Run> {
+    let (artifact, sb3) = build_in_memory(input, false)?;
+    artifact.eprint();
+    if artifact.failure() {
+        return Ok(Run::build_failed());
+    }
+    let mut vm = VM::new(Cursor::new(sb3))?;
+    vm.green_flag();
+    Ok(run_vm(&artifact, vm, timeout, is_interactive))
+}
+
+/// Build the project with its `test` blocks, and run each of them by itself, until it
+/// finishes, or until `timeout` seconds have passed. `report` is called after each test.
+/// Projects without tests are run from the green flag instead, and reported without a
+/// test.
+pub fn test(
+    input: PathBuf,
+    timeout: f64,
+    mut report: impl FnMut(Option<&Test>, &Run),
+) -> anyhow::Result<()> {
+    let (artifact, sb3) = build_in_memory(Some(input), true)?;
+    artifact.eprint();
+    if artifact.failure() {
+        report(None, &Run::build_failed());
+        return Ok(());
+    }
+    let tests = tests(&artifact);
+    if tests.is_empty() {
+        let mut vm = VM::new(Cursor::new(&sb3))?;
+        vm.green_flag();
+        report(None, &run_vm(&artifact, vm, Some(timeout), false));
+        return Ok(());
+    }
+    for test in &tests {
+        let mut vm = VM::new(Cursor::new(&sb3))?;
+        vm.broadcast(&test.broadcast, Some(&test.sprite));
+        report(Some(test), &run_vm(&artifact, vm, Some(timeout), false));
+    }
+    Ok(())
+}
+
+impl Run {
+    fn build_failed() -> Self {
+        Self {
+            outcome: None,
+            errors: 0,
+            unsupported: vec![],
+        }
+    }
+}
+
+fn run_vm(artifact: &Artifact, mut vm: VM, timeout: Option<f64>, is_interactive: bool) -> Run {
+    let mut console = Console {
+        artifact,
+        is_interactive,
+        errors: 0,
+    };
+    let outcome = vm.run(&mut console, timeout);
+    Run {
+        outcome: Some(outcome),
+        errors: console.errors,
+        unsupported: vm.unsupported().iter().cloned().collect(),
+    }
+}
+
+/// The tests of the Stage, and then of each sprite by name, in the order they are
+/// written.
+fn tests(artifact: &Artifact) -> Vec<Test> {
+    let mut sprites: Vec<(&str, &Sprite)> = artifact
+        .project
+        .sprites
+        .iter()
+        .map(|(name, sprite)| (name.as_str(), sprite))
+        .collect();
+    sprites.sort_by_key(|(name, _)| *name);
+    sprites.insert(0, (STAGE_NAME, &artifact.project.stage));
+    let mut tests = vec![];
+    for (sprite_name, sprite) in sprites {
+        for event in &sprite.events {
+            if let EventKind::Test { name } = &event.kind {
+                tests.push(Test {
+                    sprite: sprite_name.into(),
+                    name: name.clone(),
+                    broadcast: event.kind.broadcast().unwrap(),
+                });
+            }
+        }
+    }
+    tests
+}
@@ -1,22 +1,136 @@
+use std::collections::VecDeque;
+
 use logos::{
     Logos,
     SpannedIter,
 };
 
 use super::token::Token;
-use crate::diagnostic::{
-    Diagnostic,
-    DiagnosticKind,
+use crate::{
+    diagnostic::{
+        Diagnostic,
+        DiagnosticKind,
+    },
+    misc::SmolStr,
 };
 
+type Item = Result<(usize, Token, usize), Diagnostic>;
+
 pub struct Lexer<'source> {
+    source: &'source str,
+    offset: usize,
     token_stream: SpannedIter<'source, Token>,
+    pending: VecDeque<Item>,
 }
 
 impl<'source> Lexer<'source> {
     pub fn new(source: &'source str) -> Self {
+        Self::with_offset(source, 0)
+    }
+
+    /// Lexes `source`, which begins at `offset` in the file.
+    fn with_offset(source: &'source str, offset: usize) -> Self {
         Self {
+            source,
+            offset,
             token_stream: Token::lexer(source).spanned(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Splits an f-string into its text and the tokens of its interpolations. An
+    /// f-string without interpolations is a string.
+    fn fstring(&mut self, start: usize, end: usize) {
+        let source = &self.source[start..end];
+        let offset = self.offset + start;
+        let mut text = String::new();
+        let mut text_start = offset;
+        let mut is_first = true;
+        let mut chars = source[..source.len() - 1].char_indices().skip(2).peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    let mut escape_end = chars.next().map_or(i + 1, |(j, c)| j + c.len_utf8());
+                    if &source[i..escape_end] == "\\u" {
+                        for _ in 0..4 {
+                            if let Some((j, _)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                                escape_end = j + 1;
+                            }
+                        }
+                    }
+                    let escape = &source[i..escape_end];
+                    match unescape(escape) {
+                        Some(c) => text.push(c),
+                        None => self.pending.push_back(Err(Diagnostic {
+                            kind: DiagnosticKind::InvalidEscape(escape.into()),
+                            span: offset + i..offset + escape_end,
+                            notes: vec![],
+                        })),
+                    }
+                }
+                '{' | '}' if chars.next_if(|(_, next)| *next == c).is_some() => text.push(c),
+                '{' => {
+                    let mut depth = 1;
+                    let mut in_string = false;
+                    let mut interpolation_end = i + 1;
+                    while let Some((j, c)) = chars.next() {
+                        match c {
+                            '\\' => {
+                                chars.next();
+                            }
+                            '"' => in_string = !in_string,
+                            '{' if !in_string => depth += 1,
+                            '}' if !in_string => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    interpolation_end = j;
+                                    break;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    let text = SmolStr::from(std::mem::take(&mut text));
+                    let token = if std::mem::replace(&mut is_first, false) {
+                        Token::FStrBegin(text)
+                    } else {
+                        Token::FStrMiddle(text)
+                    };
+                    self.pending
+                        .push_back(Ok((text_start, token, offset + i + 1)));
+                    self.pending.extend(Lexer::with_offset(
+                        &source[i + 1..interpolation_end],
+                        offset + i + 1,
+                    ));
+                    text_start = offset + interpolation_end;
+                }
+                c => text.push(c),
+            }
+        }
+        let token = if is_first {
+            Token::Str(text.into())
+        } else {
+            Token::FStrEnd(text.into())
+        };
+        self.pending
+            .push_back(Ok((text_start, token, offset + source.len())));
+    }
+}
+
+/// The character written by an escape sequence, which is the same as in JSON strings.
+fn unescape(escape: &str) -> Option<char> {
+    match escape {
+        "\\\"" => Some('"'),
+        "\\\\" => Some('\\'),
+        "\\/" => Some('/'),
+        "\\b" => Some('\u{8}'),
+        "\\f" => Some('\u{c}'),
+        "\\n" => Some('\n'),
+        "\\r" => Some('\r'),
+        "\\t" => Some('\t'),
+        _ => {
+            let hex = escape.strip_prefix("\\u").filter(|hex| hex.len() == 4)?;
+            char::from_u32(u32::from_str_radix(hex, 16).ok()?)
         }
     }
 }
@@ -28,16 +142,68 @@ impl<'source> From<&'source str> for Lexer<'source> {
 }
 
 impl Iterator for Lexer<'_> {
-    type Item = Result<(usize, Token, usize), Diagnostic>;
+    type Item = Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.token_stream.next().map(|(token, span)| {
-            token
-                .map(|token| (span.start, token, span.end))
-                .map_err(|_| Diagnostic {
-                    kind: DiagnosticKind::InvalidToken,
-                    span: span.clone(),
-                })
-        })
+        if let Some(item) = self.pending.pop_front() {
+            return Some(item);
+        }
+        let (token, span) = self.token_stream.next()?;
+        match token {
+            Ok(Token::FStr) => {
+                self.fstring(span.start, span.end);
+                self.pending.pop_front()
+            }
+            Ok(token) => Some(Ok((
+                self.offset + span.start,
+                token,
+                self.offset + span.end,
+            ))),
+            Err(_) => Some(Err(Diagnostic {
+                kind: DiagnosticKind::InvalidToken,
+                span: self.offset + span.start..self.offset + span.end,
+                notes: vec![],
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fstring_is_split_around_its_interpolations() {
+        let source = r#"f"x: {x}, {{y}}: {f"{y}"}""#;
+        let tokens: Vec<_> = Lexer::new(source).collect::<Result<_, _>>().unwrap();
+        let tokens: Vec<_> = tokens
+            .into_iter()
+            .map(|(start, token, end)| (token, &source[start..end]))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (Token::FStrBegin("x: ".into()), r#"f"x: {"#),
+                (Token::Name("x".into()), "x"),
+                (Token::FStrMiddle(", {y}: ".into()), "}, {{y}}: {"),
+                (Token::FStrBegin("".into()), r#"f"{"#),
+                (Token::Name("y".into()), "y"),
+                (Token::FStrEnd("".into()), r#"}""#),
+                (Token::FStrEnd("".into()), r#"}""#),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_escape_in_fstring_points_at_the_escape() {
+        let source = r#"f"a\qb é{x}""#;
+        let diagnostics: Vec<_> = Lexer::new(source).filter_map(Result::err).collect();
+        let [diagnostic] = diagnostics.as_slice() else {
+            panic!("{diagnostics:?}");
+        };
+        assert!(
+            matches!(&diagnostic.kind, DiagnosticKind::InvalidEscape(escape) if escape == "\\q")
+        );
+        assert_eq!(&source[diagnostic.span.clone()], "\\q");
     }
 }
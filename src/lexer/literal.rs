@@ -13,6 +13,31 @@ pub fn string(lex: &mut Lexer<Token>) -> Option<SmolStr> {
         .map(SmolStr::from)
 }
 
+/// Consumes an f-string after its opening `f"`, including any strings inside of its
+/// interpolations.
+pub fn fstring(lex: &mut Lexer<Token>) -> bool {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut chars = lex.remainder().char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' if depth == 0 => {
+                lex.bump(i + 1);
+                return true;
+            }
+            '"' => in_string = !in_string,
+            '{' if depth == 0 && chars.next_if(|(_, c)| *c == '{').is_some() => {}
+            '{' if !in_string => depth += 1,
+            '}' if !in_string && depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+    false
+}
+
 pub fn arg(lex: &mut Lexer<Token>) -> SmolStr {
     SmolStr::from(&lex.slice()[1..])
 }
@@ -40,6 +40,15 @@ pub enum Token {
     Float(f64),
     #[regex(r#""([^"\\]|\\["\\/bfnrt]|\\u[0-9a-zA-Z]{4})*""#, string)]
     Str(SmolStr),
+    /// An f-string, which is split into the tokens below by the adaptor.
+    #[token("f\"", fstring)]
+    FStr,
+    /// The text of an f-string before its first interpolation.
+    FStrBegin(SmolStr),
+    /// The text of an f-string between two interpolations.
+    FStrMiddle(SmolStr),
+    /// The text of an f-string after its last interpolation.
+    FStrEnd(SmolStr),
     #[token("costumes")]
     Costumes,
     #[token("sounds")]
@@ -54,6 +63,10 @@ pub enum Token {
     Return,
     #[token("nowarp")]
     NoWarp,
+    #[token("inline")]
+    Inline,
+    #[token("recursive")]
+    Recursive,
     #[token("on")]
     On,
     #[token("onflag")]
@@ -68,8 +81,14 @@ pub enum Token {
     OnLoudness,
     #[token("ontimer")]
     OnTimer,
+    #[token("onvideomotion")]
+    OnVideoMotion,
+    #[token("onmakeykey")]
+    OnMakeyKey,
     #[token("onclone")]
     OnClone,
+    #[token("test")]
+    Test,
     #[token("if")]
     If,
     #[token("else")]
@@ -82,6 +101,16 @@ pub enum Token {
     Forever,
     #[token("repeat")]
     Repeat,
+    #[token("while")]
+    While,
+    #[token("for")]
+    For,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
+    #[token("match")]
+    Match,
     #[token(",")]
     Comma,
     #[token("(")]
@@ -96,6 +125,8 @@ pub enum Token {
     Assign,
     #[token("==")]
     Eq,
+    #[token("=>")]
+    FatArrow,
     #[token("++")]
     Increment,
     #[token("--")]
@@ -253,6 +284,10 @@ impl Display for Token {
             Token::Hex(value) => write!(f, "{}", value),
             Token::Float(value) => write!(f, "{}", value),
             Token::Str(value) => write!(f, "\"{}\"", value),
+            Token::FStr => write!(f, "f\""),
+            Token::FStrBegin(value) => write!(f, "f\"{}{{", value),
+            Token::FStrMiddle(value) => write!(f, "}}{}{{", value),
+            Token::FStrEnd(value) => write!(f, "}}{}\"", value),
             Token::Costumes => write!(f, "costumes"),
             Token::Sounds => write!(f, "sounds"),
             Token::Local => write!(f, "local"),
@@ -260,6 +295,8 @@ impl Display for Token {
             Token::Func => write!(f, "func"),
             Token::Return => write!(f, "return"),
             Token::NoWarp => write!(f, "nowarp"),
+            Token::Inline => write!(f, "inline"),
+            Token::Recursive => write!(f, "recursive"),
             Token::On => write!(f, "on"),
             Token::OnFlag => write!(f, "onflag"),
             Token::OnKey => write!(f, "onkey"),
@@ -267,13 +304,21 @@ impl Display for Token {
             Token::OnBackdrop => write!(f, "onbackdrop"),
             Token::OnLoudness => write!(f, "onloudness"),
             Token::OnTimer => write!(f, "ontimer"),
+            Token::OnVideoMotion => write!(f, "onvideomotion"),
+            Token::OnMakeyKey => write!(f, "onmakeykey"),
             Token::OnClone => write!(f, "onclone"),
+            Token::Test => write!(f, "test"),
             Token::If => write!(f, "if"),
             Token::Else => write!(f, "else"),
             Token::Elif => write!(f, "elif"),
             Token::Until => write!(f, "until"),
             Token::Forever => write!(f, "forever"),
             Token::Repeat => write!(f, "repeat"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+            Token::Match => write!(f, "match"),
             Token::Comma => write!(f, ","),
             Token::LParen => write!(f, "("),
             Token::RParen => write!(f, ")"),
@@ -281,6 +326,7 @@ impl Display for Token {
             Token::RBrace => write!(f, "}}"),
             Token::Assign => write!(f, "="),
             Token::Eq => write!(f, "=="),
+            Token::FatArrow => write!(f, "=>"),
             Token::Increment => write!(f, "++"),
             Token::Decrement => write!(f, "--"),
             Token::AssignAdd => write!(f, "+="),
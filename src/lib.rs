@@ -5,20 +5,27 @@ pub mod blocks;
 pub mod codegen;
 pub mod config;
 pub mod diagnostic;
+pub mod fmt;
 pub mod lexer;
 pub mod misc;
 pub mod parser;
 pub mod pre_processor;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod decompile;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod frontend;
 
 #[cfg(not(target_arch = "wasm32"))]
-pub mod fmt;
+pub mod lsp;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod standard_library;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod vm;
+
 #[cfg(target_arch = "wasm32")]
 pub mod standard_library {
     use std::path::Path;
@@ -46,6 +53,8 @@ pub mod standard_library {
         }
     }
 }
+#[cfg(test)]
+mod testing;
 pub mod translation_unit;
 pub mod vfs;
 pub mod visitor;
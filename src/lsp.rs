@@ -0,0 +1,355 @@
+mod symbols;
+mod workspace;
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use lsp_server::{
+    Connection,
+    Message,
+    Notification,
+    Request,
+    Response,
+};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument,
+        DidCloseTextDocument,
+        DidOpenTextDocument,
+        DidSaveTextDocument,
+        Notification as _,
+        PublishDiagnostics,
+    },
+    request::{
+        GotoDefinition,
+        HoverRequest,
+        References,
+        Request as _,
+    },
+    GotoDefinitionParams,
+    GotoDefinitionResponse,
+    Hover,
+    HoverContents,
+    HoverParams,
+    HoverProviderCapability,
+    Location,
+    MarkupContent,
+    MarkupKind,
+    OneOf,
+    Position,
+    PublishDiagnosticsParams,
+    ReferenceParams,
+    ServerCapabilities,
+    TextDocumentSyncCapability,
+    TextDocumentSyncKind,
+    Uri,
+};
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
+use symbols::{
+    Occurrence,
+    SymbolKind,
+};
+use workspace::{
+    path_to_uri,
+    project_root,
+    uri_to_path,
+    Analysis,
+    LineIndex,
+    Target,
+};
+
+use crate::ast::{
+    Arg,
+    Type,
+};
+
+/// Run the language server over stdin and stdout until the client shuts it down.
+pub fn serve() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    let mut server = Server {
+        connection: &connection,
+        documents: Default::default(),
+        analyses: Default::default(),
+        published: Default::default(),
+    };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                server.request(request)?;
+            }
+            Message::Notification(notification) => server.notification(notification)?,
+            Message::Response(_) => {}
+        }
+    }
+    // The writer thread only stops once every sender has been dropped.
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    documents: FxHashMap<PathBuf, String>,
+    /// Analyses of each project, by project directory.
+    analyses: FxHashMap<PathBuf, Analysis>,
+    /// Files that diagnostics were last published for, by project directory.
+    published: FxHashMap<PathBuf, FxHashSet<PathBuf>>,
+}
+
+impl Server<'_> {
+    fn notification(&mut self, notification: Notification) -> anyhow::Result<()> {
+        let path = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = serde_json::from_value::<lsp_types::DidOpenTextDocumentParams>(
+                    notification.params,
+                )?;
+                let path = uri_to_path(&params.text_document.uri);
+                if let Some(path) = &path {
+                    self.documents
+                        .insert(path.clone(), params.text_document.text);
+                }
+                path
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = serde_json::from_value::<lsp_types::DidChangeTextDocumentParams>(
+                    notification.params,
+                )?;
+                let path = uri_to_path(&params.text_document.uri);
+                // Only full document sync is supported, so the last change is the whole document.
+                if let (Some(path), Some(change)) =
+                    (&path, params.content_changes.into_iter().last())
+                {
+                    self.documents.insert(path.clone(), change.text);
+                }
+                path
+            }
+            DidSaveTextDocument::METHOD => {
+                let params = serde_json::from_value::<lsp_types::DidSaveTextDocumentParams>(
+                    notification.params,
+                )?;
+                uri_to_path(&params.text_document.uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = serde_json::from_value::<lsp_types::DidCloseTextDocumentParams>(
+                    notification.params,
+                )?;
+                let path = uri_to_path(&params.text_document.uri);
+                if let Some(path) = &path {
+                    self.documents.remove(path);
+                }
+                path
+            }
+            _ => None,
+        };
+        if let Some(root) = path.as_deref().and_then(project_root) {
+            self.analyze(root)?;
+        }
+        Ok(())
+    }
+
+    fn analyze(&mut self, root: PathBuf) -> anyhow::Result<()> {
+        let analysis = match Analysis::new(&root, &self.documents) {
+            Ok(analysis) => analysis,
+            Err(err) => {
+                eprintln!("error: {:?}", err);
+                return Ok(());
+            }
+        };
+        let previous = self.published.remove(&root).unwrap_or_default();
+        let mut published = FxHashSet::default();
+        for (path, diagnostics) in &analysis.diagnostics {
+            self.publish(path, diagnostics.clone())?;
+            published.insert(path.clone());
+        }
+        for path in previous.difference(&published) {
+            self.publish(path, vec![])?;
+        }
+        self.published.insert(root.clone(), published);
+        self.analyses.insert(root, analysis);
+        Ok(())
+    }
+
+    fn publish(&self, path: &Path, diagnostics: Vec<lsp_types::Diagnostic>) -> anyhow::Result<()> {
+        let Some(uri) = path_to_uri(path) else {
+            return Ok(());
+        };
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                PublishDiagnostics::METHOD.into(),
+                PublishDiagnosticsParams::new(uri, diagnostics, None),
+            )))?;
+        Ok(())
+    }
+
+    fn request(&mut self, request: Request) -> anyhow::Result<()> {
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                serde_json::to_value(
+                    self.definition(&position.text_document.uri, position.position)
+                        .map(GotoDefinitionResponse::Scalar),
+                )?
+            }
+            References::METHOD => {
+                let params: ReferenceParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position;
+                serde_json::to_value(self.references(
+                    &position.text_document.uri,
+                    position.position,
+                    params.context.include_declaration,
+                ))?
+            }
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                serde_json::to_value(self.hover(&position.text_document.uri, position.position))?
+            }
+            _ => serde_json::Value::Null,
+        };
+        self.connection
+            .sender
+            .send(Message::Response(Response::new_ok(request.id, result)))?;
+        Ok(())
+    }
+
+    fn text(&self, path: &Path) -> String {
+        self.documents
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| std::fs::read_to_string(path).unwrap_or_default())
+    }
+
+    /// Find the symbol under the cursor, along with the analysis and target it was found in.
+    fn occurrence_at(
+        &self,
+        uri: &Uri,
+        position: Position,
+    ) -> Option<(&Analysis, &Target, &Occurrence)> {
+        let path = uri_to_path(uri)?;
+        let analysis = self.analyses.get(&project_root(&path)?)?;
+        let offset = LineIndex::new(&self.text(&path)).offset(position);
+        analysis.targets.iter().find_map(|target| {
+            let position = target
+                .translation_unit
+                .untranslate_position(&path, offset)?;
+            Some((analysis, target, target.index.at(position)?))
+        })
+    }
+
+    fn location(&self, target: &Target, span: &logos::Span) -> Option<Location> {
+        let (start, include) = target.translation_unit.translate_position(span.start);
+        let end = start + span.len();
+        let line_index = LineIndex::new(&self.text(&include.path));
+        Some(Location::new(
+            path_to_uri(&include.path)?,
+            line_index.range(start..end),
+        ))
+    }
+
+    fn definition(&self, uri: &Uri, position: Position) -> Option<Location> {
+        let (analysis, _, occurrence) = self.occurrence_at(uri, position)?;
+        let target = analysis.target(&occurrence.symbol.target)?;
+        let definition = target.index.definition(&occurrence.symbol)?;
+        self.location(target, &definition.span)
+    }
+
+    fn references(
+        &self,
+        uri: &Uri,
+        position: Position,
+        include_declaration: bool,
+    ) -> Option<Vec<Location>> {
+        let (analysis, _, occurrence) = self.occurrence_at(uri, position)?;
+        let symbol = &occurrence.symbol;
+        let mut locations = vec![];
+        let mut seen = FxHashSet::default();
+        // Global variables and lists can be used by every sprite.
+        for target in &analysis.targets {
+            if target.name != symbol.target
+                && !(symbol.target == "stage"
+                    && matches!(symbol.kind, SymbolKind::Var | SymbolKind::List))
+            {
+                continue;
+            }
+            for reference in target.index.references(symbol) {
+                if reference.is_definition && !include_declaration {
+                    continue;
+                }
+                let Some(location) = self.location(target, &reference.span) else {
+                    continue;
+                };
+                // Files that are included by several sprites would be reported once for each.
+                if seen.insert((location.uri.as_str().to_owned(), location.range.start)) {
+                    locations.push(location);
+                }
+            }
+        }
+        Some(locations)
+    }
+
+    fn hover(&self, uri: &Uri, position: Position) -> Option<Hover> {
+        let (analysis, _, occurrence) = self.occurrence_at(uri, position)?;
+        let symbol = &occurrence.symbol;
+        let sprite = &analysis.target(&symbol.target)?.sprite;
+        let signature = match symbol.kind {
+            SymbolKind::Proc => {
+                let proc = sprite.procs.get(&symbol.name)?;
+                let args = format_args(&sprite.proc_args[&symbol.name]);
+                let inline = if proc.inline { "inline " } else { "" };
+                let recursive = if proc.recursive { "recursive " } else { "" };
+                let nowarp = if proc.warp { "" } else { "nowarp " };
+                if args.is_empty() {
+                    format!("{inline}{recursive}{nowarp}proc {}", proc.name)
+                } else {
+                    format!("{inline}{recursive}{nowarp}proc {} {args}", proc.name)
+                }
+            }
+            SymbolKind::Func => {
+                let func = sprite.funcs.get(&symbol.name)?;
+                let args = format_args(&sprite.func_args[&symbol.name]);
+                let inline = if func.inline { "inline " } else { "" };
+                let recursive = if func.recursive { "recursive " } else { "" };
+                match &func.type_ {
+                    Type::Value => format!("{inline}{recursive}func {}({args})", func.name),
+                    type_ => format!("{inline}{recursive}func {}({args}) {type_}", func.name),
+                }
+            }
+            _ => return None,
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```goboscript\n{signature}\n```"),
+            }),
+            range: None,
+        })
+    }
+}
+
+fn format_args(args: &[Arg]) -> String {
+    args.iter()
+        .map(|arg| match &arg.type_ {
+            Type::Value => arg.name.to_string(),
+            type_ => format!("{type_} {}", arg.name),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,674 @@
+use logos::Span;
+use rustc_hash::FxHashSet;
+
+use crate::{
+    ast::*,
+    misc::SmolStr,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Proc,
+    Func,
+    Var,
+    List,
+    Struct,
+    Enum,
+    Arg,
+    Local,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Proc(SmolStr),
+    Func(SmolStr),
+    /// The script at this index in the sprite's events.
+    Event(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// The sprite that the symbol is declared in, `stage` for global variables and lists.
+    pub target: SmolStr,
+    /// The procedure or function that an argument or local variable belongs to.
+    pub scope: Option<Scope>,
+    pub name: SmolStr,
+}
+
+#[derive(Debug)]
+pub struct Occurrence {
+    pub symbol: Symbol,
+    /// Span of the name in the translation unit.
+    pub span: Span,
+    pub is_definition: bool,
+}
+
+/// Every place where a symbol is declared or used in a sprite, collected from the AST
+/// before any of the visitor passes have transformed it.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl SymbolIndex {
+    pub fn new(target: &str, sprite: &Sprite, stage: Option<&Sprite>) -> Self {
+        let mut indexer = Indexer {
+            target: target.into(),
+            sprite,
+            stage,
+            scope: None,
+            locals: Default::default(),
+            implicit_vars: Default::default(),
+            assigned_vars: Default::default(),
+            index: Default::default(),
+        };
+        indexer.sprite();
+        let mut index = indexer.index;
+        index
+            .occurrences
+            .sort_by_key(|occurrence| occurrence.span.start);
+        index
+    }
+
+    /// The occurrence whose name contains the given translation unit position.
+    pub fn at(&self, position: usize) -> Option<&Occurrence> {
+        self.occurrences
+            .iter()
+            .find(|occurrence| occurrence.span.start <= position && position <= occurrence.span.end)
+    }
+
+    pub fn definition(&self, symbol: &Symbol) -> Option<&Occurrence> {
+        // Implicitly declared variables have no declaration, so fall back to the first use.
+        self.occurrences
+            .iter()
+            .filter(|occurrence| &occurrence.symbol == symbol)
+            .min_by_key(|occurrence| !occurrence.is_definition)
+    }
+
+    pub fn references<'a>(&'a self, symbol: &'a Symbol) -> impl Iterator<Item = &'a Occurrence> {
+        self.occurrences
+            .iter()
+            .filter(move |occurrence| &occurrence.symbol == symbol)
+    }
+}
+
+struct Indexer<'a> {
+    target: SmolStr,
+    sprite: &'a Sprite,
+    stage: Option<&'a Sprite>,
+    scope: Option<Scope>,
+    locals: FxHashSet<SmolStr>,
+    /// Variables which are declared by assigning to them, instead of with `var`.
+    implicit_vars: FxHashSet<SmolStr>,
+    /// Implicitly declared variables whose first assignment has been seen.
+    assigned_vars: FxHashSet<SmolStr>,
+    index: SymbolIndex,
+}
+
+impl Indexer<'_> {
+    fn push(
+        &mut self,
+        kind: SymbolKind,
+        target: SmolStr,
+        name: &SmolStr,
+        span: &Span,
+        is_definition: bool,
+    ) {
+        let scope = match kind {
+            SymbolKind::Arg | SymbolKind::Local => self.scope.clone(),
+            _ => None,
+        };
+        self.index.occurrences.push(Occurrence {
+            symbol: Symbol {
+                kind,
+                target,
+                scope,
+                name: name.clone(),
+            },
+            span: span.clone(),
+            is_definition,
+        });
+    }
+
+    fn define(&mut self, kind: SymbolKind, name: &SmolStr, span: &Span) {
+        self.push(kind, self.target.clone(), name, span, true);
+    }
+
+    /// Find the sprite that declares a variable, list or enum, preferring the sprite
+    /// over the stage.
+    fn resolve(&self, name: &SmolStr) -> Option<(SymbolKind, SmolStr)> {
+        if self.scope.is_some() && self.locals.contains(name) {
+            return Some((SymbolKind::Local, self.target.clone()));
+        }
+        for (sprite, target) in [
+            (Some(self.sprite), &self.target),
+            (self.stage, &"stage".into()),
+        ] {
+            let Some(sprite) = sprite else {
+                continue;
+            };
+            if sprite.vars.contains_key(name) {
+                return Some((SymbolKind::Var, target.clone()));
+            }
+            if sprite.lists.contains_key(name) {
+                return Some((SymbolKind::List, target.clone()));
+            }
+            if sprite.enums.contains_key(name) {
+                return Some((SymbolKind::Enum, target.clone()));
+            }
+        }
+        if self.implicit_vars.contains(name) {
+            return Some((SymbolKind::Var, self.target.clone()));
+        }
+        None
+    }
+
+    fn sprite(&mut self) {
+        let sprite = self.sprite;
+        for var in sprite.vars.values() {
+            self.define(SymbolKind::Var, &var.name, &var.span);
+            self.type_(&var.type_);
+            if let Some(default) = &var.default {
+                self.const_expr(default);
+            }
+        }
+        for list in sprite.lists.values() {
+            self.define(SymbolKind::List, &list.name, &list.span);
+            self.type_(&list.type_);
+            match &list.default {
+                Some(ListDefault::Values(values)) => {
+                    for value in values {
+                        self.const_expr(value);
+                    }
+                }
+                Some(ListDefault::FixedLength(default, length)) => {
+                    self.const_expr(default);
+                    self.const_expr(length);
+                }
+                _ => {}
+            }
+        }
+        for struct_ in sprite.structs.values() {
+            self.define(SymbolKind::Struct, &struct_.name, &struct_.span);
+            for field in &struct_.fields {
+                self.type_(&field.type_);
+                if let Some(default) = &field.default {
+                    self.const_expr(default);
+                }
+            }
+        }
+        for enum_ in sprite.enums.values() {
+            self.define(SymbolKind::Enum, &enum_.name, &enum_.span);
+        }
+        for proc in sprite.procs.values() {
+            self.define(SymbolKind::Proc, &proc.name, &proc.span);
+        }
+        for func in sprite.funcs.values() {
+            self.define(SymbolKind::Func, &func.name, &func.span);
+            self.type_(&func.type_);
+        }
+        // Implicitly declared variables are collected first, so that they can be used
+        // before the statement that declares them.
+        let bodies = sprite
+            .proc_definitions
+            .values()
+            .chain(sprite.func_definitions.values())
+            .chain(sprite.events.iter().map(|event| &event.body));
+        for body in bodies {
+            collect_implicit_vars(body, &mut self.implicit_vars);
+        }
+        for proc in sprite.procs.values() {
+            self.scope = Some(Scope::Proc(proc.name.clone()));
+            self.args(&sprite.proc_args[&proc.name]);
+            self.stmts(&sprite.proc_definitions[&proc.name]);
+        }
+        for func in sprite.funcs.values() {
+            self.scope = Some(Scope::Func(func.name.clone()));
+            self.args(&sprite.func_args[&func.name]);
+            self.stmts(&sprite.func_definitions[&func.name]);
+        }
+        for (i, event) in sprite.events.iter().enumerate() {
+            self.scope = Some(Scope::Event(i));
+            self.locals.clear();
+            if let EventKind::OnLoudnessGt { value }
+            | EventKind::OnTimerGt { value }
+            | EventKind::OnVideoMotionGt { value } = &event.kind
+            {
+                self.expr(value);
+            }
+            self.stmts(&event.body);
+        }
+        self.scope = None;
+    }
+
+    fn args(&mut self, args: &[Arg]) {
+        self.locals.clear();
+        for arg in args {
+            self.define(SymbolKind::Arg, &arg.name, &arg.span);
+            self.type_(&arg.type_);
+            if let Some(default) = &arg.default {
+                self.const_expr(default);
+            }
+        }
+    }
+
+    fn type_(&mut self, type_: &Type) {
+        if let Type::Struct { name, span } = type_ {
+            self.struct_name(name, span);
+        }
+    }
+
+    fn struct_name(&mut self, name: &SmolStr, span: &Span) {
+        let target = if self.sprite.structs.contains_key(name) {
+            self.target.clone()
+        } else if self
+            .stage
+            .is_some_and(|stage| stage.structs.contains_key(name))
+        {
+            "stage".into()
+        } else {
+            return;
+        };
+        self.push(SymbolKind::Struct, target, name, span, false);
+    }
+
+    fn name(&mut self, name: &Name) {
+        if let Some((kind, target)) = self.resolve(name.basename()) {
+            self.push(kind, target, name.basename(), &name.basespan(), false);
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Repeat { times, body } => {
+                self.expr(times);
+                self.stmts(body);
+            }
+            Stmt::Forever { body, .. } => self.stmts(body),
+            Stmt::Branch {
+                cond,
+                if_body,
+                else_body,
+            } => {
+                self.expr(cond);
+                self.stmts(if_body);
+                self.stmts(else_body);
+            }
+            Stmt::Until { cond, body } | Stmt::While { cond, body } => {
+                self.expr(cond);
+                self.stmts(body);
+            }
+            Stmt::For {
+                name,
+                start,
+                end,
+                body,
+            } => {
+                self.expr(start);
+                self.expr(end);
+                self.assign(name, false);
+                self.stmts(body);
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Match { value, arms, .. } => {
+                self.expr(value);
+                for arm in arms {
+                    if !arm.is_wildcard() {
+                        for pattern in &arm.patterns {
+                            self.expr(pattern);
+                        }
+                    }
+                    self.stmts(&arm.body);
+                }
+            }
+            Stmt::SetVar {
+                name,
+                value,
+                type_,
+                is_local,
+                ..
+            } => {
+                self.type_(type_);
+                self.expr(value);
+                self.assign(name, *is_local);
+            }
+            Stmt::Destructure {
+                names,
+                value,
+                is_local,
+            } => {
+                self.expr(value);
+                for name in names {
+                    self.assign(name, *is_local);
+                }
+            }
+            Stmt::ChangeVar { name, value } => {
+                self.name(name);
+                self.expr(value);
+            }
+            Stmt::Show(name) | Stmt::Hide(name) | Stmt::DeleteList(name) => self.name(name),
+            Stmt::AddToList { name, value } => {
+                self.name(name);
+                self.expr(value);
+            }
+            Stmt::DeleteListIndex { name, index } => {
+                self.name(name);
+                self.expr(index);
+            }
+            Stmt::InsertAtList { name, index, value }
+            | Stmt::SetListIndex { name, index, value } => {
+                self.name(name);
+                self.expr(index);
+                self.expr(value);
+            }
+            Stmt::Block { args, kwargs, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                for (_, arg) in kwargs.values() {
+                    self.expr(arg);
+                }
+            }
+            Stmt::ProcCall {
+                name,
+                span,
+                args,
+                kwargs,
+            } => {
+                if self.sprite.procs.contains_key(name) {
+                    self.push(SymbolKind::Proc, self.target.clone(), name, span, false);
+                }
+                for arg in args {
+                    self.expr(arg);
+                }
+                for (_, arg) in kwargs.values() {
+                    self.expr(arg);
+                }
+            }
+            Stmt::FuncCall {
+                name,
+                span,
+                args,
+                kwargs,
+            } => self.func_call(name, span, args, kwargs.values().map(|(_, arg)| arg)),
+            Stmt::Return { value, .. } => self.expr(value),
+        }
+    }
+
+    /// An assignment to a variable that was not declared, declares it.
+    fn assign(&mut self, name: &Name, is_local: bool) {
+        let basename = name.basename();
+        if is_local && self.scope.is_some() {
+            let is_definition = self.locals.insert(basename.clone());
+            self.push(
+                SymbolKind::Local,
+                self.target.clone(),
+                basename,
+                &name.basespan(),
+                is_definition,
+            );
+        } else if self.resolve(basename) == Some((SymbolKind::Var, self.target.clone()))
+            && !self.sprite.vars.contains_key(basename)
+        {
+            let is_definition = self.assigned_vars.insert(basename.clone());
+            self.push(
+                SymbolKind::Var,
+                self.target.clone(),
+                basename,
+                &name.basespan(),
+                is_definition,
+            );
+        } else {
+            self.name(name);
+        }
+    }
+
+    fn func_call<'b>(
+        &mut self,
+        name: &SmolStr,
+        span: &Span,
+        args: &[Expr],
+        kwargs: impl Iterator<Item = &'b Expr>,
+    ) {
+        if self.sprite.funcs.contains_key(name) {
+            self.push(SymbolKind::Func, self.target.clone(), name, span, false);
+        }
+        for arg in args {
+            self.expr(arg);
+        }
+        for arg in kwargs {
+            self.expr(arg);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Value { .. } => {}
+            Expr::Name(name) => self.name(name),
+            Expr::Dot { lhs, .. } => self.expr(lhs),
+            Expr::Arg(name) => {
+                let is_arg = match &self.scope {
+                    Some(Scope::Proc(proc)) => &self.sprite.proc_args[proc],
+                    Some(Scope::Func(func)) => &self.sprite.func_args[func],
+                    Some(Scope::Event(_)) | None => return,
+                }
+                .iter()
+                .any(|arg| &arg.name == name.basename());
+                if is_arg {
+                    self.push(
+                        SymbolKind::Arg,
+                        self.target.clone(),
+                        name.basename(),
+                        &name.basespan(),
+                        false,
+                    );
+                }
+            }
+            Expr::Repr { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::FuncCall {
+                name,
+                span,
+                args,
+                kwargs,
+            } => self.func_call(name, span, args, kwargs.values().map(|(_, arg)| arg)),
+            Expr::UnOp { opr, .. } => self.expr(opr),
+            Expr::BinOp { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::StructLiteral { name, span, fields } => {
+                self.struct_name(name, span);
+                for field in fields {
+                    self.expr(&field.value);
+                }
+            }
+            Expr::Property { object, .. } => self.expr(object),
+            Expr::Ternary {
+                condition,
+                tvalue,
+                fvalue,
+            } => {
+                self.expr(condition);
+                self.expr(tvalue);
+                self.expr(fvalue);
+            }
+        }
+    }
+
+    fn const_expr(&mut self, const_expr: &ConstExpr) {
+        match const_expr {
+            ConstExpr::Value { .. } => {}
+            ConstExpr::EnumVariant {
+                enum_name,
+                enum_name_span,
+                ..
+            } => {
+                if let Some((SymbolKind::Enum, target)) = self.resolve(enum_name) {
+                    self.push(SymbolKind::Enum, target, enum_name, enum_name_span, false);
+                }
+            }
+            ConstExpr::StructLiteral { name, span, .. } => self.struct_name(name, span),
+        }
+    }
+}
+
+fn collect_implicit_vars(stmts: &[Stmt], vars: &mut FxHashSet<SmolStr>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::While { body, .. } => collect_implicit_vars(body, vars),
+            Stmt::For { name, body, .. } => {
+                vars.insert(name.basename().clone());
+                collect_implicit_vars(body, vars);
+            }
+            Stmt::Match { arms, .. } => {
+                for arm in arms {
+                    collect_implicit_vars(&arm.body, vars);
+                }
+            }
+            Stmt::Branch {
+                if_body, else_body, ..
+            } => {
+                collect_implicit_vars(if_body, vars);
+                collect_implicit_vars(else_body, vars);
+            }
+            Stmt::SetVar {
+                name,
+                is_local: false,
+                ..
+            } => {
+                vars.insert(name.basename().clone());
+            }
+            Stmt::Destructure {
+                names,
+                is_local: false,
+                ..
+            } => {
+                vars.extend(names.iter().map(|name| name.basename().clone()));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        io,
+        path::{
+            Path,
+            PathBuf,
+        },
+        rc::Rc,
+    };
+
+    use super::*;
+    use crate::{
+        parser,
+        translation_unit::TranslationUnit,
+        vfs::VFS,
+    };
+
+    struct TestFS(HashMap<PathBuf, Vec<u8>>);
+
+    impl VFS for TestFS {
+        fn read_dir(&mut self, _path: &Path) -> io::Result<Vec<PathBuf>> {
+            Ok(self.0.keys().cloned().collect())
+        }
+
+        fn read_file<'a>(&'a mut self, path: &Path) -> io::Result<Box<dyn io::Read + 'a>> {
+            let content = self
+                .0
+                .get(path)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            Ok(Box::new(io::Cursor::new(content)))
+        }
+
+        fn is_dir(&self, _path: &Path) -> bool {
+            false
+        }
+
+        fn is_file(&self, path: &Path) -> bool {
+            self.0.contains_key(path)
+        }
+
+        fn glob(&mut self, _pattern: &str) -> io::Result<Vec<PathBuf>> {
+            Ok(vec![])
+        }
+    }
+
+    fn parse(source: &str) -> Sprite {
+        let path = PathBuf::from("main.gs");
+        let fs = TestFS(HashMap::from([(path.clone(), source.as_bytes().to_vec())]));
+        let unit = TranslationUnit::new(Rc::new(RefCell::new(fs)), path).unwrap();
+        let (sprite, diagnostics) = parser::parse(&unit);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        sprite
+    }
+
+    fn definition_of(index: &SymbolIndex, source: &str, needle: &str, nth: usize) -> Span {
+        let position = source.match_indices(needle).nth(nth).unwrap().0;
+        let occurrence = index.at(position).unwrap();
+        index.definition(&occurrence.symbol).unwrap().span.clone()
+    }
+
+    #[test]
+    fn procs_args_and_locals_resolve_to_their_definitions() {
+        let source = "proc jump height { local y = $height; change_y y; }\nonflag { jump 10; }\n";
+        let index = SymbolIndex::new("main", &parse(source), None);
+        let height = source.find("height").unwrap();
+        assert_eq!(
+            definition_of(&index, source, "height", 1),
+            height..height + 6
+        );
+        let y = source.find("y =").unwrap();
+        assert_eq!(definition_of(&index, source, "y;", 0), y..y + 1);
+        let jump = source.find("jump").unwrap();
+        assert_eq!(definition_of(&index, source, "jump", 1), jump..jump + 4);
+    }
+
+    #[test]
+    fn sprite_declarations_shadow_the_stage() {
+        let stage_source = "var score = 0;\nlist items;\n";
+        let stage = parse(stage_source);
+        let source = "list score;\nonflag { add 1 to score; add 2 to items; }\n";
+        let index = SymbolIndex::new("main", &parse(source), Some(&stage));
+        let position = source.rfind("score").unwrap();
+        let symbol = &index.at(position).unwrap().symbol;
+        assert_eq!(
+            (symbol.kind, symbol.target.as_str()),
+            (SymbolKind::List, "main")
+        );
+        let position = source.find("items").unwrap();
+        let symbol = &index.at(position).unwrap().symbol;
+        assert_eq!(
+            (symbol.kind, symbol.target.as_str()),
+            (SymbolKind::List, "stage")
+        );
+    }
+
+    #[test]
+    fn implicitly_declared_variables_are_defined_by_their_first_assignment() {
+        let source = "onflag { say x; x = 1; x += 2; }\n";
+        let index = SymbolIndex::new("main", &parse(source), None);
+        let symbol = &index.at(source.find("x;").unwrap()).unwrap().symbol;
+        assert_eq!(index.references(symbol).count(), 3);
+        let x = source.find("x =").unwrap();
+        assert_eq!(index.definition(symbol).unwrap().span, x..x + 1);
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    cell::RefCell,
+    io::{
+        self,
+        Cursor,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+};
+
+use annotate_snippets::Level;
+use lsp_types::{
+    Diagnostic as LspDiagnostic,
+    DiagnosticRelatedInformation,
+    DiagnosticSeverity,
+    Location,
+    NumberOrString,
+    Position,
+    Range,
+    Uri,
+};
+use rustc_hash::FxHashMap;
+
+use super::symbols::SymbolIndex;
+use crate::{
+    ast::{
+        Project,
+        Sprite,
+    },
+    codegen::build::build_with_cache,
+    diagnostic::SpriteDiagnostics,
+    misc::SmolStr,
+    parser,
+    translation_unit::{
+        Owner,
+        TranslationUnit,
+    },
+    vfs::{
+        RealFS,
+        VFS,
+    },
+};
+
+/// The real file system, with the contents of documents that are open in the editor
+/// taking precedence over what is saved on disk.
+struct OverlayFS {
+    real: RealFS,
+    documents: FxHashMap<PathBuf, String>,
+}
+
+impl VFS for OverlayFS {
+    fn read_dir(&mut self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.real.read_dir(path)
+    }
+
+    fn read_file<'a>(&'a mut self, path: &Path) -> io::Result<Box<dyn io::Read + 'a>> {
+        match self.documents.get(path) {
+            Some(text) => Ok(Box::new(Cursor::new(text.as_bytes()))),
+            None => self.real.read_file(path),
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.real.is_dir(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.documents.contains_key(path) || self.real.is_file(path)
+    }
+
+    fn glob(&mut self, pattern: &str) -> io::Result<Vec<PathBuf>> {
+        self.real.glob(pattern)
+    }
+}
+
+pub struct Target {
+    pub name: SmolStr,
+    pub translation_unit: TranslationUnit,
+    /// The sprite as it was parsed, before the visitor passes transformed it.
+    pub sprite: Sprite,
+    pub index: SymbolIndex,
+}
+
+/// The result of compiling a project, kept around to answer requests about it.
+pub struct Analysis {
+    pub targets: Vec<Target>,
+    pub diagnostics: FxHashMap<PathBuf, Vec<LspDiagnostic>>,
+}
+
+impl Analysis {
+    pub fn new(root: &Path, documents: &FxHashMap<PathBuf, String>) -> anyhow::Result<Self> {
+        let fs = Rc::new(RefCell::new(OverlayFS {
+            real: RealFS,
+            documents: documents.clone(),
+        }));
+        let text = |path: &Path| fs.borrow_mut().read_to_string(path).unwrap_or_default();
+        // Tests are included, so that their diagnostics are shown too. Calls are not
+        // inlined, so that they can still be found.
+        let artifact = build_with_cache(
+            fs.clone(),
+            root.to_path_buf(),
+            Cursor::new(vec![]),
+            None,
+            true,
+            false,
+            &[],
+            &mut Default::default(),
+        )?;
+        let mut sprites_diagnostics: Vec<(SmolStr, SpriteDiagnostics)> =
+            artifact.sprites_diagnostics.into_iter().collect();
+        sprites_diagnostics.sort_by(|(a, _), (b, _)| a.cmp(b));
+        sprites_diagnostics.insert(0, ("stage".into(), artifact.stage_diagnostics));
+
+        let mut diagnostics: FxHashMap<PathBuf, Vec<LspDiagnostic>> = FxHashMap::default();
+        let mut line_indexes: FxHashMap<PathBuf, LineIndex> = FxHashMap::default();
+        for (name, sprite_diagnostics) in &sprites_diagnostics {
+            let sprite = sprite_of(&artifact.project, name);
+            let unit = &sprite_diagnostics.translation_unit;
+            for diagnostic in &sprite_diagnostics.diagnostics {
+                let Some(level) = sprite_diagnostics.level(diagnostic) else {
+                    continue;
+                };
+                if diagnostic.kind.should_be_suppressed() {
+                    continue;
+                }
+                let (span, include) = unit.translate_span(&diagnostic.span);
+                if level != Level::Error && !matches!(include.owner, Owner::Local) {
+                    continue;
+                }
+                let related_information = diagnostic
+                    .notes
+                    .iter()
+                    .filter_map(|note| {
+                        let (span, include) = unit.translate_span(&note.span);
+                        let line_index = line_indexes
+                            .entry(include.path.clone())
+                            .or_insert_with(|| LineIndex::new(&text(&include.path)));
+                        Some(DiagnosticRelatedInformation {
+                            location: Location::new(
+                                path_to_uri(&include.path)?,
+                                line_index.range(span),
+                            ),
+                            message: note.label.clone(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let line_index = line_indexes
+                    .entry(include.path.clone())
+                    .or_insert_with(|| LineIndex::new(&text(&include.path)));
+                let mut message = diagnostic.kind.to_string(sprite);
+                if let Some(help) = diagnostic.kind.help(sprite) {
+                    message.push_str("\nhelp: ");
+                    message.push_str(&help);
+                }
+                diagnostics
+                    .entry(include.path.clone())
+                    .or_default()
+                    .push(LspDiagnostic {
+                        range: line_index.range(span),
+                        severity: Some(match level {
+                            Level::Error => DiagnosticSeverity::ERROR,
+                            Level::Warning => DiagnosticSeverity::WARNING,
+                            _ => DiagnosticSeverity::INFORMATION,
+                        }),
+                        code: Some(NumberOrString::String(diagnostic.kind.code().into())),
+                        source: Some("goboscript".into()),
+                        message,
+                        related_information: (!related_information.is_empty())
+                            .then_some(related_information),
+                        ..Default::default()
+                    });
+            }
+        }
+
+        let mut targets: Vec<Target> = sprites_diagnostics
+            .into_iter()
+            .map(|(name, sprite_diagnostics)| {
+                let translation_unit = sprite_diagnostics.translation_unit;
+                let (sprite, _) = parser::parse(&translation_unit);
+                Target {
+                    name,
+                    translation_unit,
+                    sprite,
+                    index: SymbolIndex::default(),
+                }
+            })
+            .collect();
+        let (stage, sprites) = targets.split_at_mut(1);
+        let stage = &mut stage[0];
+        stage.index = SymbolIndex::new(&stage.name, &stage.sprite, None);
+        for target in sprites {
+            target.index = SymbolIndex::new(&target.name, &target.sprite, Some(&stage.sprite));
+        }
+        Ok(Self {
+            targets,
+            diagnostics,
+        })
+    }
+
+    pub fn target(&self, name: &str) -> Option<&Target> {
+        self.targets.iter().find(|target| target.name == name)
+    }
+}
+
+fn sprite_of<'a>(project: &'a Project, name: &str) -> &'a Sprite {
+    match name {
+        "stage" => &project.stage,
+        name => &project.sprites[name],
+    }
+}
+
+/// The directory of the project that a source file belongs to, which is the closest
+/// ancestor with a `goboscript.toml` or a `stage.gs`.
+pub fn project_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("goboscript.toml").is_file() || dir.join("stage.gs").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Converts between byte offsets and LSP positions, which count UTF-16 code units.
+pub struct LineIndex {
+    text: String,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            text: text.to_owned(),
+            line_starts,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self
+            .text
+            .get(start..offset)
+            .map(|prefix| prefix.encode_utf16().count())
+            .unwrap_or(offset - start);
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut character = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if character >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            character += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    pub fn range(&self, span: std::ops::Range<usize>) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    let path = uri.as_str().strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut chars = path.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // Windows paths look like `/C:/Users/...` after the scheme.
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_owned(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+pub fn path_to_uri(path: &Path) -> Option<Uri> {
+    let path = path.to_str()?.replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_index_counts_utf16_code_units() {
+        let line_index = LineIndex::new("say \"é😀\";\nhide;\n");
+        assert_eq!(line_index.position(0), Position::new(0, 0));
+        let hide = "say \"é😀\";\n".len();
+        assert_eq!(line_index.position(hide), Position::new(1, 0));
+        let quote = "say \"é😀".len();
+        assert_eq!(line_index.position(quote), Position::new(0, 8));
+        assert_eq!(line_index.offset(Position::new(0, 8)), quote);
+        assert_eq!(line_index.offset(Position::new(1, 2)), hide + 2);
+        assert_eq!(line_index.offset(Position::new(0, 100)), hide - 1);
+    }
+
+    #[test]
+    fn uris_round_trip() {
+        let path = PathBuf::from("/home/user/my project/main.gs");
+        let uri = path_to_uri(&path).unwrap();
+        assert_eq!(uri.as_str(), "file:///home/user/my%20project/main.gs");
+        assert_eq!(uri_to_path(&uri), Some(path));
+    }
+}
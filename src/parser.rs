@@ -10,6 +10,7 @@ use crate::{
     },
     pre_processor::PreProcessor,
     translation_unit::TranslationUnit,
+    visitor::modules,
 };
 
 lalrpop_mod!(
@@ -45,14 +46,39 @@ fn preprocess(mut tokens: Vec<SpannedToken>) -> (Vec<SpannedToken>, Option<Diagn
 }
 
 /// Parse the tokens into a sprite AST
-fn parse_sprite(tokens: Vec<SpannedToken>) -> (Sprite, Vec<Diagnostic>) {
+fn parse_sprite(
+    translation_unit: &TranslationUnit,
+    tokens: Vec<SpannedToken>,
+) -> (Sprite, Vec<Diagnostic>) {
     let parser = SpriteParser::new();
     let mut sprite = Sprite::default();
     let mut diagnostics = Vec::new();
+    let mut unparsed = Vec::new();
 
-    if let Err(parse_error) = parser.parse(&mut sprite, &mut diagnostics, tokens) {
+    if let Err(parse_error) = parser.parse(
+        &mut sprite,
+        &mut diagnostics,
+        &mut unparsed,
+        translation_unit,
+        tokens.iter().cloned(),
+    ) {
         diagnostics.push(parse_error.into());
     }
+    sprite.unparsed_names = tokens
+        .into_iter()
+        .filter(|(start, _, end)| {
+            unparsed
+                .iter()
+                .any(|span| span.start <= *start && *end <= span.end)
+        })
+        .filter_map(|(start, token, _)| match token {
+            Token::Name(name) | Token::Arg(name) => match translation_unit.namespace_at(start) {
+                Some(namespace) => Some(format!("{namespace}.{name}").into()),
+                None => Some(name),
+            },
+            _ => None,
+        })
+        .collect();
 
     (sprite, diagnostics)
 }
@@ -63,18 +89,117 @@ fn parse_sprite(tokens: Vec<SpannedToken>) -> (Sprite, Vec<Diagnostic>) {
 /// 1. Tokenizes the source code
 /// 2. Applies preprocessing transformations
 /// 3. Parses the tokens into an AST
+/// 4. Qualifies the names used by modules, and the names of modules used by the sprite
 ///
 /// Returns the parsed sprite and any diagnostics encountered during parsing.
 pub fn parse(translation_unit: &TranslationUnit) -> (Sprite, Vec<Diagnostic>) {
     let (tokens, tokenize_diagnostics) = tokenize(translation_unit);
     let (tokens, preprocess_diagnostic) = preprocess(tokens);
-    let (sprite, parse_diagnostics) = parse_sprite(tokens);
+    let (mut sprite, parse_diagnostics) = parse_sprite(translation_unit, tokens);
+    let modules_diagnostics = modules::visit_sprite(&mut sprite, translation_unit);
 
     let all_diagnostics = tokenize_diagnostics
         .into_iter()
         .chain(preprocess_diagnostic)
         .chain(parse_diagnostics)
+        .chain(modules_diagnostics)
         .collect();
 
     (sprite, all_diagnostics)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        codegen::build::ParseCache,
+        diagnostic::DiagnosticKind,
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn parsing_continues_after_syntax_errors() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             onflag {\n\
+                 say 1 say 2;\n\
+                 x = = 5;\n\
+                 missing;\n\
+             }\n\
+             proc broken ( {}\n\
+             onclick { say 3 }\n",
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        let main = &artifact.sprites_diagnostics["main"];
+        let mut diagnostics: Vec<_> = main.diagnostics.iter().collect();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        let kinds: Vec<_> = diagnostics
+            .into_iter()
+            .map(|diagnostic| &diagnostic.kind)
+            .collect();
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                DiagnosticKind::UnrecognizedToken(..),
+                DiagnosticKind::UnrecognizedToken(..),
+                DiagnosticKind::UnrecognizedProcedure(name),
+                DiagnosticKind::UnrecognizedToken(..),
+                DiagnosticKind::UnrecognizedToken(..),
+            ] if name == "missing"
+        ));
+        assert_eq!(artifact.project.sprites["main"].events.len(), 2);
+    }
+
+    #[test]
+    fn names_from_code_which_could_not_be_parsed_are_not_reported() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             struct MyStruct }\n\
+             list MyStruct items;\n\
+             onflag {\n\
+                 local w = 1 +;\n\
+                 say w;\n\
+                 MyStruct s = MyStruct {};\n\
+                 if 1 in items.field { say missing; }\n\
+                 say length items;\n\
+             }\n",
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(artifact.failure());
+        let kinds: Vec<_> = artifact.sprites_diagnostics["main"]
+            .diagnostics
+            .iter()
+            .map(|diagnostic| &diagnostic.kind)
+            .filter(|kind| !matches!(kind, DiagnosticKind::UnrecognizedToken(..)))
+            .collect();
+        assert!(matches!(
+            kinds.as_slice(),
+            [DiagnosticKind::UnrecognizedVariable(name)] if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn fstrings_are_joined() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            onflag {
+                x = 3;
+                say f"x: {x}, {{x}}: {f"{x * 2}"}";
+                say f"{x}";
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: x: 3, {x}: 6", "main: 3"]);
+        let (fstring, _) = build_sb3(
+            "costumes \"blank.svg\";\nonflag { x = 1; say f\"{x + 1}\"; }\n",
+            false,
+        );
+        let (expr, _) = build_sb3(
+            "costumes \"blank.svg\";\nonflag { x = 1; say x + 1; }\n",
+            false,
+        );
+        assert_eq!(fstring.block_count, expr.block_count);
+    }
+}
@@ -105,6 +105,7 @@ impl<'a> PreProcessor<'a, '_> {
             return Err(Diagnostic {
                 kind: DiagnosticKind::UnrecognizedEof(vec![]),
                 span,
+                notes: vec![],
             });
         }
         Ok(())
@@ -270,6 +271,7 @@ impl<'a> PreProcessor<'a, '_> {
                     given: arity,
                 },
                 span: macro_name_span,
+                notes: vec![],
             });
         };
 
@@ -379,6 +381,7 @@ impl<'a> PreProcessor<'a, '_> {
                     vec!["(".to_string()],
                 ),
                 span: get_span(&self.tokens[*self.i]),
+                notes: vec![],
             });
         }
 
@@ -476,6 +479,7 @@ impl<'a> PreProcessor<'a, '_> {
             return Err(Diagnostic {
                 kind: DiagnosticKind::UnrecognizedEof(vec![]),
                 span: get_span(&self.tokens[i - 1]),
+                notes: vec![],
             });
         }
 
@@ -520,6 +524,7 @@ impl<'a> PreProcessor<'a, '_> {
                         return Err(Diagnostic {
                             kind: DiagnosticKind::UnrecognizedEof(vec![]),
                             span: get_span(&self.tokens[i - 1]),
+                            notes: vec![],
                         });
                     }
                     token = get_token(&self.tokens[i]).clone();
@@ -538,6 +543,7 @@ impl<'a> PreProcessor<'a, '_> {
             return Err(Diagnostic {
                 kind: DiagnosticKind::InvalidToken,
                 span,
+                notes: vec![],
             });
         };
 
@@ -545,6 +551,7 @@ impl<'a> PreProcessor<'a, '_> {
             return Err(Diagnostic {
                 kind: DiagnosticKind::InvalidToken,
                 span,
+                notes: vec![],
             });
         };
 
@@ -552,6 +559,7 @@ impl<'a> PreProcessor<'a, '_> {
             return Err(Diagnostic {
                 kind: DiagnosticKind::InvalidToken,
                 span,
+                notes: vec![],
             });
         }
 
@@ -560,10 +568,12 @@ impl<'a> PreProcessor<'a, '_> {
             Some(Ok(_)) => Err(Diagnostic {
                 kind: DiagnosticKind::InvalidToken,
                 span,
+                notes: vec![],
             }),
             Some(Err(_)) => Err(Diagnostic {
                 kind: DiagnosticKind::InvalidToken,
                 span,
+                notes: vec![],
             }),
         }
     }
@@ -594,6 +604,7 @@ impl<'a> PreProcessor<'a, '_> {
                     given: args.len(),
                 },
                 span: macro_name_span,
+                notes: vec![],
             });
         }
 
@@ -604,6 +615,7 @@ impl<'a> PreProcessor<'a, '_> {
             return Err(Diagnostic {
                 kind: DiagnosticKind::InvalidToken,
                 span: macro_name_span,
+                notes: vec![],
             });
         };
 
@@ -611,6 +623,7 @@ impl<'a> PreProcessor<'a, '_> {
             return Err(Diagnostic {
                 kind: DiagnosticKind::InvalidToken,
                 span: macro_name_span,
+                notes: vec![],
             });
         };
 
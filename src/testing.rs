@@ -0,0 +1,173 @@
+//! Helpers for the tests of each part of the compiler, which build projects from
+//! source files in memory.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{
+        self,
+        Cursor,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+};
+
+use semver::Version;
+
+use crate::{
+    codegen::build::{
+        build_with_cache,
+        ParseCache,
+    },
+    diagnostic::Artifact,
+    standard_library::new_standard_library,
+    vfs::VFS,
+    vm::{
+        Host,
+        LogLevel,
+        Outcome,
+        VM,
+    },
+};
+
+pub struct TestFS(pub HashMap<PathBuf, Vec<u8>>);
+
+impl VFS for TestFS {
+    fn read_dir(&mut self, _path: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(self
+            .0
+            .keys()
+            .filter(|path| path.parent() == Some(Path::new("")))
+            .cloned()
+            .collect())
+    }
+
+    fn read_file<'a>(&'a mut self, path: &Path) -> io::Result<Box<dyn io::Read + 'a>> {
+        let content = self
+            .0
+            .get(path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(Box::new(Cursor::new(content)))
+    }
+
+    fn is_dir(&self, _path: &Path) -> bool {
+        false
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.0.contains_key(path)
+    }
+
+    fn glob(&mut self, _pattern: &str) -> io::Result<Vec<PathBuf>> {
+        Ok(vec![])
+    }
+}
+
+pub fn try_build(fs: &Rc<RefCell<TestFS>>, cache: &mut ParseCache) -> Artifact {
+    let stdlib = new_standard_library(Version::new(0, 0, 0), Path::new("std"));
+    build_with_cache(
+        fs.clone(),
+        "".into(),
+        Cursor::new(vec![]),
+        Some(stdlib),
+        false,
+        true,
+        &[],
+        cache,
+    )
+    .unwrap()
+}
+
+pub fn build(fs: &Rc<RefCell<TestFS>>, cache: &mut ParseCache) -> Artifact {
+    let artifact = try_build(fs, cache);
+    assert!(!artifact.failure());
+    artifact
+}
+
+pub fn project(main: &str) -> Rc<RefCell<TestFS>> {
+    Rc::new(RefCell::new(TestFS(HashMap::from([
+        ("stage.gs".into(), b"costumes \"blank.svg\";\n".to_vec()),
+        ("blank.svg".into(), b"<svg/>".to_vec()),
+        ("main.gs".into(), main.as_bytes().to_vec()),
+    ]))))
+}
+
+pub struct Output(pub Vec<String>);
+
+impl Host for Output {
+    fn say(&mut self, sprite: &str, message: &str) {
+        self.0.push(format!("{sprite}: {message}"));
+    }
+
+    fn log(&mut self, _level: LogLevel, sprite: &str, message: &str) {
+        self.0.push(format!("{sprite}: {message}"));
+    }
+
+    fn assertion_failed(&mut self, sprite: &str, block: &str, values: Option<(&str, &str)>) {
+        self.0.push(format!("{sprite}: {block} {values:?}"));
+    }
+}
+
+pub fn build_project_sb3(
+    fs: Rc<RefCell<TestFS>>,
+    include_tests: bool,
+) -> (Artifact, Cursor<Vec<u8>>) {
+    let stdlib = new_standard_library(Version::new(0, 0, 0), Path::new("std"));
+    let mut sb3 = Cursor::new(vec![]);
+    let artifact = build_with_cache(
+        fs,
+        "".into(),
+        &mut sb3,
+        Some(stdlib),
+        include_tests,
+        true,
+        &[],
+        &mut Default::default(),
+    )
+    .unwrap();
+    assert!(!artifact.failure());
+    sb3.set_position(0);
+    (artifact, sb3)
+}
+
+pub fn build_sb3(main: &str, include_tests: bool) -> (Artifact, Cursor<Vec<u8>>) {
+    build_project_sb3(project(main), include_tests)
+}
+
+/// The `project.json` of an `.sb3` file.
+pub fn project_json(sb3: Cursor<Vec<u8>>) -> serde_json::Value {
+    let mut zip = zip::ZipArchive::new(sb3).unwrap();
+    serde_json::from_reader(zip.by_name("project.json").unwrap()).unwrap()
+}
+
+/// Runs an `.sb3` file from the green flag.
+pub fn run_sb3(sb3: Cursor<Vec<u8>>, timeout: Option<f64>) -> (Outcome, Vec<String>) {
+    let mut vm = VM::new(sb3).unwrap();
+    vm.green_flag();
+    let mut output = Output(vec![]);
+    let outcome = vm.run(&mut output, timeout);
+    (outcome, output.0)
+}
+
+pub fn run(main: &str, timeout: Option<f64>) -> (Outcome, Vec<String>) {
+    let (_, sb3) = build_sb3(main, false);
+    run_sb3(sb3, timeout)
+}
+
+pub const TESTS: &str = r#"costumes "blank.svg";
+    onflag {
+        say "flag";
+    }
+    test "addition" {
+        assert 1 + 1 == 2;
+        assert_eq 1 + 1, 2;
+        say "passed";
+    }
+    test "subtraction" {
+        assert_eq 3 - 1, 1;
+        say "unreachable";
+    }
+    "#;
@@ -1,7 +1,10 @@
 use std::{
     cell::RefCell,
     io,
-    path::PathBuf,
+    path::{
+        Path,
+        PathBuf,
+    },
     rc::Rc,
     str,
 };
@@ -19,6 +22,7 @@ use crate::{
         Diagnostic,
         DiagnosticKind,
     },
+    misc::SmolStr,
     standard_library::StandardLibrary,
     vfs::VFS,
 };
@@ -39,6 +43,9 @@ pub struct Include {
     pub source_range: Span,
     pub path: PathBuf,
     pub owner: Owner,
+    /// The module that the source code belongs to, such as `ns` for a file imported with
+    /// `import "path" as ns`. Modules imported by a module are named `outer.inner`.
+    pub namespace: Option<SmolStr>,
 }
 
 #[derive(Tsify, Serialize, Deserialize)]
@@ -47,7 +54,7 @@ pub struct TranslationUnit {
     pub text: Vec<u8>,
     defines: FxHashSet<String>,
     includes: Vec<Include>,
-    included: FxHashSet<PathBuf>,
+    included: FxHashSet<(Option<SmolStr>, PathBuf)>,
     current_include: usize,
 }
 
@@ -70,9 +77,10 @@ impl TranslationUnit {
             source_range: 0..unit.text.len(),
             path: unit.path.clone(),
             owner: Owner::Local,
+            namespace: None,
         });
         unit.included
-            .insert(unit.path.normalize_lexically().unwrap());
+            .insert((None, unit.path.normalize_lexically().unwrap()));
         Ok(unit)
     }
 
@@ -88,6 +96,61 @@ impl TranslationUnit {
         }
         panic!("invalid position {position} in {}", self.path.display());
     }
+
+    /// The range of `span` in the source file it starts in. A span which continues into
+    /// another file ends with its first file.
+    pub fn translate_span(&self, span: &Span) -> (Span, &Include) {
+        let (start, include) = self.translate_position(span.start);
+        let end = if span.end > span.start {
+            let (end, end_include) = self.translate_position(span.end - 1);
+            if end_include.path == include.path {
+                end + 1
+            } else {
+                include.source_range.end
+            }
+        } else {
+            start
+        };
+        (start..end, include)
+    }
+
+    /// The inverse of [`Self::translate_position`], returns the position in the translation
+    /// unit of a position in one of the included source files.
+    pub fn untranslate_position(&self, path: &Path, position: usize) -> Option<usize> {
+        self.includes.iter().find_map(|include| {
+            (include.path == path && include.source_range.contains(&position))
+                .then(|| include.unit_range.start + (position - include.source_range.start))
+        })
+    }
+
+    pub fn includes(&self) -> &[Include] {
+        &self.includes
+    }
+
+    /// Returns the module that the source code at `position` belongs to.
+    pub fn namespace_at(&self, position: usize) -> Option<&SmolStr> {
+        self.includes
+            .iter()
+            .find(|include| include.unit_range.contains(&position))
+            .and_then(|include| include.namespace.as_ref())
+    }
+
+    /// Returns `name` prefixed with the module that the source code at `position` belongs
+    /// to, such as `ns.name`.
+    pub fn qualify(&self, position: usize, name: SmolStr) -> SmolStr {
+        match self.namespace_at(position) {
+            Some(namespace) => format!("{namespace}.{name}").into(),
+            None => name,
+        }
+    }
+
+    /// Returns the names of all modules imported into the translation unit.
+    pub fn namespaces(&self) -> FxHashSet<SmolStr> {
+        self.includes
+            .iter()
+            .filter_map(|include| include.namespace.clone())
+            .collect()
+    }
 }
 
 pub fn parse_translation_unit(
@@ -154,6 +217,7 @@ pub fn parse_translation_unit(
                 add_include_to_translation_unit(
                     unit,
                     path,
+                    None,
                     start..j,
                     i,
                     fs.clone(),
@@ -218,6 +282,7 @@ pub fn parse_translation_unit(
                 diagnostics.push(Diagnostic {
                     kind: DiagnosticKind::UnknownDirective(name.into()),
                     span: start..j,
+                    notes: vec![],
                 });
                 i = j;
             } else if starts_with_directive(&unit.text[i..], b"%if") {
@@ -270,15 +335,85 @@ pub fn parse_translation_unit(
                 diagnostics.push(Diagnostic {
                     kind: DiagnosticKind::UnknownDirective(name.into()),
                     span: start..j,
+                    notes: vec![],
                 });
                 i = j;
             }
+        } else if (i == 0 || unit.text[i - 1] == b'\n') && starts_with_import(&unit.text[i..]) {
+            while unit.text[i] == b' ' || unit.text[i] == b'\t' {
+                i += 1;
+            }
+            let start = i;
+            let j = unit.text[i..]
+                .iter()
+                .position(|c| *c == b'\n')
+                .map(|j| i + j + 1)
+                .unwrap_or(unit.text.len());
+            let line = std::str::from_utf8(&unit.text[i..j])
+                .unwrap()
+                .trim()
+                .to_owned();
+            // replace `i` with `#` so the parser treats the import as a comment
+            unit.text[i] = b'#';
+            i = j;
+            let Some((path, namespace)) = parse_import(&line) else {
+                diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::InvalidImport,
+                    span: start..j - 1,
+                    notes: vec![],
+                });
+                continue;
+            };
+            add_include_to_translation_unit(
+                unit,
+                path,
+                Some(namespace),
+                start..j - 1,
+                i,
+                fs.clone(),
+                stdlib,
+                diagnostics,
+                None,
+            );
         } else {
             i += 1;
         }
     }
 }
 
+fn starts_with_import(text: &[u8]) -> bool {
+    let indent = text
+        .iter()
+        .take_while(|c| **c == b' ' || **c == b'\t')
+        .count();
+    text[indent..]
+        .strip_prefix(b"import")
+        .is_some_and(|rest| rest.trim_ascii_start().starts_with(b"\""))
+}
+
+/// Parses `import "path" as ns`, returning the path and the namespace.
+fn parse_import(line: &str) -> Option<(String, SmolStr)> {
+    let rest = line
+        .strip_prefix("import")?
+        .trim_start()
+        .strip_prefix('"')?;
+    let (path, rest) = rest.split_once('"')?;
+    let namespace = rest.trim().strip_prefix("as")?;
+    if !namespace.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let namespace = namespace.trim().trim_end_matches(';').trim_end();
+    let mut chars = namespace.chars();
+    if !chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+    Some((path.to_owned(), namespace.into()))
+}
+
 fn starts_with_directive(text: &[u8], directive: &[u8]) -> bool {
     text.starts_with(directive)
         && text
@@ -289,6 +424,7 @@ fn starts_with_directive(text: &[u8], directive: &[u8]) -> bool {
 fn add_include_to_translation_unit(
     unit: &mut TranslationUnit,
     path: String,
+    import_as: Option<SmolStr>,
     span: Span,
     start: usize,
     fs: Rc<RefCell<dyn VFS>>,
@@ -316,12 +452,21 @@ fn add_include_to_translation_unit(
         path = path.with_added_extension("gs");
     }
 
-    if unit.included.contains(&path) {
+    // An included file belongs to the module that includes it, an imported file is a new
+    // module inside of it.
+    let parent_namespace = unit.includes[unit.current_include].namespace.clone();
+    let namespace = match import_as {
+        Some(import_as) => Some(match &parent_namespace {
+            Some(parent_namespace) => format!("{parent_namespace}.{import_as}").into(),
+            None => import_as,
+        }),
+        None => parent_namespace,
+    };
+
+    if !unit.included.insert((namespace.clone(), path.clone())) {
         return;
     }
 
-    unit.included.insert(path.clone());
-
     let mut buffer = match fs.read_to_vec(&path) {
         Ok(buffer) => buffer,
         Err(error) => {
@@ -331,6 +476,7 @@ fn add_include_to_translation_unit(
                     help,
                 },
                 span,
+                notes: vec![],
             });
             return;
         }
@@ -354,6 +500,7 @@ fn add_include_to_translation_unit(
                 ..(current_include.source_range.start + top_unit_range.len()),
             path: current_include.path.clone(),
             owner: current_include.owner,
+            namespace: current_include.namespace.clone(),
         },
     );
 
@@ -365,6 +512,7 @@ fn add_include_to_translation_unit(
             source_range: 0..buffer.len(),
             path,
             owner,
+            namespace,
         },
     );
 
@@ -380,6 +528,7 @@ fn add_include_to_translation_unit(
                     + bottom_unit_range.len()),
             path: current_include.path,
             owner: current_include.owner,
+            namespace: current_include.namespace,
         },
     );
 
@@ -414,6 +563,21 @@ mod tests {
         assert!(!starts_with_directive(b"%endifx\n", b"%endif"));
     }
 
+    #[test]
+    fn imports_are_parsed() {
+        assert_eq!(
+            parse_import(r#"import "std/math" as math"#),
+            Some(("std/math".to_owned(), "math".into()))
+        );
+        assert_eq!(
+            parse_import(r#"import "lib" as lib;"#),
+            Some(("lib".to_owned(), "lib".into()))
+        );
+        assert_eq!(parse_import(r#"import "lib" aslib"#), None);
+        assert_eq!(parse_import(r#"import "lib" as 1lib"#), None);
+        assert_eq!(parse_import(r#"import "lib""#), None);
+    }
+
     #[test]
     fn root_file_is_already_included() {
         let fs = Rc::new(RefCell::new(TestFS(HashMap::from([(
@@ -1,7 +1,14 @@
+pub mod infer;
+pub mod inline;
+pub mod loops;
+pub mod match_;
+pub mod modules;
 pub mod pass0;
 pub mod pass1;
 pub mod pass2;
 pub mod pass3;
 pub mod pass4;
+pub mod recursion;
 pub mod ternary;
 mod transformations;
+mod walk;
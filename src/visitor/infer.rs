@@ -0,0 +1,437 @@
+use logos::Span;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    ast::*,
+    blocks::{
+        input_kind,
+        BinOp,
+        Block,
+        Repr,
+    },
+    diagnostic::{
+        DiagnosticKind,
+        SpriteDiagnostics,
+    },
+    misc::SmolStr,
+};
+
+/// Infers whether expressions are numbers, strings or booleans, from literals, block
+/// outputs and annotations such as `var number score = 0;`, and warns about values given
+/// to an input which takes another kind. Values of unknown kind are never reported.
+pub fn visit_project(
+    project: &Project,
+    stage_diagnostics: &mut SpriteDiagnostics,
+    sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
+) {
+    visit_sprite(&project.stage, None, stage_diagnostics);
+    for (sprite_name, sprite) in &project.sprites {
+        visit_sprite(
+            sprite,
+            Some(&project.stage),
+            sprites_diagnostics.get_mut(sprite_name).unwrap(),
+        );
+    }
+}
+
+struct V<'a, 'b> {
+    sprite: &'a Sprite,
+    stage: Option<&'a Sprite>,
+    args: &'a [Arg],
+    locals: Option<&'a FxHashMap<SmolStr, Var>>,
+    return_type: Option<&'a Type>,
+    d: &'b mut SpriteDiagnostics,
+}
+
+fn visit_sprite(sprite: &Sprite, stage: Option<&Sprite>, d: &mut SpriteDiagnostics) {
+    let mut v = V {
+        sprite,
+        stage,
+        args: &[],
+        locals: None,
+        return_type: None,
+        d,
+    };
+    for var in sprite.vars.values() {
+        if let Some(default) = &var.default {
+            v.const_expr(var.type_.kind(), default);
+        }
+    }
+    for proc in sprite.procs.values() {
+        v.args = &sprite.proc_args[&proc.name];
+        v.locals = sprite.proc_locals.get(&proc.name);
+        v.return_type = None;
+        v.arg_defaults();
+        v.stmts(&sprite.proc_definitions[&proc.name]);
+    }
+    for func in sprite.funcs.values() {
+        v.args = &sprite.func_args[&func.name];
+        v.locals = sprite.func_locals.get(&func.name);
+        v.return_type = Some(&func.type_);
+        v.arg_defaults();
+        v.stmts(&sprite.func_definitions[&func.name]);
+    }
+    v.args = &[];
+    v.return_type = None;
+    for event in &sprite.events {
+        v.locals = Some(&event.locals);
+        if let EventKind::OnLoudnessGt { value }
+        | EventKind::OnTimerGt { value }
+        | EventKind::OnVideoMotionGt { value } = &event.kind
+        {
+            v.check(Kind::Number, value);
+        }
+        v.stmts(&event.body);
+    }
+}
+
+impl<'a> V<'a, '_> {
+    fn get_struct(&self, name: &str) -> Option<&'a Struct> {
+        self.sprite
+            .structs
+            .get(name)
+            .or_else(|| self.stage.and_then(|stage| stage.structs.get(name)))
+    }
+
+    fn get_list(&self, name: &str) -> Option<&'a List> {
+        self.sprite
+            .lists
+            .get(name)
+            .or_else(|| self.stage.and_then(|stage| stage.lists.get(name)))
+    }
+
+    fn get_var(&self, name: &str) -> Option<&'a Var> {
+        self.locals
+            .and_then(|locals| locals.get(name))
+            .or_else(|| self.sprite.vars.get(name))
+            .or_else(|| self.stage.and_then(|stage| stage.vars.get(name)))
+    }
+
+    /// The type of the field at `path`, such as `pos.x`, of a value of type `type_`.
+    fn field_type(&self, mut type_: &'a Type, path: &str) -> Option<&'a Type> {
+        for field_name in path.split('.') {
+            let (struct_name, _) = type_.struct_()?;
+            let struct_ = self.get_struct(struct_name)?;
+            type_ = &struct_.fields.iter().find(|f| f.name == field_name)?.type_;
+        }
+        Some(type_)
+    }
+
+    fn name_type(&self, type_: &'a Type, name: &Name) -> Option<&'a Type> {
+        match name.fieldname() {
+            Some(path) => self.field_type(type_, path),
+            None => Some(type_),
+        }
+    }
+
+    fn var_type(&self, name: &Name) -> Option<&'a Type> {
+        if self.get_list(name.basename()).is_some() {
+            return None;
+        }
+        self.name_type(&self.get_var(name.basename())?.type_, name)
+    }
+
+    fn arg_type(&self, name: &Name) -> Option<&'a Type> {
+        let arg = self.args.iter().find(|arg| &arg.name == name.basename())?;
+        self.name_type(&arg.type_, name)
+    }
+
+    /// The type of a value of struct type, which can be accessed with `.`.
+    fn expr_type(&self, expr: &Expr) -> Option<&'a Type> {
+        match expr {
+            Expr::Name(name) => self.var_type(name),
+            Expr::Arg(name) => self.arg_type(name),
+            Expr::FuncCall { name, .. } => self.sprite.funcs.get(name).map(|func| &func.type_),
+            Expr::Dot { lhs, rhs, .. } => self.field_type(self.expr_type(lhs)?, rhs),
+            _ => None,
+        }
+    }
+
+    fn kind(&mut self, expr: &Expr) -> Kind {
+        match expr {
+            Expr::Value { value, .. } => Kind::of_value(value),
+            Expr::Name(name) => self.var_type(name).map_or(Kind::Unknown, Type::kind),
+            Expr::Arg(name) => self.arg_type(name).map_or(Kind::Unknown, Type::kind),
+            Expr::Dot { lhs, .. } => {
+                self.kind(lhs);
+                self.expr_type(expr).map_or(Kind::Unknown, Type::kind)
+            }
+            Expr::Repr { repr, args, .. } => {
+                self.repr_args(*repr, args);
+                repr.kind()
+            }
+            Expr::FuncCall {
+                name, args, kwargs, ..
+            } => match self.sprite.funcs.get(name) {
+                Some(func) => {
+                    self.call_args(&self.sprite.func_args[name], args, kwargs);
+                    func.type_.kind()
+                }
+                None => Kind::Unknown,
+            },
+            Expr::UnOp { op, opr, .. } => {
+                let (opr_kind, kind) = op.kinds();
+                self.check(opr_kind, opr);
+                kind
+            }
+            Expr::BinOp { op, lhs, rhs, .. } => {
+                if let (BinOp::Of, Expr::Name(name)) = (op, lhs.as_ref()) {
+                    if let Some(list) = self.get_list(name.basename()) {
+                        self.check(Kind::Number, rhs);
+                        return list.type_.kind();
+                    }
+                }
+                let (lhs_kind, rhs_kind, kind) = op.kinds();
+                self.check(lhs_kind, lhs);
+                self.check(rhs_kind, rhs);
+                kind
+            }
+            Expr::StructLiteral { name, fields, .. } => {
+                let struct_ = self.get_struct(name);
+                for field in fields {
+                    let kind = struct_
+                        .and_then(|struct_| struct_.fields.iter().find(|f| f.name == field.name))
+                        .map_or(Kind::Unknown, |f| f.type_.kind());
+                    self.check(kind, &field.value);
+                }
+                Kind::Unknown
+            }
+            Expr::Property { object, .. } => {
+                self.kind(object);
+                Kind::Unknown
+            }
+            Expr::Ternary {
+                condition,
+                tvalue,
+                fvalue,
+            } => {
+                self.check(Kind::Boolean, condition);
+                let tkind = self.kind(tvalue);
+                let fkind = self.kind(fvalue);
+                tkind.union(fkind)
+            }
+        }
+    }
+
+    fn check(&mut self, expected: Kind, expr: &Expr) {
+        let found = self.kind(expr);
+        if !expected.accepts(found) {
+            self.d.report(
+                DiagnosticKind::KindMismatch { expected, found },
+                &expr.span(),
+            );
+        }
+    }
+
+    fn const_expr(&mut self, expected: Kind, const_expr: &ConstExpr) {
+        if let ConstExpr::Value { value, span } = const_expr {
+            let found = Kind::of_value(value);
+            if !expected.accepts(found) {
+                self.d
+                    .report(DiagnosticKind::KindMismatch { expected, found }, span);
+            }
+        }
+    }
+
+    fn arg_defaults(&mut self) {
+        for arg in self.args {
+            if let Some(default) = &arg.default {
+                self.const_expr(arg.type_.kind(), default);
+            }
+        }
+    }
+
+    fn call_args(
+        &mut self,
+        params: &[Arg],
+        args: &[Expr],
+        kwargs: &FxHashMap<SmolStr, (Span, Expr)>,
+    ) {
+        for (i, arg) in args.iter().enumerate() {
+            let kind = params
+                .get(i)
+                .map_or(Kind::Unknown, |param| param.type_.kind());
+            self.check(kind, arg);
+        }
+        for (name, (_, arg)) in kwargs {
+            let kind = params
+                .iter()
+                .find(|param| &param.name == name)
+                .map_or(Kind::Unknown, |param| param.type_.kind());
+            self.check(kind, arg);
+        }
+    }
+
+    fn block_args(&mut self, block: Block, args: &[Expr]) {
+        let menu = block.menu().map(|menu| menu.input);
+        for (input, arg) in block.args().iter().zip(args) {
+            let kind = if Some(*input) == menu {
+                Kind::Unknown
+            } else {
+                input_kind(input)
+            };
+            self.check(kind, arg);
+        }
+    }
+
+    fn repr_args(&mut self, repr: Repr, args: &[Expr]) {
+        let menu = repr.menu().map(|menu| menu.input);
+        for (input, arg) in repr.args().iter().zip(args) {
+            let kind = if Some(*input) == menu {
+                Kind::Unknown
+            } else {
+                input_kind(input)
+            };
+            self.check(kind, arg);
+        }
+    }
+
+    fn list_kind(&self, name: &Name) -> Kind {
+        self.get_list(name.basename())
+            .map_or(Kind::Unknown, |list| list.type_.kind())
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Repeat { times, body } => {
+                self.check(Kind::Number, times);
+                self.stmts(body);
+            }
+            Stmt::Forever { body, .. } => self.stmts(body),
+            Stmt::Branch {
+                cond,
+                if_body,
+                else_body,
+            } => {
+                self.check(Kind::Boolean, cond);
+                self.stmts(if_body);
+                self.stmts(else_body);
+            }
+            Stmt::Until { cond, body } | Stmt::While { cond, body } => {
+                self.check(Kind::Boolean, cond);
+                self.stmts(body);
+            }
+            Stmt::For {
+                start, end, body, ..
+            } => {
+                self.check(Kind::Number, start);
+                self.check(Kind::Number, end);
+                self.stmts(body);
+            }
+            Stmt::Match { value, arms, .. } => {
+                self.kind(value);
+                for arm in arms {
+                    self.stmts(&arm.body);
+                }
+            }
+            Stmt::SetVar {
+                name, value, type_, ..
+            } => {
+                let kind = match type_ {
+                    Type::Kind { kind, .. } => *kind,
+                    _ => self.var_type(name).map_or(Kind::Unknown, Type::kind),
+                };
+                self.check(kind, value);
+            }
+            Stmt::Destructure { value, .. } => {
+                self.kind(value);
+            }
+            Stmt::ChangeVar { value, .. } => self.check(Kind::Number, value),
+            Stmt::AddToList { name, value } => self.check(self.list_kind(name), value),
+            Stmt::DeleteListIndex { index, .. } => self.check(Kind::Number, index),
+            Stmt::InsertAtList { name, index, value }
+            | Stmt::SetListIndex { name, index, value } => {
+                self.check(Kind::Number, index);
+                self.check(self.list_kind(name), value);
+            }
+            Stmt::Block { block, args, .. } => self.block_args(*block, args),
+            Stmt::ProcCall {
+                name, args, kwargs, ..
+            } => {
+                let params = self
+                    .sprite
+                    .proc_args
+                    .get(name)
+                    .map_or(&[][..], |params| params.as_slice());
+                self.call_args(params, args, kwargs);
+            }
+            Stmt::FuncCall {
+                name, args, kwargs, ..
+            } => {
+                let params = self
+                    .sprite
+                    .func_args
+                    .get(name)
+                    .map_or(&[][..], |params| params.as_slice());
+                self.call_args(params, args, kwargs);
+            }
+            Stmt::Return { value, .. } => {
+                let kind = self.return_type.map_or(Kind::Unknown, Type::kind);
+                self.check(kind, value);
+            }
+            Stmt::Show(_)
+            | Stmt::Hide(_)
+            | Stmt::DeleteList(_)
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Kind,
+        codegen::build::ParseCache,
+        diagnostic::DiagnosticKind,
+        testing::*,
+    };
+
+    #[test]
+    fn mismatched_kinds_are_reported() {
+        let fs = project(
+            r#"costumes "blank.svg";
+            var number score = "5";
+            list number scores;
+            func half(number n) number { return $n / 2; }
+            onflag {
+                say "abc" * 2;
+                set_x 1 < 2;
+                score = "high";
+                add "none" to scores;
+                say half(score > 1) & "x" * 1 & score + scores[1];
+                if "yes" { say 1; }
+                say answer() * 2;
+            }
+            "#,
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(!artifact.failure());
+        let mismatches: Vec<(Kind, Kind)> = artifact.sprites_diagnostics["main"]
+            .diagnostics
+            .iter()
+            .filter_map(|diagnostic| match diagnostic.kind {
+                DiagnosticKind::KindMismatch { expected, found } => Some((expected, found)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            mismatches,
+            [
+                (Kind::Number, Kind::String),
+                (Kind::Number, Kind::Boolean),
+                (Kind::Number, Kind::String),
+                (Kind::Number, Kind::String),
+                (Kind::Number, Kind::Boolean),
+                (Kind::Number, Kind::String),
+                (Kind::Boolean, Kind::String),
+            ]
+        );
+    }
+}
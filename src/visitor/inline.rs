@@ -0,0 +1,841 @@
+use logos::Span;
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
+
+use super::{
+    transformations::keyword_arguments,
+    walk::{
+        calls,
+        stmt_names,
+        walk_stmts,
+        Callable,
+    },
+};
+use crate::{
+    ast::*,
+    blocks::{
+        BinOp,
+        Block,
+        UnOp,
+    },
+    codegen::sb3::qualify_local_var_name,
+    diagnostic::{
+        DiagnosticKind,
+        SpriteDiagnostics,
+    },
+    misc::SmolStr,
+};
+
+/// A procedure or function whose calls are replaced by its body.
+struct Callee {
+    span: Span,
+    /// Whether it was declared with `inline`, rather than picked by `inline_threshold`.
+    explicit: bool,
+    args: Vec<Arg>,
+    locals: FxHashMap<SmolStr, Var>,
+    /// The body, without the `return`s at its end and with the other `return`s lowered.
+    body: Vec<Stmt>,
+    /// Variables used by the `return`s before the end of the body. The first is set to 1
+    /// once the body has returned, and the rest count the iterations of the loops that it
+    /// returns from.
+    return_vars: Vec<SmolStr>,
+    calls: FxHashSet<Callable>,
+    /// Variables and lists used by the body, other than its local variables.
+    free_names: FxHashSet<SmolStr>,
+}
+
+struct V<'a> {
+    callees: &'a FxHashMap<Callable, Callee>,
+    vars: &'a mut FxHashMap<SmolStr, Var>,
+    inlined: FxHashSet<Callable>,
+    d: &'a mut SpriteDiagnostics,
+}
+
+/// Replaces calls to procedures and functions declared with `inline`, or small enough
+/// for `inline_threshold`, with their bodies. This runs after `pass1`, so every call to
+/// a function is a statement of its own. If `inline` is not set, only the procedures
+/// and functions which cannot be inlined are reported.
+pub fn visit_project(
+    project: &mut Project,
+    inline_threshold: Option<u64>,
+    inline: bool,
+    stage_diagnostics: &mut SpriteDiagnostics,
+    sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
+) {
+    visit_sprite(
+        &mut project.stage,
+        inline_threshold,
+        inline,
+        stage_diagnostics,
+    );
+    for (sprite_name, sprite) in &mut project.sprites {
+        visit_sprite(
+            sprite,
+            inline_threshold,
+            inline,
+            sprites_diagnostics.get_mut(sprite_name).unwrap(),
+        );
+    }
+}
+
+fn visit_sprite(
+    sprite: &mut Sprite,
+    inline_threshold: Option<u64>,
+    inline: bool,
+    d: &mut SpriteDiagnostics,
+) {
+    let mut callees = FxHashMap::default();
+    for proc in sprite.procs.values() {
+        let key = Callable::Proc(proc.name.clone());
+        if let Some(callee) = callee(
+            &proc.name,
+            &proc.span,
+            proc.inline,
+            &sprite.proc_definitions[&proc.name],
+            &sprite.proc_args[&proc.name],
+            &sprite.proc_locals[&proc.name],
+            inline_threshold,
+            d,
+        ) {
+            callees.insert(key, callee);
+        }
+    }
+    for func in sprite.funcs.values() {
+        let key = Callable::Func(func.name.clone());
+        if let Some(callee) = callee(
+            &func.name,
+            &func.span,
+            func.inline,
+            &sprite.func_definitions[&func.name],
+            &sprite.func_args[&func.name],
+            &sprite.func_locals[&func.name],
+            inline_threshold,
+            d,
+        ) {
+            callees.insert(key, callee);
+        }
+    }
+    if callees.is_empty() {
+        return;
+    }
+    remove_recursive(&mut callees, d);
+    remove_shadowed(&mut callees, sprite, d);
+    if !inline {
+        return;
+    }
+    let mut v = V {
+        callees: &callees,
+        vars: &mut sprite.vars,
+        inlined: FxHashSet::default(),
+        d,
+    };
+    for (name, body) in &mut sprite.proc_definitions {
+        if !callees.contains_key(&Callable::Proc(name.clone())) {
+            visit_stmts(body, &mut v);
+        }
+    }
+    for (name, body) in &mut sprite.func_definitions {
+        if !callees.contains_key(&Callable::Func(name.clone())) {
+            visit_stmts(body, &mut v);
+        }
+    }
+    for event in &mut sprite.events {
+        visit_stmts(&mut event.body, &mut v);
+    }
+    for key in v.inlined {
+        match key {
+            Callable::Proc(name) => sprite.inlined_procs.insert(name),
+            Callable::Func(name) => sprite.inlined_funcs.insert(name),
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn callee(
+    name: &SmolStr,
+    span: &Span,
+    explicit: bool,
+    body: &[Stmt],
+    args: &[Arg],
+    locals: &FxHashMap<SmolStr, Var>,
+    inline_threshold: Option<u64>,
+    d: &mut SpriteDiagnostics,
+) -> Option<Callee> {
+    let mut size = 0;
+    let mut has_loops = false;
+    let mut stops = false;
+    let mut uses_unknown_args = false;
+    let mut calls = FxHashSet::default();
+    let mut free_names = FxHashSet::default();
+    let mut targets = vec![];
+    walk_stmts(
+        body,
+        &mut |stmt| {
+            size += 1;
+            match stmt {
+                Stmt::Repeat { .. } | Stmt::Forever { .. } | Stmt::Until { .. } => {
+                    has_loops = true;
+                }
+                Stmt::Block {
+                    block: Block::StopThisScript,
+                    ..
+                } => stops = true,
+                _ => {}
+            }
+            calls.extend(Callable::called_by(stmt));
+            targets.extend(stmt_names(stmt).map(|name| name.basename().clone()));
+        },
+        &mut |expr| match expr {
+            Expr::Name(name) => {
+                free_names.insert(name.basename().clone());
+            }
+            Expr::Arg(name) => {
+                uses_unknown_args |= !args.iter().any(|arg| &arg.name == name.basename());
+            }
+            _ => {}
+        },
+    );
+    let is_small = inline_threshold.is_some_and(|threshold| size <= threshold && !has_loops);
+    // Unknown arguments are reported when the definition is compiled.
+    if !(explicit || is_small) || uses_unknown_args {
+        return None;
+    }
+    if stops {
+        if explicit {
+            d.report(
+                DiagnosticKind::CannotInline {
+                    name: name.clone(),
+                    reason: "it uses stop_this_script".into(),
+                },
+                span,
+            );
+        }
+        return None;
+    }
+    let mut body = body.to_vec();
+    strip_returns(&mut body);
+    let mut return_vars = vec![];
+    if contains_return(&body) {
+        let flag = qualify_local_var_name(name, "return:done");
+        return_vars.push(flag.clone());
+        let mut r = Returns {
+            callee: name,
+            flag: Name::Name {
+                name: flag,
+                span: span.clone(),
+            },
+            span,
+            vars: &mut return_vars,
+        };
+        body = r.lower(body);
+    }
+    free_names.extend(targets);
+    free_names.retain(|name| !locals.contains_key(name));
+    Some(Callee {
+        span: span.clone(),
+        explicit,
+        args: args.to_vec(),
+        locals: locals.clone(),
+        body,
+        return_vars,
+        calls,
+        free_names,
+    })
+}
+
+fn remove(
+    callees: &mut FxHashMap<Callable, Callee>,
+    key: &Callable,
+    reason: String,
+    d: &mut SpriteDiagnostics,
+) {
+    let callee = callees.remove(key).unwrap();
+    if callee.explicit {
+        d.report(
+            DiagnosticKind::CannotInline {
+                name: key.name().clone(),
+                reason: reason.into(),
+            },
+            &callee.span,
+        );
+    }
+}
+
+/// The callees which are inlined along with `key`, including itself.
+fn reachable(callees: &FxHashMap<Callable, Callee>, key: &Callable) -> FxHashSet<Callable> {
+    let mut reached = FxHashSet::default();
+    let mut stack = vec![key.clone()];
+    while let Some(key) = stack.pop() {
+        let Some(callee) = callees.get(&key) else {
+            continue;
+        };
+        if reached.insert(key) {
+            stack.extend(callee.calls.iter().cloned());
+        }
+    }
+    reached
+}
+
+fn remove_recursive(callees: &mut FxHashMap<Callable, Callee>, d: &mut SpriteDiagnostics) {
+    let recursive: Vec<Callable> = callees
+        .iter()
+        .filter(|(key, callee)| {
+            callee
+                .calls
+                .iter()
+                .any(|call| reachable(callees, call).contains(*key))
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in recursive {
+        remove(callees, &key, "it calls itself".into(), d);
+    }
+}
+
+/// Callees which use a variable that the local variables of a caller shadow cannot be
+/// inlined into it, and are called normally instead.
+fn remove_shadowed(
+    callees: &mut FxHashMap<Callable, Callee>,
+    sprite: &Sprite,
+    d: &mut SpriteDiagnostics,
+) {
+    loop {
+        let mut shadowed = None;
+        let callers = sprite
+            .procs
+            .keys()
+            .map(|name| {
+                (
+                    Some(Callable::Proc(name.clone())),
+                    name,
+                    &sprite.proc_definitions[name],
+                    &sprite.proc_locals[name],
+                )
+            })
+            .chain(sprite.funcs.keys().map(|name| {
+                (
+                    Some(Callable::Func(name.clone())),
+                    name,
+                    &sprite.func_definitions[name],
+                    &sprite.func_locals[name],
+                )
+            }))
+            .chain(
+                sprite
+                    .events
+                    .iter()
+                    .map(|event| (None, &event.scope, &event.body, &event.locals)),
+            );
+        'callers: for (caller, caller_name, body, locals) in callers {
+            if locals.is_empty() || caller.is_some_and(|caller| callees.contains_key(&caller)) {
+                continue;
+            }
+            for call in calls(body) {
+                for key in reachable(callees, &call) {
+                    if let Some(name) = callees[&key]
+                        .free_names
+                        .iter()
+                        .find(|name| locals.contains_key(*name))
+                    {
+                        shadowed = Some((key, name.clone(), caller_name.clone()));
+                        break 'callers;
+                    }
+                }
+            }
+        }
+        let Some((key, name, caller)) = shadowed else {
+            break;
+        };
+        remove(
+            callees,
+            &key,
+            format!("it uses {name}, which is a local variable of {caller}"),
+            d,
+        );
+    }
+}
+
+fn contains_return(stmts: &[Stmt]) -> bool {
+    let mut found = false;
+    walk_stmts(
+        stmts,
+        &mut |stmt| found |= matches!(stmt, Stmt::Return { .. }),
+        &mut |_| {},
+    );
+    found
+}
+
+/// Lowers the `return`s before the end of an inlined body, which set a flag and skip the
+/// statements that would run after them, the same way that `loops` lowers `break`.
+struct Returns<'a> {
+    callee: &'a str,
+    flag: Name,
+    span: &'a Span,
+    vars: &'a mut Vec<SmolStr>,
+}
+
+impl Returns<'_> {
+    fn lower(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut lowered = vec![];
+        let mut stmts = stmts.into_iter();
+        while let Some(stmt) = stmts.next() {
+            if !contains_return(std::slice::from_ref(&stmt)) {
+                lowered.push(stmt);
+                continue;
+            }
+            match stmt {
+                Stmt::Return { .. } => {
+                    lowered.push(self.set(&self.flag, self.number(1.0)));
+                    return lowered;
+                }
+                Stmt::Branch {
+                    cond,
+                    if_body,
+                    else_body,
+                } => lowered.push(Stmt::Branch {
+                    cond,
+                    if_body: self.lower(if_body),
+                    else_body: self.lower(else_body),
+                }),
+                Stmt::Until { cond, body } => lowered.push(Stmt::Until {
+                    cond: Box::new(BinOp::Or.to_expr(self.span.clone(), *cond, self.is(1.0))),
+                    body: self.lower(body),
+                }),
+                Stmt::Forever { body, .. } => lowered.push(Stmt::Until {
+                    cond: Box::new(self.is(1.0)),
+                    body: self.lower(body),
+                }),
+                Stmt::Repeat { times, body } => {
+                    // Scratch's repeat block rounds the number of times.
+                    let remaining = qualify_local_var_name(
+                        self.callee,
+                        &format!("return:remaining:{}", self.vars.len()),
+                    );
+                    self.vars.push(remaining.clone());
+                    let remaining = Name::Name {
+                        name: remaining,
+                        span: self.span.clone(),
+                    };
+                    lowered
+                        .push(self.set(&remaining, UnOp::Round.to_expr(self.span.clone(), *times)));
+                    let mut body = self.lower(body);
+                    body.push(Stmt::decrement(remaining.clone()));
+                    let ended = BinOp::Lt.to_expr(
+                        self.span.clone(),
+                        Expr::Name(remaining),
+                        self.number(1.0),
+                    );
+                    lowered.push(Stmt::Until {
+                        cond: Box::new(BinOp::Or.to_expr(self.span.clone(), ended, self.is(1.0))),
+                        body,
+                    });
+                }
+                _ => unreachable!(),
+            }
+            let rest = self.lower(stmts.collect());
+            if !rest.is_empty() {
+                lowered.push(Stmt::Branch {
+                    cond: Box::new(self.is(0.0)),
+                    if_body: rest,
+                    else_body: vec![],
+                });
+            }
+            return lowered;
+        }
+        lowered
+    }
+
+    fn number(&self, value: f64) -> Expr {
+        Value::from(value).to_expr(self.span.clone())
+    }
+
+    fn is(&self, value: f64) -> Expr {
+        BinOp::Eq.to_expr(
+            self.span.clone(),
+            Expr::Name(self.flag.clone()),
+            self.number(value),
+        )
+    }
+
+    fn set(&self, name: &Name, value: Expr) -> Stmt {
+        Stmt::SetVar {
+            name: name.clone(),
+            value: Box::new(value),
+            type_: Type::Value,
+            is_local: false,
+            is_cloud: false,
+        }
+    }
+}
+
+fn strip_returns(stmts: &mut Vec<Stmt>) {
+    match stmts.last_mut() {
+        Some(Stmt::Return { .. }) => {
+            stmts.pop();
+        }
+        Some(Stmt::Branch {
+            if_body, else_body, ..
+        }) => {
+            strip_returns(if_body);
+            strip_returns(else_body);
+        }
+        _ => {}
+    }
+}
+
+fn visit_stmts(stmts: &mut Vec<Stmt>, v: &mut V) {
+    let mut i = 0;
+    while i < stmts.len() {
+        // The inlined statements are visited again, as they may call other callees.
+        if let Some(inlined) = inline_call(&mut stmts[i], v) {
+            stmts.splice(i..i + 1, inlined);
+            continue;
+        }
+        match &mut stmts[i] {
+            Stmt::Repeat { body, .. } | Stmt::Forever { body, .. } | Stmt::Until { body, .. } => {
+                visit_stmts(body, v)
+            }
+            Stmt::Branch {
+                if_body, else_body, ..
+            } => {
+                visit_stmts(if_body, v);
+                visit_stmts(else_body, v);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+fn inline_call(stmt: &mut Stmt, v: &mut V) -> Option<Vec<Stmt>> {
+    let (key, args, kwargs) = match stmt {
+        Stmt::ProcCall {
+            name, args, kwargs, ..
+        } => (Callable::Proc(name.clone()), args, kwargs),
+        Stmt::FuncCall {
+            name, args, kwargs, ..
+        } => (Callable::Func(name.clone()), args, kwargs),
+        _ => return None,
+    };
+    let callee = v.callees.get(&key)?;
+    keyword_arguments(Some(&callee.args), args, kwargs, v.d);
+    // The wrong number of arguments is reported when the call is compiled.
+    if args.len() != callee.args.len() {
+        return None;
+    }
+    let name = key.name();
+    let mut stmts = vec![];
+    let mut values = FxHashMap::default();
+    for (arg, value) in callee.args.iter().zip(args.drain(..)) {
+        if let Expr::Value { value, .. } = &value {
+            if arg.type_.is_value() {
+                values.insert(arg.name.clone(), value.clone());
+                continue;
+            }
+        }
+        let var_name = qualify_local_var_name(name, &format!("${}", arg.name));
+        declare(v.vars, &var_name, &arg.span, &arg.type_);
+        stmts.push(Stmt::SetVar {
+            name: Name::Name {
+                name: var_name,
+                span: arg.span.clone(),
+            },
+            value: Box::new(value),
+            type_: arg.type_.clone(),
+            is_local: false,
+            is_cloud: false,
+        });
+    }
+    for local in callee.locals.values() {
+        declare(
+            v.vars,
+            &qualify_local_var_name(name, &local.name),
+            &local.span,
+            &local.type_,
+        );
+    }
+    for var in &callee.return_vars {
+        declare(v.vars, var, &callee.span, &Type::Value);
+    }
+    if let Some(flag) = callee.return_vars.first() {
+        stmts.push(Stmt::SetVar {
+            name: Name::Name {
+                name: flag.clone(),
+                span: callee.span.clone(),
+            },
+            value: Box::new(Value::from(0.0).to_expr(callee.span.clone())),
+            type_: Type::Value,
+            is_local: false,
+            is_cloud: false,
+        });
+    }
+    let mut body = callee.body.clone();
+    let r = R {
+        callee: name,
+        values: &values,
+        locals: &callee.locals,
+    };
+    r.stmts(&mut body);
+    stmts.extend(body);
+    v.inlined.insert(key);
+    Some(stmts)
+}
+
+fn declare(vars: &mut FxHashMap<SmolStr, Var>, name: &SmolStr, span: &Span, type_: &Type) {
+    vars.entry(name.clone()).or_insert_with(|| Var {
+        name: name.clone(),
+        span: span.clone(),
+        type_: type_.clone(),
+        default: None,
+        is_cloud: false,
+        is_used: true,
+    });
+}
+
+/// Renames the arguments and local variables in an inlined body, the same way that
+/// local variables are named in the project.
+struct R<'a> {
+    callee: &'a str,
+    /// Arguments which were given a literal value, which is used in their place.
+    values: &'a FxHashMap<SmolStr, Value>,
+    locals: &'a FxHashMap<SmolStr, Var>,
+}
+
+impl R<'_> {
+    fn rename(name: &mut Name, new_name: SmolStr) {
+        match name {
+            Name::Name { name, .. } => *name = new_name,
+            Name::DotName { lhs, .. } => *lhs = new_name,
+        }
+    }
+
+    fn name(&self, name: &mut Name) {
+        if self.locals.contains_key(name.basename()) {
+            Self::rename(name, qualify_local_var_name(self.callee, name.basename()));
+        }
+    }
+
+    fn stmts(&self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Repeat { times, body } => {
+                self.expr(times);
+                self.stmts(body);
+            }
+            Stmt::Forever { body, .. } => self.stmts(body),
+            Stmt::Branch {
+                cond,
+                if_body,
+                else_body,
+            } => {
+                self.expr(cond);
+                self.stmts(if_body);
+                self.stmts(else_body);
+            }
+            Stmt::Until { cond, body } => {
+                self.expr(cond);
+                self.stmts(body);
+            }
+            Stmt::While { .. }
+            | Stmt::For { .. }
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Match { .. } => unreachable!(),
+            Stmt::SetVar {
+                name,
+                value,
+                is_local,
+                ..
+            } => {
+                self.name(name);
+                *is_local = false;
+                self.expr(value);
+            }
+            Stmt::Destructure {
+                names,
+                value,
+                is_local,
+            } => {
+                for name in names {
+                    self.name(name);
+                }
+                *is_local = false;
+                self.expr(value);
+            }
+            Stmt::ChangeVar { name, value } => {
+                self.name(name);
+                self.expr(value);
+            }
+            Stmt::Show(name) | Stmt::Hide(name) => self.name(name),
+            Stmt::AddToList { value, .. } => self.expr(value),
+            Stmt::DeleteList(_) => {}
+            Stmt::DeleteListIndex { index, .. } => self.expr(index),
+            Stmt::InsertAtList { index, value, .. } | Stmt::SetListIndex { index, value, .. } => {
+                self.expr(index);
+                self.expr(value);
+            }
+            Stmt::Block { args, kwargs, .. }
+            | Stmt::ProcCall { args, kwargs, .. }
+            | Stmt::FuncCall { args, kwargs, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                for (_, arg) in kwargs.values_mut() {
+                    self.expr(arg);
+                }
+            }
+            Stmt::Return { value, .. } => self.expr(value),
+        }
+    }
+
+    fn expr(&self, expr: &mut Expr) {
+        match expr {
+            Expr::Value { .. } => {}
+            Expr::Name(name) => self.name(name),
+            Expr::Arg(name) => {
+                let value = match name {
+                    Name::Name { .. } => self.values.get(name.basename()),
+                    Name::DotName { .. } => None,
+                };
+                *expr = match value {
+                    Some(value) => Expr::Value {
+                        value: value.clone(),
+                        span: name.span(),
+                    },
+                    None => {
+                        let mut name = name.clone();
+                        let var_name =
+                            qualify_local_var_name(self.callee, &format!("${}", name.basename()));
+                        Self::rename(&mut name, var_name);
+                        Expr::Name(name)
+                    }
+                };
+            }
+            Expr::Dot { lhs, .. } => self.expr(lhs),
+            Expr::Repr { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::FuncCall { args, kwargs, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                for (_, arg) in kwargs.values_mut() {
+                    self.expr(arg);
+                }
+            }
+            Expr::UnOp { opr, .. } => self.expr(opr),
+            Expr::BinOp { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::StructLiteral { fields, .. } => {
+                for field in fields {
+                    self.expr(&mut field.value);
+                }
+            }
+            Expr::Property { object, .. } => self.expr(object),
+            Expr::Ternary { .. } => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostic::DiagnosticKind,
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn inline_procs_and_funcs_are_replaced_by_their_bodies() {
+        let main = r#"costumes "blank.svg";
+            var total = 0;
+            inline func square(x) { return $x * $x; }
+            inline proc accumulate n {
+                local doubled = $n * 2;
+                total += doubled;
+            }
+            inline func sign(x) {
+                if $x < 0 { return -1; } else { return 1; }
+            }
+            inline proc countdown n {
+                if $n > 0 { countdown $n - 1; }
+            }
+            onflag {
+                accumulate 3;
+                accumulate square(total + 1);
+                say total;
+                say sign(0 - total) & sign(total);
+                countdown 2;
+            }
+            "#;
+        let (outcome, output) = run(main, None);
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: 104", "main: -11"]);
+        let (artifact, _) = build_sb3(main, false);
+        let sprite = &artifact.project.sprites["main"];
+        assert!(sprite.inlined_procs.contains("accumulate"));
+        assert!(sprite.inlined_funcs.contains("square"));
+        assert!(!sprite.inlined_procs.contains("countdown"));
+        assert!(sprite.vars.contains_key("accumulate:doubled"));
+        assert!(artifact.sprites_diagnostics["main"].diagnostics.iter().any(
+            |diagnostic| matches!(
+                &diagnostic.kind,
+                DiagnosticKind::CannotInline { name, .. } if name == "countdown"
+            )
+        ));
+    }
+
+    #[test]
+    fn inline_funcs_can_return_before_their_end() {
+        let main = r#"costumes "blank.svg";
+            list items = [4, 8, 15];
+            inline func sign(x) {
+                if $x < 0 { return -1; }
+                if $x == 0 { return 0; }
+                return 1;
+            }
+            inline func find(value) {
+                i = 1;
+                repeat length items {
+                    if items[i] == $value { return i; }
+                    i += 1;
+                }
+                return 0;
+            }
+            inline func root(n) {
+                r = 0;
+                forever {
+                    r += 1;
+                    if r * r > $n { return r - 1; }
+                }
+            }
+            onflag {
+                say sign(-5) & sign(0) & sign(7);
+                say find(8) & find(16) & root(10);
+            }
+            "#;
+        let (outcome, output) = run(main, None);
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: -101", "main: 203"]);
+        let (artifact, _) = build_sb3(main, false);
+        let sprite = &artifact.project.sprites["main"];
+        for name in ["sign", "find", "root"] {
+            assert!(sprite.inlined_funcs.contains(name), "{name}");
+        }
+        assert!(!artifact.sprites_diagnostics["main"]
+            .diagnostics
+            .iter()
+            .any(|diagnostic| matches!(diagnostic.kind, DiagnosticKind::CannotInline { .. })));
+    }
+}
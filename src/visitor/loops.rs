@@ -0,0 +1,358 @@
+use logos::Span;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    ast::*,
+    blocks::{
+        BinOp,
+        UnOp,
+    },
+    diagnostic::{
+        DiagnosticKind,
+        SpriteDiagnostics,
+    },
+    misc::SmolStr,
+};
+
+/// Values of the variable that a loop with `break` or `continue` uses to remember how the
+/// current iteration ended. It is set to 0 at the start of every iteration.
+const CONTINUE: f64 = 1.0;
+const BREAK: f64 = 2.0;
+
+struct V<'a> {
+    vars: &'a mut FxHashMap<SmolStr, Var>,
+    loops: &'a mut usize,
+    d: &'a mut SpriteDiagnostics,
+}
+
+impl V<'_> {
+    /// Loops are numbered across the whole project, so that the variables of a sprite's
+    /// loop never have the same name as the variables of a stage's loop.
+    fn next_loop(&mut self) -> usize {
+        *self.loops += 1;
+        *self.loops
+    }
+
+    fn new_var(&mut self, name: String, span: &Span) -> Name {
+        let name: SmolStr = name.into();
+        self.vars.insert(
+            name.clone(),
+            Var {
+                name: name.clone(),
+                span: span.clone(),
+                type_: Type::Value,
+                default: None,
+                is_cloud: false,
+                is_used: true,
+            },
+        );
+        Name::Name {
+            name,
+            span: span.clone(),
+        }
+    }
+}
+
+/// What the loop repeats for, after `while` and `for` have been rewritten as `until`.
+enum Kind {
+    Repeat(Box<Expr>),
+    Forever,
+    Until(Box<Expr>),
+}
+
+pub fn visit_project(
+    project: &mut Project,
+    stage_diagnostics: &mut SpriteDiagnostics,
+    sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
+) {
+    let mut loops = 0;
+    visit_sprite(&mut project.stage, &mut loops, stage_diagnostics);
+    for (sprite_name, sprite) in &mut project.sprites {
+        visit_sprite(
+            sprite,
+            &mut loops,
+            sprites_diagnostics.get_mut(sprite_name).unwrap(),
+        );
+    }
+}
+
+fn visit_sprite(sprite: &mut Sprite, loops: &mut usize, d: &mut SpriteDiagnostics) {
+    let v = &mut V {
+        vars: &mut sprite.vars,
+        loops,
+        d,
+    };
+    for body in sprite.proc_definitions.values_mut() {
+        visit_stmts(body, false, v);
+    }
+    for body in sprite.func_definitions.values_mut() {
+        visit_stmts(body, false, v);
+    }
+    for event in &mut sprite.events {
+        visit_stmts(&mut event.body, false, v);
+    }
+}
+
+/// Lower every loop in `stmts`. `break` and `continue` statements that are not inside a
+/// loop are reported, and ones that are inside a loop are left for the loop to lower.
+fn visit_stmts(stmts: &mut Vec<Stmt>, in_loop: bool, v: &mut V) {
+    let mut i = 0;
+    while i < stmts.len() {
+        match &mut stmts[i] {
+            Stmt::Branch {
+                if_body, else_body, ..
+            } => {
+                visit_stmts(if_body, in_loop, v);
+                visit_stmts(else_body, in_loop, v);
+            }
+            Stmt::Break { span } if !in_loop => {
+                v.d.report(DiagnosticKind::BreakOutsideLoop, span);
+                stmts.remove(i);
+                continue;
+            }
+            Stmt::Continue { span } if !in_loop => {
+                v.d.report(DiagnosticKind::ContinueOutsideLoop, span);
+                stmts.remove(i);
+                continue;
+            }
+            Stmt::Repeat { .. }
+            | Stmt::Forever { .. }
+            | Stmt::Until { .. }
+            | Stmt::While { .. }
+            | Stmt::For { .. } => {
+                let lowered = visit_loop(stmts.remove(i), v);
+                let len = lowered.len();
+                stmts.splice(i..i, lowered);
+                i += len;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+fn visit_loop(stmt: Stmt, v: &mut V) -> Vec<Stmt> {
+    match stmt {
+        Stmt::Repeat { times, body } => {
+            let span = times.span();
+            lower_loop(Kind::Repeat(times), body, None, span, v)
+        }
+        Stmt::Forever { body, span } => lower_loop(Kind::Forever, body, None, span, v),
+        Stmt::Until { cond, body } => {
+            let span = cond.span();
+            lower_loop(Kind::Until(cond), body, None, span, v)
+        }
+        Stmt::While { cond, body } => {
+            let span = cond.span();
+            let cond = UnOp::Not.to_expr(span.clone(), *cond);
+            lower_loop(Kind::Until(Box::new(cond)), body, None, span, v)
+        }
+        Stmt::For {
+            name,
+            start,
+            end,
+            body,
+        } => {
+            let span = name.span();
+            let cond = BinOp::Gt.to_expr(span.clone(), Expr::Name(name.clone()), *end);
+            let mut stmts = vec![set_var(&name, *start)];
+            stmts.extend(lower_loop(
+                Kind::Until(Box::new(cond)),
+                body,
+                Some(Stmt::increment(name)),
+                span,
+                v,
+            ));
+            stmts
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Lower a loop, `step` is run at the end of every iteration, even if it ended with
+/// `continue`.
+fn lower_loop(
+    kind: Kind,
+    mut body: Vec<Stmt>,
+    step: Option<Stmt>,
+    span: Span,
+    v: &mut V,
+) -> Vec<Stmt> {
+    visit_stmts(&mut body, true, v);
+    let has_break = contains(&body, &|stmt| matches!(stmt, Stmt::Break { .. }));
+    let has_continue = contains(&body, &|stmt| matches!(stmt, Stmt::Continue { .. }));
+    if !has_break && !has_continue {
+        body.extend(step);
+        return vec![kind.into_stmt(body, span)];
+    }
+    let id = v.next_loop();
+    let flag = v.new_var(format!("loop:{id}"), &span);
+    let mut lowered_body = vec![set_var(&flag, number(0.0, &span))];
+    lowered_body.extend(lower_jumps(body, &flag, v));
+    if let Some(step) = step {
+        if has_break {
+            lowered_body.push(Stmt::Branch {
+                cond: Box::new(UnOp::Not.to_expr(span.clone(), is(&flag, BREAK, &span))),
+                if_body: vec![step],
+                else_body: vec![],
+            });
+        } else {
+            lowered_body.push(step);
+        }
+    }
+    if !has_break {
+        return vec![kind.into_stmt(lowered_body, span)];
+    }
+    let is_break = is(&flag, BREAK, &span);
+    let mut stmts = vec![set_var(&flag, number(0.0, &span))];
+    let cond = match kind {
+        Kind::Repeat(times) => {
+            // Scratch's repeat block rounds the number of times.
+            let remaining = v.new_var(format!("loop:{id}:remaining"), &span);
+            stmts.push(set_var(
+                &remaining,
+                UnOp::Round.to_expr(span.clone(), *times),
+            ));
+            lowered_body.push(Stmt::decrement(remaining.clone()));
+            BinOp::Or.to_expr(
+                span.clone(),
+                BinOp::Lt.to_expr(span.clone(), Expr::Name(remaining), number(1.0, &span)),
+                is_break,
+            )
+        }
+        Kind::Forever => is_break,
+        Kind::Until(cond) => BinOp::Or.to_expr(span.clone(), *cond, is_break),
+    };
+    stmts.push(Stmt::Until {
+        cond: Box::new(cond),
+        body: lowered_body,
+    });
+    stmts
+}
+
+impl Kind {
+    fn into_stmt(self, body: Vec<Stmt>, span: Span) -> Stmt {
+        match self {
+            Kind::Repeat(times) => Stmt::Repeat { times, body },
+            Kind::Forever => Stmt::Forever { body, span },
+            Kind::Until(cond) => Stmt::Until { cond, body },
+        }
+    }
+}
+
+/// Replace the `break` and `continue` statements of a loop body with assignments to
+/// `flag`, and only run the statements that follow them if `flag` is still 0.
+fn lower_jumps(stmts: Vec<Stmt>, flag: &Name, v: &mut V) -> Vec<Stmt> {
+    let mut lowered = vec![];
+    let mut stmts = stmts.into_iter().peekable();
+    while let Some(stmt) = stmts.next() {
+        match stmt {
+            stmt @ (Stmt::Break { .. } | Stmt::Continue { .. }) => {
+                let span = stmt.span();
+                if stmts.peek().is_some() {
+                    v.d.report(DiagnosticKind::FollowedByUnreachableCode, &span);
+                }
+                let value = if matches!(stmt, Stmt::Break { .. }) {
+                    BREAK
+                } else {
+                    CONTINUE
+                };
+                lowered.push(set_var(flag, number(value, &span)));
+                return lowered;
+            }
+            Stmt::Branch {
+                cond,
+                if_body,
+                else_body,
+            } if contains(&if_body, &is_jump) || contains(&else_body, &is_jump) => {
+                let span = cond.span();
+                lowered.push(Stmt::Branch {
+                    cond,
+                    if_body: lower_jumps(if_body, flag, v),
+                    else_body: lower_jumps(else_body, flag, v),
+                });
+                let rest = lower_jumps(stmts.collect(), flag, v);
+                if !rest.is_empty() {
+                    lowered.push(Stmt::Branch {
+                        cond: Box::new(is(flag, 0.0, &span)),
+                        if_body: rest,
+                        else_body: vec![],
+                    });
+                }
+                return lowered;
+            }
+            stmt => lowered.push(stmt),
+        }
+    }
+    lowered
+}
+
+fn is_jump(stmt: &Stmt) -> bool {
+    matches!(stmt, Stmt::Break { .. } | Stmt::Continue { .. })
+}
+
+/// Whether any statement in `stmts` matches `predicate`, looking inside branches but not
+/// inside loops, as those have already been lowered.
+fn contains(stmts: &[Stmt], predicate: &dyn Fn(&Stmt) -> bool) -> bool {
+    stmts.iter().any(|stmt| {
+        predicate(stmt)
+            || matches!(stmt, Stmt::Branch { if_body, else_body, .. }
+                if contains(if_body, predicate) || contains(else_body, predicate))
+    })
+}
+
+fn number(value: f64, span: &Span) -> Expr {
+    Value::from(value).to_expr(span.clone())
+}
+
+fn is(flag: &Name, value: f64, span: &Span) -> Expr {
+    BinOp::Eq.to_expr(span.clone(), Expr::Name(flag.clone()), number(value, span))
+}
+
+fn set_var(name: &Name, value: Expr) -> Stmt {
+    Stmt::SetVar {
+        name: name.clone(),
+        value: Box::new(value),
+        type_: Type::Value,
+        is_local: false,
+        is_cloud: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::build::ParseCache,
+        testing::*,
+    };
+
+    #[test]
+    fn loops_with_break_and_continue_are_lowered() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             onflag {\n\
+                 for i = 1 to 10 { if i == 2 { continue; } if i == 5 { break; } say i; }\n\
+                 while 1 < 2 { break; }\n\
+                 repeat 3 { continue; }\n\
+             }\n",
+        );
+        let artifact = build(&fs, &mut ParseCache::default());
+        let sprite = &artifact.project.sprites["main"];
+        assert!(sprite.vars.contains_key("loop:1"));
+        assert!(sprite.vars.contains_key("loop:2"));
+        assert!(sprite.vars.contains_key("loop:3"));
+    }
+
+    #[test]
+    fn break_outside_of_a_loop_is_an_error() {
+        let fs = project("costumes \"blank.svg\";\nonflag { break; }\n");
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(artifact.failure());
+        assert!(matches!(
+            artifact.sprites_diagnostics["main"].diagnostics[0].kind,
+            DiagnosticKind::BreakOutsideLoop
+        ));
+    }
+}
@@ -0,0 +1,308 @@
+use logos::Span;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    ast::*,
+    blocks::BinOp,
+    diagnostic::{
+        DiagnosticKind,
+        Note,
+        SpriteDiagnostics,
+    },
+    misc::SmolStr,
+};
+
+struct V<'a> {
+    vars: &'a mut FxHashMap<SmolStr, Var>,
+    enums: &'a FxHashMap<SmolStr, Enum>,
+    global_enums: Option<&'a FxHashMap<SmolStr, Enum>>,
+    matches: &'a mut usize,
+    d: &'a mut SpriteDiagnostics,
+}
+
+impl V<'_> {
+    fn get_enum(&self, name: &str) -> Option<&Enum> {
+        self.enums.get(name).or_else(|| {
+            self.global_enums
+                .and_then(|global_enums| global_enums.get(name))
+        })
+    }
+
+    /// The enum and variant that `pattern` refers to, if it is an `Enum.Variant`
+    /// expression. Unrecognized variants are reported later, when the pattern is resolved.
+    fn enum_variant(&self, pattern: &Expr) -> Option<(&Enum, SmolStr, Span)> {
+        let Expr::Dot { lhs, rhs, rhs_span } = pattern else {
+            return None;
+        };
+        let Expr::Name(Name::Name { name, span }) = &**lhs else {
+            return None;
+        };
+        let enum_ = self.get_enum(name)?;
+        if !enum_.variants.iter().any(|variant| &variant.name == rhs) {
+            return None;
+        }
+        Some((enum_, rhs.clone(), span.start..rhs_span.end))
+    }
+}
+
+pub fn visit_project(
+    project: &mut Project,
+    stage_diagnostics: &mut SpriteDiagnostics,
+    sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
+) {
+    let mut matches = 0;
+    let stage = &mut project.stage;
+    visit_sprite(
+        &mut V {
+            vars: &mut stage.vars,
+            enums: &stage.enums,
+            global_enums: None,
+            matches: &mut matches,
+            d: stage_diagnostics,
+        },
+        &mut stage.proc_definitions,
+        &mut stage.func_definitions,
+        &mut stage.events,
+    );
+    for (sprite_name, sprite) in &mut project.sprites {
+        visit_sprite(
+            &mut V {
+                vars: &mut sprite.vars,
+                enums: &sprite.enums,
+                global_enums: Some(&project.stage.enums),
+                matches: &mut matches,
+                d: sprites_diagnostics.get_mut(sprite_name).unwrap(),
+            },
+            &mut sprite.proc_definitions,
+            &mut sprite.func_definitions,
+            &mut sprite.events,
+        );
+    }
+}
+
+fn visit_sprite(
+    v: &mut V,
+    proc_definitions: &mut FxHashMap<SmolStr, Vec<Stmt>>,
+    func_definitions: &mut FxHashMap<SmolStr, Vec<Stmt>>,
+    events: &mut [Event],
+) {
+    for body in proc_definitions.values_mut() {
+        visit_stmts(body, v);
+    }
+    for body in func_definitions.values_mut() {
+        visit_stmts(body, v);
+    }
+    for event in events {
+        visit_stmts(&mut event.body, v);
+    }
+}
+
+fn visit_stmts(stmts: &mut Vec<Stmt>, v: &mut V) {
+    let mut i = 0;
+    while i < stmts.len() {
+        match &mut stmts[i] {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::While { body, .. }
+            | Stmt::For { body, .. } => visit_stmts(body, v),
+            Stmt::Branch {
+                if_body, else_body, ..
+            } => {
+                visit_stmts(if_body, v);
+                visit_stmts(else_body, v);
+            }
+            Stmt::Match { .. } => {
+                let Stmt::Match { value, arms, span } = stmts.remove(i) else {
+                    unreachable!()
+                };
+                let lowered = lower_match(*value, arms, span, v);
+                let len = lowered.len();
+                stmts.splice(i..i, lowered);
+                i += len;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Lower a match statement to a chain of if-else statements. The value is stored in a
+/// variable first, unless it can be evaluated again without side effects.
+fn lower_match(value: Expr, mut arms: Vec<MatchArm>, span: Span, v: &mut V) -> Vec<Stmt> {
+    check_arms(&arms, &span, v);
+    let mut stmts = vec![];
+    let value = match value {
+        value @ (Expr::Value { .. } | Expr::Name(_) | Expr::Arg(_)) => value,
+        value => {
+            *v.matches += 1;
+            let name: SmolStr = format!("match:{}", *v.matches).into();
+            v.vars.insert(
+                name.clone(),
+                Var {
+                    name: name.clone(),
+                    span: span.clone(),
+                    type_: Type::Value,
+                    default: None,
+                    is_cloud: false,
+                    is_used: true,
+                },
+            );
+            let name = Name::Name {
+                name,
+                span: span.clone(),
+            };
+            stmts.push(Stmt::SetVar {
+                name: name.clone(),
+                value: Box::new(value),
+                type_: Type::Value,
+                is_local: false,
+                is_cloud: false,
+            });
+            Expr::Name(name)
+        }
+    };
+    let mut else_body = vec![];
+    if let Some(wildcard) = arms.iter().position(MatchArm::is_wildcard) {
+        else_body = arms.drain(wildcard..).next().unwrap().body;
+        visit_stmts(&mut else_body, v);
+    }
+    for arm in arms.into_iter().rev() {
+        let mut if_body = arm.body;
+        visit_stmts(&mut if_body, v);
+        let cond = arm
+            .patterns
+            .into_iter()
+            .map(|pattern| BinOp::Eq.to_expr(pattern.span(), value.clone(), pattern))
+            .reduce(|lhs, rhs| BinOp::Or.to_expr(lhs.span(), lhs, rhs))
+            .unwrap();
+        else_body = vec![Stmt::Branch {
+            cond: Box::new(cond),
+            if_body,
+            else_body,
+        }];
+    }
+    stmts.extend(else_body);
+    stmts
+}
+
+/// Report enum variants that are handled by more than one arm, and enum variants that
+/// are not handled at all when there is no `_` arm.
+fn check_arms(arms: &[MatchArm], span: &Span, v: &mut V) {
+    let mut enums: Vec<&Enum> = vec![];
+    // The span of the first pattern of each handled variant, by enum and variant name.
+    let mut handled: FxHashMap<(SmolStr, SmolStr), Span> = FxHashMap::default();
+    let mut diagnostics = vec![];
+    let mut has_wildcard = false;
+    for (i, arm) in arms.iter().enumerate() {
+        if arm.is_wildcard() {
+            if i + 1 < arms.len() {
+                diagnostics.push((
+                    DiagnosticKind::FollowedByUnreachableCode,
+                    arm.span.clone(),
+                    vec![],
+                ));
+            }
+            has_wildcard = true;
+            break;
+        }
+        for pattern in &arm.patterns {
+            let Some((enum_, variant_name, pattern_span)) = v.enum_variant(pattern) else {
+                continue;
+            };
+            if !enums.iter().any(|other| other.name == enum_.name) {
+                enums.push(enum_);
+            }
+            if let Some(first) = handled.get(&(enum_.name.clone(), variant_name.clone())) {
+                let notes = vec![Note::new("first handled here", first)];
+                diagnostics.push((
+                    DiagnosticKind::DuplicateMatchArm {
+                        enum_name: enum_.name.clone(),
+                        variant_name,
+                    },
+                    pattern_span,
+                    notes,
+                ));
+            } else {
+                handled.insert((enum_.name.clone(), variant_name), pattern_span);
+            }
+        }
+    }
+    if !has_wildcard {
+        for enum_ in enums {
+            let variant_names: Vec<SmolStr> = enum_
+                .variants
+                .iter()
+                .filter(|variant| {
+                    !handled.contains_key(&(enum_.name.clone(), variant.name.clone()))
+                })
+                .map(|variant| variant.name.clone())
+                .collect();
+            if !variant_names.is_empty() {
+                diagnostics.push((
+                    DiagnosticKind::MissingMatchArms {
+                        enum_name: enum_.name.clone(),
+                        variant_names,
+                    },
+                    span.clone(),
+                    vec![],
+                ));
+            }
+        }
+    }
+    for (kind, span, notes) in diagnostics {
+        v.d.report_with_notes(kind, &span, notes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::build::ParseCache,
+        testing::*,
+    };
+
+    #[test]
+    fn match_reports_missing_and_duplicate_enum_arms() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             enum State { Idle, Walking, Jumping }\n\
+             onflag {\n\
+                 state = State.Idle;\n\
+                 match state { State.Idle => {} State.Idle, State.Walking => {} }\n\
+             }\n",
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        let kinds: Vec<_> = artifact.sprites_diagnostics["main"]
+            .diagnostics
+            .iter()
+            .map(|diagnostic| &diagnostic.kind)
+            .collect();
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                DiagnosticKind::DuplicateMatchArm { variant_name, .. },
+                DiagnosticKind::MissingMatchArms { variant_names, .. },
+            ] if variant_name == "Idle" && variant_names == &["Jumping"]
+        ));
+    }
+
+    #[test]
+    fn match_with_wildcard_arm_is_exhaustive() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             enum State { Idle, Walking, Jumping }\n\
+             onflag {\n\
+                 state = State.Idle;\n\
+                 match state { State.Idle => { say 1; } _ => { say 2; } }\n\
+                 match state + 1 { 1 => { say 1; } }\n\
+             }\n",
+        );
+        let artifact = build(&fs, &mut ParseCache::default());
+        assert!(artifact.project.sprites["main"]
+            .vars
+            .contains_key("match:1"));
+    }
+}
@@ -0,0 +1,730 @@
+//! Files imported with `import "path" as ns` are modules. The parser declares the names of
+//! a module as `ns.name`, so they never collide with the names of the sprite or of other
+//! modules. This pass qualifies the names used inside of a module the same way, and turns
+//! `ns.name` in the code which imports the module into the qualified name.
+//!
+//! Names which begin with `_` are private to their module.
+
+use logos::Span;
+use rustc_hash::FxHashSet;
+
+use crate::{
+    ast::*,
+    diagnostic::{
+        Diagnostic,
+        DiagnosticKind,
+    },
+    misc::SmolStr,
+    translation_unit::TranslationUnit,
+};
+
+#[derive(Default)]
+struct Symbols {
+    procs: FxHashSet<SmolStr>,
+    funcs: FxHashSet<SmolStr>,
+    /// Variables, lists and enums, which are all used by their name in expressions.
+    values: FxHashSet<SmolStr>,
+    structs: FxHashSet<SmolStr>,
+    namespaces: FxHashSet<SmolStr>,
+}
+
+struct V<'a> {
+    symbols: &'a Symbols,
+    /// The module of the code being visited, or `None` for the sprite itself.
+    namespace: Option<SmolStr>,
+    /// Local variables of the procedure or function being visited, which are never
+    /// qualified.
+    locals: FxHashSet<SmolStr>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+pub fn visit_sprite(sprite: &mut Sprite, unit: &TranslationUnit) -> Vec<Diagnostic> {
+    let namespaces = unit.namespaces();
+    let mut diagnostics = vec![];
+    if namespaces.is_empty() {
+        return diagnostics;
+    }
+    let mut symbols = Symbols {
+        procs: sprite.procs.keys().cloned().collect(),
+        funcs: sprite.funcs.keys().cloned().collect(),
+        values: sprite
+            .vars
+            .keys()
+            .chain(sprite.lists.keys())
+            .chain(sprite.enums.keys())
+            .cloned()
+            .collect(),
+        structs: sprite.structs.keys().cloned().collect(),
+        namespaces,
+    };
+    // Variables which a module assigns to without declaring them belong to the module.
+    for (namespace, body) in bodies(sprite, unit) {
+        let Some(namespace) = namespace else {
+            continue;
+        };
+        let mut locals = FxHashSet::default();
+        collect_locals(body, &mut locals);
+        collect_assigned(body, &locals, &namespace, &mut symbols.values);
+    }
+    check_clashes(sprite, unit, &symbols.namespaces, &mut diagnostics);
+    let v = &mut V {
+        symbols: &symbols,
+        namespace: None,
+        locals: Default::default(),
+        diagnostics: &mut diagnostics,
+    };
+    for proc in sprite.procs.values() {
+        v.namespace = namespace_of(&proc.name);
+        for arg in sprite.proc_args.get_mut(&proc.name).unwrap() {
+            v.visit_arg(arg);
+        }
+        let body = sprite.proc_definitions.get_mut(&proc.name).unwrap();
+        v.locals.clear();
+        collect_locals(body, &mut v.locals);
+        v.visit_stmts(body);
+    }
+    for func in sprite.funcs.values_mut() {
+        v.namespace = namespace_of(&func.name);
+        v.visit_type(&mut func.type_);
+        for arg in sprite.func_args.get_mut(&func.name).unwrap() {
+            v.visit_arg(arg);
+        }
+        let body = sprite.func_definitions.get_mut(&func.name).unwrap();
+        v.locals.clear();
+        collect_locals(body, &mut v.locals);
+        v.visit_stmts(body);
+    }
+    v.locals.clear();
+    for event in &mut sprite.events {
+        v.namespace = unit.namespace_at(event.span.start).cloned();
+        match &mut event.kind {
+            EventKind::OnLoudnessGt { value }
+            | EventKind::OnTimerGt { value }
+            | EventKind::OnVideoMotionGt { value } => v.visit_expr(value),
+            _ => {}
+        }
+        v.visit_stmts(&mut event.body);
+    }
+    for var in sprite.vars.values_mut() {
+        v.namespace = namespace_of(&var.name);
+        v.visit_type(&mut var.type_);
+        if let Some(default) = &mut var.default {
+            v.visit_const_expr(default);
+        }
+    }
+    for list in sprite.lists.values_mut() {
+        v.namespace = namespace_of(&list.name);
+        v.visit_type(&mut list.type_);
+        match &mut list.default {
+            Some(ListDefault::Values(values)) => {
+                for value in values {
+                    v.visit_const_expr(value);
+                }
+            }
+            Some(ListDefault::FixedLength(default, length)) => {
+                v.visit_const_expr(default);
+                v.visit_const_expr(length);
+            }
+            Some(ListDefault::File { .. }) | None => {}
+        }
+    }
+    for struct_ in sprite.structs.values_mut() {
+        v.namespace = namespace_of(&struct_.name);
+        for field in &mut struct_.fields {
+            v.visit_type(&mut field.type_);
+            if let Some(default) = &mut field.default {
+                v.visit_const_expr(default);
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Returns the module that a qualified name such as `ns.name` was declared in.
+fn namespace_of(name: &str) -> Option<SmolStr> {
+    name.rsplit_once('.').map(|(namespace, _)| namespace.into())
+}
+
+fn qualify(namespace: Option<&SmolStr>, name: &str) -> SmolStr {
+    match namespace {
+        Some(namespace) => format!("{namespace}.{name}").into(),
+        None => name.into(),
+    }
+}
+
+/// Returns the body of every procedure, function and event, with the module it is in.
+fn bodies<'a>(sprite: &'a Sprite, unit: &TranslationUnit) -> Vec<(Option<SmolStr>, &'a Vec<Stmt>)> {
+    let procs = sprite
+        .proc_definitions
+        .iter()
+        .map(|(name, body)| (namespace_of(name), body));
+    let funcs = sprite
+        .func_definitions
+        .iter()
+        .map(|(name, body)| (namespace_of(name), body));
+    let events = sprite
+        .events
+        .iter()
+        .map(|event| (unit.namespace_at(event.span.start).cloned(), &event.body));
+    procs.chain(funcs).chain(events).collect()
+}
+
+fn for_each_body<'a>(stmt: &'a Stmt, mut f: impl FnMut(&'a Vec<Stmt>)) {
+    match stmt {
+        Stmt::Repeat { body, .. }
+        | Stmt::Forever { body, .. }
+        | Stmt::Until { body, .. }
+        | Stmt::While { body, .. }
+        | Stmt::For { body, .. } => f(body),
+        Stmt::Branch {
+            if_body, else_body, ..
+        } => {
+            f(if_body);
+            f(else_body);
+        }
+        Stmt::Match { arms, .. } => {
+            for arm in arms {
+                f(&arm.body);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_locals(stmts: &[Stmt], locals: &mut FxHashSet<SmolStr>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::SetVar {
+                name,
+                is_local: true,
+                ..
+            } => {
+                locals.insert(name.basename().clone());
+            }
+            Stmt::Destructure {
+                names,
+                is_local: true,
+                ..
+            } => {
+                locals.extend(names.iter().map(|name| name.basename().clone()));
+            }
+            _ => for_each_body(stmt, |body| collect_locals(body, locals)),
+        }
+    }
+}
+
+fn collect_assigned(
+    stmts: &[Stmt],
+    locals: &FxHashSet<SmolStr>,
+    namespace: &SmolStr,
+    values: &mut FxHashSet<SmolStr>,
+) {
+    for stmt in stmts {
+        let names = match stmt {
+            Stmt::SetVar {
+                name,
+                is_local: false,
+                ..
+            }
+            | Stmt::For { name, .. } => std::slice::from_ref(name),
+            Stmt::Destructure {
+                names,
+                is_local: false,
+                ..
+            } => names.as_slice(),
+            _ => &[],
+        };
+        for name in names {
+            if let Name::Name { name, .. } = name {
+                if !locals.contains(name) {
+                    values.insert(qualify(Some(namespace), name));
+                }
+            }
+        }
+        for_each_body(stmt, |body| {
+            collect_assigned(body, locals, namespace, values)
+        });
+    }
+}
+
+/// The fields of struct variables are named `name.field`, the same as the symbols of a
+/// module, so a variable cannot have the name of a module that can be used where it is.
+fn check_clashes(
+    sprite: &Sprite,
+    unit: &TranslationUnit,
+    namespaces: &FxHashSet<SmolStr>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut clashes = FxHashSet::default();
+    let mut check = |namespace: Option<&SmolStr>, name: &str, span: Span| {
+        let qualified = qualify(namespace, name);
+        if namespaces.contains(&qualified) && clashes.insert(qualified.clone()) {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::NamespaceClash(qualified),
+                span,
+                notes: vec![],
+            });
+        }
+    };
+    let declared = sprite
+        .vars
+        .values()
+        .map(|var| (&var.name, &var.span))
+        .chain(sprite.lists.values().map(|list| (&list.name, &list.span)))
+        .chain(
+            sprite
+                .enums
+                .values()
+                .map(|enum_| (&enum_.name, &enum_.span)),
+        );
+    for (name, span) in declared {
+        check(None, name, span.clone());
+    }
+    for (namespace, body) in bodies(sprite, unit) {
+        let mut names = vec![];
+        collect_targets(body, &mut names);
+        for name in names {
+            if let Name::Name { name, span } = name {
+                check(namespace.as_ref(), name, span.clone());
+            }
+        }
+    }
+}
+
+/// Collects the names assigned to by `stmts`, both local and not.
+fn collect_targets<'a>(stmts: &'a [Stmt], names: &mut Vec<&'a Name>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::SetVar { name, .. } | Stmt::For { name, .. } => names.push(name),
+            Stmt::Destructure { names: targets, .. } => names.extend(targets),
+            _ => {}
+        }
+        for_each_body(stmt, |body| collect_targets(body, names));
+    }
+}
+
+impl V<'_> {
+    /// Returns the qualified name of `name`, which is either the name of a module followed
+    /// by the name of one of its symbols, or a symbol of the module being visited.
+    fn resolve(
+        &mut self,
+        name: &str,
+        span: &Span,
+        declared: &FxHashSet<SmolStr>,
+    ) -> Option<SmolStr> {
+        if let Some((namespace, name)) = name.split_once('.') {
+            return self.resolve_in(namespace, name, span);
+        }
+        let qualified = qualify(self.namespace.as_ref(), name);
+        (self.namespace.is_some() && declared.contains(&qualified)).then_some(qualified)
+    }
+
+    /// Returns the qualified name of `name` in the module imported as `namespace`, if there
+    /// is one.
+    fn resolve_in(&mut self, namespace: &str, name: &str, span: &Span) -> Option<SmolStr> {
+        let namespace = qualify(self.namespace.as_ref(), namespace);
+        if !self.symbols.namespaces.contains(&namespace) {
+            return None;
+        }
+        if name.starts_with('_') {
+            self.diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::PrivateSymbol {
+                    namespace: namespace.clone(),
+                    name: name.into(),
+                },
+                span: span.clone(),
+                notes: vec![],
+            });
+        }
+        Some(qualify(Some(&namespace), name))
+    }
+
+    fn resolve_value(&mut self, name: &SmolStr, span: &Span) -> Option<SmolStr> {
+        if self.locals.contains(name) {
+            return None;
+        }
+        let symbols = self.symbols;
+        self.resolve(name, span, &symbols.values)
+    }
+
+    fn visit_name(&mut self, name: &mut Name) {
+        match name {
+            Name::Name { name, span } => {
+                if let Some(qualified) = self.resolve_value(name, span) {
+                    *name = qualified;
+                }
+            }
+            Name::DotName {
+                lhs,
+                lhs_span,
+                rhs,
+                rhs_span,
+                is_generated,
+            } => {
+                let (field, rest) = match rhs.split_once('.') {
+                    Some((field, rest)) => (field, Some(rest)),
+                    None => (rhs.as_str(), None),
+                };
+                let span = lhs_span.start..rhs_span.start + field.len();
+                if let Some(qualified) = self.resolve_in(lhs, field, &span) {
+                    *name = match rest {
+                        Some(rest) => Name::DotName {
+                            lhs: qualified,
+                            lhs_span: span.clone(),
+                            rhs: rest.into(),
+                            rhs_span: span.end + 1..rhs_span.end,
+                            is_generated: *is_generated,
+                        },
+                        None => Name::Name {
+                            name: qualified,
+                            span,
+                        },
+                    };
+                } else if let Some(qualified) = self.resolve_value(lhs, lhs_span) {
+                    *lhs = qualified;
+                }
+            }
+        }
+    }
+
+    fn visit_type(&mut self, type_: &mut Type) {
+        if let Type::Struct { name, span } = type_ {
+            let symbols = self.symbols;
+            if let Some(qualified) = self.resolve(name, span, &symbols.structs) {
+                *name = qualified;
+            }
+        }
+    }
+
+    fn visit_arg(&mut self, arg: &mut Arg) {
+        self.visit_type(&mut arg.type_);
+        if let Some(default) = &mut arg.default {
+            self.visit_const_expr(default);
+        }
+    }
+
+    fn visit_const_expr(&mut self, expr: &mut ConstExpr) {
+        let symbols = self.symbols;
+        match expr {
+            ConstExpr::Value { .. } => {}
+            ConstExpr::EnumVariant {
+                enum_name,
+                enum_name_span,
+                ..
+            } => {
+                if let Some(qualified) = self.resolve(enum_name, enum_name_span, &symbols.values) {
+                    *enum_name = qualified;
+                }
+            }
+            ConstExpr::StructLiteral { name, span, .. } => {
+                if let Some(qualified) = self.resolve(name, span, &symbols.structs) {
+                    *name = qualified;
+                }
+            }
+        }
+    }
+
+    fn visit_exprs<'e>(&mut self, exprs: impl IntoIterator<Item = &'e mut Expr>) {
+        for expr in exprs {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        let symbols = self.symbols;
+        // `ns.name` is a dot on the name of a module.
+        if let Expr::Dot { lhs, rhs, rhs_span } = expr {
+            if let Expr::Name(Name::Name { name, span }) = lhs.as_ref() {
+                let span = span.start..rhs_span.end;
+                if let Some(qualified) = self.resolve_in(name, rhs, &span) {
+                    *expr = Expr::Name(Name::Name {
+                        name: qualified,
+                        span,
+                    });
+                    return;
+                }
+            }
+        }
+        match expr {
+            Expr::Value { .. } | Expr::Arg(_) => {}
+            Expr::Name(name) => self.visit_name(name),
+            Expr::Dot { lhs, .. } => self.visit_expr(lhs),
+            Expr::Repr { args, .. } => self.visit_exprs(args),
+            Expr::FuncCall {
+                name,
+                span,
+                args,
+                kwargs,
+            } => {
+                if let Some(qualified) = self.resolve(name, span, &symbols.funcs) {
+                    *name = qualified;
+                }
+                self.visit_exprs(args);
+                self.visit_exprs(kwargs.values_mut().map(|(_, value)| value));
+            }
+            Expr::UnOp { opr, .. } => self.visit_expr(opr),
+            Expr::BinOp { lhs, rhs, .. } => {
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+            }
+            Expr::StructLiteral { name, span, fields } => {
+                if let Some(qualified) = self.resolve(name, span, &symbols.structs) {
+                    *name = qualified;
+                }
+                self.visit_exprs(fields.iter_mut().map(|field| field.value.as_mut()));
+            }
+            Expr::Property { object, .. } => self.visit_expr(object),
+            Expr::Ternary {
+                condition,
+                tvalue,
+                fvalue,
+            } => {
+                self.visit_expr(condition);
+                self.visit_expr(tvalue);
+                self.visit_expr(fvalue);
+            }
+        }
+    }
+
+    fn visit_stmts(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        let symbols = self.symbols;
+        match stmt {
+            Stmt::Repeat { times, body } => {
+                self.visit_expr(times);
+                self.visit_stmts(body);
+            }
+            Stmt::Forever { body, .. } => self.visit_stmts(body),
+            Stmt::Branch {
+                cond,
+                if_body,
+                else_body,
+            } => {
+                self.visit_expr(cond);
+                self.visit_stmts(if_body);
+                self.visit_stmts(else_body);
+            }
+            Stmt::Until { cond, body } | Stmt::While { cond, body } => {
+                self.visit_expr(cond);
+                self.visit_stmts(body);
+            }
+            Stmt::For {
+                name,
+                start,
+                end,
+                body,
+            } => {
+                self.visit_name(name);
+                self.visit_expr(start);
+                self.visit_expr(end);
+                self.visit_stmts(body);
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Match { value, arms, .. } => {
+                self.visit_expr(value);
+                for arm in arms {
+                    self.visit_exprs(&mut arm.patterns);
+                    self.visit_stmts(&mut arm.body);
+                }
+            }
+            Stmt::SetVar {
+                name, value, type_, ..
+            } => {
+                self.visit_name(name);
+                self.visit_expr(value);
+                self.visit_type(type_);
+            }
+            Stmt::Destructure { names, value, .. } => {
+                for name in names {
+                    self.visit_name(name);
+                }
+                self.visit_expr(value);
+            }
+            Stmt::ChangeVar { name, value } | Stmt::AddToList { name, value } => {
+                self.visit_name(name);
+                self.visit_expr(value);
+            }
+            Stmt::Show(name) | Stmt::Hide(name) | Stmt::DeleteList(name) => self.visit_name(name),
+            Stmt::DeleteListIndex { name, index } => {
+                self.visit_name(name);
+                self.visit_expr(index);
+            }
+            Stmt::InsertAtList { name, index, value }
+            | Stmt::SetListIndex { name, index, value } => {
+                self.visit_name(name);
+                self.visit_expr(index);
+                self.visit_expr(value);
+            }
+            Stmt::Block { args, kwargs, .. } => {
+                self.visit_exprs(args);
+                self.visit_exprs(kwargs.values_mut().map(|(_, value)| value));
+            }
+            Stmt::ProcCall {
+                name,
+                span,
+                args,
+                kwargs,
+            } => {
+                if let Some(qualified) = self.resolve(name, span, &symbols.procs) {
+                    *name = qualified;
+                }
+                self.visit_exprs(args);
+                self.visit_exprs(kwargs.values_mut().map(|(_, value)| value));
+            }
+            Stmt::FuncCall {
+                name,
+                span,
+                args,
+                kwargs,
+            } => {
+                if let Some(qualified) = self.resolve(name, span, &symbols.funcs) {
+                    *name = qualified;
+                }
+                self.visit_exprs(args);
+                self.visit_exprs(kwargs.values_mut().map(|(_, value)| value));
+            }
+            Stmt::Return { value, .. } => self.visit_expr(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        path::Path,
+    };
+
+    use semver::Version;
+
+    use crate::{
+        codegen::build::{
+            build_with_cache,
+            ParseCache,
+        },
+        diagnostic::DiagnosticKind,
+        standard_library::new_standard_library,
+        testing::*,
+        vm::{
+            Outcome,
+            VM,
+        },
+    };
+
+    #[test]
+    fn vm_runs_imported_modules() {
+        let fs = project(
+            r#"costumes "blank.svg";
+            import "lib/counter" as counter
+            var count = 100;
+            proc step {
+                count += 10;
+            }
+            onflag {
+                counter.step;
+                counter.step;
+                step;
+                counter.Pair p = counter.pair(counter.Side.Right);
+                say counter.count & count & p.a & p.b;
+            }
+            "#,
+        );
+        fs.borrow_mut().0.insert(
+            "lib/counter.gs".into(),
+            br#"struct Pair { a, b }
+            enum Side { Left, Right }
+            var count = 0;
+            func _twice(v) {
+                return $v * 2;
+            }
+            proc step {
+                local by = 1;
+                count += by;
+            }
+            func pair(side) Pair {
+                return Pair { a: $side, b: _twice($side) };
+            }
+            "#
+            .to_vec(),
+        );
+        let artifact = build(&fs, &mut ParseCache::default());
+        assert!(artifact.project.sprites["main"]
+            .procs
+            .contains_key("counter.step"));
+        let stdlib = new_standard_library(Version::new(0, 0, 0), Path::new("std"));
+        let mut sb3 = Cursor::new(vec![]);
+        build_with_cache(
+            fs,
+            "".into(),
+            &mut sb3,
+            Some(stdlib),
+            false,
+            true,
+            &[],
+            &mut Default::default(),
+        )
+        .unwrap();
+        sb3.set_position(0);
+        let mut vm = VM::new(sb3).unwrap();
+        vm.green_flag();
+        let mut output = Output(vec![]);
+        assert_eq!(vm.run(&mut output, None), Outcome::Finished);
+        assert_eq!(output.0, ["main: 211012"]);
+    }
+
+    #[test]
+    fn private_module_symbols_are_an_error() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             import \"lib\" as lib\n\
+             onflag { say lib._secret(); }\n",
+        );
+        fs.borrow_mut()
+            .0
+            .insert("lib.gs".into(), b"func _secret() { return 1; }\n".to_vec());
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(artifact.failure());
+        assert!(artifact.sprites_diagnostics["main"].diagnostics.iter().any(
+            |diagnostic| matches!(
+                &diagnostic.kind,
+                DiagnosticKind::PrivateSymbol { namespace, name }
+                    if namespace == "lib" && name == "_secret"
+            )
+        ));
+    }
+
+    #[test]
+    fn variables_named_like_a_module_are_an_error() {
+        for main in [
+            "onflag { S m = S { v: 50 }; }",
+            "var m = 0;",
+            "proc p { local S m = S { v: 50 }; say m.v; }",
+        ] {
+            let fs = project(&format!(
+                "costumes \"blank.svg\";\n\
+                 import \"lib\" as m\n\
+                 struct S {{ v }}\n\
+                 {main}\n\
+                 onflag {{ say m.v; }}\n"
+            ));
+            fs.borrow_mut()
+                .0
+                .insert("lib.gs".into(), b"var v = 1;\n".to_vec());
+            let artifact = try_build(&fs, &mut ParseCache::default());
+            assert!(artifact.failure(), "{main}");
+            let clashes = artifact.sprites_diagnostics["main"]
+                .diagnostics
+                .iter()
+                .filter(|diagnostic| {
+                    matches!(&diagnostic.kind, DiagnosticKind::NamespaceClash(name) if name == "m")
+                })
+                .count();
+            assert_eq!(clashes, 1, "{main}");
+        }
+    }
+}
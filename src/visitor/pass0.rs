@@ -11,7 +11,10 @@ use crate::{
         },
         sounds::SOUND_FORMATS,
     },
-    diagnostic::SpriteDiagnostics,
+    diagnostic::{
+        DiagnosticKind,
+        SpriteDiagnostics,
+    },
     misc::SmolStr,
     vfs::VFS,
     visitor::ternary::extract_ternary_from_stmts,
@@ -30,13 +33,24 @@ pub fn visit_project(
     stage_diagnostics: &mut SpriteDiagnostics,
     sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
 ) {
-    visit_sprite(fs, input, &mut project.stage, None, stage_diagnostics);
+    // Event scripts are numbered across the whole project, so that the local variables of
+    // a sprite's script never have the same name as the variables of a stage's script.
+    let mut events = 0;
+    visit_sprite(
+        fs,
+        input,
+        &mut project.stage,
+        None,
+        &mut events,
+        stage_diagnostics,
+    );
     for (sprite_name, sprite) in &mut project.sprites {
         visit_sprite(
             fs,
             input,
             sprite,
             Some(&mut project.stage),
+            &mut events,
             sprites_diagnostics.get_mut(sprite_name).unwrap(),
         );
     }
@@ -47,6 +61,7 @@ fn visit_sprite(
     input: &Path,
     sprite: &mut Sprite,
     mut stage: Option<&mut Sprite>,
+    events: &mut usize,
     d: &mut SpriteDiagnostics,
 ) {
     visit_assets(fs, input, &mut sprite.costumes, true, is_costume_ext, d);
@@ -54,6 +69,11 @@ fn visit_sprite(
     for enum_ in sprite.enums.values_mut() {
         visit_enum(enum_);
     }
+    visit_structs(
+        &mut sprite.structs,
+        stage.as_deref().map(|stage| &stage.structs),
+        d,
+    );
     for proc in sprite.procs.values_mut() {
         sprite
             .proc_locals
@@ -73,6 +93,16 @@ fn visit_sprite(
         sprite
             .func_locals
             .insert(func.name.clone(), Default::default());
+        if func.type_.is_value() {
+            if let Some(len) = tuple_return_len(&sprite.func_definitions[&func.name]) {
+                let tuple = Struct::tuple(len, func.span.clone());
+                func.type_ = Type::Struct {
+                    name: tuple.name.clone(),
+                    span: func.span.clone(),
+                };
+                sprite.structs.entry(tuple.name.clone()).or_insert(tuple);
+            }
+        }
         let name: SmolStr = format!("{}:return", func.name).into();
         if !sprite.vars.contains_key(&name) {
             sprite.vars.insert(
@@ -99,11 +129,13 @@ fn visit_sprite(
         );
     }
     for event in &mut sprite.events {
+        *events += 1;
+        event.scope = format!("{}#{}", event.kind.keyword(), events).into();
         extract_ternary_from_stmts(&mut event.body);
         visit_stmts(
             &mut event.body,
             &mut V {
-                locals: None,
+                locals: Some(&mut event.locals),
                 vars: &mut sprite.vars,
                 global_vars: stage.as_mut().map(|stage| &mut stage.vars),
             },
@@ -111,6 +143,92 @@ fn visit_sprite(
     }
 }
 
+/// Returns the number of values given to the first `return a, b;` in `stmts`.
+fn tuple_return_len(stmts: &[Stmt]) -> Option<usize> {
+    stmts.iter().find_map(|stmt| match stmt {
+        Stmt::Return { value, .. } => match value.as_ref() {
+            Expr::StructLiteral { name, fields, .. }
+                if *name == Struct::tuple_name(fields.len()) =>
+            {
+                Some(fields.len())
+            }
+            _ => None,
+        },
+        Stmt::Repeat { body, .. } | Stmt::Forever { body, .. } | Stmt::Until { body, .. } => {
+            tuple_return_len(body)
+        }
+        Stmt::Branch {
+            if_body, else_body, ..
+        } => tuple_return_len(if_body).or_else(|| tuple_return_len(else_body)),
+        _ => None,
+    })
+}
+
+/// Struct-typed fields which name an unknown struct, or which would make a struct contain
+/// itself, are reported and turned into plain value fields.
+fn visit_structs(
+    structs: &mut FxHashMap<SmolStr, Struct>,
+    global_structs: Option<&FxHashMap<SmolStr, Struct>>,
+    d: &mut SpriteDiagnostics,
+) {
+    let get_struct = |name: &str| {
+        structs
+            .get(name)
+            .or_else(|| global_structs.and_then(|global_structs| global_structs.get(name)))
+    };
+    let mut invalid_fields = vec![];
+    for struct_ in structs.values() {
+        for (i, field) in struct_.fields.iter().enumerate() {
+            let Some((type_name, type_span)) = field.type_.struct_() else {
+                continue;
+            };
+            if get_struct(type_name).is_none() {
+                d.report(
+                    DiagnosticKind::UnrecognizedStruct(type_name.clone()),
+                    type_span,
+                );
+                invalid_fields.push((struct_.name.clone(), i));
+            } else if contains_struct(type_name, &struct_.name, &get_struct, &mut vec![]) {
+                d.report(
+                    DiagnosticKind::RecursiveStruct(struct_.name.clone()),
+                    &field.span,
+                );
+                invalid_fields.push((struct_.name.clone(), i));
+            }
+        }
+    }
+    for (struct_name, i) in invalid_fields {
+        structs.get_mut(&struct_name).unwrap().fields[i].type_ = Type::Value;
+    }
+}
+
+fn contains_struct<'a, F>(
+    name: &'a SmolStr,
+    target: &SmolStr,
+    get_struct: &F,
+    visited: &mut Vec<&'a SmolStr>,
+) -> bool
+where
+    F: Fn(&str) -> Option<&'a Struct>,
+{
+    if name == target {
+        return true;
+    }
+    if visited.contains(&name) {
+        return false;
+    }
+    visited.push(name);
+    let Some(struct_) = get_struct(name) else {
+        return false;
+    };
+    struct_.fields.iter().any(|field| {
+        field
+            .type_
+            .struct_()
+            .is_some_and(|(type_name, _)| contains_struct(type_name, target, get_struct, visited))
+    })
+}
+
 fn visit_enum(enum_: &mut Enum) {
     let mut index = 0.0;
     for variant in &mut enum_.variants {
@@ -207,65 +325,168 @@ fn visit_stmt(stmt: &mut Stmt, v: &mut V) {
             is_local,
             is_cloud,
             ..
+        } => declare_var(name, type_, *is_local, *is_cloud, v),
+        Stmt::Destructure {
+            names, is_local, ..
         } => {
-            let basename = name.basename();
-            let var = Var {
-                name: basename.clone(),
-                span: name.span(),
-                type_: type_.clone(),
-                default: v.vars.get(basename).and_then(|var| var.default.clone()),
-                is_cloud: *is_cloud,
-                is_used: false,
-            };
-            if *is_local {
-                if let Some(locals) = &mut v.locals {
-                    if let Some(existing_declaration) = locals.get(basename) {
-                        if existing_declaration.type_.is_value() {
-                            locals.insert(basename.clone(), var);
-                        }
-                    } else {
-                        locals.insert(basename.clone(), var);
-                    }
-                }
-                return;
-            }
-            if v.locals
-                .as_ref()
-                .is_some_and(|locals| locals.contains_key(basename))
-            {
-                return;
+            for name in names {
+                declare_var(name, &Type::Value, *is_local, false, v);
             }
-            if v.global_vars
-                .as_ref()
-                .is_some_and(|global_vars| global_vars.contains_key(basename))
-            {
-                return;
+        }
+        _ => (),
+    }
+}
+
+fn declare_var(name: &Name, type_: &Type, is_local: bool, is_cloud: bool, v: &mut V) {
+    let basename = name.basename();
+    let var = Var {
+        name: basename.clone(),
+        span: name.span(),
+        type_: type_.clone(),
+        default: v.vars.get(basename).and_then(|var| var.default.clone()),
+        is_cloud,
+        is_used: false,
+    };
+    if is_local {
+        if let Some(locals) = &mut v.locals {
+            if let Some(existing_declaration) = locals.get(basename) {
+                if existing_declaration.type_.is_value() {
+                    locals.insert(basename.clone(), var);
+                }
+            } else {
+                locals.insert(basename.clone(), var);
             }
-            //if let Some(existing_declaration) = v.vars.get(basename) {
-            // This condition ensures that variables with a specific type (e.g., a struct type) are not overwritten
-            // by a previous statement that didn't specify a type (which defaults to type `Value`).
-            // In this context, variables don't need to be explicitly declared if the type is `Value`.
-            // The syntax for setting variables is as follows:
-            // - For `Value` type: `variable_name = value;`
-            // - For a specific struct type: `typeName variable_name = value;`
-            //
-            // Since the visitor processes every variable assignment statement, this check ensures that if an
-            // existing variable has a specific type (not `Value`), it is preserved when a new statement tries to
-            // reassign it without a type (defaulting to `Value`). Only variables that are of type `Value` can be
-            // overwritten by the new assignment.
+        }
+        return;
+    }
+    if v.locals
+        .as_ref()
+        .is_some_and(|locals| locals.contains_key(basename))
+    {
+        return;
+    }
+    if v.global_vars
+        .as_ref()
+        .is_some_and(|global_vars| global_vars.contains_key(basename))
+    {
+        return;
+    }
+    //if let Some(existing_declaration) = v.vars.get(basename) {
+    // This condition ensures that variables with a specific type (e.g., a struct type) are not overwritten
+    // by a previous statement that didn't specify a type (which defaults to type `Value`).
+    // In this context, variables don't need to be explicitly declared if the type is `Value`.
+    // The syntax for setting variables is as follows:
+    // - For `Value` type: `variable_name = value;`
+    // - For a specific struct type: `typeName variable_name = value;`
+    //
+    // Since the visitor processes every variable assignment statement, this check ensures that if an
+    // existing variable has a specific type (not `Value`), it is preserved when a new statement tries to
+    // reassign it without a type (defaulting to `Value`). Only variables that are of type `Value` can be
+    // overwritten by the new assignment.
 
-            // TODO: Make redeclaration of variables with different struct types an error.
-            // if existing_declaration.type_.is_value() {
-            //     v.vars.insert(basename.clone(), var);
-            // }
-            if !v.vars.contains_key(basename)
-                && v.global_vars
-                    .as_ref()
-                    .is_some_and(|vars| !vars.contains_key(basename))
-            {
-                v.vars.insert(basename.clone(), var);
+    // TODO: Make redeclaration of variables with different struct types an error.
+    // if existing_declaration.type_.is_value() {
+    //     v.vars.insert(basename.clone(), var);
+    // }
+    if !v.vars.contains_key(basename)
+        && v.global_vars
+            .as_ref()
+            .is_some_and(|vars| !vars.contains_key(basename))
+    {
+        v.vars.insert(basename.clone(), var);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        codegen::build::ParseCache,
+        diagnostic::DiagnosticKind,
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn event_scripts_have_their_own_locals() {
+        let main = r#"costumes "blank.svg";
+            struct Pair { a = 0, b = 0 }
+            var x = 100;
+            inline proc bump { x += 1; }
+            onflag {
+                local x = 1;
+                local Pair p = Pair { a: 3, b: 4 };
+                bump;
+                say x & p.b;
+                broadcast_and_wait "go";
+                say x;
+            }
+            on "go" {
+                local x = 2;
+                say x;
             }
+            "#;
+        let (outcome, output) = run(main, None);
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: 14", "main: 2", "main: 1"]);
+        let (artifact, sb3) = build_sb3(main, false);
+        assert!(artifact.sprites_diagnostics["main"].diagnostics.iter().any(
+            |diagnostic| matches!(
+                &diagnostic.kind,
+                DiagnosticKind::CannotInline { name, .. } if name == "bump"
+            )
+        ));
+        let mut zip = zip::ZipArchive::new(sb3).unwrap();
+        let project: serde_json::Value =
+            serde_json::from_reader(zip.by_name("project.json").unwrap()).unwrap();
+        let variables = project["targets"][1]["variables"].as_object().unwrap();
+        for name in ["x", "onflag#1:x", "onflag#1:p.b", "on#2:x"] {
+            assert!(variables.values().any(|variable| variable[0] == name));
         }
-        _ => (),
+    }
+
+    #[test]
+    fn event_scripts_of_the_stage_and_sprites_have_different_locals() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             onflag { local x = 2; wait 0; say x; }\n",
+        );
+        fs.borrow_mut().0.insert(
+            "stage.gs".into(),
+            b"costumes \"blank.svg\";\nonflag { local x = 1; wait 0; log x; }\n".to_vec(),
+        );
+        let (_, sb3) = build_project_sb3(fs.clone(), false);
+        let (outcome, mut output) = run_sb3(sb3, None);
+        assert_eq!(outcome, Outcome::Finished);
+        output.sort();
+        assert_eq!(output, ["Stage: 1", "main: 2"]);
+        let (_, sb3) = build_project_sb3(fs, false);
+        let project = project_json(sb3);
+        let names = |target: usize| -> Vec<String> {
+            project["targets"][target]["variables"]
+                .as_object()
+                .unwrap()
+                .values()
+                .map(|variable| variable[0].as_str().unwrap().to_owned())
+                .collect()
+        };
+        assert_eq!(names(0), ["onflag#1:x"]);
+        assert_eq!(names(1), ["onflag#2:x"]);
+    }
+
+    #[test]
+    fn recursive_structs_are_an_error() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             struct Node { value, Node next }\n\
+             onflag { Node n = Node { value: 1, next: 2 }; say n.value; }\n",
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(artifact.failure());
+        assert!(artifact.sprites_diagnostics["main"].diagnostics.iter().any(
+            |diagnostic| matches!(
+                &diagnostic.kind,
+                DiagnosticKind::RecursiveStruct(name) if name == "Node"
+            )
+        ));
     }
 }
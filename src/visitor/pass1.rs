@@ -57,7 +57,9 @@ fn visit_sprite(sprite: &mut Sprite, callsites: &mut usize) {
             },
         );
         match &mut event.kind {
-            EventKind::OnLoudnessGt { value } | EventKind::OnTimerGt { value } => {
+            EventKind::OnLoudnessGt { value }
+            | EventKind::OnTimerGt { value }
+            | EventKind::OnVideoMotionGt { value } => {
                 visit_expr(
                     value,
                     &mut vec![],
@@ -114,6 +116,13 @@ fn visit_stmt(stmt: &mut Stmt, s: &mut S) -> Vec<Stmt> {
             before.extend(cond_callsites.iter().cloned());
             body.extend(cond_callsites);
         }
+        Stmt::While { .. }
+        | Stmt::For { .. }
+        | Stmt::Break { .. }
+        | Stmt::Continue { .. }
+        | Stmt::Match { .. } => {
+            unreachable!()
+        }
         Stmt::SetVar {
             name: _,
             value,
@@ -123,6 +132,13 @@ fn visit_stmt(stmt: &mut Stmt, s: &mut S) -> Vec<Stmt> {
         } => {
             visit_expr(value, &mut before, s);
         }
+        Stmt::Destructure {
+            names: _,
+            value,
+            is_local: _,
+        } => {
+            visit_expr(value, &mut before, s);
+        }
         Stmt::ChangeVar { name: _, value } => {
             visit_expr(value, &mut before, s);
         }
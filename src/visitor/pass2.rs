@@ -1,4 +1,12 @@
-use rustc_hash::FxHashMap;
+use std::{
+    cell::RefCell,
+    iter,
+};
+
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
 use logos::Span;
 
 use super::transformations::{
@@ -15,6 +23,7 @@ use crate::{
     diagnostic::{
         keys,
         DiagnosticKind,
+        Note,
         SpriteDiagnostics,
     },
     misc::SmolStr,
@@ -36,10 +45,14 @@ pub struct S<'a> {
     pub global_lists: Option<&'a FxHashMap<SmolStr, List>>,
     pub global_enums: Option<&'a FxHashMap<SmolStr, Enum>>,
     pub global_structs: Option<&'a FxHashMap<SmolStr, Struct>>,
+    /// Variables which are never assigned to, with their default values.
+    pub constants: &'a FxHashMap<SmolStr, Value>,
+    /// The constants which were read by the body being visited.
+    pub read_constants: &'a RefCell<FxHashSet<SmolStr>>,
 }
 
-impl S<'_> {
-    pub fn get_var(&self, name: &str) -> Option<&Var> {
+impl<'a> S<'a> {
+    pub fn get_var(&self, name: &str) -> Option<&'a Var> {
         self.local_vars
             .and_then(|local_vars| local_vars.get(name))
             .or_else(|| self.vars.get(name))
@@ -49,21 +62,33 @@ impl S<'_> {
             })
     }
 
-    pub fn get_list(&self, name: &str) -> Option<&List> {
+    pub fn get_list(&self, name: &str) -> Option<&'a List> {
         self.lists.get(name).or_else(|| {
             self.global_lists
                 .and_then(|global_lists| global_lists.get(name))
         })
     }
 
-    pub fn get_struct(&self, name: &str) -> Option<&Struct> {
+    pub fn get_struct(&self, name: &str) -> Option<&'a Struct> {
         self.structs.get(name).or_else(|| {
             self.global_structs
                 .and_then(|global_structs| global_structs.get(name))
         })
     }
 
-    pub fn get_enum(&self, name: &str) -> Option<&Enum> {
+    /// Returns the type of `name` inside a value of type `type_`, following its field
+    /// through nested structs.
+    pub fn field_type(&self, type_: &'a Type, name: &Name) -> Option<&'a Type> {
+        let Some(path) = name.fieldname() else {
+            return Some(type_);
+        };
+        let (type_name, _) = type_.struct_()?;
+        let s = *self;
+        self.get_struct(type_name)?
+            .field_type(path, &|name| s.get_struct(name))
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&'a Enum> {
         self.enums.get(name).or_else(|| {
             self.global_enums
                 .and_then(|global_enums| global_enums.get(name))
@@ -76,19 +101,102 @@ pub fn visit_project(
     stage_diagnostics: &mut SpriteDiagnostics,
     sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
 ) {
-    visit_sprite(&mut project.stage, None, stage_diagnostics);
+    let mut assigned = FxHashSet::default();
+    for sprite in iter::once(&project.stage).chain(project.sprites.values()) {
+        let bodies = sprite
+            .proc_definitions
+            .values()
+            .chain(sprite.func_definitions.values())
+            .chain(sprite.events.iter().map(|event| &event.body));
+        for body in bodies {
+            collect_assigned(body, &mut assigned);
+        }
+    }
+    let stage_constants = constants(&project.stage.vars, &assigned, &FxHashMap::default());
+    visit_sprite(
+        &mut project.stage,
+        None,
+        &stage_constants,
+        stage_diagnostics,
+    );
     for (sprite_name, sprite) in &mut project.sprites {
+        let constants = constants(&sprite.vars, &assigned, &stage_constants);
         visit_sprite(
             sprite,
             Some(&project.stage),
+            &constants,
             sprites_diagnostics.get_mut(sprite_name).unwrap(),
         );
     }
 }
 
-fn visit_sprite(sprite: &mut Sprite, stage: Option<&Sprite>, d: D) {
+/// Collects the names of the variables which are assigned to in `stmts`. Variables which
+/// are shown are included too, because their monitors can be turned into sliders.
+fn collect_assigned(stmts: &[Stmt], assigned: &mut FxHashSet<SmolStr>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::SetVar { name, .. } | Stmt::ChangeVar { name, .. } | Stmt::Show(name) => {
+                assigned.insert(name.basename().clone());
+            }
+            Stmt::Destructure { names, .. } => {
+                assigned.extend(names.iter().map(|name| name.basename().clone()));
+            }
+            Stmt::For { name, body, .. } => {
+                assigned.insert(name.basename().clone());
+                collect_assigned(body, assigned);
+            }
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::While { body, .. } => collect_assigned(body, assigned),
+            Stmt::Branch {
+                if_body, else_body, ..
+            } => {
+                collect_assigned(if_body, assigned);
+                collect_assigned(else_body, assigned);
+            }
+            Stmt::Match { arms, .. } => {
+                for arm in arms {
+                    collect_assigned(&arm.body, assigned);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the variables of `vars` which always hold their default value, along with the
+/// constants of the stage which are not shadowed by `vars`.
+fn constants(
+    vars: &FxHashMap<SmolStr, Var>,
+    assigned: &FxHashSet<SmolStr>,
+    global_constants: &FxHashMap<SmolStr, Value>,
+) -> FxHashMap<SmolStr, Value> {
+    let mut constants: FxHashMap<SmolStr, Value> = global_constants
+        .iter()
+        .filter(|(name, _)| !vars.contains_key(*name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    for var in vars.values() {
+        if var.is_cloud || !var.type_.is_value() || assigned.contains(&var.name) {
+            continue;
+        }
+        if let Some(ConstExpr::Value { value, .. }) = &var.default {
+            constants.insert(var.name.clone(), value.clone());
+        }
+    }
+    constants
+}
+
+fn visit_sprite(
+    sprite: &mut Sprite,
+    stage: Option<&Sprite>,
+    constants: &FxHashMap<SmolStr, Value>,
+    d: D,
+) {
     for proc in sprite.procs.values() {
         let proc_definition = sprite.proc_definitions.get_mut(&proc.name).unwrap();
+        let read_constants = RefCell::default();
         visit_stmts(
             proc_definition,
             S {
@@ -106,13 +214,22 @@ fn visit_sprite(sprite: &mut Sprite, stage: Option<&Sprite>, d: D) {
                 global_lists: stage.map(|stage| &stage.lists),
                 global_enums: stage.map(|stage| &stage.enums),
                 global_structs: stage.map(|stage| &stage.structs),
+                constants,
+                read_constants: &read_constants,
             },
             d,
             true,
         );
+        sprite
+            .proc_references
+            .get_mut(&proc.name)
+            .unwrap()
+            .constants
+            .extend(read_constants.into_inner());
     }
     for func in sprite.funcs.values() {
         let func_definition = sprite.func_definitions.get_mut(&func.name).unwrap();
+        let read_constants = RefCell::default();
         visit_stmts(
             func_definition,
             S {
@@ -130,11 +247,21 @@ fn visit_sprite(sprite: &mut Sprite, stage: Option<&Sprite>, d: D) {
                 global_lists: stage.map(|stage| &stage.lists),
                 global_enums: stage.map(|stage| &stage.enums),
                 global_structs: stage.map(|stage| &stage.structs),
+                constants,
+                read_constants: &read_constants,
             },
             d,
             true,
         );
+        sprite
+            .func_references
+            .get_mut(&func.name)
+            .unwrap()
+            .constants
+            .extend(read_constants.into_inner());
     }
+    // Struct literals in the defaults of variables are not in any body.
+    let read_constants = RefCell::default();
     let s = S {
         proc_args: &sprite.proc_args,
         func_args: &sprite.func_args,
@@ -150,15 +277,36 @@ fn visit_sprite(sprite: &mut Sprite, stage: Option<&Sprite>, d: D) {
         global_lists: stage.map(|stage| &stage.lists),
         global_enums: stage.map(|stage| &stage.enums),
         global_structs: stage.map(|stage| &stage.structs),
+        constants,
+        read_constants: &read_constants,
     };
     for event in &mut sprite.events {
-        visit_stmts(&mut event.body, s, d, true);
+        let read_constants = RefCell::default();
+        let event_s = S {
+            read_constants: &read_constants,
+            ..s
+        };
+        visit_stmts(
+            &mut event.body,
+            S {
+                local_vars: Some(&event.locals),
+                ..event_s
+            },
+            d,
+            true,
+        );
         match &mut event.kind {
-            EventKind::OnLoudnessGt { value } | EventKind::OnTimerGt { value } => {
-                visit_expr(value, s, d);
+            EventKind::OnLoudnessGt { value }
+            | EventKind::OnTimerGt { value }
+            | EventKind::OnVideoMotionGt { value } => {
+                visit_expr(value, event_s, d);
             }
             _ => {}
         }
+        event
+            .references
+            .constants
+            .extend(read_constants.into_inner());
     }
     let struct_literals: Vec<_> = sprite
         .vars
@@ -192,6 +340,11 @@ fn visit_stmts(stmts: &mut Vec<Stmt>, s: S, d: D, top_level: bool) {
                 is_local,
                 is_cloud,
             } => visit_stmt_set_var(s, d, name, value, type_, is_local, is_cloud),
+            Stmt::Destructure {
+                names,
+                value,
+                is_local,
+            } => visit_stmt_destructure(s, d, names, value, is_local),
             Stmt::SetListIndex { name, index, value } => {
                 visit_stmt_list_set(s, d, name, index, value)
             }
@@ -214,12 +367,10 @@ fn visit_stmts(stmts: &mut Vec<Stmt>, s: S, d: D, top_level: bool) {
             _ => None,
         };
         if let Some(replace) = replace {
-            let len = replace.len();
-            stmts.remove(i);
-            for replace in replace.into_iter().rev() {
-                stmts.insert(i, replace);
-            }
-            i += len.saturating_sub(1);
+            // The replacements are visited again, so that fields which are themselves
+            // structs get expanded too.
+            stmts.splice(i..i + 1, replace);
+            continue;
         }
         i += 1;
     }
@@ -247,6 +398,13 @@ fn visit_stmt(stmt: &mut Stmt, s: S, d: D) {
             visit_expr(cond, s, d);
             visit_stmts(body, s, d, false);
         }
+        Stmt::While { .. }
+        | Stmt::For { .. }
+        | Stmt::Break { .. }
+        | Stmt::Continue { .. }
+        | Stmt::Match { .. } => {
+            unreachable!()
+        }
         Stmt::SetVar {
             name: _,
             value,
@@ -256,6 +414,13 @@ fn visit_stmt(stmt: &mut Stmt, s: S, d: D) {
         } => {
             visit_expr(value, s, d);
         }
+        Stmt::Destructure {
+            names: _,
+            value,
+            is_local: _,
+        } => {
+            visit_expr(value, s, d);
+        }
         Stmt::ChangeVar { name: _, value } => {
             visit_expr(value, s, d);
         }
@@ -397,12 +562,17 @@ fn visit_expr(expr: &mut Expr, s: S, d: D) {
         }
         Expr::Ternary { .. } => unreachable!(),
     }
+    transformations::apply(expr, |expr| transformations::constant_var(expr, s));
     transformations::apply(expr, |expr| transformations::enum_field_access(expr, s, d));
     transformations::apply(expr, transformations::minus);
     transformations::apply(expr, transformations::less_than_equal);
     transformations::apply(expr, transformations::greater_than_equal);
     transformations::apply(expr, transformations::not_equal);
     transformations::apply(expr, transformations::floor_div);
+    // Literals are folded first, because identities like `x + 0` would keep a literal
+    // such as `"1e3"` as it is, where Scratch converts it to a number.
+    transformations::apply(expr, transformations::bin_op);
+    transformations::apply(expr, transformations::un_op);
     transformations::apply(expr, transformations::add_zero_left);
     transformations::apply(expr, transformations::add_zero_right);
     transformations::apply(expr, transformations::sub_zero);
@@ -413,8 +583,6 @@ fn visit_expr(expr: &mut Expr, s: S, d: D) {
     transformations::apply(expr, transformations::mul_zero_right);
     transformations::apply(expr, transformations::join_empty_left);
     transformations::apply(expr, transformations::join_empty_right);
-    transformations::apply(expr, transformations::bin_op);
-    transformations::apply(expr, transformations::un_op);
     transformations::apply(expr, |expr| transformations::variable_field_access(expr, s));
     transformations::apply(expr, |expr| transformations::arg_field_access(expr, s));
     transformations::apply(expr, |expr| transformations::list_field_access(expr, s));
@@ -443,7 +611,7 @@ fn visit_stmt_set_var(
                 name: Name::DotName {
                     lhs: name.basename().clone(),
                     lhs_span: name.basespan().clone(),
-                    rhs: struct_literal_field.name.clone(),
+                    rhs: qualify_field_path(name, &struct_literal_field.name),
                     rhs_span: struct_literal_field.span.clone(),
                     is_generated: true,
                 },
@@ -456,6 +624,52 @@ fn visit_stmt_set_var(
     )
 }
 
+fn visit_stmt_destructure(
+    s: S,
+    d: D,
+    names: &[Name],
+    value: &Expr,
+    is_local: &bool,
+) -> Option<Vec<Stmt>> {
+    let Expr::StructLiteral { name, fields, .. } = value else {
+        d.report(DiagnosticKind::NotStruct, &value.span());
+        return Some(vec![]);
+    };
+    let Some(struct_) = s.get_struct(name) else {
+        return Some(vec![]);
+    };
+    if struct_.fields.len() != names.len() {
+        d.report(
+            DiagnosticKind::DestructureCountMismatch {
+                expected: struct_.fields.len(),
+                given: names.len(),
+            },
+            &(names[0].span().start..names[names.len() - 1].span().end),
+        );
+        return Some(vec![]);
+    }
+    Some(
+        names
+            .iter()
+            .zip(&struct_.fields)
+            .filter_map(|(name, field)| {
+                let mut value = StructLiteralField::find(fields, &field.name)?.clone();
+                // Each field is read by the destructuring, so it is no longer a generated name.
+                if let Expr::Name(Name::DotName { is_generated, .. }) = &mut value {
+                    *is_generated = false;
+                }
+                Some(Stmt::SetVar {
+                    name: name.clone(),
+                    value: Box::new(value),
+                    type_: Type::Value,
+                    is_local: *is_local,
+                    is_cloud: false,
+                })
+            })
+            .collect(),
+    )
+}
+
 fn visit_stmt_list_set(s: S, d: D, name: &Name, index: &Expr, value: &Expr) -> Option<Vec<Stmt>> {
     let expr = value;
     let struct_literal_fields = get_struct_literal_for_type(s, d, name, expr, |basename| {
@@ -468,7 +682,7 @@ fn visit_stmt_list_set(s: S, d: D, name: &Name, index: &Expr, value: &Expr) -> O
                 name: Name::DotName {
                     lhs: name.basename().clone(),
                     lhs_span: name.basespan().clone(),
-                    rhs: struct_literal_field.name.clone(),
+                    rhs: qualify_field_path(name, &struct_literal_field.name),
                     rhs_span: struct_literal_field.span.clone(),
                     is_generated: true,
                 },
@@ -491,7 +705,7 @@ fn visit_stmt_list_add(s: S, d: D, name: &Name, value: &Expr) -> Option<Vec<Stmt
                 name: Name::DotName {
                     lhs: name.basename().clone(),
                     lhs_span: name.basespan().clone(),
-                    rhs: struct_literal_field.name.clone(),
+                    rhs: qualify_field_path(name, &struct_literal_field.name),
                     rhs_span: struct_literal_field.span.clone(),
                     is_generated: true,
                 },
@@ -502,11 +716,7 @@ fn visit_stmt_list_add(s: S, d: D, name: &Name, value: &Expr) -> Option<Vec<Stmt
 }
 
 fn visit_stmt_delete_list(s: S, name: &Name) -> Option<Vec<Stmt>> {
-    if name.fieldname().is_some() {
-        return None;
-    }
-    let basename = name.basename();
-    let type_ = &s.get_list(basename)?.type_;
+    let type_ = s.field_type(&s.get_list(name.basename())?.type_, name)?;
     let (type_name, _) = type_.struct_()?;
     let struct_ = s.get_struct(type_name)?;
     Some(
@@ -517,7 +727,7 @@ fn visit_stmt_delete_list(s: S, name: &Name) -> Option<Vec<Stmt>> {
                 Stmt::DeleteList(Name::DotName {
                     lhs: name.basename().clone(),
                     lhs_span: name.basespan().clone(),
-                    rhs: struct_field.name.clone(),
+                    rhs: qualify_field_path(name, &struct_field.name),
                     rhs_span: struct_field.span.clone(),
                     is_generated: true,
                 })
@@ -544,7 +754,7 @@ fn visit_stmt_insert_at_list(
                 name: Name::DotName {
                     lhs: name.basename().clone(),
                     lhs_span: name.basespan().clone(),
-                    rhs: struct_literal_field.name.clone(),
+                    rhs: qualify_field_path(name, &struct_literal_field.name),
                     rhs_span: struct_literal_field.span.clone(),
                     is_generated: true,
                 },
@@ -556,11 +766,7 @@ fn visit_stmt_insert_at_list(
 }
 
 fn visit_stmt_delete_list_index(s: S, _d: D, name: &Name, index: &Expr) -> Option<Vec<Stmt>> {
-    if name.fieldname().is_some() {
-        return None;
-    }
-    let basename = name.basename();
-    let type_ = &s.get_list(basename)?.type_;
+    let type_ = s.field_type(&s.get_list(name.basename())?.type_, name)?;
     let (type_name, _) = type_.struct_()?;
     let struct_ = s.get_struct(type_name)?;
     Some(
@@ -571,7 +777,7 @@ fn visit_stmt_delete_list_index(s: S, _d: D, name: &Name, index: &Expr) -> Optio
                 name: Name::DotName {
                     lhs: name.basename().clone(),
                     lhs_span: name.basespan().clone(),
-                    rhs: struct_field.name.clone(),
+                    rhs: qualify_field_path(name, &struct_field.name),
                     rhs_span: struct_field.span.clone(),
                     is_generated: true,
                 },
@@ -591,12 +797,8 @@ fn get_struct_literal_for_type<'a, T>(
 where
     T: FnOnce(&str) -> Option<&'a Type>,
 {
-    if name.fieldname().is_some() {
-        return None;
-    }
-    let basename = name.basename();
     let basespan = name.basespan();
-    let type_ = get_type(basename)?;
+    let type_ = s.field_type(get_type(name.basename())?, name)?;
     let (type_name, type_span) = type_.struct_()?;
     let struct_ = s.get_struct(type_name)?;
     let (struct_literal_name, struct_literal_span, struct_literal_fields) = match expr {
@@ -641,6 +843,14 @@ where
     Some(struct_literal_fields)
 }
 
+/// Returns the path to `field` of the struct held by `name`, which may itself be a field.
+fn qualify_field_path(name: &Name, field: &SmolStr) -> SmolStr {
+    match name.fieldname() {
+        Some(path) => format!("{path}.{field}").into(),
+        None => field.clone(),
+    }
+}
+
 fn visit_stmt_return(_value: &Expr) -> Option<Vec<Stmt>> {
     Some(vec![Stmt::Block {
         block: Block::StopThisScript,
@@ -657,15 +867,16 @@ fn struct_literal(s: S, d: D, name: &SmolStr, span: &Span, fields: &mut Vec<Stru
     let mut new_fields: Vec<StructLiteralField> = vec![];
 
     // First, add any provided fields in their original order
-    let mut provided_field_names = std::collections::HashSet::new();
+    let mut provided_field_names = FxHashMap::default();
     for field in fields.iter() {
-        if !provided_field_names.insert(&field.name) {
-            d.report(
+        if let Some(first) = provided_field_names.insert(&field.name, &field.span) {
+            d.report_with_notes(
                 DiagnosticKind::DuplicateField {
                     struct_name: struct_.name.clone(),
                     field_name: field.name.clone(),
                 },
                 &field.span,
+                vec![Note::new("first given here", first)],
             );
         }
         new_fields.push(field.clone());
@@ -673,13 +884,24 @@ fn struct_literal(s: S, d: D, name: &SmolStr, span: &Span, fields: &mut Vec<Stru
 
     // Then, add default values for missing fields
     for struct_field in &struct_.fields {
-        if !provided_field_names.contains(&struct_field.name) {
+        if !provided_field_names.contains_key(&struct_field.name) {
             if let Some(default) = &struct_field.default {
                 new_fields.push(StructLiteralField {
                     name: struct_field.name.clone(),
                     span: default.span(),
                     value: Box::new(default.clone().into()),
                 });
+            } else if let Some((type_name, _)) = struct_field.type_.struct_() {
+                // Fields which are structs are filled in from their own defaults.
+                new_fields.push(StructLiteralField {
+                    name: struct_field.name.clone(),
+                    span: span.clone(),
+                    value: Box::new(Expr::StructLiteral {
+                        name: type_name.clone(),
+                        span: span.clone(),
+                        fields: vec![],
+                    }),
+                });
             } else {
                 d.report(
                     DiagnosticKind::MissingField {
@@ -705,30 +927,28 @@ fn const_struct_literal(
     let Some(struct_) = s.get_struct(name) else {
         return;
     };
-    let mut provided_field_names = std::collections::HashSet::new();
+    let mut provided_field_names = FxHashMap::default();
     for field in fields.iter() {
-        if !provided_field_names.insert(&field.name) {
-            d.report(
+        if let Some(first) = provided_field_names.insert(&field.name, &field.name_span) {
+            d.report_with_notes(
                 DiagnosticKind::DuplicateField {
                     struct_name: struct_.name.clone(),
                     field_name: field.name.clone(),
                 },
                 &field.name_span,
+                vec![Note::new("first given here", first)],
             );
         }
     }
 }
 
 fn visit_show_or_hide_monitor(name: &Name, s: S, _d: D, is_show: bool) -> Option<Vec<Stmt>> {
-    if name.fieldname().is_some() {
-        return None;
-    }
     let basename = name.basename();
     let type_ = s
         .get_list(basename)
         .map(|list| &list.type_)
         .or_else(|| s.get_var(basename).map(|var| &var.type_))?;
-    let (type_name, _) = type_.struct_()?;
+    let (type_name, _) = s.field_type(type_, name)?.struct_()?;
     let struct_ = s.get_struct(type_name)?;
     Some(
         struct_
@@ -739,7 +959,7 @@ fn visit_show_or_hide_monitor(name: &Name, s: S, _d: D, is_show: bool) -> Option
                     Stmt::Show(Name::DotName {
                         lhs: name.basename().clone(),
                         lhs_span: name.basespan().clone(),
-                        rhs: field.name.clone(),
+                        rhs: qualify_field_path(name, &field.name),
                         rhs_span: field.span.clone(),
                         is_generated: true,
                     })
@@ -747,7 +967,7 @@ fn visit_show_or_hide_monitor(name: &Name, s: S, _d: D, is_show: bool) -> Option
                     Stmt::Hide(Name::DotName {
                         lhs: name.basename().clone(),
                         lhs_span: name.basespan().clone(),
-                        rhs: field.name.clone(),
+                        rhs: qualify_field_path(name, &field.name),
                         rhs_span: field.span.clone(),
                         is_generated: true,
                     })
@@ -756,3 +976,87 @@ fn visit_show_or_hide_monitor(name: &Name, s: S, _d: D, is_show: bool) -> Option
             .collect(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        codegen::build::ParseCache,
+        diagnostic::DiagnosticKind,
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn vm_runs_nested_structs() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            struct Vec2 { x = 0, y = 0 }
+            struct Body { Vec2 pos, Vec2 vel = Vec2 { x: 1, y: 2 } }
+            var Body body = Body {};
+            list Body bodies;
+            proc report Body b {
+                say $b.pos.x & $b.vel.y;
+            }
+            onflag {
+                body.pos.x = 3;
+                body.vel = Vec2 { x: 4, y: 5 };
+                report body;
+                add Body { pos: body.vel } to bodies;
+                bodies[1].pos.y += 1;
+                say bodies[1].pos.y & bodies[1].vel.x;
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: 35", "main: 61"]);
+    }
+
+    #[test]
+    fn vm_runs_tuple_returns_and_destructuring() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            struct Vec2 { x, y }
+            func divmod(a, b) {
+                if $b == 0 {
+                    return 0, 0;
+                }
+                return $a // $b, $a % $b;
+            }
+            proc report {
+                local Vec2 v = Vec2 { x: 3, y: 4 };
+                local a, b = v;
+                say a & b;
+            }
+            onflag {
+                q, r = divmod(7, 2);
+                say q & r;
+                report;
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: 31", "main: 34"]);
+    }
+
+    #[test]
+    fn destructuring_the_wrong_number_of_values_is_an_error() {
+        let fs = project(
+            "costumes \"blank.svg\";\n\
+             func pair() { return 1, 2; }\n\
+             onflag { a, b, c = pair(); }\n",
+        );
+        let artifact = try_build(&fs, &mut ParseCache::default());
+        assert!(artifact.failure());
+        assert!(artifact.sprites_diagnostics["main"].diagnostics.iter().any(
+            |diagnostic| matches!(
+                diagnostic.kind,
+                DiagnosticKind::DestructureCountMismatch {
+                    expected: 2,
+                    given: 3
+                }
+            )
+        ));
+    }
+}
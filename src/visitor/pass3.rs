@@ -6,6 +6,12 @@ struct S<'a> {
     func: Option<&'a Func>,
 }
 
+impl S<'_> {
+    fn target(&mut self, name: &Name) {
+        self.references.targets.insert(name.basename().clone());
+    }
+}
+
 pub fn visit_project(project: &mut Project) {
     visit_sprite(&mut project.stage);
     for sprite in project.sprites.values_mut() {
@@ -46,7 +52,9 @@ fn visit_sprite(sprite: &mut Sprite) {
         };
         visit_stmts(&event.body, s);
         match &mut event.kind {
-            EventKind::OnLoudnessGt { value } | EventKind::OnTimerGt { value } => {
+            EventKind::OnLoudnessGt { value }
+            | EventKind::OnTimerGt { value }
+            | EventKind::OnVideoMotionGt { value } => {
                 visit_expr(value, s);
             }
             _ => {}
@@ -80,40 +88,46 @@ fn visit_stmt(stmt: &Stmt, s: &mut S) {
             visit_expr(cond, s);
             visit_stmts(body, s);
         }
+        Stmt::While { .. }
+        | Stmt::For { .. }
+        | Stmt::Break { .. }
+        | Stmt::Continue { .. }
+        | Stmt::Match { .. }
+        | Stmt::Destructure { .. } => {
+            unreachable!()
+        }
         Stmt::SetVar {
-            name: _,
+            name,
             value,
             type_: _,
             is_local: _,
             is_cloud: _,
         } => {
+            s.target(name);
             visit_expr(value, s);
         }
-        Stmt::ChangeVar { name: _, value } => {
+        Stmt::ChangeVar { name, value } => {
+            s.target(name);
             visit_expr(value, s);
         }
-        Stmt::Show(_name) => {}
-        Stmt::Hide(_name) => {}
-        Stmt::AddToList { name: _, value } => {
+        Stmt::Show(name) => s.target(name),
+        Stmt::Hide(name) => s.target(name),
+        Stmt::AddToList { name, value } => {
+            s.target(name);
             visit_expr(value, s);
         }
-        Stmt::DeleteList(_name) => {}
-        Stmt::DeleteListIndex { name: _, index } => {
+        Stmt::DeleteList(name) => s.target(name),
+        Stmt::DeleteListIndex { name, index } => {
+            s.target(name);
             visit_expr(index, s);
         }
-        Stmt::InsertAtList {
-            name: _,
-            index,
-            value,
-        } => {
+        Stmt::InsertAtList { name, index, value } => {
+            s.target(name);
             visit_expr(index, s);
             visit_expr(value, s);
         }
-        Stmt::SetListIndex {
-            name: _,
-            index,
-            value,
-        } => {
+        Stmt::SetListIndex { name, index, value } => {
+            s.target(name);
             visit_expr(index, s);
             visit_expr(value, s);
         }
@@ -237,9 +251,10 @@ fn visit_expr(expr: &Expr, s: &mut S) {
         }
         Expr::Property {
             object,
-            property: _,
+            property,
             span: _,
         } => {
+            s.references.properties.insert(property.clone());
             visit_expr(object, s);
         }
         Expr::Ternary { .. } => unreachable!(),
@@ -1,3 +1,5 @@
+use std::iter;
+
 use rustc_hash::{
     FxHashMap,
     FxHashSet,
@@ -11,6 +13,9 @@ use crate::{
 struct Scope<'a> {
     used_procs: &'a mut FxHashSet<SmolStr>,
     used_funcs: &'a mut FxHashSet<SmolStr>,
+    used_names: &'a mut FxHashSet<SmolStr>,
+    global_used_names: Option<&'a mut FxHashSet<SmolStr>>,
+    properties: &'a mut FxHashSet<SmolStr>,
     proc_args: &'a mut FxHashMap<SmolStr, Vec<Arg>>,
     func_args: &'a mut FxHashMap<SmolStr, Vec<Arg>>,
     vars: &'a mut FxHashMap<SmolStr, Var>,
@@ -37,10 +42,16 @@ impl Scope<'_> {
             return;
         };
         struct_.is_used = true;
-        let Some(f) = struct_.fields.iter_mut().find(|f| &f.name == field) else {
-            return;
-        };
-        f.is_used = true;
+        Self::mark_field_path(type_name, field, structs);
+    }
+
+    /// Marks the fields of the struct-typed local variables of an event as used.
+    fn mark_event_local_struct_fields(event: &Event, structs: &mut FxHashMap<SmolStr, Struct>) {
+        for refr in &event.references.names {
+            if refr.proc.is_none() && refr.func.is_none() && event.locals.contains_key(&refr.name) {
+                Self::mark_struct_field(refr, structs, &event.locals);
+            }
+        }
     }
 
     fn mark_arg_struct_field(
@@ -56,23 +67,43 @@ impl Scope<'_> {
         else {
             return;
         };
-        let Some(struct_) = structs.get_mut(type_name) else {
-            return;
-        };
-        let Some(f) = struct_.fields.iter_mut().find(|f| &f.name == field) else {
-            return;
-        };
-        f.is_used = true;
+        Self::mark_field_path(type_name, field, structs);
+    }
+
+    /// Marks each field along a dotted path such as `pos.x` as used, along with the structs
+    /// of the nested fields.
+    fn mark_field_path(type_name: &SmolStr, path: &str, structs: &mut FxHashMap<SmolStr, Struct>) {
+        let mut type_name = type_name.clone();
+        for (i, name) in path.split('.').enumerate() {
+            let Some(struct_) = structs.get_mut(&type_name) else {
+                return;
+            };
+            if i > 0 {
+                struct_.is_used = true;
+            }
+            let Some(f) = struct_.fields.iter_mut().find(|f| f.name == name) else {
+                return;
+            };
+            f.is_used = true;
+            let Some((name, _)) = f.type_.struct_() else {
+                return;
+            };
+            type_name = name.clone();
+        }
     }
 }
 
 pub fn visit_project(project: &mut Project) {
+    let mut properties = FxHashSet::default();
     // first, visit the stage
     for event in &project.stage.events {
         resolve_references(
             &mut Scope {
                 used_procs: &mut project.stage.used_procs,
                 used_funcs: &mut project.stage.used_funcs,
+                used_names: &mut project.stage.used_names,
+                global_used_names: None,
+                properties: &mut properties,
                 proc_args: &mut project.stage.proc_args,
                 func_args: &mut project.stage.func_args,
                 vars: &mut project.stage.vars,
@@ -90,6 +121,7 @@ pub fn visit_project(project: &mut Project) {
             &project.stage.func_references,
             &event.references,
         );
+        Scope::mark_event_local_struct_fields(event, &mut project.stage.structs);
     }
 
     // then visit each sprite
@@ -99,6 +131,9 @@ pub fn visit_project(project: &mut Project) {
                 &mut Scope {
                     used_procs: &mut sprite.used_procs,
                     used_funcs: &mut sprite.used_funcs,
+                    used_names: &mut sprite.used_names,
+                    global_used_names: Some(&mut project.stage.used_names),
+                    properties: &mut properties,
                     proc_args: &mut sprite.proc_args,
                     func_args: &mut sprite.func_args,
                     vars: &mut sprite.vars,
@@ -116,8 +151,14 @@ pub fn visit_project(project: &mut Project) {
                 &sprite.func_references,
                 &event.references,
             );
+            Scope::mark_event_local_struct_fields(event, &mut sprite.structs);
         }
     }
+
+    // Any sprite can read the variables of another sprite with `"Sprite"."name"`.
+    for sprite in iter::once(&mut project.stage).chain(project.sprites.values_mut()) {
+        sprite.used_names.extend(properties.iter().cloned());
+    }
 }
 
 fn resolve_references(
@@ -128,6 +169,24 @@ fn resolve_references(
     func_references: &FxHashMap<SmolStr, References>,
     references: &References,
 ) {
+    let names = references
+        .names
+        .iter()
+        .chain(&references.generated_names)
+        .map(|refr| &refr.name)
+        .chain(&references.targets)
+        .chain(&references.constants);
+    for name in names {
+        scope.used_names.insert(name.clone());
+        if !scope.vars.contains_key(name) && !scope.lists.contains_key(name) {
+            if let Some(global_used_names) = &mut scope.global_used_names {
+                global_used_names.insert(name.clone());
+            }
+        }
+    }
+    scope
+        .properties
+        .extend(references.properties.iter().cloned());
     for refr in &references.args {
         if let Some(arg) = refr
             .proc
@@ -278,3 +337,114 @@ fn resolve_references(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        path::Path,
+    };
+
+    use semver::Version;
+
+    use crate::{
+        codegen::build::build_with_cache,
+        diagnostic::DiagnosticKind,
+        standard_library::new_standard_library,
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn unused_code_is_left_out() {
+        let (artifact, _) = build_sb3(
+            r#"costumes "blank.svg";
+            var unused = 2;
+            list dead_list;
+            proc dead { add 1 to dead_list; say dead_list; }
+            onflag { say 1; stop_all; say 2; }
+            "#,
+            false,
+        );
+        // The flag, say and stop blocks are kept. The definition, prototype, add, say and
+        // list reporter blocks of `dead` and the unreachable say block are saved.
+        assert_eq!(artifact.block_count, 3);
+        assert_eq!(artifact.blocks_saved, 6);
+        let diagnostics = &artifact.sprites_diagnostics["main"].diagnostics;
+        assert!(diagnostics.iter().any(|diagnostic| matches!(
+            &diagnostic.kind,
+            DiagnosticKind::UnusedVariable(name) if name == "unused"
+        )));
+        assert!(diagnostics.iter().any(|diagnostic| matches!(
+            &diagnostic.kind,
+            DiagnosticKind::UnusedList(name) if name == "dead_list"
+        )));
+        let project = &artifact.project.sprites["main"];
+        assert!(!project.used_names.contains("unused"));
+        assert!(!project.used_names.contains("dead_list"));
+    }
+
+    #[test]
+    fn constants_which_are_read_are_used() {
+        let fs = project(
+            r#"costumes "blank.svg";
+            var speed = 5;
+            onflag { say speed & gravity; }
+            "#,
+        );
+        fs.borrow_mut().0.insert(
+            "stage.gs".into(),
+            b"costumes \"blank.svg\";\nvar gravity = 10;\n".to_vec(),
+        );
+        let (artifact, sb3) = build_project_sb3(fs, false);
+        for diagnostics in [
+            &artifact.stage_diagnostics,
+            &artifact.sprites_diagnostics["main"],
+        ] {
+            assert!(!diagnostics
+                .diagnostics
+                .iter()
+                .any(|diagnostic| matches!(diagnostic.kind, DiagnosticKind::UnusedVariable(_))));
+        }
+        let (outcome, output) = run_sb3(sb3.clone(), None);
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: 510"]);
+        let project = project_json(sb3);
+        for (target, name) in [(0, "gravity"), (1, "speed")] {
+            let variables = project["targets"][target]["variables"].as_object().unwrap();
+            assert!(variables.values().any(|variable| variable[0] == name));
+        }
+    }
+    #[test]
+    fn unused_procs_are_left_out_of_debug_builds() {
+        let fs = project(
+            r#"costumes "blank.svg";
+            var unused = 2;
+            proc dead { say 3; }
+            onflag { say 1; stop_all; say 2; }
+            "#,
+        );
+        let stdlib = new_standard_library(Version::new(0, 0, 0), Path::new("std"));
+        let mut sb3 = Cursor::new(vec![]);
+        let artifact = build_with_cache(
+            fs,
+            "".into(),
+            &mut sb3,
+            Some(stdlib),
+            false,
+            false,
+            &[],
+            &mut Default::default(),
+        )
+        .unwrap();
+        assert!(!artifact.failure());
+        // The definition, prototype and say blocks of `dead`, and the unreachable say
+        // block, are saved, but the unused variable is kept.
+        assert_eq!(artifact.block_count, 3);
+        assert_eq!(artifact.blocks_saved, 4);
+        sb3.set_position(0);
+        let project = project_json(sb3);
+        let variables = project["targets"][1]["variables"].as_object().unwrap();
+        assert!(variables.values().any(|variable| variable[0] == "unused"));
+    }
+}
@@ -0,0 +1,356 @@
+use logos::Span;
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
+
+use super::walk::{
+    calls,
+    stmt_names,
+    walk_stmts,
+    Callable,
+};
+use crate::{
+    ast::*,
+    blocks::BinOp,
+    diagnostic::{
+        DiagnosticKind,
+        SpriteDiagnostics,
+    },
+    misc::SmolStr,
+};
+
+/// The list which recursive procedures and functions save their variables to.
+pub const STACK: &str = "@stack";
+
+/// Local variables, and the variables generated for function results, inlined arguments,
+/// loops and matches, are shared by every call of a procedure or function. Procedures and
+/// functions declared with `recursive` push them to a stack before each call which can
+/// reach themselves again, and pop them after it. This runs after `pass2`, so struct
+/// variables are saved field by field.
+pub fn visit_project(
+    project: &mut Project,
+    stage_diagnostics: &mut SpriteDiagnostics,
+    sprites_diagnostics: &mut FxHashMap<SmolStr, SpriteDiagnostics>,
+) {
+    let stage_structs = project.stage.structs.clone();
+    visit_sprite(&mut project.stage, &stage_structs, stage_diagnostics);
+    for (sprite_name, sprite) in &mut project.sprites {
+        visit_sprite(
+            sprite,
+            &stage_structs,
+            sprites_diagnostics.get_mut(sprite_name).unwrap(),
+        );
+    }
+}
+
+fn visit_sprite(
+    sprite: &mut Sprite,
+    stage_structs: &FxHashMap<SmolStr, Struct>,
+    d: &mut SpriteDiagnostics,
+) {
+    let mut graph: FxHashMap<Callable, FxHashSet<Callable>> = FxHashMap::default();
+    for (name, body) in &sprite.proc_definitions {
+        graph.insert(Callable::Proc(name.clone()), calls(body));
+    }
+    for (name, body) in &sprite.func_definitions {
+        graph.insert(Callable::Func(name.clone()), calls(body));
+    }
+    let reachable: FxHashMap<Callable, FxHashSet<Callable>> = graph
+        .keys()
+        .map(|callable| (callable.clone(), reachable(&graph, callable)))
+        .collect();
+    let mut callables: Vec<&Callable> = reachable
+        .iter()
+        .filter(|(callable, reached)| reached.contains(*callable))
+        .map(|(callable, _)| callable)
+        .collect();
+    callables.sort_by_key(|callable| callable.name());
+    for callable in callables {
+        let (span, recursive, body, locals) = match callable {
+            Callable::Proc(name) => {
+                let proc = &sprite.procs[name];
+                (
+                    proc.span.clone(),
+                    proc.recursive,
+                    &sprite.proc_definitions[name],
+                    &sprite.proc_locals[name],
+                )
+            }
+            Callable::Func(name) => {
+                let func = &sprite.funcs[name];
+                (
+                    func.span.clone(),
+                    func.recursive,
+                    &sprite.func_definitions[name],
+                    &sprite.func_locals[name],
+                )
+            }
+        };
+        let saved = saved_names(body, locals, sprite, stage_structs, &span);
+        if saved.is_empty() {
+            continue;
+        }
+        if !recursive {
+            d.report(
+                DiagnosticKind::UnsafeRecursion(callable.name().clone()),
+                &span,
+            );
+            continue;
+        }
+        // Calls which cannot reach this procedure or function again do not overwrite
+        // its variables.
+        let reenters = |call: &Callable| {
+            reachable
+                .get(call)
+                .is_some_and(|reached| reached.contains(callable))
+        };
+        let body = match callable {
+            Callable::Proc(name) => sprite.proc_definitions.get_mut(name).unwrap(),
+            Callable::Func(name) => sprite.func_definitions.get_mut(name).unwrap(),
+        };
+        visit_stmts(body, &reenters, &saved, &span);
+        sprite.lists.entry(STACK.into()).or_insert_with(|| List {
+            name: STACK.into(),
+            span: span.clone(),
+            type_: Type::Value,
+            default: None,
+            is_used: true,
+        });
+    }
+}
+
+fn reachable(
+    graph: &FxHashMap<Callable, FxHashSet<Callable>>,
+    callable: &Callable,
+) -> FxHashSet<Callable> {
+    let mut reached = FxHashSet::default();
+    let mut stack: Vec<&Callable> = graph[callable].iter().collect();
+    while let Some(callable) = stack.pop() {
+        if reached.insert(callable.clone()) {
+            if let Some(calls) = graph.get(callable) {
+                stack.extend(calls);
+            }
+        }
+    }
+    reached
+}
+
+/// The variables in `body` which another call can overwrite, as one name per field.
+fn saved_names(
+    body: &[Stmt],
+    locals: &FxHashMap<SmolStr, Var>,
+    sprite: &Sprite,
+    stage_structs: &FxHashMap<SmolStr, Struct>,
+    span: &Span,
+) -> Vec<Name> {
+    let mut targets: Vec<SmolStr> = vec![];
+    let mut names: Vec<SmolStr> = vec![];
+    walk_stmts(
+        body,
+        &mut |stmt| targets.extend(stmt_names(stmt).map(|name| name.basename().clone())),
+        &mut |expr| {
+            if let Expr::Name(name) = expr {
+                names.push(name.basename().clone());
+            }
+        },
+    );
+    names.extend(targets);
+    let mut seen = FxHashSet::default();
+    names.retain(|name| seen.insert(name.clone()));
+    // Loop flags and match values are sprite variables too, which the loops and matches
+    // of another call would overwrite.
+    let is_generated = |name: &str| {
+        name.starts_with('@')
+            || name.starts_with("loop:")
+            || name.starts_with("match:")
+            || name.split_once(':').is_some_and(|(callee, var_name)| {
+                var_name != "return"
+                    && (sprite.inlined_procs.contains(callee)
+                        || sprite.inlined_funcs.contains(callee))
+            })
+    };
+    let get_struct = |name: &str| sprite.structs.get(name).or_else(|| stage_structs.get(name));
+    let mut saved = vec![];
+    for name in names {
+        let var = match locals.get(&name) {
+            Some(var) => var,
+            None if is_generated(&name) => match sprite.vars.get(&name) {
+                Some(var) => var,
+                None => continue,
+            },
+            None => continue,
+        };
+        match var
+            .type_
+            .struct_()
+            .and_then(|(type_name, _)| get_struct(type_name))
+        {
+            Some(struct_) => {
+                for (path, _) in struct_.leaves(&get_struct) {
+                    saved.push(Name::DotName {
+                        lhs: name.clone(),
+                        lhs_span: span.clone(),
+                        rhs: path,
+                        rhs_span: span.clone(),
+                        is_generated: true,
+                    });
+                }
+            }
+            None => saved.push(Name::Name {
+                name,
+                span: span.clone(),
+            }),
+        }
+    }
+    saved
+}
+
+fn visit_stmts(
+    stmts: &mut Vec<Stmt>,
+    reenters: &impl Fn(&Callable) -> bool,
+    saved: &[Name],
+    span: &Span,
+) {
+    let stack = || Name::Name {
+        name: STACK.into(),
+        span: span.clone(),
+    };
+    let last = || Value::from("last").to_expr(span.clone());
+    let mut i = 0;
+    while i < stmts.len() {
+        match &mut stmts[i] {
+            Stmt::Repeat { body, .. } | Stmt::Forever { body, .. } | Stmt::Until { body, .. } => {
+                visit_stmts(body, reenters, saved, span)
+            }
+            Stmt::Branch {
+                if_body, else_body, ..
+            } => {
+                visit_stmts(if_body, reenters, saved, span);
+                visit_stmts(else_body, reenters, saved, span);
+            }
+            _ => {}
+        }
+        if !Callable::called_by(&stmts[i]).is_some_and(|call| reenters(&call)) {
+            i += 1;
+            continue;
+        }
+        let push = saved.iter().map(|name| Stmt::AddToList {
+            name: stack(),
+            value: Box::new(Expr::Name(name.clone())),
+        });
+        let pop = saved.iter().rev().flat_map(|name| {
+            [
+                Stmt::SetVar {
+                    name: name.clone(),
+                    value: Box::new(BinOp::Of.to_expr(span.clone(), Expr::Name(stack()), last())),
+                    type_: Type::Value,
+                    is_local: false,
+                    is_cloud: false,
+                },
+                Stmt::DeleteListIndex {
+                    name: stack(),
+                    index: Box::new(last()),
+                },
+            ]
+        });
+        let call = stmts.remove(i);
+        let wrapped: Vec<Stmt> = push.chain([call]).chain(pop).collect();
+        i += wrapped.len();
+        stmts.splice(i - wrapped.len()..i - wrapped.len(), wrapped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostic::DiagnosticKind,
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn recursive_procs_and_funcs_keep_their_locals() {
+        let main = r#"costumes "blank.svg";
+            struct Pair { a = 0, b = 0 }
+            recursive func fib(n) {
+                if $n < 2 { return $n; }
+                return fib($n - 1) + fib($n - 2);
+            }
+            recursive proc unwind n {
+                local Pair p = Pair { a: $n, b: $n * 10 };
+                if $n > 0 { unwind $n - 1; }
+                say p.a & p.b;
+            }
+            func clobbered(n) {
+                local kept = $n;
+                if $n > 0 { local ignored = clobbered($n - 1); }
+                return kept;
+            }
+            onflag {
+                say fib(10);
+                unwind 2;
+                say clobbered(3);
+            }
+            "#;
+        let (outcome, output) = run(main, None);
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(
+            output,
+            ["main: 55", "main: 00", "main: 110", "main: 220", "main: 0"]
+        );
+        let (artifact, _) = build_sb3(main, false);
+        let diagnostics = &artifact.sprites_diagnostics["main"].diagnostics;
+        assert!(diagnostics.iter().any(|diagnostic| matches!(
+            &diagnostic.kind,
+            DiagnosticKind::UnsafeRecursion(name) if name == "clobbered"
+        )));
+        assert!(!diagnostics.iter().any(|diagnostic| matches!(
+            &diagnostic.kind,
+            DiagnosticKind::UnsafeRecursion(name) if name != "clobbered"
+        )));
+    }
+
+    #[test]
+    fn recursive_calls_keep_the_loop_state_of_their_caller() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            recursive proc walk depth {
+                local i = 0;
+                while i < 2 {
+                    i += 1;
+                    say $depth & ":" & i;
+                    if $depth > 0 { walk $depth - 1; } else { break; }
+                }
+                say "end " & $depth;
+            }
+            recursive proc count depth {
+                repeat 2 {
+                    if $depth > 0 { count $depth - 1; } else { break; }
+                    say "count " & $depth;
+                }
+            }
+            onflag {
+                walk 1;
+                count 1;
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(
+            output,
+            [
+                "main: 1:1",
+                "main: 0:1",
+                "main: end 0",
+                "main: 1:2",
+                "main: 0:1",
+                "main: end 0",
+                "main: end 1",
+                "main: count 1",
+                "main: count 1",
+            ]
+        );
+    }
+}
@@ -66,6 +66,7 @@ fn stmt_find_closest_ternary(stmt: &Stmt) -> Option<&Expr> {
         Stmt::Branch { cond, .. } => expr_find_closest_ternary(cond),
         Stmt::Until { cond, .. } => expr_find_closest_ternary(cond),
         Stmt::SetVar { value, .. } => expr_find_closest_ternary(value),
+        Stmt::Destructure { value, .. } => expr_find_closest_ternary(value),
         Stmt::ChangeVar { value, .. } => expr_find_closest_ternary(value),
         Stmt::AddToList { value, .. } => expr_find_closest_ternary(value),
         Stmt::DeleteListIndex { index, .. } => expr_find_closest_ternary(index),
@@ -115,6 +116,13 @@ fn stmt_find_closest_ternary(stmt: &Stmt) -> Option<&Expr> {
             return None;
         }
         Stmt::Return { value, .. } => expr_find_closest_ternary(value),
+        Stmt::While { .. }
+        | Stmt::For { .. }
+        | Stmt::Break { .. }
+        | Stmt::Continue { .. }
+        | Stmt::Match { .. } => {
+            unreachable!()
+        }
     }
 }
 
@@ -172,6 +180,7 @@ fn stmt_split_closest_ternary(stmt: &mut Stmt, condition: bool) -> bool {
         Stmt::Branch { cond, .. } => expr_split_closest_ternary(cond, condition),
         Stmt::Until { cond, .. } => expr_split_closest_ternary(cond, condition),
         Stmt::SetVar { value, .. } => expr_split_closest_ternary(value, condition),
+        Stmt::Destructure { value, .. } => expr_split_closest_ternary(value, condition),
         Stmt::ChangeVar { value, .. } => expr_split_closest_ternary(value, condition),
         Stmt::AddToList { value, .. } => expr_split_closest_ternary(value, condition),
         Stmt::DeleteListIndex { index, .. } => expr_split_closest_ternary(index, condition),
@@ -223,6 +232,13 @@ fn stmt_split_closest_ternary(stmt: &mut Stmt, condition: bool) -> bool {
             false
         }
         Stmt::Return { value, .. } => expr_split_closest_ternary(value, condition),
+        Stmt::While { .. }
+        | Stmt::For { .. }
+        | Stmt::Break { .. }
+        | Stmt::Continue { .. }
+        | Stmt::Match { .. } => {
+            unreachable!()
+        }
     }
 }
 
@@ -71,31 +71,18 @@ pub fn list_field_access(expr: &Expr, s: S) -> Option<Expr> {
     };
     let list = s.get_list(name)?;
     let (type_name, type_span) = list.type_.struct_()?;
-    let struct_ = s.get_struct(type_name)?;
-    Some(Expr::StructLiteral {
-        name: type_name.clone(),
-        span: type_span.clone(),
-        fields: struct_
-            .fields
-            .iter()
-            .map(|field| StructLiteralField {
-                name: field.name.clone(),
-                span: field.span.clone(),
-                value: BinOp::Of
-                    .to_expr(
-                        span.clone(),
-                        Expr::Name(Name::DotName {
-                            lhs: name.clone(),
-                            lhs_span: span.clone(),
-                            rhs: field.name.clone(),
-                            rhs_span: field.span.clone(),
-                            is_generated: true,
-                        }),
-                        rhs.as_ref().clone(),
-                    )
-                    .into(),
-            })
-            .collect(),
+    struct_literal_of_fields(s, type_name, type_span, None, &|path, field_span| {
+        BinOp::Of.to_expr(
+            span.clone(),
+            Expr::Name(Name::DotName {
+                lhs: name.clone(),
+                lhs_span: span.clone(),
+                rhs: path,
+                rhs_span: field_span.clone(),
+                is_generated: true,
+            }),
+            rhs.as_ref().clone(),
+        )
     })
 }
 
@@ -103,47 +90,59 @@ pub fn variable_field_access(expr: &Expr, s: S) -> Option<Expr> {
     let Expr::Name(name) = expr else {
         return None;
     };
-    if name.fieldname().is_some() {
-        return None;
-    }
-    let basename = name.basename();
     let span = name.span();
-    let var = &s.get_var(basename)?;
-    let (type_name, type_span) = var.type_.struct_()?;
-    let struct_ = s.get_struct(type_name)?;
-    Some(Expr::StructLiteral {
-        name: type_name.clone(),
-        span: type_span.clone(),
-        fields: struct_
-            .fields
-            .iter()
-            .map(|field| StructLiteralField {
-                name: field.name.clone(),
-                span: field.span.clone(),
-                value: Expr::Name(Name::DotName {
-                    lhs: var.name.clone(),
-                    lhs_span: span.clone(),
-                    rhs: field.name.clone(),
-                    rhs_span: field.span.clone(),
-                    is_generated: true,
-                })
-                .into(),
+    let var = s.get_var(name.basename())?;
+    let (type_name, type_span) = s.field_type(&var.type_, name)?.struct_()?;
+    struct_literal_of_fields(
+        s,
+        type_name,
+        type_span,
+        name.fieldname(),
+        &|path, field_span| {
+            Expr::Name(Name::DotName {
+                lhs: var.name.clone(),
+                lhs_span: span.clone(),
+                rhs: path,
+                rhs_span: field_span.clone(),
+                is_generated: true,
             })
-            .collect(),
-    })
+        },
+    )
 }
 
 pub fn arg_field_access(expr: &Expr, s: S) -> Option<Expr> {
     let Expr::Arg(name) = expr else {
         return None;
     };
-    if name.fieldname().is_some() {
-        return None;
-    }
-    let basename = name.basename();
     let span = name.span();
-    let arg = s.args?.iter().find(|arg| &arg.name == basename)?;
-    let (type_name, type_span) = arg.type_.struct_()?;
+    let arg = s.args?.iter().find(|arg| arg.name == *name.basename())?;
+    let (type_name, type_span) = s.field_type(&arg.type_, name)?.struct_()?;
+    struct_literal_of_fields(
+        s,
+        type_name,
+        type_span,
+        name.fieldname(),
+        &|path, field_span| {
+            Expr::Arg(Name::DotName {
+                lhs: arg.name.clone(),
+                lhs_span: span.clone(),
+                rhs: path,
+                rhs_span: field_span.clone(),
+                is_generated: true,
+            })
+        },
+    )
+}
+
+/// Builds a literal of the struct `type_name`, where `field_value` gives the value of each
+/// field from its path below `prefix`. Fields which are structs become nested literals.
+fn struct_literal_of_fields(
+    s: S,
+    type_name: &SmolStr,
+    type_span: &Span,
+    prefix: Option<&SmolStr>,
+    field_value: &dyn Fn(SmolStr, &Span) -> Expr,
+) -> Option<Expr> {
     let struct_ = s.get_struct(type_name)?;
     Some(Expr::StructLiteral {
         name: type_name.clone(),
@@ -151,22 +150,43 @@ pub fn arg_field_access(expr: &Expr, s: S) -> Option<Expr> {
         fields: struct_
             .fields
             .iter()
-            .map(|field| StructLiteralField {
-                name: field.name.clone(),
-                span: field.span.clone(),
-                value: Expr::Arg(Name::DotName {
-                    lhs: arg.name.clone(),
-                    lhs_span: span.clone(),
-                    rhs: field.name.clone(),
-                    rhs_span: field.span.clone(),
-                    is_generated: true,
-                })
-                .into(),
+            .map(|field| {
+                let path: SmolStr = match prefix {
+                    Some(prefix) => format!("{prefix}.{}", field.name).into(),
+                    None => field.name.clone(),
+                };
+                let value = match field.type_.struct_() {
+                    Some((name, span)) => {
+                        struct_literal_of_fields(s, name, span, Some(&path), field_value)
+                    }
+                    None => None,
+                }
+                .unwrap_or_else(|| field_value(path, &field.span));
+                StructLiteralField {
+                    name: field.name.clone(),
+                    span: field.span.clone(),
+                    value: value.into(),
+                }
             })
             .collect(),
     })
 }
 
+/// Replaces a variable which is never assigned to with its default value.
+pub fn constant_var(expr: &Expr, s: S) -> Option<Expr> {
+    let Expr::Name(Name::Name { name, span }) = expr else {
+        return None;
+    };
+    if s.local_vars
+        .is_some_and(|local_vars| local_vars.contains_key(name))
+    {
+        return None;
+    }
+    let value = s.constants.get(name)?;
+    s.read_constants.borrow_mut().insert(name.clone());
+    Some(value.clone().to_expr(span.clone()))
+}
+
 pub fn bin_op(expr: &Expr) -> Option<Expr> {
     let Expr::BinOp { op, span, lhs, rhs } = expr else {
         return None;
@@ -183,7 +203,12 @@ pub fn bin_op(expr: &Expr) -> Option<Expr> {
     else {
         return None;
     };
-    Some(Value::bin_op(*op, lhs_value, rhs_value).to_expr(span.clone()))
+    // The half of a surrogate pair is left for Scratch to find, as it would be replaced
+    // with U+FFFD in a literal.
+    if matches!(op, BinOp::Of) && Value::letter_is_surrogate(lhs_value, rhs_value) {
+        return None;
+    }
+    folded(Value::bin_op(*op, lhs_value, rhs_value), span)
 }
 
 pub fn un_op(expr: &Expr) -> Option<Expr> {
@@ -196,7 +221,16 @@ pub fn un_op(expr: &Expr) -> Option<Expr> {
     else {
         return None;
     };
-    Some(Value::un_op(*op, opr_value).to_expr(span.clone()))
+    folded(Value::un_op(*op, opr_value), span)
+}
+
+/// Booleans are not folded, because a literal can only hold a number or a string, and
+/// Scratch converts `true` to `1` but `"true"` to `0`.
+fn folded(value: Value, span: &Span) -> Option<Expr> {
+    if let Value::Boolean(_) = value {
+        return None;
+    }
+    Some(value.to_expr(span.clone()))
 }
 
 pub fn minus(expr: &Expr) -> Option<Expr> {
@@ -579,3 +613,78 @@ pub fn enum_field_access(expr: &Expr, s: S, d: D) -> Option<Expr> {
         span: lhs.span().start..rhs_span.end,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::*,
+        vm::Outcome,
+    };
+
+    #[test]
+    fn constants_are_folded_and_propagated() {
+        let main = r#"costumes "blank.svg";
+            var offset = 10;
+            var moved = 10;
+            enum Dir { Up = 2 }
+            onflag {
+                say 2 * 3 + offset;
+                say "a" & "b" & offset;
+                say Dir.Up * 4 == 8;
+                say moved + 1;
+            }
+            onclick { moved = 20; }
+            "#;
+        let (outcome, output) = run(main, None);
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(output, ["main: 16", "main: ab10", "main: true", "main: 11"]);
+        let (artifact, _) = build_sb3(main, false);
+        // The flag, 4 say blocks, the `==` and `moved + 1` blocks, and 2 blocks for `onclick`.
+        assert_eq!(artifact.block_count, 10);
+    }
+
+    #[test]
+    fn halves_of_surrogate_pairs_are_not_folded() {
+        let (artifact, sb3) = build_sb3(
+            "costumes \"blank.svg\";\n\
+             onflag { say \"a😀\"[2] & \"a😀\"[3]; say \"é😀\"[1]; }\n",
+            false,
+        );
+        // The flag, 2 say blocks, the join and 2 letter of blocks.
+        assert_eq!(artifact.block_count, 6);
+        let json = project_json(sb3).to_string();
+        assert!(!json.contains('\u{FFFD}'));
+        assert!(json.contains("\"é\""));
+    }
+
+    #[test]
+    fn literals_are_converted_to_numbers_by_identities() {
+        let main = r#"costumes "blank.svg";
+            var big = "1e3";
+            onflag {
+                say "1e3" * 1;
+                say "1e3" + 0;
+                say big + 0;
+                say " 5 " - 0;
+                say "0x10" / 1;
+                say "12px" + 0;
+            }
+            "#;
+        let (outcome, output) = run(main, None);
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(
+            output,
+            [
+                "main: 1000",
+                "main: 1000",
+                "main: 1000",
+                "main: 5",
+                "main: 16",
+                "main: 0",
+            ]
+        );
+        let (artifact, _) = build_sb3(main, false);
+        // The flag and 6 say blocks.
+        assert_eq!(artifact.block_count, 7);
+    }
+}
@@ -0,0 +1,149 @@
+use rustc_hash::FxHashSet;
+
+use crate::{
+    ast::*,
+    misc::SmolStr,
+};
+
+/// A procedure or function, as procedures and functions may share names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callable {
+    Proc(SmolStr),
+    Func(SmolStr),
+}
+
+impl Callable {
+    pub fn name(&self) -> &SmolStr {
+        match self {
+            Callable::Proc(name) | Callable::Func(name) => name,
+        }
+    }
+
+    /// The procedure or function that `stmt` calls.
+    pub fn called_by(stmt: &Stmt) -> Option<Self> {
+        match stmt {
+            Stmt::ProcCall { name, .. } => Some(Callable::Proc(name.clone())),
+            Stmt::FuncCall { name, .. } => Some(Callable::Func(name.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// The procedures and functions called in `stmts`.
+pub fn calls(stmts: &[Stmt]) -> FxHashSet<Callable> {
+    let mut calls = FxHashSet::default();
+    walk_stmts(
+        stmts,
+        &mut |stmt| calls.extend(Callable::called_by(stmt)),
+        &mut |_| {},
+    );
+    calls
+}
+
+/// The variables or lists that `stmt` operates on.
+pub fn stmt_names(stmt: &Stmt) -> impl Iterator<Item = &Name> {
+    let name = match stmt {
+        Stmt::SetVar { name, .. }
+        | Stmt::ChangeVar { name, .. }
+        | Stmt::Show(name)
+        | Stmt::Hide(name)
+        | Stmt::AddToList { name, .. }
+        | Stmt::DeleteList(name)
+        | Stmt::DeleteListIndex { name, .. }
+        | Stmt::InsertAtList { name, .. }
+        | Stmt::SetListIndex { name, .. } => Some(name),
+        _ => None,
+    };
+    let names = match stmt {
+        Stmt::Destructure { names, .. } => names.as_slice(),
+        _ => &[],
+    };
+    name.into_iter().chain(names)
+}
+
+/// Calls `f` on every statement in `stmts` and `g` on every expression, including those in
+/// nested bodies. Loops, `match` and ternaries must have been lowered already.
+pub fn walk_stmts(stmts: &[Stmt], f: &mut impl FnMut(&Stmt), g: &mut impl FnMut(&Expr)) {
+    for stmt in stmts {
+        f(stmt);
+        match stmt {
+            Stmt::Repeat { times, body } => {
+                walk_expr(times, g);
+                walk_stmts(body, f, g);
+            }
+            Stmt::Forever { body, .. } => walk_stmts(body, f, g),
+            Stmt::Branch {
+                cond,
+                if_body,
+                else_body,
+            } => {
+                walk_expr(cond, g);
+                walk_stmts(if_body, f, g);
+                walk_stmts(else_body, f, g);
+            }
+            Stmt::Until { cond, body } => {
+                walk_expr(cond, g);
+                walk_stmts(body, f, g);
+            }
+            Stmt::While { .. }
+            | Stmt::For { .. }
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Match { .. } => unreachable!(),
+            Stmt::SetVar { value, .. }
+            | Stmt::Destructure { value, .. }
+            | Stmt::ChangeVar { value, .. }
+            | Stmt::AddToList { value, .. } => walk_expr(value, g),
+            Stmt::Show(_) | Stmt::Hide(_) | Stmt::DeleteList(_) => {}
+            Stmt::DeleteListIndex { index, .. } => walk_expr(index, g),
+            Stmt::InsertAtList { index, value, .. } | Stmt::SetListIndex { index, value, .. } => {
+                walk_expr(index, g);
+                walk_expr(value, g);
+            }
+            Stmt::Block { args, kwargs, .. }
+            | Stmt::ProcCall { args, kwargs, .. }
+            | Stmt::FuncCall { args, kwargs, .. } => {
+                for arg in args {
+                    walk_expr(arg, g);
+                }
+                for (_, arg) in kwargs.values() {
+                    walk_expr(arg, g);
+                }
+            }
+            Stmt::Return { value, .. } => walk_expr(value, g),
+        }
+    }
+}
+
+pub fn walk_expr(expr: &Expr, g: &mut impl FnMut(&Expr)) {
+    g(expr);
+    match expr {
+        Expr::Value { .. } | Expr::Name(_) | Expr::Arg(_) => {}
+        Expr::Dot { lhs, .. } => walk_expr(lhs, g),
+        Expr::Repr { args, .. } => {
+            for arg in args {
+                walk_expr(arg, g);
+            }
+        }
+        Expr::FuncCall { args, kwargs, .. } => {
+            for arg in args {
+                walk_expr(arg, g);
+            }
+            for (_, arg) in kwargs.values() {
+                walk_expr(arg, g);
+            }
+        }
+        Expr::UnOp { opr, .. } => walk_expr(opr, g),
+        Expr::BinOp { lhs, rhs, .. } => {
+            walk_expr(lhs, g);
+            walk_expr(rhs, g);
+        }
+        Expr::StructLiteral { fields, .. } => {
+            for field in fields {
+                walk_expr(&field.value, g);
+            }
+        }
+        Expr::Property { object, .. } => walk_expr(object, g),
+        Expr::Ternary { .. } => unreachable!(),
+    }
+}
@@ -0,0 +1,507 @@
+//! A headless interpreter for the blocks that do not draw anything, to run projects from
+//! the command line. It runs the `project.json` of a built project, so it runs exactly
+//! what Scratch would.
+//!
+//! Time is virtual: each frame, every script runs until it yields, and then the clock
+//! advances by [`FRAME`]. `wait` and `timer` use this clock, so projects run as fast as
+//! possible, and give the same results every time.
+
+mod evaluate;
+mod execute;
+mod project;
+mod thread;
+
+use std::{
+    collections::BTreeSet,
+    io::{
+        Read,
+        Seek,
+    },
+    rc::Rc,
+};
+
+use project::{
+    BlockRef,
+    Sprite,
+};
+use thread::{
+    Thread,
+    ThreadState,
+};
+
+use crate::{
+    ast::Value,
+    misc::SmolStr,
+};
+
+/// Length of a frame in seconds.
+pub const FRAME: f64 = 1.0 / 30.0;
+
+const MAX_CLONES: usize = 300;
+
+/// Receives the output of a running project.
+pub trait Host {
+    /// A sprite used a `say` or `think` block.
+    fn say(&mut self, sprite: &str, message: &str);
+
+    /// A sprite used `log`, `warn` or `error`.
+    fn log(&mut self, level: LogLevel, sprite: &str, message: &str);
+
+    /// An `assert` or `assert_eq` failed, and the project was stopped. `values` are the
+    /// values that `assert_eq` compared.
+    fn assertion_failed(&mut self, sprite: &str, block: &str, values: Option<(&str, &str)>);
+
+    /// A sprite used an `ask` block.
+    fn ask(&mut self, _sprite: &str, _question: &str) -> String {
+        String::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Log,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every script finished.
+    Finished,
+    /// A `stop_all` block was used.
+    Stopped,
+    /// An assertion failed.
+    Failed,
+    /// Scripts were still running when the timeout was reached.
+    TimedOut,
+}
+
+/// The Stage, a sprite or a clone.
+#[derive(Debug, Clone)]
+struct Target {
+    sprite: usize,
+    variables: Vec<Value>,
+    lists: Vec<Vec<Value>>,
+    is_clone: bool,
+    is_deleted: bool,
+}
+
+/// Cloning the VM is cheap, because the sprites are shared.
+#[derive(Clone)]
+pub struct VM {
+    sprites: Vec<Rc<Sprite>>,
+    /// The Stage is always the first target, and its variables and lists are global.
+    targets: Vec<Target>,
+    /// Indices of targets from the bottom layer to the top layer. Scripts of targets in
+    /// higher layers run first.
+    layers: Vec<usize>,
+    threads: Vec<Thread>,
+    next_thread_id: usize,
+    /// Seconds since the project was started.
+    time: f64,
+    timer_start: f64,
+    answer: SmolStr,
+    is_stopped: bool,
+    has_failed: bool,
+    /// Opcodes of blocks that were run, but are not supported and did nothing.
+    unsupported: BTreeSet<SmolStr>,
+}
+
+impl VM {
+    /// Load the project from an `.sb3` file.
+    pub fn new<T: Read + Seek>(sb3: T) -> anyhow::Result<Self> {
+        Ok(Self::from_sprites(project::load_sb3(sb3)?))
+    }
+
+    /// Load the project from its `project.json`.
+    pub fn from_project_json(project_json: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::from_sprites(project::load(project_json)?))
+    }
+
+    fn from_sprites(sprites: Vec<Sprite>) -> Self {
+        let targets = sprites
+            .iter()
+            .enumerate()
+            .map(|(i, sprite)| Target {
+                sprite: i,
+                variables: sprite.variables.clone(),
+                lists: sprite.lists.clone(),
+                is_clone: false,
+                is_deleted: false,
+            })
+            .collect();
+        let mut layers: Vec<usize> = (0..sprites.len()).collect();
+        layers.sort_by_key(|&i| (!sprites[i].is_stage, sprites[i].layer_order));
+        Self {
+            sprites: sprites.into_iter().map(Rc::new).collect(),
+            targets,
+            layers,
+            threads: vec![],
+            next_thread_id: 0,
+            time: 0.0,
+            timer_start: 0.0,
+            answer: arcstr::literal!(""),
+            is_stopped: false,
+            has_failed: false,
+            unsupported: BTreeSet::new(),
+        }
+    }
+
+    /// Seconds since the project was started.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Opcodes of blocks that were run, but are not supported and did nothing.
+    pub fn unsupported(&self) -> &BTreeSet<SmolStr> {
+        &self.unsupported
+    }
+
+    /// Click the green flag.
+    pub fn green_flag(&mut self) {
+        self.start_hats("event_whenflagclicked", None, None);
+    }
+
+    /// Start the scripts that receive `broadcast`, only in the original target of
+    /// `sprite_name` if it is given. Returns whether any script was started.
+    pub fn broadcast(&mut self, broadcast: &str, sprite_name: Option<&str>) -> bool {
+        let target = match sprite_name {
+            Some(sprite_name) => match self.original(sprite_name) {
+                Some(target) => Some(target),
+                None => return false,
+            },
+            None => None,
+        };
+        !self
+            .start_hats("event_whenbroadcastreceived", Some(broadcast), target)
+            .is_empty()
+    }
+
+    /// Run until every script has finished, or until `timeout` seconds have passed.
+    pub fn run(&mut self, host: &mut dyn Host, timeout: Option<f64>) -> Outcome {
+        loop {
+            if self.has_failed {
+                return Outcome::Failed;
+            }
+            if self.is_stopped {
+                return Outcome::Stopped;
+            }
+            if self.threads.is_empty() {
+                return Outcome::Finished;
+            }
+            if timeout.is_some_and(|timeout| self.time >= timeout) {
+                return Outcome::TimedOut;
+            }
+            self.frame(host);
+            self.time += FRAME;
+        }
+    }
+
+    fn frame(&mut self, host: &mut dyn Host) {
+        // Threads that are started during the frame are run in the same frame.
+        let mut i = 0;
+        while i < self.threads.len() && !self.is_stopped {
+            if self.is_ready(&self.threads[i]) {
+                // The thread is replaced by a placeholder while it runs, which records
+                // whether it was stopped or restarted by itself.
+                let placeholder = Thread {
+                    stack: vec![],
+                    state: ThreadState::Running,
+                    ..self.threads[i]
+                };
+                let mut thread = std::mem::replace(&mut self.threads[i], placeholder);
+                thread.state = ThreadState::Running;
+                self.step(&mut thread, host);
+                let placeholder = &mut self.threads[i];
+                if placeholder.state == ThreadState::Done {
+                    thread.state = ThreadState::Done;
+                } else if !placeholder.stack.is_empty() {
+                    thread.stack = std::mem::take(&mut placeholder.stack);
+                    thread.state = ThreadState::Running;
+                }
+                self.threads[i] = thread;
+            }
+            i += 1;
+        }
+        self.threads
+            .retain(|thread| thread.state != ThreadState::Done);
+    }
+
+    fn is_ready(&self, thread: &Thread) -> bool {
+        match &thread.state {
+            ThreadState::Running => true,
+            ThreadState::Sleeping { until } => self.time >= *until,
+            ThreadState::Joining { threads } => !threads.iter().any(|&id| self.is_running(id)),
+            ThreadState::Done => false,
+        }
+    }
+
+    fn is_running(&self, id: usize) -> bool {
+        self.threads
+            .iter()
+            .any(|thread| thread.id == id && thread.state != ThreadState::Done)
+    }
+
+    /// Start the scripts with the hat block `opcode`, or restart them if they are already
+    /// running. Returns the IDs of the threads.
+    fn start_hats(
+        &mut self,
+        opcode: &str,
+        broadcast: Option<&str>,
+        target: Option<usize>,
+    ) -> Vec<usize> {
+        let mut started = vec![];
+        for t in self.layers.clone().into_iter().rev() {
+            if target.is_some_and(|target| target != t) {
+                continue;
+            }
+            let sprite = self.sprites[self.targets[t].sprite].clone();
+            for &hat in &sprite.scripts {
+                let block = &sprite.blocks[hat];
+                if block.opcode != opcode {
+                    continue;
+                }
+                if let Some(broadcast) = broadcast {
+                    let Some(name) = block.fields.get("BROADCAST_OPTION") else {
+                        continue;
+                    };
+                    if name.to_lowercase() != broadcast.to_lowercase() {
+                        continue;
+                    }
+                }
+                started.push(self.start_thread(t, hat, block.next));
+            }
+        }
+        started
+    }
+
+    fn start_thread(&mut self, target: usize, hat: BlockRef, first: Option<BlockRef>) -> usize {
+        if let Some(thread) = self.threads.iter_mut().find(|thread| {
+            thread.target == target && thread.hat == hat && thread.state != ThreadState::Done
+        }) {
+            thread.restart(first);
+            return thread.id;
+        }
+        let id = self.next_thread_id;
+        self.next_thread_id += 1;
+        self.threads.push(Thread::new(id, target, hat, first));
+        id
+    }
+
+    fn stop_threads(&mut self, predicate: impl Fn(&Thread) -> bool) {
+        for thread in &mut self.threads {
+            if predicate(thread) {
+                thread.state = ThreadState::Done;
+            }
+        }
+    }
+
+    /// The original target of the sprite, which is not a clone.
+    fn original(&self, sprite_name: &str) -> Option<usize> {
+        self.targets.iter().position(|target| {
+            !target.is_clone && *self.sprites[target.sprite].name == *sprite_name
+        })
+    }
+
+    fn create_clone(&mut self, source: usize) {
+        let Target {
+            sprite,
+            variables,
+            lists,
+            ..
+        } = &self.targets[source];
+        if self.sprites[*sprite].is_stage {
+            return;
+        }
+        let clones = self
+            .targets
+            .iter()
+            .filter(|target| target.is_clone && !target.is_deleted)
+            .count();
+        if clones >= MAX_CLONES {
+            return;
+        }
+        let clone = Target {
+            sprite: *sprite,
+            variables: variables.clone(),
+            lists: lists.clone(),
+            is_clone: true,
+            is_deleted: false,
+        };
+        // The threads of deleted clones have all stopped, so their slot can be reused.
+        let index = match self.targets.iter().position(|target| target.is_deleted) {
+            Some(index) => {
+                self.targets[index] = clone;
+                index
+            }
+            None => {
+                self.targets.push(clone);
+                self.targets.len() - 1
+            }
+        };
+        // Clones are placed behind the target that they were cloned from.
+        let layer = self.layers.iter().position(|&t| t == source).unwrap();
+        self.layers.insert(layer, index);
+        self.start_hats("control_start_as_clone", None, Some(index));
+    }
+
+    fn delete_clone(&mut self, target: usize) {
+        if !self.targets[target].is_clone {
+            return;
+        }
+        self.targets[target].is_deleted = true;
+        self.layers.retain(|&t| t != target);
+        self.stop_threads(|thread| thread.target == target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::EventKind,
+        testing::*,
+    };
+
+    #[test]
+    fn vm_runs_procedures_functions_and_lists() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            list items = [1, 2];
+            proc greet name {
+                say "hello " & $name;
+                stop_this_script;
+                say "unreachable";
+            }
+            func fact(n) {
+                if $n < 2 {
+                    return 1;
+                }
+                return $n * fact($n - 1);
+            }
+            onflag {
+                greet "world";
+                add "x" to items;
+                delete items[1];
+                say items[2] & length items;
+                say fact(10);
+                i = 0;
+                repeat 3 {
+                    i += 1;
+                }
+                say i / 2;
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(
+            output,
+            [
+                "main: hello world",
+                "main: x2",
+                "main: 3628800",
+                "main: 1.5"
+            ]
+        );
+    }
+
+    #[test]
+    fn vm_runs_broadcasts_and_clones() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            var id = 0;
+            onflag {
+                broadcast_and_wait "ready";
+                say "after ready";
+                id = 1;
+                clone;
+                id = 2;
+                clone;
+                wait 1;
+                log "done";
+            }
+            on "ready" {
+                wait 0.5;
+                say "ready";
+            }
+            onclone {
+                say "clone " & id;
+                delete_this_clone;
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Finished);
+        assert_eq!(
+            output,
+            [
+                "main: ready",
+                "main: after ready",
+                "main: clone 1",
+                "main: clone 2",
+                "main: done"
+            ]
+        );
+    }
+
+    #[test]
+    fn vm_stops_and_times_out() {
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            onflag {
+                forever {
+                    wait 1;
+                    say timer();
+                }
+            }
+            "#,
+            Some(2.5),
+        );
+        assert_eq!(outcome, Outcome::TimedOut);
+        assert_eq!(output.len(), 2);
+        let (outcome, output) = run(
+            r#"costumes "blank.svg";
+            onflag {
+                say "before";
+                stop_all;
+                say "after";
+            }
+            "#,
+            None,
+        );
+        assert_eq!(outcome, Outcome::Stopped);
+        assert_eq!(output, ["main: before"]);
+    }
+
+    #[test]
+    fn vm_runs_tests_by_themselves() {
+        let (artifact, sb3) = build_sb3(TESTS, true);
+        let broadcast = |name: &str| EventKind::Test { name: name.into() }.broadcast().unwrap();
+        let vm = VM::new(sb3).unwrap();
+
+        let mut passing = vm.clone();
+        assert!(passing.broadcast(&broadcast("addition"), Some("main")));
+        let mut output = Output(vec![]);
+        assert_eq!(passing.run(&mut output, None), Outcome::Finished);
+        assert_eq!(output.0, ["main: passed"]);
+
+        let mut failing = vm.clone();
+        assert!(failing.broadcast(&broadcast("subtraction"), Some("main")));
+        let mut output = Output(vec![]);
+        assert_eq!(failing.run(&mut output, None), Outcome::Failed);
+        let [failure] = output.0.as_slice() else {
+            panic!("{:?}", output.0);
+        };
+        let (block, values) = failure
+            .strip_prefix("main: ")
+            .unwrap()
+            .split_once(' ')
+            .unwrap();
+        assert_eq!(values, r#"Some(("2", "1"))"#);
+        let span = artifact.sprites_diagnostics["main"]
+            .block_span(block)
+            .unwrap();
+        assert_eq!(span.start, TESTS.find("assert_eq 3 - 1").unwrap());
+    }
+}
@@ -0,0 +1,299 @@
+use chrono::{
+    Datelike,
+    Local,
+    TimeZone,
+    Timelike,
+    Utc,
+};
+
+use super::{
+    project::{
+        BlockRef,
+        Input,
+        Sprite,
+        VarRef,
+    },
+    thread::Thread,
+    VM,
+};
+use crate::{
+    ast::{
+        ListIndex,
+        Value,
+    },
+    blocks::{
+        BinOp,
+        UnOp,
+    },
+    misc::SmolStr,
+};
+
+impl VM {
+    pub(super) fn variable_mut(&mut self, target: usize, variable: VarRef) -> &mut Value {
+        let target = if variable.is_global { 0 } else { target };
+        &mut self.targets[target].variables[variable.index]
+    }
+
+    pub(super) fn list_mut(&mut self, target: usize, list: VarRef) -> &mut Vec<Value> {
+        let target = if list.is_global { 0 } else { target };
+        &mut self.targets[target].lists[list.index]
+    }
+
+    /// The value of an input of a block. Empty inputs are empty strings.
+    pub(super) fn input(
+        &mut self,
+        thread: &Thread,
+        sprite: &Sprite,
+        block: BlockRef,
+        name: &str,
+    ) -> Value {
+        match sprite.blocks[block].inputs.get(name) {
+            None => Value::from(""),
+            Some(Input::Value(value)) => value.clone(),
+            Some(Input::Block(reporter)) => self.evaluate(thread, sprite, *reporter),
+            Some(Input::Variable(variable)) => self.variable_mut(thread.target, *variable).clone(),
+            Some(Input::List(list)) => list_contents(self.list_mut(thread.target, *list)),
+        }
+    }
+
+    pub(super) fn condition(
+        &mut self,
+        thread: &Thread,
+        sprite: &Sprite,
+        block: BlockRef,
+        name: &str,
+    ) -> bool {
+        self.input(thread, sprite, block, name).to_boolean()
+    }
+
+    fn bin_op(
+        &mut self,
+        op: BinOp,
+        thread: &Thread,
+        sprite: &Sprite,
+        block: BlockRef,
+        lhs: &str,
+        rhs: &str,
+    ) -> Value {
+        let lhs = self.input(thread, sprite, block, lhs);
+        let rhs = self.input(thread, sprite, block, rhs);
+        Value::bin_op(op, &lhs, &rhs)
+    }
+
+    fn evaluate(&mut self, thread: &Thread, sprite: &Sprite, block: BlockRef) -> Value {
+        if let Some(value) = sprite.menu(block) {
+            return Value::from(value.clone());
+        }
+        let target = thread.target;
+        let opcode = sprite.blocks[block].opcode.as_str();
+        match opcode {
+            "operator_add" => self.bin_op(BinOp::Add, thread, sprite, block, "NUM1", "NUM2"),
+            "operator_subtract" => self.bin_op(BinOp::Sub, thread, sprite, block, "NUM1", "NUM2"),
+            "operator_multiply" => self.bin_op(BinOp::Mul, thread, sprite, block, "NUM1", "NUM2"),
+            "operator_divide" => self.bin_op(BinOp::Div, thread, sprite, block, "NUM1", "NUM2"),
+            "operator_mod" => self.bin_op(BinOp::Mod, thread, sprite, block, "NUM1", "NUM2"),
+            "operator_lt" => self.bin_op(BinOp::Lt, thread, sprite, block, "OPERAND1", "OPERAND2"),
+            "operator_gt" => self.bin_op(BinOp::Gt, thread, sprite, block, "OPERAND1", "OPERAND2"),
+            "operator_equals" => {
+                self.bin_op(BinOp::Eq, thread, sprite, block, "OPERAND1", "OPERAND2")
+            }
+            "operator_and" => {
+                self.bin_op(BinOp::And, thread, sprite, block, "OPERAND1", "OPERAND2")
+            }
+            "operator_or" => self.bin_op(BinOp::Or, thread, sprite, block, "OPERAND1", "OPERAND2"),
+            "operator_not" => Value::from(!self.condition(thread, sprite, block, "OPERAND")),
+            "operator_join" => {
+                self.bin_op(BinOp::Join, thread, sprite, block, "STRING1", "STRING2")
+            }
+            "operator_letter_of" => {
+                self.bin_op(BinOp::Of, thread, sprite, block, "STRING", "LETTER")
+            }
+            "operator_contains" => {
+                self.bin_op(BinOp::In, thread, sprite, block, "STRING2", "STRING1")
+            }
+            "operator_length" => {
+                Value::un_op(UnOp::Length, &self.input(thread, sprite, block, "STRING"))
+            }
+            "operator_round" => {
+                Value::un_op(UnOp::Round, &self.input(thread, sprite, block, "NUM"))
+            }
+            "operator_mathop" => {
+                let number = self.input(thread, sprite, block, "NUM");
+                let operator = sprite.blocks[block].fields.get("OPERATOR");
+                match mathop(operator.map_or("", |o| o.as_str())) {
+                    Some(op) => Value::un_op(op, &number),
+                    None => Value::from(0.0),
+                }
+            }
+            "operator_random" => {
+                let from = self.input(thread, sprite, block, "FROM");
+                let to = self.input(thread, sprite, block, "TO");
+                Value::from(random(&from, &to))
+            }
+            "argument_reporter_string_number" | "argument_reporter_boolean" => sprite.blocks[block]
+                .fields
+                .get("VALUE")
+                .and_then(|name| thread.argument(name))
+                .cloned()
+                .unwrap_or(Value::from(0.0)),
+            "data_variable" => match sprite.blocks[block].variable {
+                Some(variable) => self.variable_mut(target, variable).clone(),
+                None => Value::from(0.0),
+            },
+            "data_listcontents" => match sprite.blocks[block].list {
+                Some(list) => list_contents(self.list_mut(target, list)),
+                None => Value::from(""),
+            },
+            "data_itemoflist" => {
+                let index = self.input(thread, sprite, block, "INDEX");
+                let Some(list) = sprite.blocks[block].list else {
+                    return Value::from("");
+                };
+                let list = self.list_mut(target, list);
+                match list_index(&index, list.len(), false) {
+                    Some(ListIndex::Index(index)) => list[index].clone(),
+                    _ => Value::from(""),
+                }
+            }
+            "data_itemnumoflist" => {
+                let item = self.input(thread, sprite, block, "ITEM");
+                let Some(list) = sprite.blocks[block].list else {
+                    return Value::from(0.0);
+                };
+                let list = self.list_mut(target, list);
+                let index = list
+                    .iter()
+                    .position(|other| Value::compare(other, &item) == 0.0)
+                    .map_or(0, |index| index + 1);
+                Value::from(index)
+            }
+            "data_lengthoflist" => match sprite.blocks[block].list {
+                Some(list) => Value::from(self.list_mut(target, list).len()),
+                None => Value::from(0.0),
+            },
+            "data_listcontainsitem" => {
+                let item = self.input(thread, sprite, block, "ITEM");
+                let Some(list) = sprite.blocks[block].list else {
+                    return Value::from(false);
+                };
+                let list = self.list_mut(target, list);
+                Value::from(list.iter().any(|other| Value::compare(other, &item) == 0.0))
+            }
+            "sensing_timer" => Value::from(self.time - self.timer_start),
+            "sensing_answer" => Value::from(self.answer.clone()),
+            "sensing_username" => Value::from(""),
+            "sensing_dayssince2000" => {
+                let start = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+                let milliseconds = (Utc::now() - start).num_milliseconds() as f64;
+                Value::from(milliseconds / 86_400_000.0)
+            }
+            "sensing_current" => {
+                let now = Local::now();
+                let menu = sprite.blocks[block].fields.get("CURRENTMENU");
+                Value::from(match menu.map(|menu| menu.to_lowercase()).as_deref() {
+                    Some("year") => now.year() as f64,
+                    Some("month") => now.month() as f64,
+                    Some("date") => now.day() as f64,
+                    Some("dayofweek") => now.weekday().number_from_sunday() as f64,
+                    Some("hour") => now.hour() as f64,
+                    Some("minute") => now.minute() as f64,
+                    Some("second") => now.second() as f64,
+                    _ => 0.0,
+                })
+            }
+            "sensing_of" => {
+                let object = self.input(thread, sprite, block, "OBJECT").to_string();
+                let property = sprite.blocks[block].fields.get("PROPERTY");
+                self.attribute_of(&object, property.map_or("", |p| p.as_str()))
+                    .unwrap_or_else(|| {
+                        self.unsupported.insert(opcode.into());
+                        Value::from(0.0)
+                    })
+            }
+            _ => {
+                self.unsupported.insert(opcode.into());
+                Value::from(0.0)
+            }
+        }
+    }
+
+    /// The value of a variable of another sprite, or of the Stage.
+    fn attribute_of(&self, object: &str, property: &str) -> Option<Value> {
+        let target = if object == "_stage_" {
+            0
+        } else {
+            self.original(object)?
+        };
+        let index = *self.sprites[self.targets[target].sprite]
+            .variable_names
+            .get(property)?;
+        Some(self.targets[target].variables[index].clone())
+    }
+}
+
+/// Like `Cast.toListIndex` in Scratch.
+pub fn list_index(index: &Value, length: usize, accept_all: bool) -> Option<ListIndex> {
+    if let Value::String(string) = index {
+        match string.as_str() {
+            "all" => return accept_all.then_some(ListIndex::All),
+            "last" => return (length > 0).then(|| ListIndex::Index(length - 1)),
+            "random" | "any" => {
+                return (length > 0).then(|| ListIndex::Index(rand::random_range(0..length)))
+            }
+            _ => {}
+        }
+    }
+    let index = index.to_number().floor();
+    if index < 1.0 || index > length as f64 {
+        return None;
+    }
+    Some(ListIndex::Index(index as usize - 1))
+}
+
+/// Lists of single characters are joined without spaces.
+fn list_contents(list: &[Value]) -> Value {
+    let separator = if list
+        .iter()
+        .all(|item| matches!(item, Value::String(string) if string.encode_utf16().count() == 1))
+    {
+        ""
+    } else {
+        " "
+    };
+    let items: Vec<SmolStr> = list.iter().map(Value::to_string).collect();
+    Value::from(items.join(separator))
+}
+
+fn mathop(operator: &str) -> Option<UnOp> {
+    Some(match operator {
+        "abs" => UnOp::Abs,
+        "floor" => UnOp::Floor,
+        "ceiling" => UnOp::Ceil,
+        "sqrt" => UnOp::Sqrt,
+        "sin" => UnOp::Sin,
+        "cos" => UnOp::Cos,
+        "tan" => UnOp::Tan,
+        "asin" => UnOp::Asin,
+        "acos" => UnOp::Acos,
+        "atan" => UnOp::Atan,
+        "ln" => UnOp::Ln,
+        "log" => UnOp::Log,
+        "e ^" => UnOp::AntiLn,
+        "10 ^" => UnOp::AntiLog,
+        _ => return None,
+    })
+}
+
+/// Random integers if both bounds are integers, otherwise random decimals.
+fn random(from: &Value, to: &Value) -> f64 {
+    let (from_number, to_number) = (from.to_number(), to.to_number());
+    let low = from_number.min(to_number);
+    let high = from_number.max(to_number);
+    if low == high {
+        return low;
+    }
+    if from.is_integer() && to.is_integer() {
+        return low + (rand::random::<f64>() * (high + 1.0 - low)).floor();
+    }
+    rand::random::<f64>() * (high - low) + low
+}
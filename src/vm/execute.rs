@@ -0,0 +1,431 @@
+use rustc_hash::FxHashMap;
+
+use super::{
+    evaluate::list_index,
+    project::{
+        BlockRef,
+        Input,
+        Sprite,
+    },
+    thread::{
+        Frame,
+        FrameKind,
+        Thread,
+        ThreadState,
+    },
+    Host,
+    LogLevel,
+    VM,
+};
+use crate::ast::{
+    js_round,
+    ListIndex,
+    Value,
+};
+
+/// How many blocks a thread may run before it yields, even in warp mode, so that a
+/// project that never yields can still time out.
+const WARP_BUDGET: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Yield,
+}
+
+impl VM {
+    /// Run the thread until it yields.
+    pub(super) fn step(&mut self, thread: &mut Thread, host: &mut dyn Host) {
+        let sprite = self.sprites[self.targets[thread.target].sprite].clone();
+        let mut budget = WARP_BUDGET;
+        while thread.state == ThreadState::Running && !self.is_stopped {
+            let Some(frame) = thread.stack.last_mut() else {
+                thread.state = ThreadState::Done;
+                return;
+            };
+            let flow = match frame.next {
+                Some(block) => {
+                    frame.next = sprite.blocks[block].next;
+                    self.execute(thread, &sprite, block, host)
+                }
+                None => self.end_frame(thread, &sprite),
+            };
+            budget -= 1;
+            if flow == Flow::Yield || budget == 0 {
+                return;
+            }
+        }
+    }
+
+    /// The blocks of the frame have run. Loops yield at the end of each iteration, unless
+    /// in warp mode.
+    fn end_frame(&mut self, thread: &mut Thread, sprite: &Sprite) -> Flow {
+        let is_warp = thread.is_warp();
+        let frame = thread.stack.last_mut().unwrap();
+        if frame.is_loop() && !is_warp && !frame.has_yielded {
+            frame.has_yielded = true;
+            return Flow::Yield;
+        }
+        frame.has_yielded = false;
+        let repeat = match &mut frame.kind {
+            FrameKind::Script | FrameKind::Branch | FrameKind::Procedure { .. } => false,
+            FrameKind::Repeat { body, remaining } => {
+                *remaining -= 1.0;
+                if *remaining >= 0.0 {
+                    frame.next = *body;
+                }
+                *remaining >= 0.0
+            }
+            FrameKind::Forever { body } => {
+                frame.next = *body;
+                true
+            }
+            &mut FrameKind::Until { block, body } => {
+                let repeat = !self.condition(thread, sprite, block, "CONDITION");
+                if repeat {
+                    thread.stack.last_mut().unwrap().next = body;
+                }
+                repeat
+            }
+            &mut FrameKind::While { block, body } => {
+                let repeat = self.condition(thread, sprite, block, "CONDITION");
+                if repeat {
+                    thread.stack.last_mut().unwrap().next = body;
+                }
+                repeat
+            }
+        };
+        if !repeat {
+            thread.stack.pop();
+        }
+        Flow::Continue
+    }
+
+    fn substack(sprite: &Sprite, block: BlockRef, name: &str) -> Option<BlockRef> {
+        match sprite.blocks[block].inputs.get(name) {
+            Some(Input::Block(body)) => Some(*body),
+            _ => None,
+        }
+    }
+
+    fn sleep(&self, thread: &mut Thread, seconds: f64) -> Flow {
+        thread.state = ThreadState::Sleeping {
+            until: self.time + seconds,
+        };
+        Flow::Yield
+    }
+
+    fn execute(
+        &mut self,
+        thread: &mut Thread,
+        sprite: &Sprite,
+        block: BlockRef,
+        host: &mut dyn Host,
+    ) -> Flow {
+        let target = thread.target;
+        let opcode = sprite.blocks[block].opcode.as_str();
+        match opcode {
+            "control_forever" => {
+                let body = Self::substack(sprite, block, "SUBSTACK");
+                thread
+                    .stack
+                    .push(Frame::new(body, FrameKind::Forever { body }));
+            }
+            "control_repeat" => {
+                let times = js_round(self.input(thread, sprite, block, "TIMES").to_number());
+                if times >= 1.0 {
+                    let body = Self::substack(sprite, block, "SUBSTACK");
+                    thread.stack.push(Frame::new(
+                        body,
+                        FrameKind::Repeat {
+                            body,
+                            remaining: times - 1.0,
+                        },
+                    ));
+                }
+            }
+            "control_repeat_until" => {
+                if !self.condition(thread, sprite, block, "CONDITION") {
+                    let body = Self::substack(sprite, block, "SUBSTACK");
+                    thread
+                        .stack
+                        .push(Frame::new(body, FrameKind::Until { block, body }));
+                }
+            }
+            "control_while" => {
+                if self.condition(thread, sprite, block, "CONDITION") {
+                    let body = Self::substack(sprite, block, "SUBSTACK");
+                    thread
+                        .stack
+                        .push(Frame::new(body, FrameKind::While { block, body }));
+                }
+            }
+            "control_if" => {
+                if self.condition(thread, sprite, block, "CONDITION") {
+                    self.branch(thread, sprite, block, "SUBSTACK");
+                }
+            }
+            "control_if_else" => {
+                if self.condition(thread, sprite, block, "CONDITION") {
+                    self.branch(thread, sprite, block, "SUBSTACK");
+                } else {
+                    self.branch(thread, sprite, block, "SUBSTACK2");
+                }
+            }
+            "control_wait" => {
+                let duration = self.input(thread, sprite, block, "DURATION").to_number();
+                return self.sleep(thread, duration);
+            }
+            "control_wait_until" => {
+                if !self.condition(thread, sprite, block, "CONDITION") {
+                    thread.stack.last_mut().unwrap().next = Some(block);
+                    return Flow::Yield;
+                }
+            }
+            "control_stop" => {
+                match sprite.blocks[block]
+                    .fields
+                    .get("STOP_OPTION")
+                    .map(|option| option.as_str())
+                {
+                    Some("all") => {
+                        self.is_stopped = true;
+                        thread.state = ThreadState::Done;
+                    }
+                    Some("other scripts in sprite" | "other scripts in stage") => {
+                        let id = thread.id;
+                        self.stop_threads(|other| other.target == target && other.id != id);
+                    }
+                    _ => thread.stop_this_script(),
+                }
+            }
+            "control_create_clone_of" => {
+                let option = self
+                    .input(thread, sprite, block, "CLONE_OPTION")
+                    .to_string();
+                let source = if option == "_myself_" {
+                    Some(target)
+                } else {
+                    self.original(&option)
+                };
+                if let Some(source) = source {
+                    self.create_clone(source);
+                }
+            }
+            "control_delete_this_clone" => {
+                if self.targets[target].is_clone {
+                    self.delete_clone(target);
+                    thread.state = ThreadState::Done;
+                    return Flow::Yield;
+                }
+            }
+            "event_broadcast" => {
+                let name = self
+                    .input(thread, sprite, block, "BROADCAST_INPUT")
+                    .to_string();
+                self.start_hats("event_whenbroadcastreceived", Some(&name), None);
+            }
+            "event_broadcastandwait" => {
+                let name = self
+                    .input(thread, sprite, block, "BROADCAST_INPUT")
+                    .to_string();
+                let threads = self.start_hats("event_whenbroadcastreceived", Some(&name), None);
+                if !threads.is_empty() {
+                    thread.state = ThreadState::Joining { threads };
+                    return Flow::Yield;
+                }
+            }
+            "procedures_call" => return self.call(thread, sprite, block, host),
+            "looks_say" | "looks_think" => {
+                let message = self.input(thread, sprite, block, "MESSAGE").to_string();
+                if !message.is_empty() {
+                    host.say(&sprite.name, &message);
+                }
+            }
+            "looks_sayforsecs" | "looks_thinkforsecs" => {
+                let message = self.input(thread, sprite, block, "MESSAGE").to_string();
+                let seconds = self.input(thread, sprite, block, "SECS").to_number();
+                if !message.is_empty() {
+                    host.say(&sprite.name, &message);
+                }
+                return self.sleep(thread, seconds);
+            }
+            "sensing_askandwait" => {
+                let question = self.input(thread, sprite, block, "QUESTION").to_string();
+                self.answer = host.ask(&sprite.name, &question).into();
+            }
+            "sensing_resettimer" => self.timer_start = self.time,
+            "data_setvariableto" => {
+                let value = self.input(thread, sprite, block, "VALUE");
+                if let Some(variable) = sprite.blocks[block].variable {
+                    *self.variable_mut(target, variable) = value;
+                }
+            }
+            "data_changevariableby" => {
+                let value = self.input(thread, sprite, block, "VALUE").to_number();
+                if let Some(variable) = sprite.blocks[block].variable {
+                    let variable = self.variable_mut(target, variable);
+                    *variable = Value::from(variable.to_number() + value);
+                }
+            }
+            "data_showvariable" | "data_hidevariable" | "data_showlist" | "data_hidelist" => {}
+            "data_addtolist" => {
+                let item = self.input(thread, sprite, block, "ITEM");
+                if let Some(list) = sprite.blocks[block].list {
+                    self.list_mut(target, list).push(item);
+                }
+            }
+            "data_deleteoflist" => {
+                let index = self.input(thread, sprite, block, "INDEX");
+                if let Some(list) = sprite.blocks[block].list {
+                    let list = self.list_mut(target, list);
+                    match list_index(&index, list.len(), true) {
+                        Some(ListIndex::All) => list.clear(),
+                        Some(ListIndex::Index(index)) => {
+                            list.remove(index);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            "data_deletealloflist" => {
+                if let Some(list) = sprite.blocks[block].list {
+                    self.list_mut(target, list).clear();
+                }
+            }
+            "data_insertatlist" => {
+                let item = self.input(thread, sprite, block, "ITEM");
+                let index = self.input(thread, sprite, block, "INDEX");
+                if let Some(list) = sprite.blocks[block].list {
+                    let list = self.list_mut(target, list);
+                    if let Some(ListIndex::Index(index)) = list_index(&index, list.len() + 1, false)
+                    {
+                        list.insert(index, item);
+                    }
+                }
+            }
+            "data_replaceitemoflist" => {
+                let item = self.input(thread, sprite, block, "ITEM");
+                let index = self.input(thread, sprite, block, "INDEX");
+                if let Some(list) = sprite.blocks[block].list {
+                    let list = self.list_mut(target, list);
+                    if let Some(ListIndex::Index(index)) = list_index(&index, list.len(), false) {
+                        list[index] = item;
+                    }
+                }
+            }
+            _ => {
+                self.unsupported.insert(opcode.into());
+            }
+        }
+        Flow::Continue
+    }
+
+    fn branch(&mut self, thread: &mut Thread, sprite: &Sprite, block: BlockRef, name: &str) {
+        if let Some(body) = Self::substack(sprite, block, name) {
+            thread.stack.push(Frame::new(Some(body), FrameKind::Branch));
+        }
+    }
+
+    fn call(
+        &mut self,
+        thread: &mut Thread,
+        sprite: &Sprite,
+        block: BlockRef,
+        host: &mut dyn Host,
+    ) -> Flow {
+        let Some(mutation) = &sprite.blocks[block].mutation else {
+            return Flow::Continue;
+        };
+        let proccode = &mutation.proccode;
+        if let Some(name) = debugger_procedure(proccode) {
+            let message = mutation
+                .argument_ids
+                .first()
+                .map(|id| self.input(thread, sprite, block, id).to_string())
+                .unwrap_or_default();
+            let level = match name {
+                "log" => LogLevel::Log,
+                "warn" => LogLevel::Warn,
+                "error" => LogLevel::Error,
+                "assert" | "assert_eq" => return self.assert(thread, sprite, block, host),
+                _ => return Flow::Continue,
+            };
+            host.log(level, &sprite.name, &message);
+            return Flow::Continue;
+        }
+        // Calls to procedures that are not defined do nothing.
+        let Some(procedure) = sprite.procedures.get(proccode) else {
+            return Flow::Continue;
+        };
+        let mut arguments = FxHashMap::default();
+        for (id, name) in &procedure.arguments {
+            let value = self.input(thread, sprite, block, id);
+            arguments.insert(name.clone(), value);
+        }
+        let is_recursive = thread.is_in_procedure(proccode);
+        let warp = procedure.warp || thread.is_warp();
+        thread.stack.push(Frame::new(
+            procedure.body,
+            FrameKind::Procedure {
+                proccode: proccode.clone(),
+                arguments,
+                warp,
+            },
+        ));
+        // Like Scratch, recursive calls yield, unless in warp mode.
+        if is_recursive && !warp {
+            Flow::Yield
+        } else {
+            Flow::Continue
+        }
+    }
+
+    /// A failed assertion stops the project, like a panic.
+    fn assert(
+        &mut self,
+        thread: &Thread,
+        sprite: &Sprite,
+        block: BlockRef,
+        host: &mut dyn Host,
+    ) -> Flow {
+        let mutation = sprite.blocks[block].mutation.as_ref().unwrap();
+        let values: Vec<Value> = mutation
+            .argument_ids
+            .iter()
+            .map(|id| self.input(thread, sprite, block, id))
+            .collect();
+        let failed = match values.as_slice() {
+            [condition] => !condition.to_boolean(),
+            [left, right] => Value::compare(left, right) != 0.0,
+            _ => false,
+        };
+        if !failed {
+            return Flow::Continue;
+        }
+        let values = match values.as_slice() {
+            [left, right] => Some((left.to_string(), right.to_string())),
+            _ => None,
+        };
+        host.assertion_failed(
+            &sprite.name,
+            &sprite.blocks[block].id,
+            values
+                .as_ref()
+                .map(|(left, right)| (left.as_str(), right.as_str())),
+        );
+        self.has_failed = true;
+        self.is_stopped = true;
+        Flow::Yield
+    }
+}
+
+/// `log`, `warn`, `error`, `breakpoint`, `assert` and `assert_eq` are procedures with zero-width spaces around
+/// their names, which are recognized by the TurboWarp debugger.
+fn debugger_procedure(proccode: &str) -> Option<&str> {
+    let name = proccode
+        .strip_prefix("\u{200b}\u{200b}")?
+        .split("\u{200b}\u{200b}")
+        .next()?;
+    Some(name)
+}
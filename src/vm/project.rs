@@ -0,0 +1,419 @@
+use std::io::{
+    Read,
+    Seek,
+};
+
+use anyhow::Context;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use serde_json::Value as Json;
+
+use crate::{
+    ast::Value,
+    misc::SmolStr,
+};
+
+/// Index of a block in [`Sprite::blocks`].
+pub type BlockRef = usize;
+
+/// A variable or list, either of the sprite itself or of the Stage.
+#[derive(Debug, Clone, Copy)]
+pub struct VarRef {
+    pub is_global: bool,
+    pub index: usize,
+}
+
+#[derive(Debug)]
+pub enum Input {
+    Value(Value),
+    Block(BlockRef),
+    Variable(VarRef),
+    List(VarRef),
+}
+
+#[derive(Debug)]
+pub struct Block {
+    /// The ID in the `project.json`.
+    pub id: SmolStr,
+    pub opcode: SmolStr,
+    pub next: Option<BlockRef>,
+    pub is_shadow: bool,
+    pub inputs: FxHashMap<SmolStr, Input>,
+    /// The value of each field, without the ID.
+    pub fields: FxHashMap<SmolStr, SmolStr>,
+    /// The `VARIABLE` field.
+    pub variable: Option<VarRef>,
+    /// The `LIST` field.
+    pub list: Option<VarRef>,
+    pub mutation: Option<Mutation>,
+}
+
+#[derive(Debug)]
+pub struct Mutation {
+    pub proccode: SmolStr,
+    pub argument_ids: Vec<SmolStr>,
+    pub argument_names: Vec<SmolStr>,
+    pub warp: bool,
+}
+
+#[derive(Debug)]
+pub struct Procedure {
+    pub body: Option<BlockRef>,
+    /// The ID and the name of each argument.
+    pub arguments: Vec<(SmolStr, SmolStr)>,
+    pub warp: bool,
+}
+
+/// The code and the initial state of a target in `project.json`. Clones share the
+/// sprite of the target that they were cloned from.
+#[derive(Debug)]
+pub struct Sprite {
+    pub name: SmolStr,
+    pub is_stage: bool,
+    pub layer_order: usize,
+    pub blocks: Vec<Block>,
+    /// Top-level blocks, in the order that they appear in `project.json`.
+    pub scripts: Vec<BlockRef>,
+    /// By the proccode.
+    pub procedures: FxHashMap<SmolStr, Procedure>,
+    pub variables: Vec<Value>,
+    pub variable_names: FxHashMap<SmolStr, usize>,
+    pub lists: Vec<Vec<Value>>,
+}
+
+impl Sprite {
+    /// The value of the field of a shadow block that is used as a menu.
+    pub fn menu(&self, block: BlockRef) -> Option<&SmolStr> {
+        let block = &self.blocks[block];
+        if !block.is_shadow || block.fields.len() != 1 {
+            return None;
+        }
+        block.fields.values().next()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawProject {
+    targets: Vec<RawTarget>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTarget {
+    name: SmolStr,
+    is_stage: bool,
+    #[serde(default)]
+    layer_order: usize,
+    #[serde(default)]
+    variables: serde_json::Map<String, Json>,
+    #[serde(default)]
+    lists: serde_json::Map<String, Json>,
+    #[serde(default)]
+    blocks: serde_json::Map<String, Json>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawBlock {
+    opcode: SmolStr,
+    next: Option<String>,
+    #[serde(default)]
+    shadow: bool,
+    #[serde(default)]
+    top_level: bool,
+    #[serde(default)]
+    inputs: serde_json::Map<String, Json>,
+    #[serde(default)]
+    fields: serde_json::Map<String, Json>,
+    mutation: Option<RawMutation>,
+}
+
+#[derive(Deserialize)]
+struct RawMutation {
+    proccode: Option<SmolStr>,
+    argumentids: Option<String>,
+    argumentnames: Option<String>,
+    warp: Option<Json>,
+}
+
+/// Variables and lists of a target, by their ID.
+#[derive(Default)]
+struct Data {
+    variables: Vec<Value>,
+    variable_ids: FxHashMap<String, usize>,
+    variable_names: FxHashMap<SmolStr, usize>,
+    lists: Vec<Vec<Value>>,
+    list_ids: FxHashMap<String, usize>,
+}
+
+impl Data {
+    fn new(target: &RawTarget) -> Self {
+        let mut data = Self::default();
+        for (id, variable) in &target.variables {
+            let name = variable.get(0).map(value).unwrap_or(Value::from(""));
+            data.variable_names
+                .insert(name.to_string(), data.variables.len());
+            data.variable_ids.insert(id.clone(), data.variables.len());
+            data.variables
+                .push(variable.get(1).map(value).unwrap_or(Value::from(0.0)));
+        }
+        for (id, list) in &target.lists {
+            data.list_ids.insert(id.clone(), data.lists.len());
+            data.lists.push(
+                list.get(1)
+                    .and_then(Json::as_array)
+                    .map(|items| items.iter().map(value).collect())
+                    .unwrap_or_default(),
+            );
+        }
+        data
+    }
+}
+
+/// Read the targets of the `project.json` in an `.sb3` file. The Stage is first.
+pub fn load_sb3<T: Read + Seek>(sb3: T) -> anyhow::Result<Vec<Sprite>> {
+    let mut archive = zip::ZipArchive::new(sb3)?;
+    let mut project_json = vec![];
+    archive
+        .by_name("project.json")
+        .context("project.json not found")?
+        .read_to_end(&mut project_json)?;
+    load(&project_json)
+}
+
+/// Read the targets of a `project.json`. The Stage is first.
+pub fn load(project_json: &[u8]) -> anyhow::Result<Vec<Sprite>> {
+    let mut project: RawProject = serde_json::from_slice(project_json)?;
+    let stage = project
+        .targets
+        .iter()
+        .position(|target| target.is_stage)
+        .context("project has no Stage")?;
+    project.targets.swap(0, stage);
+    let mut stage_data = Data::new(&project.targets[0]);
+    let mut sprites = vec![];
+    for (i, target) in project.targets.iter().enumerate() {
+        if i == 0 {
+            sprites.push(load_target(target, &mut stage_data, None));
+        } else {
+            let mut data = Data::new(target);
+            sprites.push(load_target(target, &mut data, Some(&mut stage_data)));
+        }
+    }
+    // Variables that did not exist were created in the Stage while loading the sprites.
+    sprites[0].variables = stage_data.variables;
+    sprites[0].variable_names = stage_data.variable_names;
+    sprites[0].lists = stage_data.lists;
+    Ok(sprites)
+}
+
+struct Loader<'a> {
+    ids: FxHashMap<&'a str, BlockRef>,
+    data: &'a mut Data,
+    stage_data: Option<&'a mut Data>,
+}
+
+impl Loader<'_> {
+    /// Find the variable with the ID, or create it if it does not exist, like Scratch.
+    fn variable(&mut self, id: &str, name: &str) -> VarRef {
+        if let Some(&index) = self.data.variable_ids.get(id) {
+            return VarRef {
+                is_global: self.stage_data.is_none(),
+                index,
+            };
+        }
+        if let Some(&index) = self
+            .stage_data
+            .as_ref()
+            .and_then(|stage_data| stage_data.variable_ids.get(id))
+        {
+            return VarRef {
+                is_global: true,
+                index,
+            };
+        }
+        let data = self.stage_data.as_deref_mut().unwrap_or(self.data);
+        let index = data.variables.len();
+        data.variables.push(Value::from(0.0));
+        data.variable_ids.insert(id.to_owned(), index);
+        data.variable_names.insert(name.into(), index);
+        VarRef {
+            is_global: true,
+            index,
+        }
+    }
+
+    /// Find the list with the ID, or create it if it does not exist, like Scratch.
+    fn list(&mut self, id: &str) -> VarRef {
+        if let Some(&index) = self.data.list_ids.get(id) {
+            return VarRef {
+                is_global: self.stage_data.is_none(),
+                index,
+            };
+        }
+        if let Some(&index) = self
+            .stage_data
+            .as_ref()
+            .and_then(|stage_data| stage_data.list_ids.get(id))
+        {
+            return VarRef {
+                is_global: true,
+                index,
+            };
+        }
+        let data = self.stage_data.as_deref_mut().unwrap_or(self.data);
+        let index = data.lists.len();
+        data.lists.push(vec![]);
+        data.list_ids.insert(id.to_owned(), index);
+        VarRef {
+            is_global: true,
+            index,
+        }
+    }
+
+    fn input(&mut self, input: &Json) -> Option<Input> {
+        let input = input.as_array()?;
+        match input.get(1)? {
+            Json::String(id) => self.ids.get(id.as_str()).copied().map(Input::Block),
+            Json::Array(primitive) => self.primitive(primitive),
+            // An empty boolean or substack input.
+            _ => None,
+        }
+    }
+
+    fn primitive(&mut self, primitive: &[Json]) -> Option<Input> {
+        let id = || primitive.get(2).and_then(Json::as_str).unwrap_or_default();
+        let name = || primitive.get(1).and_then(Json::as_str).unwrap_or_default();
+        Some(match primitive.first()?.as_u64()? {
+            12 => Input::Variable(self.variable(id(), name())),
+            13 => Input::List(self.list(id())),
+            _ => Input::Value(value(primitive.get(1)?)),
+        })
+    }
+
+    fn block(&mut self, id: &str, raw: RawBlock) -> Block {
+        let mut inputs = FxHashMap::default();
+        for (name, input) in &raw.inputs {
+            if let Some(input) = self.input(input) {
+                inputs.insert(name.into(), input);
+            }
+        }
+        let mut fields = FxHashMap::default();
+        let mut variable = None;
+        let mut list = None;
+        for (name, field) in &raw.fields {
+            let field_value = field.get(0).map(value).unwrap_or(Value::from(""));
+            let id = field.get(1).and_then(Json::as_str);
+            match (name.as_str(), id) {
+                ("VARIABLE", Some(id)) => {
+                    variable = Some(self.variable(id, &field_value.to_string()));
+                }
+                ("LIST", Some(id)) => list = Some(self.list(id)),
+                _ => {}
+            }
+            fields.insert(name.into(), field_value.to_string());
+        }
+        Block {
+            id: id.into(),
+            opcode: raw.opcode,
+            next: raw
+                .next
+                .and_then(|next| self.ids.get(next.as_str()).copied()),
+            is_shadow: raw.shadow,
+            inputs,
+            fields,
+            variable,
+            list,
+            mutation: raw.mutation.map(mutation),
+        }
+    }
+}
+
+fn load_target(target: &RawTarget, data: &mut Data, stage_data: Option<&mut Data>) -> Sprite {
+    let raw_blocks: Vec<(&str, RawBlock)> = target
+        .blocks
+        .iter()
+        // Top-level variable and list reporters are arrays, and do nothing.
+        .filter(|(_, block)| block.is_object())
+        .filter_map(|(id, block)| Some((id.as_str(), serde_json::from_value(block.clone()).ok()?)))
+        .collect();
+    let mut loader = Loader {
+        ids: raw_blocks
+            .iter()
+            .enumerate()
+            .map(|(i, (id, _))| (*id, i))
+            .collect(),
+        data,
+        stage_data,
+    };
+    let mut scripts = vec![];
+    let mut blocks = vec![];
+    for (i, (id, raw)) in raw_blocks.into_iter().enumerate() {
+        if raw.top_level {
+            scripts.push(i);
+        }
+        blocks.push(loader.block(id, raw));
+    }
+    let mut procedures = FxHashMap::default();
+    for block in &blocks {
+        if block.opcode != "procedures_definition" {
+            continue;
+        }
+        let Some(Input::Block(prototype)) = block.inputs.get("custom_block") else {
+            continue;
+        };
+        let Some(mutation) = &blocks[*prototype].mutation else {
+            continue;
+        };
+        procedures.insert(
+            mutation.proccode.clone(),
+            Procedure {
+                body: block.next,
+                arguments: mutation
+                    .argument_ids
+                    .iter()
+                    .cloned()
+                    .zip(mutation.argument_names.iter().cloned())
+                    .collect(),
+                warp: mutation.warp,
+            },
+        );
+    }
+    Sprite {
+        name: target.name.clone(),
+        is_stage: target.is_stage,
+        layer_order: target.layer_order,
+        blocks,
+        scripts,
+        procedures,
+        variables: loader.data.variables.clone(),
+        variable_names: loader.data.variable_names.clone(),
+        lists: loader.data.lists.clone(),
+    }
+}
+
+fn mutation(raw: RawMutation) -> Mutation {
+    let strings = |json: Option<String>| -> Vec<SmolStr> {
+        json.and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    };
+    Mutation {
+        proccode: raw.proccode.unwrap_or_default(),
+        argument_ids: strings(raw.argumentids),
+        argument_names: strings(raw.argumentnames),
+        warp: match raw.warp {
+            Some(Json::Bool(warp)) => warp,
+            Some(Json::String(warp)) => warp == "true",
+            _ => false,
+        },
+    }
+}
+
+fn value(json: &Json) -> Value {
+    match json {
+        Json::Bool(boolean) => Value::from(*boolean),
+        Json::Number(number) => Value::from(number.as_f64().unwrap_or_default()),
+        Json::String(string) => Value::from(string.as_str()),
+        _ => Value::from(""),
+    }
+}
@@ -0,0 +1,144 @@
+use rustc_hash::FxHashMap;
+
+use super::project::BlockRef;
+use crate::{
+    ast::Value,
+    misc::SmolStr,
+};
+
+/// A running script.
+#[derive(Debug, Clone)]
+pub struct Thread {
+    pub id: usize,
+    pub target: usize,
+    pub hat: BlockRef,
+    pub stack: Vec<Frame>,
+    pub state: ThreadState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThreadState {
+    Running,
+    /// Waiting until the virtual time reaches `until`.
+    Sleeping {
+        until: f64,
+    },
+    /// Waiting for the threads started by a broadcast to finish.
+    Joining {
+        threads: Vec<usize>,
+    },
+    Done,
+}
+
+/// A sequence of blocks that is being run.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub next: Option<BlockRef>,
+    pub kind: FrameKind,
+    /// Whether the thread has already yielded at the end of this loop iteration.
+    pub has_yielded: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum FrameKind {
+    Script,
+    Branch,
+    Repeat {
+        body: Option<BlockRef>,
+        remaining: f64,
+    },
+    Forever {
+        body: Option<BlockRef>,
+    },
+    /// The condition is an input of `block`.
+    Until {
+        block: BlockRef,
+        body: Option<BlockRef>,
+    },
+    While {
+        block: BlockRef,
+        body: Option<BlockRef>,
+    },
+    Procedure {
+        proccode: SmolStr,
+        arguments: FxHashMap<SmolStr, Value>,
+        warp: bool,
+    },
+}
+
+impl Frame {
+    pub fn new(next: Option<BlockRef>, kind: FrameKind) -> Self {
+        Self {
+            next,
+            kind,
+            has_yielded: false,
+        }
+    }
+
+    pub fn is_loop(&self) -> bool {
+        matches!(
+            self.kind,
+            FrameKind::Repeat { .. }
+                | FrameKind::Forever { .. }
+                | FrameKind::Until { .. }
+                | FrameKind::While { .. }
+        )
+    }
+}
+
+impl Thread {
+    pub fn new(id: usize, target: usize, hat: BlockRef, first: Option<BlockRef>) -> Self {
+        Self {
+            id,
+            target,
+            hat,
+            stack: vec![Frame::new(first, FrameKind::Script)],
+            state: ThreadState::Running,
+        }
+    }
+
+    pub fn restart(&mut self, first: Option<BlockRef>) {
+        self.stack = vec![Frame::new(first, FrameKind::Script)];
+        self.state = ThreadState::Running;
+    }
+
+    /// Loops do not yield in warp mode, which is inherited from the procedure that is
+    /// running.
+    pub fn is_warp(&self) -> bool {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|frame| match &frame.kind {
+                FrameKind::Procedure { warp, .. } => Some(*warp),
+                _ => None,
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn is_in_procedure(&self, proccode: &str) -> bool {
+        self.stack.iter().any(|frame| {
+            matches!(&frame.kind, FrameKind::Procedure { proccode: other, .. } if other == proccode)
+        })
+    }
+
+    /// The arguments of the procedure that is running.
+    pub fn argument(&self, name: &str) -> Option<&Value> {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|frame| match &frame.kind {
+                FrameKind::Procedure { arguments, .. } => Some(arguments.get(name)),
+                _ => None,
+            })?
+    }
+
+    /// Return from the procedure that is running, or finish if there is none.
+    pub fn stop_this_script(&mut self) {
+        while let Some(frame) = self.stack.pop() {
+            if matches!(frame.kind, FrameKind::Procedure { .. }) {
+                return;
+            }
+        }
+        self.state = ThreadState::Done;
+    }
+}
@@ -25,6 +25,10 @@ use crate::{
         Artifact,
         Diagnostic,
     },
+    fmt::{
+        format_source,
+        FmtError,
+    },
     misc::base64,
     standard_library::StandardLibrary,
     vfs::MemFS,
@@ -56,8 +60,14 @@ pub fn build(fs: Ts<MemFS>) -> Result<Ts<Build>, JsError> {
         path: "stdlib".into(),
         version: Version::new(0, 0, 0),
     };
-    let artifact = build_impl(fs, "project".into(), Cursor::new(&mut file), Some(stdlib))
-        .map_err(|error| JsError::new(&error.to_string()))?;
+    let artifact = build_impl(
+        fs,
+        "project".into(),
+        Cursor::new(&mut file),
+        Some(stdlib),
+        false,
+    )
+    .map_err(|error| JsError::new(&error.to_string()))?;
     Ok(Build { file, artifact }.into_ts()?)
 }
 
@@ -70,3 +80,8 @@ pub fn diagnostic_to_string(
     let sprite = sprite.to_rust()?;
     Ok(diagnostic.kind.to_string(&sprite))
 }
+
+#[wasm_bindgen]
+pub fn format(source: String) -> Result<String, JsError> {
+    format_source(&source).map_err(|FmtError::AnyhowError(error)| JsError::new(&error.to_string()))
+}
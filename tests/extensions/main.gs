@@ -0,0 +1,10 @@
+costumes "blank.svg";
+
+onflag {
+    change_voice "SQUEAK";
+    voice = "TENOR";
+    change_voice voice;
+    if is_exactly("a", "A") {
+        speak_words "exactly" & newline();
+    }
+}
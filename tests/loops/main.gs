@@ -0,0 +1,30 @@
+costumes "blank.svg";
+
+onflag {
+    i = 0;
+    while i < 10 {
+        i += 1;
+        if i % 2 == 0 {
+            continue;
+        }
+        say i;
+    }
+    for j = 1 to 10 {
+        if j > 5 {
+            break;
+        }
+        say j;
+    }
+    repeat 10 {
+        if i > 20 {
+            break;
+        }
+        i += 1;
+    }
+    forever {
+        if i > 30 {
+            break;
+        }
+        i += 1;
+    }
+}
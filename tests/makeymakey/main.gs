@@ -0,0 +1,13 @@
+costumes "blank.svg";
+
+onmakeykey "space" {
+    say "space";
+}
+
+onmakeykey "up arrow" {
+    say "up";
+}
+
+onmakeykey "w" {
+    say "w";
+}
@@ -0,0 +1,33 @@
+costumes "blank.svg";
+
+enum State {
+    Idle,
+    Walking,
+    Jumping,
+    Falling
+}
+
+onflag {
+    state = State.Idle;
+    forever {
+        match state {
+            State.Idle => {
+                say "idle";
+            }
+            State.Walking => {
+                change_x 10;
+            }
+            State.Jumping, State.Falling => {
+                change_y 10;
+            }
+        }
+        match key_pressed("space") + 1 {
+            1 => {
+                state = State.Idle;
+            }
+            _ => {
+                state = State.Jumping;
+            }
+        }
+    }
+}
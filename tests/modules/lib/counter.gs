@@ -0,0 +1,28 @@
+import "./steps" as steps
+
+struct Pair {
+    a,
+    b
+}
+
+enum Side {
+    Left,
+    Right
+}
+
+var count = 0;
+list history;
+
+func _twice(v) {
+    return $v * 2;
+}
+
+proc step {
+    local by = steps.size();
+    count += by;
+    add count to history;
+}
+
+func pair(side) Pair {
+    return Pair { a: $side, b: _twice($side) };
+}
@@ -0,0 +1,3 @@
+func size() {
+    return 1;
+}
@@ -0,0 +1,35 @@
+costumes "blank.svg";
+import "lib/counter" as counter
+
+var count = 100;
+
+proc step {
+    count += 10;
+}
+
+onflag {
+    counter.step;
+    step;
+    say counter.count & ", " & count;
+}
+
+test "names of a module do not collide" {
+    counter.step;
+    counter.step;
+    step;
+    assert_eq counter.count, 2;
+    assert_eq count, 110;
+}
+
+test "lists of a module" {
+    counter.step;
+    add 5 to counter.history;
+    assert_eq length counter.history, 2;
+    assert_eq counter.history[2], 5;
+}
+
+test "structs and enums of a module" {
+    counter.Pair p = counter.pair(counter.Side.Right);
+    assert_eq p.a, 1;
+    assert_eq p.b, 2;
+}
@@ -0,0 +1,61 @@
+costumes "blank.svg";
+
+struct Vec2 {
+    x = 0,
+    y = 0
+}
+
+struct Body {
+    Vec2 pos,
+    Vec2 vel = Vec2 { x: 1, y: 1 },
+    mass = 1
+}
+
+var Body player = Body { mass: 10 };
+list Body bodies;
+
+proc step Body body {
+    say $body.pos.x + $body.vel.x;
+}
+
+func speed(Body body) {
+    return $body.vel.x + $body.vel.y;
+}
+
+onflag {
+    step player;
+    say speed(player) * player.mass;
+}
+
+test "fields of nested structs" {
+    assert_eq player.pos.x, 0;
+    assert_eq player.vel.x, 1;
+    assert_eq player.mass, 10;
+}
+
+test "assigning nested fields" {
+    player.pos.x = 5;
+    player.pos.y += 2;
+    assert_eq player.pos.x, 5;
+    assert_eq player.pos.y, 2;
+    player.vel = Vec2 { x: 3, y: 4 };
+    assert_eq speed(player), 7;
+}
+
+test "nested struct literals" {
+    Body body = Body { pos: Vec2 { x: 2, y: 3 }, vel: player.vel, mass: 4 };
+    assert_eq body.pos.y, 3;
+    assert_eq body.vel.x, 1;
+    Vec2 pos = body.pos;
+    assert_eq pos.x, 2;
+}
+
+test "lists of nested structs" {
+    add Body { pos: Vec2 { x: 7, y: 8 } } to bodies;
+    assert_eq bodies[1].pos.x, 7;
+    assert_eq bodies[1].vel.y, 1;
+    bodies[1].pos.x = 9;
+    assert_eq bodies[1].pos.x, 9;
+    assert_eq length bodies, 1;
+    assert_eq 9 in bodies.pos.x, 1;
+}
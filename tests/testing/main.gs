@@ -0,0 +1,29 @@
+costumes "blank.svg";
+
+list numbers = [3, 1, 2];
+
+func sum() {
+    local total = 0;
+    for i = 1 to length numbers {
+        total += numbers[i];
+    }
+    return total;
+}
+
+onflag {
+    say sum();
+}
+
+test "sum of the list" {
+    assert_eq sum(), 6;
+}
+
+test "adding to the list" {
+    add 4 to numbers;
+    assert length numbers == 4;
+    assert_eq sum(), 10;
+}
+
+test "tests start from the initial state" {
+    assert_eq length numbers, 3;
+}
@@ -0,0 +1,15 @@
+costumes "blank.svg";
+
+onflag {
+    speak "hello";
+    speak answer();
+    set_voice_alto;
+    set_voice_tenor;
+    set_voice_squeak;
+    set_voice_giant;
+    set_voice_kitten;
+    set_voice "GIANT";
+    set_voice answer();
+    set_speech_language "de";
+    set_speech_language answer();
+}
@@ -0,0 +1,7 @@
+costumes "blank.svg";
+
+onflag {
+    say translate("hello", "fr");
+    say translate(answer(), answer());
+    say language();
+}
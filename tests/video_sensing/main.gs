@@ -0,0 +1,11 @@
+costumes "blank.svg";
+
+onflag {
+    turn_video_on;
+    turn_video_on_flipped;
+    set_video_transparency 50;
+}
+
+onvideomotion > 10 {
+    turn_video_off;
+}